@@ -0,0 +1,145 @@
+// Cooks the shaders listed in shaders/manifest.txt into a pack that release builds embed, and
+// sets cfg(shader_pack) when there is one. Without it the game compiles the sources at runtime,
+// which is what debug builds want anyway so they can hot-reload.
+
+#[cfg(windows)]
+extern crate winapi;
+#[cfg(windows)]
+extern crate wio;
+
+#[cfg(windows)]
+#[allow(dead_code)]
+#[path = "src/shader_compiler.rs"]
+mod shader_compiler;
+#[cfg(windows)]
+#[allow(dead_code)]
+#[path = "src/shader_pack.rs"]
+mod shader_pack;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const MANIFEST_PATH: &str = "shaders/manifest.txt";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", MANIFEST_PATH);
+    println!("cargo:rustc-check-cfg=cfg(shader_pack)");
+
+    if env::var("PROFILE").as_deref() != Ok("release") {
+        return;
+    }
+
+    let pack = cook_shaders();
+    if pack.is_empty() {
+        return;
+    }
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("shaders.pack");
+    fs::write(&out_path, pack).unwrap();
+    println!("cargo:rustc-cfg=shader_pack");
+}
+
+#[cfg(windows)]
+fn cook_shaders() -> Vec<u8> {
+    use shader_compiler::{compile, get_profile, ShaderDesc, ShaderStage};
+    use shader_pack::{get_shader_key, ShaderPack};
+    use std::path::PathBuf;
+    use winapi::um::d3dcommon::{
+        D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_10_1, D3D_FEATURE_LEVEL_11_0,
+        D3D_FEATURE_LEVEL_9_1, D3D_FEATURE_LEVEL_9_3,
+    };
+
+    let manifest = match fs::read_to_string(MANIFEST_PATH) {
+        Ok(manifest) => manifest,
+        Err(_) => return empty_pack(),
+    };
+
+    // Every profile the game might ask for, best first.
+    let feature_levels = [
+        D3D_FEATURE_LEVEL_11_0,
+        D3D_FEATURE_LEVEL_10_1,
+        D3D_FEATURE_LEVEL_10_0,
+        D3D_FEATURE_LEVEL_9_3,
+        D3D_FEATURE_LEVEL_9_1,
+    ];
+
+    let mut include_dirs = Vec::new();
+    let mut pack = ShaderPack::new();
+    for (line_number, line) in manifest.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let location = format!("{}:{}", MANIFEST_PATH, line_number + 1);
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap();
+        if first == "include" {
+            match words.next() {
+                Some(dir) => include_dirs.push(PathBuf::from(dir)),
+                None => panic!("{}: expected a directory", location),
+            }
+            continue;
+        }
+
+        let stage = ShaderStage::from_name(first)
+            .unwrap_or_else(|| panic!("{}: unknown shader stage {}", location, first));
+        let (path, entry_point) = match (words.next(), words.next()) {
+            (Some(path), Some(entry_point)) => (path, entry_point),
+            _ => panic!("{}: expected a path and an entry point", location),
+        };
+        let mut desc = ShaderDesc::new(path, entry_point, stage);
+        for define in words {
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            desc = desc.with_define(name, value);
+        }
+
+        let mut profiles: Vec<String> = feature_levels
+            .iter()
+            .filter_map(|&level| get_profile(stage, level))
+            .collect();
+        profiles.dedup();
+
+        // Only the best profile has to work. Shaders that need newer hardware are simply missing
+        // from the pack at the lower feature levels.
+        for (i, profile) in profiles.iter().enumerate() {
+            match compile(&desc, profile, &include_dirs, false) {
+                Ok(compiled) => {
+                    for dependency in compiled.dependencies.iter() {
+                        println!("cargo:rerun-if-changed={}", dependency.display());
+                    }
+                    for warning in compiled.warnings.lines() {
+                        println!("cargo:warning={}", warning);
+                    }
+                    pack.insert(get_shader_key(&desc, profile), compiled.bytecode);
+                }
+                Err(diagnostics) if i == 0 => {
+                    println!("cargo:rerun-if-changed={}", path);
+                    panic!("{}: failed to compile\n{}", location, diagnostics);
+                }
+                Err(_) => println!(
+                    "cargo:warning={} {} won't be available with {}",
+                    path, entry_point, profile
+                ),
+            }
+        }
+    }
+
+    if pack.is_empty() {
+        return empty_pack();
+    }
+    pack.to_bytes()
+}
+
+// Nothing to embed, the game compiles from source.
+fn empty_pack() -> Vec<u8> {
+    Vec::new()
+}
+
+// Without the D3D compiler there's nothing to cook.
+#[cfg(not(windows))]
+fn cook_shaders() -> Vec<u8> {
+    println!("cargo:warning=shaders can only be precompiled on Windows");
+    empty_pack()
+}
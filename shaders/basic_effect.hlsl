@@ -0,0 +1,97 @@
+// Used by BasicEffect, with MeshVertex input. Defines pick the features:
+//   LIGHTING      three directional lights with Blinn-Phong specular, worked out per vertex
+//   VERTEX_COLOR  multiplies by the vertex color
+//   TEXTURED      multiplies by the texture
+// Fog and alpha are always there, the constants make them do nothing when they're off.
+
+cbuffer BasicConstants : register(b0)
+{
+    row_major float4x4 World;
+    row_major float4x4 WorldViewProjection;
+    row_major float4x4 WorldInverseTranspose;
+    float4 DiffuseColor;
+    float3 EmissiveColor;
+    float SpecularPower;
+    float3 SpecularColor;
+    float FogStart;
+    float3 EyePosition;
+    // 1 / (end - start), 0 without fog.
+    float FogScale;
+    float3 FogColor;
+    // Directions the light travels in. Lights that are off are all zeros.
+    float4 LightDirection[3];
+    float4 LightDiffuseColor[3];
+    float4 LightSpecularColor[3];
+};
+
+Texture2D Texture : register(t0);
+SamplerState Sampler : register(s0);
+
+struct VSInput
+{
+    float3 Position : POSITION;
+    float3 Normal : NORMAL;
+    float2 TexCoord : TEXCOORD;
+    float4 Color : COLOR;
+};
+
+struct PSInput
+{
+    float4 Position : SV_Position;
+    float4 Diffuse : COLOR0;
+    // The fog factor goes in alpha.
+    float4 Specular : COLOR1;
+    float2 TexCoord : TEXCOORD0;
+};
+
+PSInput VSMain(VSInput input)
+{
+    PSInput output;
+    float4 position = float4(input.Position, 1.0f);
+    float3 worldPosition = mul(position, World).xyz;
+    output.Position = mul(position, WorldViewProjection);
+
+#ifdef LIGHTING
+    float3 normal = normalize(mul(input.Normal, (float3x3)WorldInverseTranspose));
+    float3 toEye = normalize(EyePosition - worldPosition);
+    float3 diffuse = 0.0f;
+    float3 specular = 0.0f;
+    [unroll]
+    for (int i = 0; i < 3; i++)
+    {
+        float3 toLight = -LightDirection[i].xyz;
+        float3 halfVector = normalize(toLight + toEye);
+        // No specular from lights behind the surface.
+        float4 terms = lit(dot(normal, toLight), dot(normal, halfVector), SpecularPower);
+        diffuse += terms.y * LightDiffuseColor[i].rgb;
+        specular += terms.z * LightSpecularColor[i].rgb;
+    }
+    output.Diffuse = float4(diffuse * DiffuseColor.rgb + EmissiveColor, DiffuseColor.a);
+    output.Specular.rgb = specular * SpecularColor;
+#else
+    output.Diffuse = DiffuseColor;
+    output.Specular.rgb = 0.0f;
+#endif
+
+#ifdef VERTEX_COLOR
+    output.Diffuse *= input.Color;
+#endif
+
+    output.Specular.a = saturate((distance(worldPosition, EyePosition) - FogStart) * FogScale);
+    output.TexCoord = input.TexCoord;
+    return output;
+}
+
+float4 PSMain(PSInput input) : SV_Target
+{
+    float4 color = input.Diffuse;
+#ifdef TEXTURED
+    color *= Texture.Sample(Sampler, input.TexCoord);
+#endif
+    color.rgb += input.Specular.rgb;
+    color.rgb = lerp(color.rgb, FogColor, input.Specular.a);
+
+    // Premultiplied, for RenderStates::premultiplied.
+    color.rgb *= color.a;
+    return color;
+}
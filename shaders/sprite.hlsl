@@ -0,0 +1,47 @@
+// Used by SpriteRenderer. Positions come in as pixels and the transform takes them to clip space.
+
+cbuffer SpriteConstants : register(b0)
+{
+    row_major float4x4 Transform;
+    // Only used by PSDistanceField, see SpriteRenderer::set_distance_field.
+    float DistanceFieldSmoothing;
+};
+
+Texture2D SpriteTexture : register(t0);
+SamplerState SpriteSampler : register(s0);
+
+struct VSInput
+{
+    float3 Position : POSITION;
+    float4 Color : COLOR;
+    float2 TexCoord : TEXCOORD;
+};
+
+struct PSInput
+{
+    float4 Position : SV_Position;
+    float4 Color : COLOR;
+    float2 TexCoord : TEXCOORD;
+};
+
+PSInput VSMain(VSInput input)
+{
+    PSInput output;
+    output.Position = mul(float4(input.Position, 1.0f), Transform);
+    output.Color = input.Color;
+    output.TexCoord = input.TexCoord;
+    return output;
+}
+
+float4 PSMain(PSInput input) : SV_Target
+{
+    return SpriteTexture.Sample(SpriteSampler, input.TexCoord) * input.Color;
+}
+
+// For textures holding signed distance fields in alpha, with the edge at 0.5.
+float4 PSDistanceField(PSInput input) : SV_Target
+{
+    float distance = SpriteTexture.Sample(SpriteSampler, input.TexCoord).a;
+    float alpha = smoothstep(0.5f - DistanceFieldSmoothing, 0.5f + DistanceFieldSmoothing, distance);
+    return input.Color * alpha;
+}
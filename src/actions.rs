@@ -0,0 +1,660 @@
+use gamepad::{GamePadAxis, GamePadButton, GamePadButtonStateTracker, GamePadState};
+use keyboard::{Key, KeyboardState, KeyboardStateTracker};
+use mouse::{ButtonState, MouseButton, MouseButtonStateTracker, MouseState};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Analog controls count as held once they're pushed at least this far.
+const PRESS_THRESHOLD: f32 = 0.5;
+
+// Anything a binding can point at.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Key(Key),
+    Mouse(MouseButton),
+    Pad(GamePadButton),
+    PadAxis(GamePadAxis),
+}
+
+impl Control {
+    // Buttons are 0 or 1, axes whatever the pad reports.
+    fn get_value(&self, devices: &DeviceStates) -> f32 {
+        let down = match *self {
+            Control::Key(key) => devices.keyboard.is_key_down(key),
+            Control::Mouse(button) => devices.mouse.is_button_down(button),
+            Control::Pad(button) => devices.gamepad.is_button_down(button),
+            Control::PadAxis(axis) => return devices.gamepad.get_axis(axis),
+        };
+
+        if down {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn is_down(&self, devices: &DeviceStates) -> bool {
+        self.get_value(devices) >= PRESS_THRESHOLD
+    }
+
+    // Parses the "Device.Name" form used in binding files, e.g. Key.Space or Pad.A.
+    pub fn from_name(name: &str) -> Option<Control> {
+        let (device, name) = name.split_once('.')?;
+
+        match device {
+            "Key" => Key::from_name(name).map(Control::Key),
+            "Mouse" => MouseButton::from_name(name).map(Control::Mouse),
+            "Pad" => GamePadButton::from_name(name).map(Control::Pad),
+            "PadAxis" => GamePadAxis::from_name(name).map(Control::PadAxis),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        match *self {
+            Control::Key(key) => format!("Key.{}", key.get_name()),
+            Control::Mouse(button) => format!("Mouse.{}", button.get_name()),
+            Control::Pad(button) => format!("Pad.{}", button.get_name()),
+            Control::PadAxis(axis) => format!("PadAxis.{}", axis.get_name()),
+        }
+    }
+
+    // The first control that went down this update, for "press a key" style rebinding.
+    // Sticks and triggers aren't considered since they rarely rest at exactly zero.
+    pub fn find_pressed(
+        keys: &KeyboardStateTracker,
+        mouse_buttons: &MouseButtonStateTracker,
+        gamepad_buttons: &GamePadButtonStateTracker,
+    ) -> Option<Control> {
+        for vk in 1..=0xfe {
+            if let Some(key) = Key::from_vk(vk) {
+                if keys.is_key_pressed(key) {
+                    return Some(Control::Key(key));
+                }
+            }
+        }
+
+        let mouse_states = [
+            (MouseButton::Left, mouse_buttons.left_button),
+            (MouseButton::Middle, mouse_buttons.middle_button),
+            (MouseButton::Right, mouse_buttons.right_button),
+            (MouseButton::X1, mouse_buttons.x_button1),
+            (MouseButton::X2, mouse_buttons.x_button2),
+        ];
+        for &(button, state) in mouse_states.iter() {
+            if state == ButtonState::Pressed {
+                return Some(Control::Mouse(button));
+            }
+        }
+
+        for bit in 0..16 {
+            let button = match GamePadButton::from_bits(1 << bit) {
+                Some(button) => button,
+                None => continue,
+            };
+            if gamepad_buttons.is_button_pressed(button) {
+                return Some(Control::Pad(button));
+            }
+        }
+
+        None
+    }
+}
+
+// The control plus any others that have to be held with it, like Key.LeftControl for Ctrl+S.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ActionBinding {
+    pub control: Control,
+    pub modifiers: Vec<Control>,
+}
+
+impl ActionBinding {
+    pub fn new(control: Control) -> ActionBinding {
+        ActionBinding {
+            control,
+            modifiers: Vec::new(),
+        }
+    }
+
+    pub fn with_modifier(mut self, modifier: Control) -> ActionBinding {
+        self.modifiers.push(modifier);
+        self
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AxisSource {
+    Control(Control),
+    // Two buttons making up one axis, like A and D for moving left and right.
+    Composite {
+        negative: Control,
+        positive: Control,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AxisBinding {
+    pub source: AxisSource,
+    // Multiplies the value. Negative values invert the axis.
+    pub scale: f32,
+    pub modifiers: Vec<Control>,
+}
+
+impl AxisBinding {
+    pub fn new(control: Control) -> AxisBinding {
+        AxisBinding {
+            source: AxisSource::Control(control),
+            scale: 1.0,
+            modifiers: Vec::new(),
+        }
+    }
+
+    pub fn composite(negative: Control, positive: Control) -> AxisBinding {
+        AxisBinding {
+            source: AxisSource::Composite { negative, positive },
+            scale: 1.0,
+            modifiers: Vec::new(),
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> AxisBinding {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_modifier(mut self, modifier: Control) -> AxisBinding {
+        self.modifiers.push(modifier);
+        self
+    }
+
+    fn get_value(&self, devices: &DeviceStates) -> f32 {
+        if !self.modifiers.iter().all(|m| m.is_down(devices)) {
+            return 0.0;
+        }
+
+        let value = match self.source {
+            AxisSource::Control(control) => control.get_value(devices),
+            AxisSource::Composite { negative, positive } => {
+                positive.get_value(devices).max(0.0) - negative.get_value(devices).max(0.0)
+            }
+        };
+
+        value * self.scale
+    }
+}
+
+// What the bindings get evaluated against.
+struct DeviceStates<'a> {
+    keyboard: &'a KeyboardState,
+    mouse: &'a MouseState,
+    gamepad: &'a GamePadState,
+}
+
+struct Action {
+    name: String,
+    bindings: Vec<ActionBinding>,
+    is_down: bool,
+    was_down: bool,
+}
+
+struct Axis {
+    name: String,
+    bindings: Vec<AxisBinding>,
+    value: f32,
+}
+
+// Named actions and axes on top of the raw devices, so game code can ask whether Jump was
+// pressed instead of which key it happens to be on. Evaluated once per update tick, right after
+// the device trackers. Unknown names are simply never down.
+pub struct ActionMap {
+    // Which gamepad the Pad and PadAxis bindings read from.
+    player: usize,
+    actions: Vec<Action>,
+    axes: Vec<Axis>,
+}
+
+impl ActionMap {
+    pub fn new() -> ActionMap {
+        ActionMap {
+            player: 0,
+            actions: Vec::new(),
+            axes: Vec::new(),
+        }
+    }
+
+    pub fn get_player(&self) -> usize {
+        self.player
+    }
+
+    pub fn set_player(&mut self, player: usize) {
+        self.player = player;
+    }
+
+    pub fn bind_action(&mut self, name: &str, binding: ActionBinding) {
+        let index = match self.actions.iter().position(|a| a.name == name) {
+            Some(index) => index,
+            None => {
+                self.actions.push(Action {
+                    name: name.to_string(),
+                    bindings: Vec::new(),
+                    is_down: false,
+                    was_down: false,
+                });
+                self.actions.len() - 1
+            }
+        };
+
+        self.actions[index].bindings.push(binding);
+    }
+
+    pub fn bind_axis(&mut self, name: &str, binding: AxisBinding) {
+        let index = match self.axes.iter().position(|a| a.name == name) {
+            Some(index) => index,
+            None => {
+                self.axes.push(Axis {
+                    name: name.to_string(),
+                    bindings: Vec::new(),
+                    value: 0.0,
+                });
+                self.axes.len() - 1
+            }
+        };
+
+        self.axes[index].bindings.push(binding);
+    }
+
+    // Keeps the action around (and its state), just without any way to trigger it.
+    pub fn clear_action_bindings(&mut self, name: &str) {
+        if let Some(action) = self.actions.iter_mut().find(|a| a.name == name) {
+            action.bindings.clear();
+        }
+    }
+
+    pub fn clear_axis_bindings(&mut self, name: &str) {
+        if let Some(axis) = self.axes.iter_mut().find(|a| a.name == name) {
+            axis.bindings.clear();
+        }
+    }
+
+    pub fn get_action_bindings(&self, name: &str) -> &[ActionBinding] {
+        match self.actions.iter().find(|a| a.name == name) {
+            Some(action) => &action.bindings,
+            None => &[],
+        }
+    }
+
+    pub fn get_axis_bindings(&self, name: &str) -> &[AxisBinding] {
+        match self.axes.iter().find(|a| a.name == name) {
+            Some(axis) => &axis.bindings,
+            None => &[],
+        }
+    }
+
+    pub fn update(&mut self, keyboard: &KeyboardState, mouse: &MouseState, gamepad: &GamePadState) {
+        let devices = DeviceStates {
+            keyboard,
+            mouse,
+            gamepad,
+        };
+
+        for action in self.actions.iter_mut() {
+            action.was_down = action.is_down;
+            action.is_down = action.bindings.iter().any(|b| {
+                b.control.is_down(&devices) && b.modifiers.iter().all(|m| m.is_down(&devices))
+            });
+        }
+
+        // When several bindings are active at once, the one pushed furthest wins, so a stick
+        // resting slightly off center doesn't cancel out the keyboard.
+        for axis in self.axes.iter_mut() {
+            let value = axis
+                .bindings
+                .iter()
+                .map(|b| b.get_value(&devices))
+                .fold(0.0f32, |a, b| if b.abs() > a.abs() { b } else { a });
+            axis.value = value.clamp(-1.0, 1.0);
+        }
+    }
+
+    // Forgets whatever was held, e.g. after the bindings changed or the window lost focus.
+    pub fn reset(&mut self) {
+        for action in self.actions.iter_mut() {
+            action.is_down = false;
+            action.was_down = false;
+        }
+        for axis in self.axes.iter_mut() {
+            axis.value = 0.0;
+        }
+    }
+
+    fn find_action(&self, name: &str) -> Option<&Action> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+
+    pub fn is_action_down(&self, name: &str) -> bool {
+        self.find_action(name).is_some_and(|a| a.is_down)
+    }
+
+    pub fn is_action_pressed(&self, name: &str) -> bool {
+        self.find_action(name)
+            .is_some_and(|a| a.is_down && !a.was_down)
+    }
+
+    pub fn is_action_released(&self, name: &str) -> bool {
+        self.find_action(name)
+            .is_some_and(|a| !a.is_down && a.was_down)
+    }
+
+    pub fn get_axis(&self, name: &str) -> f32 {
+        self.axes
+            .iter()
+            .find(|a| a.name == name)
+            .map_or(0.0, |a| a.value)
+    }
+
+    // One binding per line, e.g.
+    //   action Jump = Key.Space
+    //   action Save = Key.S + Key.LeftControl
+    //   axis MoveX = Key.A / Key.D
+    //   axis LookY = PadAxis.RightStickY * -1
+    // Empty lines and lines starting with # are ignored.
+    pub fn from_config(config: &str) -> io::Result<ActionMap> {
+        let mut map = ActionMap::new();
+
+        for (index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            map.parse_line(line).map_err(|message| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", index + 1, message),
+                )
+            })?;
+        }
+
+        Ok(map)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let mut tokens = line.split_whitespace();
+        let kind = tokens.next().unwrap_or("");
+        let name = tokens.next().ok_or("missing name")?;
+        if tokens.next() != Some("=") {
+            return Err("expected = after the name".to_string());
+        }
+
+        let tokens: Vec<&str> = tokens.collect();
+        let parse_control = |token: &str| {
+            Control::from_name(token).ok_or_else(|| format!("unknown control {}", token))
+        };
+
+        // The control part, then any number of "* scale" and "+ modifier".
+        let (source, rest) = match tokens.as_slice() {
+            [negative, "/", positive, rest @ ..] => (
+                AxisSource::Composite {
+                    negative: parse_control(negative)?,
+                    positive: parse_control(positive)?,
+                },
+                rest,
+            ),
+            [control, rest @ ..] => (AxisSource::Control(parse_control(control)?), rest),
+            [] => return Err("missing control".to_string()),
+        };
+
+        let mut scale = 1.0;
+        let mut modifiers = Vec::new();
+        for pair in rest.chunks(2) {
+            match pair {
+                ["*", value] => {
+                    scale = value
+                        .parse()
+                        .map_err(|_| format!("invalid scale {}", value))?;
+                }
+                ["+", modifier] => modifiers.push(parse_control(modifier)?),
+                _ => return Err(format!("unexpected {}", pair.join(" "))),
+            }
+        }
+
+        match (kind, source) {
+            ("action", AxisSource::Control(control)) if scale == 1.0 => {
+                self.bind_action(name, ActionBinding { control, modifiers });
+            }
+            ("action", _) => return Err("actions can't be composite or scaled".to_string()),
+            ("axis", source) => {
+                self.bind_axis(
+                    name,
+                    AxisBinding {
+                        source,
+                        scale,
+                        modifiers,
+                    },
+                );
+            }
+            _ => return Err(format!("expected action or axis, found {}", kind)),
+        }
+
+        Ok(())
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut config = String::new();
+
+        for action in self.actions.iter() {
+            for binding in action.bindings.iter() {
+                let _ = write!(
+                    config,
+                    "action {} = {}",
+                    action.name,
+                    binding.control.get_name()
+                );
+                for modifier in binding.modifiers.iter() {
+                    let _ = write!(config, " + {}", modifier.get_name());
+                }
+                config.push('\n');
+            }
+        }
+
+        for axis in self.axes.iter() {
+            for binding in axis.bindings.iter() {
+                let _ = match binding.source {
+                    AxisSource::Control(control) => {
+                        write!(config, "axis {} = {}", axis.name, control.get_name())
+                    }
+                    AxisSource::Composite { negative, positive } => write!(
+                        config,
+                        "axis {} = {} / {}",
+                        axis.name,
+                        negative.get_name(),
+                        positive.get_name()
+                    ),
+                };
+                if binding.scale != 1.0 {
+                    let _ = write!(config, " * {}", binding.scale);
+                }
+                for modifier in binding.modifiers.iter() {
+                    let _ = write!(config, " + {}", modifier.get_name());
+                }
+                config.push('\n');
+            }
+        }
+
+        config
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ActionMap> {
+        ActionMap::from_config(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_config())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(down: &[Key]) -> KeyboardState {
+        let mut state = KeyboardState::default();
+        for &key in down {
+            state.set_vk(key as u8, true);
+        }
+        state
+    }
+
+    fn axis_value(map: &mut ActionMap, keyboard: &KeyboardState, gamepad: &GamePadState) -> f32 {
+        map.update(keyboard, &MouseState::default(), gamepad);
+        map.get_axis("MoveX")
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let config = "\
+action Jump = Key.Space
+action Jump = Pad.A
+action Fire = Mouse.Left
+action Save = Key.S + Key.LeftControl
+action Menu = Pad.Start + Pad.LeftShoulder + Pad.RightShoulder
+axis MoveX = Key.A / Key.D
+axis MoveX = PadAxis.LeftStickX
+axis LookY = PadAxis.RightStickY * -1
+axis Zoom = Mouse.X1 / Mouse.X2 * 0.5 + Key.LeftShift
+";
+        let map = ActionMap::from_config(config).unwrap();
+        assert_eq!(map.to_config(), config);
+
+        assert_eq!(
+            map.get_action_bindings("Save"),
+            &[ActionBinding::new(Control::Key(Key::S))
+                .with_modifier(Control::Key(Key::LeftControl))]
+        );
+        assert_eq!(
+            map.get_axis_bindings("Zoom"),
+            &[AxisBinding::composite(
+                Control::Mouse(MouseButton::X1),
+                Control::Mouse(MouseButton::X2)
+            )
+            .with_scale(0.5)
+            .with_modifier(Control::Key(Key::LeftShift))]
+        );
+        assert_eq!(
+            map.get_axis_bindings("LookY"),
+            &[AxisBinding::new(Control::PadAxis(GamePadAxis::RightStickY)).with_scale(-1.0)]
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let map = ActionMap::from_config("# Jumping\n\n  action Jump = Key.Space  \n").unwrap();
+        assert_eq!(map.to_config(), "action Jump = Key.Space\n");
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let lines = [
+            "action",
+            "action Jump",
+            "action Jump Key.Space",
+            "action Jump =",
+            "action Jump = Key.Nope",
+            "action Jump = Keyboard.Space",
+            "action Jump = Key.A / Key.D",
+            "action Jump = Key.Space * 2",
+            "action Jump = Key.Space +",
+            "action Jump = Key.Space - Key.LeftShift",
+            "axis MoveX = Key.A /",
+            "axis MoveX = Key.A / Key.D * fast",
+            "button Jump = Key.Space",
+        ];
+        for line in lines.iter() {
+            let error = ActionMap::from_config(line).err();
+            assert!(error.is_some(), "{} parsed", line);
+        }
+
+        let error = ActionMap::from_config("action Jump = Key.Space\naction Fire = Key.Nope")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn composite_axis() {
+        let mut map = ActionMap::new();
+        map.bind_axis(
+            "MoveX",
+            AxisBinding::composite(Control::Key(Key::A), Control::Key(Key::D)),
+        );
+        map.bind_axis(
+            "MoveX",
+            AxisBinding::new(Control::PadAxis(GamePadAxis::LeftStickX)),
+        );
+
+        let centered = GamePadState::default();
+        let nudged = GamePadState {
+            left_stick_x: -0.3,
+            ..GamePadState::default()
+        };
+
+        assert_eq!(axis_value(&mut map, &keys(&[]), &centered), 0.0);
+        assert_eq!(axis_value(&mut map, &keys(&[Key::D]), &centered), 1.0);
+        assert_eq!(axis_value(&mut map, &keys(&[Key::A]), &centered), -1.0);
+        assert_eq!(
+            axis_value(&mut map, &keys(&[Key::A, Key::D]), &centered),
+            0.0
+        );
+
+        // The binding pushed furthest wins instead of them adding up or cancelling out.
+        assert_eq!(axis_value(&mut map, &keys(&[]), &nudged), -0.3);
+        assert_eq!(axis_value(&mut map, &keys(&[Key::D]), &nudged), 1.0);
+
+        map.reset();
+        assert_eq!(map.get_axis("MoveX"), 0.0);
+        assert_eq!(map.get_axis("Unbound"), 0.0);
+    }
+
+    #[test]
+    fn scaled_axis_with_modifier() {
+        let mut map = ActionMap::new();
+        map.bind_axis(
+            "MoveX",
+            AxisBinding::composite(Control::Key(Key::A), Control::Key(Key::D))
+                .with_scale(-3.0)
+                .with_modifier(Control::Key(Key::LeftShift)),
+        );
+
+        let gamepad = GamePadState::default();
+        assert_eq!(axis_value(&mut map, &keys(&[Key::D]), &gamepad), 0.0);
+        // Scaled past the end of the range, then clamped.
+        assert_eq!(
+            axis_value(&mut map, &keys(&[Key::D, Key::LeftShift]), &gamepad),
+            -1.0
+        );
+    }
+
+    #[test]
+    fn action_edges() {
+        let mut map = ActionMap::new();
+        map.bind_action(
+            "Save",
+            ActionBinding::new(Control::Key(Key::S)).with_modifier(Control::Key(Key::LeftControl)),
+        );
+
+        let mouse = MouseState::default();
+        let gamepad = GamePadState::default();
+        map.update(&keys(&[Key::S]), &mouse, &gamepad);
+        assert!(!map.is_action_down("Save"));
+
+        map.update(&keys(&[Key::S, Key::LeftControl]), &mouse, &gamepad);
+        assert!(map.is_action_pressed("Save"));
+        map.update(&keys(&[Key::S, Key::LeftControl]), &mouse, &gamepad);
+        assert!(map.is_action_down("Save") && !map.is_action_pressed("Save"));
+        map.update(&keys(&[]), &mouse, &gamepad);
+        assert!(map.is_action_released("Save"));
+    }
+}
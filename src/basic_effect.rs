@@ -0,0 +1,454 @@
+use constant_buffer::ConstantBuffer;
+use gpu_model::GpuMesh;
+use math::{Mat4, Vec3};
+use mesh::MeshVertex;
+use render_states::RenderStates;
+use shader::{ShaderDesc, ShaderHandle, ShaderLibrary, ShaderStage};
+use vertex_buffer::create_input_layout;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{
+    ID3D11Device, ID3D11DeviceContext, ID3D11InputLayout, ID3D11ShaderResourceView,
+};
+use wio::com::ComPtr;
+
+hlsl_struct! {
+    struct BasicConstants {
+        // Row major, so positions are multiplied from the left.
+        world: [[f32; 4]; 4],
+        world_view_projection: [[f32; 4]; 4],
+        world_inverse_transpose: [[f32; 4]; 4],
+        diffuse_color: [f32; 4],
+        emissive_color: [f32; 3],
+        specular_power: f32,
+        specular_color: [f32; 3],
+        fog_start: f32,
+        eye_position: [f32; 3],
+        fog_scale: f32,
+        fog_color: [f32; 3],
+        _pad: f32,
+        light_directions: [[f32; 4]; 3],
+        light_diffuse_colors: [[f32; 4]; 3],
+        light_specular_colors: [[f32; 4]; 3],
+    }
+}
+
+// Which shader an effect uses. These can't change after the effect is made, everything else can.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BasicEffectOptions {
+    // Directional lights, without them the diffuse color is all there is.
+    pub lighting: bool,
+    // Multiplies by the vertex colors.
+    pub vertex_color: bool,
+    // Multiplies by the texture given to set_texture, which has to be set before drawing.
+    pub texture: bool,
+}
+
+impl BasicEffectOptions {
+    pub fn new() -> BasicEffectOptions {
+        BasicEffectOptions::default()
+    }
+
+    pub fn with_lighting(mut self, lighting: bool) -> BasicEffectOptions {
+        self.lighting = lighting;
+        self
+    }
+
+    pub fn with_vertex_color(mut self, vertex_color: bool) -> BasicEffectOptions {
+        self.vertex_color = vertex_color;
+        self
+    }
+
+    pub fn with_texture(mut self, texture: bool) -> BasicEffectOptions {
+        self.texture = texture;
+        self
+    }
+
+    // The defines go in a fixed order so they match shaders/manifest.txt.
+    fn get_shader_desc(&self, entry_point: &str, stage: ShaderStage) -> ShaderDesc {
+        let mut desc = ShaderDesc::new("shaders/basic_effect.hlsl", entry_point, stage);
+        for &(name, enabled) in &[
+            ("LIGHTING", self.lighting),
+            ("VERTEX_COLOR", self.vertex_color),
+            ("TEXTURED", self.texture),
+        ] {
+            if enabled {
+                desc = desc.with_define(name, "1");
+            }
+        }
+        desc
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DirectionalLight {
+    // The way the light travels, doesn't have to be normalized.
+    pub direction: [f32; 3],
+    pub diffuse_color: [f32; 3],
+    pub specular_color: [f32; 3],
+}
+
+// Blends toward the color from start to end, in distance from the eye.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fog {
+    pub start: f32,
+    pub end: f32,
+    pub color: [f32; 3],
+}
+
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 3;
+
+// The key, fill and back lights of the DirectXTK EnableDefaultLighting, for a y up world.
+const DEFAULT_LIGHTS: [DirectionalLight; MAX_DIRECTIONAL_LIGHTS] = [
+    DirectionalLight {
+        direction: [-0.526_540_8, -0.573_576_5, -0.627_506_9],
+        diffuse_color: [1.0, 0.960_784_4, 0.807_843_2],
+        specular_color: [1.0, 0.960_784_4, 0.807_843_2],
+    },
+    DirectionalLight {
+        direction: [0.719_846_4, 0.342_020_1, 0.604_022_7],
+        diffuse_color: [0.964_705_9, 0.760_784_4, 0.407_843_2],
+        specular_color: [0.0, 0.0, 0.0],
+    },
+    DirectionalLight {
+        direction: [0.454_519_5, -0.766_044_4, 0.454_519_5],
+        diffuse_color: [0.323_137_3, 0.360_784_4, 0.393_725_5],
+        specular_color: [0.323_137_3, 0.360_784_4, 0.393_725_5],
+    },
+];
+const DEFAULT_AMBIENT_LIGHT: [f32; 3] = [0.053_333_32, 0.098_823_54, 0.181_960_8];
+
+struct DeviceObjects {
+    vertex_shader: ShaderHandle,
+    pixel_shader: ShaderHandle,
+    input_layout: ComPtr<ID3D11InputLayout>,
+    constants: ConstantBuffer<BasicConstants>,
+}
+
+// A ready made effect for MeshVertex geometry, in the spirit of the DirectXTK BasicEffect:
+// a material color with alpha, optional vertex colors and texture, up to three directional
+// lights and fog. Set the parameters, then apply it and draw, or let draw_mesh do both.
+//
+// Like RenderStates it keeps its parameters across a device loss and only its device objects
+// are recreated, by on_device_restored. The texture is dropped with the device, so set it again.
+pub struct BasicEffect {
+    options: BasicEffectOptions,
+    world: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    projection: [[f32; 4]; 4],
+    diffuse_color: [f32; 3],
+    alpha: f32,
+    emissive_color: [f32; 3],
+    specular_color: [f32; 3],
+    specular_power: f32,
+    ambient_light_color: [f32; 3],
+    lights: [Option<DirectionalLight>; MAX_DIRECTIONAL_LIGHTS],
+    fog: Option<Fog>,
+    double_sided: bool,
+    texture: Option<ComPtr<ID3D11ShaderResourceView>>,
+    device_objects: Option<DeviceObjects>,
+}
+
+impl BasicEffect {
+    // Draws nothing until on_device_restored has been called.
+    pub fn new(options: BasicEffectOptions) -> BasicEffect {
+        BasicEffect {
+            options,
+            world: Mat4::IDENTITY.rows,
+            view: Mat4::IDENTITY.rows,
+            projection: Mat4::IDENTITY.rows,
+            diffuse_color: [1.0; 3],
+            alpha: 1.0,
+            emissive_color: [0.0; 3],
+            specular_color: [1.0; 3],
+            specular_power: 16.0,
+            ambient_light_color: [0.0; 3],
+            lights: [None; MAX_DIRECTIONAL_LIGHTS],
+            fog: None,
+            double_sided: false,
+            texture: None,
+            device_objects: None,
+        }
+    }
+
+    pub fn get_options(&self) -> BasicEffectOptions {
+        self.options
+    }
+
+    pub fn on_device_lost(&mut self) {
+        self.device_objects = None;
+        self.texture = None;
+    }
+
+    pub unsafe fn on_device_restored(
+        &mut self,
+        device: &ID3D11Device,
+        shaders: &mut ShaderLibrary,
+    ) -> Result<(), String> {
+        let vertex_shader = shaders.load(
+            device,
+            self.options.get_shader_desc("VSMain", ShaderStage::Vertex),
+        );
+        let pixel_shader = shaders.load(
+            device,
+            self.options.get_shader_desc("PSMain", ShaderStage::Pixel),
+        );
+
+        let bytecode = shaders
+            .get_bytecode(vertex_shader)
+            .ok_or_else(|| "The basic effect vertex shader isn't available".to_string())?;
+        let input_layout = create_input_layout::<MeshVertex>(device, bytecode)?;
+        let constants = ConstantBuffer::new(device)
+            .map_err(|hr| format!("Failed to create basic effect constants, HRESULT {:x}", hr))?;
+
+        self.device_objects = Some(DeviceObjects {
+            vertex_shader,
+            pixel_shader,
+            input_layout,
+            constants,
+        });
+        Ok(())
+    }
+
+    // Row major, like the rest of the renderer.
+    pub fn set_matrices(
+        &mut self,
+        world: [[f32; 4]; 4],
+        view: [[f32; 4]; 4],
+        projection: [[f32; 4]; 4],
+    ) {
+        self.world = world;
+        self.view = view;
+        self.projection = projection;
+    }
+
+    pub fn set_world(&mut self, world: [[f32; 4]; 4]) {
+        self.world = world;
+    }
+
+    pub fn set_diffuse_color(&mut self, color: [f32; 3]) {
+        self.diffuse_color = color;
+    }
+
+    // Below 1 the effect draws without writing depth, so draw it after the opaque things.
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
+
+    // Light the surface gives off itself. Only used with lighting.
+    pub fn set_emissive_color(&mut self, color: [f32; 3]) {
+        self.emissive_color = color;
+    }
+
+    pub fn set_specular_color(&mut self, color: [f32; 3]) {
+        self.specular_color = color;
+    }
+
+    // The Blinn-Phong exponent, higher is shinier.
+    pub fn set_specular_power(&mut self, power: f32) {
+        self.specular_power = power;
+    }
+
+    pub fn set_ambient_light_color(&mut self, color: [f32; 3]) {
+        self.ambient_light_color = color;
+    }
+
+    // None turns the light off. Panics if index is MAX_DIRECTIONAL_LIGHTS or more.
+    pub fn set_light(&mut self, index: usize, light: Option<DirectionalLight>) {
+        self.lights[index] = light;
+    }
+
+    pub fn get_light(&self, index: usize) -> Option<DirectionalLight> {
+        self.lights[index]
+    }
+
+    // The standard three light rig, which looks fine on most things.
+    pub fn set_default_lighting(&mut self) {
+        self.lights = DEFAULT_LIGHTS.map(Some);
+        self.ambient_light_color = DEFAULT_AMBIENT_LIGHT;
+    }
+
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog;
+    }
+
+    // Draws back faces too instead of culling them, for materials marked double sided.
+    pub fn set_double_sided(&mut self, double_sided: bool) {
+        self.double_sided = double_sided;
+    }
+
+    // Only used when the effect was made with the texture option, e.g. with the view of a
+    // Texture. Texture alpha is taken to be straight rather than premultiplied.
+    pub fn set_texture(&mut self, texture: Option<ComPtr<ID3D11ShaderResourceView>>) {
+        self.texture = texture;
+    }
+
+    // Sets the shaders, constants, input layout, texture and render states for drawing
+    // MeshVertex triangles. Output is premultiplied and back faces, the counter-clockwise ones,
+    // are culled unless the effect is double sided. Returns false if there's nothing to draw with, because the device objects
+    // aren't there or a shader didn't compile, whose error has been printed already.
+    pub unsafe fn apply(
+        &self,
+        context: &ID3D11DeviceContext,
+        shaders: &ShaderLibrary,
+        states: &mut RenderStates,
+    ) -> Result<bool, HRESULT> {
+        let Some(ref objects) = self.device_objects else {
+            return Ok(false);
+        };
+        let (vertex_shader, pixel_shader) = match (
+            shaders.get_vertex_shader(objects.vertex_shader),
+            shaders.get_pixel_shader(objects.pixel_shader),
+        ) {
+            (Some(vertex_shader), Some(pixel_shader)) => (vertex_shader, pixel_shader),
+            _ => return Ok(false),
+        };
+
+        objects.constants.set_data(context, &self.get_constants())?;
+
+        let depth_state = if self.alpha < 1.0 {
+            states.depth_read()?
+        } else {
+            states.depth_default()?
+        };
+        context.OMSetBlendState(states.premultiplied()?.as_raw(), &[1.0; 4], 0xffff_ffff);
+        context.OMSetDepthStencilState(depth_state.as_raw(), 0);
+        let rasterizer_state = if self.double_sided {
+            states.cull_none()?
+        } else {
+            states.cull_counter_clockwise()?
+        };
+        context.RSSetState(rasterizer_state.as_raw());
+
+        context.IASetInputLayout(objects.input_layout.as_raw());
+        context.VSSetShader(vertex_shader.as_raw(), std::ptr::null(), 0);
+        context.VSSetConstantBuffers(0, 1, &objects.constants.get_buffer().as_raw());
+        context.PSSetShader(pixel_shader.as_raw(), std::ptr::null(), 0);
+        context.PSSetConstantBuffers(0, 1, &objects.constants.get_buffer().as_raw());
+        if self.options.texture {
+            let view = self
+                .texture
+                .as_ref()
+                .map_or(std::ptr::null_mut(), |t| t.as_raw());
+            context.PSSetShaderResources(0, 1, &view);
+            context.PSSetSamplers(0, 1, &states.linear_wrap()?.as_raw());
+        }
+
+        Ok(true)
+    }
+
+    // Applies the effect and draws every part of the mesh with it.
+    pub unsafe fn draw_mesh(
+        &self,
+        context: &ID3D11DeviceContext,
+        shaders: &ShaderLibrary,
+        states: &mut RenderStates,
+        mesh: &GpuMesh,
+    ) -> Result<(), HRESULT> {
+        if !self.apply(context, shaders, states)? {
+            return Ok(());
+        }
+
+        mesh.bind(context);
+        for part in 0..mesh.get_parts().len() {
+            mesh.draw_part(context, part);
+        }
+        Ok(())
+    }
+
+    fn get_constants(&self) -> BasicConstants {
+        // Normals go through the inverse transpose so they stay perpendicular to the surface
+        // under non-uniform scaling. A world that flattens things to nothing has no inverse,
+        // the world itself is close enough then.
+        let rotation = get_rotation(&self.world);
+        let normal_transform = rotation.inverse().map_or(rotation, |r| r.transpose());
+
+        let mut constants = BasicConstants {
+            world: self.world,
+            world_view_projection: (Mat4::from(self.world)
+                * Mat4::from(self.view)
+                * Mat4::from(self.projection))
+            .into(),
+            world_inverse_transpose: normal_transform.into(),
+            diffuse_color: [
+                self.diffuse_color[0],
+                self.diffuse_color[1],
+                self.diffuse_color[2],
+                self.alpha,
+            ],
+            // Ambient light only ever adds the same amount everywhere, so it can go in here.
+            emissive_color: [0, 1, 2].map(|i| {
+                self.emissive_color[i] + self.ambient_light_color[i] * self.diffuse_color[i]
+            }),
+            specular_power: self.specular_power,
+            specular_color: self.specular_color,
+            fog_start: 0.0,
+            eye_position: get_eye_position(&self.view),
+            fog_scale: 0.0,
+            fog_color: [0.0; 3],
+            _pad: 0.0,
+            light_directions: [[0.0; 4]; MAX_DIRECTIONAL_LIGHTS],
+            light_diffuse_colors: [[0.0; 4]; MAX_DIRECTIONAL_LIGHTS],
+            light_specular_colors: [[0.0; 4]; MAX_DIRECTIONAL_LIGHTS],
+        };
+
+        if let Some(fog) = self.fog {
+            constants.fog_start = fog.start;
+            // Fog that starts where it ends is all or nothing.
+            constants.fog_scale = 1.0 / (fog.end - fog.start).max(f32::EPSILON);
+            constants.fog_color = fog.color;
+        }
+
+        for (i, light) in self.lights.iter().enumerate() {
+            if let Some(light) = light {
+                let to_vector4 = |v: [f32; 3]| [v[0], v[1], v[2], 0.0];
+                constants.light_directions[i] = to_vector4(light.direction);
+                constants.light_diffuse_colors[i] = to_vector4(light.diffuse_color);
+                constants.light_specular_colors[i] = to_vector4(light.specular_color);
+            }
+        }
+
+        constants
+    }
+}
+
+// The upper left 3x3, everything but the translation.
+fn get_rotation(m: &[[f32; 4]; 4]) -> Mat4 {
+    let mut rotation = Mat4::IDENTITY;
+    for (row, m_row) in rotation.rows.iter_mut().zip(m.iter()).take(3) {
+        row[..3].copy_from_slice(&m_row[..3]);
+    }
+    rotation
+}
+
+// Where the camera is in world space. With row vectors a view takes p to p * R + t, so the eye,
+// which ends up at the origin, is -t * R^-1.
+fn get_eye_position(view: &[[f32; 4]; 4]) -> [f32; 3] {
+    let translation = Vec3::new(view[3][0], view[3][1], view[3][2]);
+    get_rotation(view)
+        .inverse()
+        .map_or(Vec3::ZERO, |inverse| -inverse.transform_vector(translation))
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hlsl_layout::check_layout;
+
+    #[test]
+    fn constants_match_hlsl() {
+        assert_eq!(check_layout::<BasicConstants>(), Ok(()));
+    }
+
+    #[test]
+    fn eye_position() {
+        let eye = Vec3::new(1.0, 2.0, 3.0);
+        let view = Mat4::look_at_lh(eye, Vec3::ZERO, Vec3::Y);
+        assert!(Vec3::from(get_eye_position(&view.rows)).distance(eye) < 1e-5);
+
+        // Translation doesn't count, and a view that can't be undone gives the origin.
+        assert_eq!(get_rotation(&view.rows).get_translation(), Vec3::ZERO);
+        let flat = Mat4::scaling(Vec3::new(1.0, 0.0, 1.0)) * Mat4::translation(eye);
+        assert_eq!(get_eye_position(&flat.rows), [0.0; 3]);
+    }
+}
@@ -0,0 +1,223 @@
+// Windows bitmaps with 1, 4, 8, 16, 24 or 32 bits per pixel, uncompressed or with bit fields.
+// Run length encoded ones aren't supported. Always decodes to 8 bit RGBA.
+
+use image::{invalid_data, Image};
+use std::io;
+
+pub const MAGIC: &[u8] = b"BM";
+
+const FILE_HEADER_SIZE: usize = 14;
+const CORE_HEADER_SIZE: u32 = 12;
+
+// biCompression values.
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+pub fn decode(data: &[u8]) -> io::Result<Image> {
+    let read_u16 = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid_data("BMP file is truncated"))
+    };
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid_data("BMP file is truncated"))
+    };
+
+    let pixel_offset = read_u32(10)? as usize;
+    let header_size = read_u32(FILE_HEADER_SIZE)?;
+
+    // The old OS/2 header has 16 bit sizes and three byte palette entries.
+    let (width, height, bit_count, compression, palette_entry_size) =
+        if header_size == CORE_HEADER_SIZE {
+            let width = read_u16(FILE_HEADER_SIZE + 4)? as i32;
+            let height = read_u16(FILE_HEADER_SIZE + 6)? as i16 as i32;
+            (width, height, read_u16(FILE_HEADER_SIZE + 10)?, BI_RGB, 3)
+        } else {
+            let width = read_u32(FILE_HEADER_SIZE + 4)? as i32;
+            let height = read_u32(FILE_HEADER_SIZE + 8)? as i32;
+            let bit_count = read_u16(FILE_HEADER_SIZE + 14)?;
+            let compression = read_u32(FILE_HEADER_SIZE + 16)?;
+            (width, height, bit_count, compression, 4)
+        };
+
+    // A negative height means the rows go top to bottom.
+    let is_top_down = height < 0;
+    let (width, height) = (width.unsigned_abs(), height.unsigned_abs());
+    if width == 0 || height == 0 {
+        return Err(invalid_data("BMP image is empty"));
+    }
+    if ![1, 4, 8, 16, 24, 32].contains(&bit_count) {
+        return Err(invalid_data("unsupported BMP bit count"));
+    }
+
+    // Red, green, blue and alpha masks. Without bit fields 16 bits is 5-5-5 and 32 bits has no
+    // alpha.
+    let masks = match (compression, bit_count) {
+        (BI_RGB, 16) => [0x7c00, 0x3e0, 0x1f, 0],
+        (BI_RGB, 32) => [0xff_0000, 0xff00, 0xff, 0],
+        (BI_RGB, _) => [0; 4],
+        (BI_BITFIELDS, 16)
+        | (BI_BITFIELDS, 32)
+        | (BI_ALPHABITFIELDS, 16)
+        | (BI_ALPHABITFIELDS, 32) => {
+            // In the info header for V4 and later, right after the old header otherwise.
+            let masks_offset = FILE_HEADER_SIZE + 40;
+            let alpha = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                read_u32(masks_offset + 12)?
+            } else {
+                0
+            };
+            [
+                read_u32(masks_offset)?,
+                read_u32(masks_offset + 4)?,
+                read_u32(masks_offset + 8)?,
+                alpha,
+            ]
+        }
+        _ => return Err(invalid_data("compressed BMP files aren't supported")),
+    };
+
+    let palette = if bit_count <= 8 {
+        let colors_used = if header_size == CORE_HEADER_SIZE {
+            0
+        } else {
+            read_u32(FILE_HEADER_SIZE + 32)? as usize
+        };
+        let count = if colors_used == 0 {
+            1 << bit_count
+        } else {
+            colors_used
+        };
+        let start = FILE_HEADER_SIZE + header_size as usize;
+        let entries = count
+            .checked_mul(palette_entry_size)
+            .and_then(|size| data.get(start..start.checked_add(size)?))
+            .ok_or_else(|| invalid_data("BMP file is truncated"))?;
+        entries
+            .chunks(palette_entry_size)
+            .map(|e| [e[2], e[1], e[0], 255])
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Rows are padded to four bytes. All of them have to be there before the pixels get
+    // allocated, which bounds the allocation by the size of the file.
+    let row_size = ((width as usize * bit_count as usize).div_ceil(32)) * 4;
+    let rows = row_size
+        .checked_mul(height as usize)
+        .and_then(|size| data.get(pixel_offset..pixel_offset.checked_add(size)?))
+        .ok_or_else(|| invalid_data("BMP file is truncated"))?;
+
+    let pixel_data_size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|count| count.checked_mul(4))
+        .ok_or_else(|| invalid_data("BMP image is too large"))?;
+    let mut pixels = vec![0u8; pixel_data_size];
+    for (row_index, row) in rows.chunks(row_size).enumerate() {
+        let y = if is_top_down {
+            row_index
+        } else {
+            height as usize - 1 - row_index
+        };
+
+        for x in 0..width as usize {
+            let rgba = match bit_count {
+                1 | 4 | 8 => {
+                    let bit = x * bit_count as usize;
+                    let shift = 8 - bit_count as usize - bit % 8;
+                    let index = (row[bit / 8] >> shift) as usize & ((1 << bit_count) - 1);
+                    *palette
+                        .get(index)
+                        .ok_or_else(|| invalid_data("BMP color index is out of range"))?
+                }
+                16 => get_masked_color(
+                    u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32,
+                    &masks,
+                ),
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+                32 => {
+                    let value = &row[x * 4..x * 4 + 4];
+                    get_masked_color(
+                        u32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+                        &masks,
+                    )
+                }
+                _ => return Err(invalid_data("unsupported BMP bit count")),
+            };
+
+            let index = (y * width as usize + x) * 4;
+            pixels[index..index + 4].copy_from_slice(&rgba);
+        }
+    }
+
+    Ok(Image::new_rgba8(width, height, false, pixels))
+}
+
+// Pulls each channel out with its mask and scales it to 8 bits. Opaque if there's no alpha mask.
+fn get_masked_color(value: u32, masks: &[u32; 4]) -> [u8; 4] {
+    let mut rgba = [255u8; 4];
+    for (channel, &mask) in rgba.iter_mut().zip(masks.iter()) {
+        if mask == 0 {
+            continue;
+        }
+        let shift = mask.trailing_zeros();
+        let max = (mask >> shift) as u64;
+        *channel = (((value & mask) >> shift) as u64 * 255 / max) as u8;
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A file and info header followed directly by the pixels.
+    fn make_bmp(width: i32, height: i32, bit_count: u16, pixels: &[u8]) -> Vec<u8> {
+        let mut bmp = MAGIC.to_vec();
+        bmp.extend_from_slice(&[0; 8]);
+        bmp.extend_from_slice(&54u32.to_le_bytes());
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&width.to_le_bytes());
+        bmp.extend_from_slice(&height.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&bit_count.to_le_bytes());
+        bmp.extend_from_slice(&[0; 24]);
+        bmp.extend_from_slice(pixels);
+        bmp
+    }
+
+    #[test]
+    fn decodes() {
+        // Rows are padded to four bytes and go bottom to top.
+        let bmp = make_bmp(1, 2, 24, &[1, 2, 3, 0, 4, 5, 6, 0]);
+        let image = decode(&bmp).unwrap();
+        assert_eq!(image.data, vec![6, 5, 4, 255, 3, 2, 1, 255]);
+
+        let top_down = make_bmp(1, -2, 24, &[1, 2, 3, 0, 4, 5, 6, 0]);
+        let image = decode(&top_down).unwrap();
+        assert_eq!(image.data, vec![3, 2, 1, 255, 6, 5, 4, 255]);
+    }
+
+    #[test]
+    fn bad_bit_counts() {
+        assert!(decode(&make_bmp(1, 1, 0, &[0; 4])).is_err());
+        assert!(decode(&make_bmp(1, 1, 7, &[0; 4])).is_err());
+    }
+
+    #[test]
+    fn more_pixels_than_data() {
+        assert!(decode(&make_bmp(0x7fff_ffff, 0x7fff_ffff, 32, &[0; 16])).is_err());
+        assert!(decode(&make_bmp(2, 2, 24, &[0; 12])).is_err());
+    }
+
+    #[test]
+    fn pixels_past_the_end() {
+        let mut bmp = make_bmp(1, 1, 24, &[0; 4]);
+        bmp[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode(&bmp).is_err());
+    }
+}
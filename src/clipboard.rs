@@ -0,0 +1,62 @@
+use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::HWND;
+use winapi::um::winbase::{GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock};
+use winapi::um::winuser::{
+    CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard,
+    SetClipboardData, CF_UNICODETEXT,
+};
+
+// Not in the winapi version we're on.
+const GMEM_MOVEABLE: UINT = 0x0002;
+
+// The text on the clipboard, or None if there is none or another program has the clipboard open.
+pub unsafe fn get_text(window: HWND) -> Option<String> {
+    if IsClipboardFormatAvailable(CF_UNICODETEXT) == 0 || OpenClipboard(window) == 0 {
+        return None;
+    }
+
+    let mut text = None;
+    let handle = GetClipboardData(CF_UNICODETEXT);
+    if !handle.is_null() {
+        let data = GlobalLock(handle) as *const u16;
+        if !data.is_null() {
+            // The size is in bytes and the text should be terminated well before the end of it,
+            // but don't count on that.
+            let length = GlobalSize(handle) / 2;
+            text = Some(::from_wide(std::slice::from_raw_parts(data, length)));
+            GlobalUnlock(handle);
+        }
+    }
+
+    CloseClipboard();
+    text
+}
+
+// Replaces whatever is on the clipboard. Returns false if the clipboard couldn't be opened.
+pub unsafe fn set_text(window: HWND, text: &str) -> bool {
+    let wide = ::to_wide(text);
+    if OpenClipboard(window) == 0 {
+        return false;
+    }
+
+    EmptyClipboard();
+
+    // The clipboard takes ownership of the memory, unless SetClipboardData fails.
+    let size = wide.len() * std::mem::size_of::<u16>();
+    let handle = GlobalAlloc(GMEM_MOVEABLE, size);
+    let mut succeeded = false;
+    if !handle.is_null() {
+        let data = GlobalLock(handle) as *mut u16;
+        if !data.is_null() {
+            std::ptr::copy_nonoverlapping(wide.as_ptr(), data, wide.len());
+            GlobalUnlock(handle);
+            succeeded = !SetClipboardData(CF_UNICODETEXT, handle).is_null();
+        }
+        if !succeeded {
+            GlobalFree(handle);
+        }
+    }
+
+    CloseClipboard();
+    succeeded
+}
@@ -0,0 +1,67 @@
+use hlsl_layout::{check_layout, get_buffer_size, HlslLayout};
+use std::marker::PhantomData;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{
+    ID3D11Buffer, ID3D11Device, ID3D11DeviceContext, ID3D11Resource, D3D11_BIND_CONSTANT_BUFFER,
+    D3D11_BUFFER_DESC, D3D11_CPU_ACCESS_WRITE, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_WRITE_DISCARD,
+    D3D11_USAGE_DYNAMIC,
+};
+use wio::com::ComPtr;
+
+// A dynamic constant buffer holding one T. T is declared with hlsl_struct! and checked against
+// the HLSL packing rules when the buffer is created, so a mismatch shows up right away instead
+// of as garbage in the shader.
+//
+// Like every other device object this has to be created again after the device is lost.
+pub struct ConstantBuffer<T: HlslLayout> {
+    buffer: ComPtr<ID3D11Buffer>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: HlslLayout> ConstantBuffer<T> {
+    pub unsafe fn new(device: &ID3D11Device) -> Result<ConstantBuffer<T>, HRESULT> {
+        if let Err(e) = check_layout::<T>() {
+            panic!("Constant buffer layout doesn't match HLSL: {}", e);
+        }
+
+        let desc = D3D11_BUFFER_DESC {
+            ByteWidth: get_buffer_size::<T>() as u32,
+            Usage: D3D11_USAGE_DYNAMIC,
+            BindFlags: D3D11_BIND_CONSTANT_BUFFER,
+            CPUAccessFlags: D3D11_CPU_ACCESS_WRITE,
+            MiscFlags: 0,
+            StructureByteStride: 0,
+        };
+
+        let mut buffer_ptr: *mut ID3D11Buffer = std::ptr::null_mut();
+        let hr = device.CreateBuffer(&desc, std::ptr::null(), &mut buffer_ptr);
+        if ::failed(hr) {
+            return Err(hr);
+        }
+
+        Ok(ConstantBuffer {
+            buffer: ComPtr::from_raw(buffer_ptr),
+            phantom: PhantomData,
+        })
+    }
+
+    // Replaces the whole contents. Discarding means the GPU can keep reading the old data for
+    // draws that are still in flight, so this is fine to call for every draw.
+    pub unsafe fn set_data(&self, context: &ID3D11DeviceContext, data: &T) -> Result<(), HRESULT> {
+        let resource = self.buffer.as_raw() as *mut ID3D11Resource;
+        let mut mapped: D3D11_MAPPED_SUBRESOURCE = std::mem::zeroed();
+        let hr = context.Map(resource, 0, D3D11_MAP_WRITE_DISCARD, 0, &mut mapped);
+        if ::failed(hr) {
+            return Err(hr);
+        }
+
+        std::ptr::copy_nonoverlapping(data as *const T, mapped.pData as *mut T, 1);
+        context.Unmap(resource, 0);
+        Ok(())
+    }
+
+    // For VSSetConstantBuffers and friends.
+    pub fn get_buffer(&self) -> &ComPtr<ID3D11Buffer> {
+        &self.buffer
+    }
+}
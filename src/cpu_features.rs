@@ -0,0 +1,145 @@
+// What DirectXMath's XMVerifyCPUSupport does: the instruction sets the game was built to use
+// have to be there, or it dies with an illegal instruction somewhere down the line. Only the
+// sets the math module can use are tracked.
+
+// The instruction sets the game won't start without, on top of whatever it was compiled to use.
+// Turn on the ones it needs whatever the build flags say, e.g. because a hot path only has an
+// AVX version. Every x86-64 CPU has SSE2, so requiring it there costs nothing.
+pub const REQUIRED_FEATURES: CpuFeatures = CpuFeatures {
+    sse2: cfg!(any(target_arch = "x86", target_arch = "x86_64")),
+    sse4_1: false,
+    avx: false,
+    f16c: false,
+    fma: false,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CpuFeatures {
+    pub sse2: bool,
+    pub sse4_1: bool,
+    pub avx: bool,
+    pub f16c: bool,
+    pub fma: bool,
+}
+
+impl CpuFeatures {
+    // What this CPU has. Anything that isn't x86 has none of them, as far as we're concerned.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn detect() -> CpuFeatures {
+        CpuFeatures {
+            sse2: is_x86_feature_detected!("sse2"),
+            sse4_1: is_x86_feature_detected!("sse4.1"),
+            avx: is_x86_feature_detected!("avx"),
+            f16c: is_x86_feature_detected!("f16c"),
+            fma: is_x86_feature_detected!("fma"),
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    pub fn detect() -> CpuFeatures {
+        CpuFeatures::default()
+    }
+
+    // REQUIRED_FEATURES plus what the compiler was allowed to use everywhere, e.g. with
+    // -C target-cpu or -C target-feature=+avx in .cargo/config.toml.
+    //
+    // Everywhere includes main and the standard library's startup code, which run before
+    // anything can be checked. So with AVX enabled that way a CPU without it can already die
+    // before the check, and the check really only protects the features that were left off.
+    pub fn get_required() -> CpuFeatures {
+        REQUIRED_FEATURES.union(&CpuFeatures::get_compiled())
+    }
+
+    pub fn get_compiled() -> CpuFeatures {
+        CpuFeatures {
+            sse2: cfg!(target_feature = "sse2"),
+            sse4_1: cfg!(target_feature = "sse4.1"),
+            avx: cfg!(target_feature = "avx"),
+            f16c: cfg!(target_feature = "f16c"),
+            fma: cfg!(target_feature = "fma"),
+        }
+    }
+
+    pub fn union(&self, other: &CpuFeatures) -> CpuFeatures {
+        CpuFeatures {
+            sse2: self.sse2 || other.sse2,
+            sse4_1: self.sse4_1 || other.sse4_1,
+            avx: self.avx || other.avx,
+            f16c: self.f16c || other.f16c,
+            fma: self.fma || other.fma,
+        }
+    }
+
+    // The names of the features that are in required but not in self.
+    pub fn get_missing(&self, required: &CpuFeatures) -> Vec<&'static str> {
+        [
+            ("SSE2", self.sse2, required.sse2),
+            ("SSE4.1", self.sse4_1, required.sse4_1),
+            ("AVX", self.avx, required.avx),
+            ("F16C", self.f16c, required.f16c),
+            ("FMA", self.fma, required.fma),
+        ]
+        .iter()
+        .filter(|&&(_, present, needed)| needed && !present)
+        .map(|&(name, _, _)| name)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing() {
+        let present = CpuFeatures {
+            sse2: true,
+            sse4_1: true,
+            ..CpuFeatures::default()
+        };
+        let required = CpuFeatures {
+            sse2: true,
+            avx: true,
+            fma: true,
+            ..CpuFeatures::default()
+        };
+        assert_eq!(present.get_missing(&required), vec!["AVX", "FMA"]);
+
+        // Having more than required is fine.
+        assert!(required.get_missing(&CpuFeatures::default()).is_empty());
+        assert!(present.get_missing(&present).is_empty());
+    }
+
+    #[test]
+    fn union() {
+        let a = CpuFeatures {
+            sse2: true,
+            avx: true,
+            ..CpuFeatures::default()
+        };
+        let b = CpuFeatures {
+            sse2: true,
+            fma: true,
+            ..CpuFeatures::default()
+        };
+        let expected = CpuFeatures {
+            sse2: true,
+            avx: true,
+            fma: true,
+            ..CpuFeatures::default()
+        };
+        assert_eq!(a.union(&b), expected);
+        assert_eq!(b.union(&a), expected);
+        assert_eq!(a.union(&CpuFeatures::default()), a);
+    }
+
+    // The configured set counts even where the build flags didn't turn it on.
+    #[test]
+    fn required_includes_configured() {
+        let required = CpuFeatures::get_required();
+        assert!(required.get_missing(&REQUIRED_FEATURES).is_empty());
+        assert!(required
+            .get_missing(&CpuFeatures::get_compiled())
+            .is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+// DirectDraw Surface files, with or without the DX10 header. Covers 2D textures, arrays and cube
+// maps with their mip chains, in the formats ImageFormat has. Volume textures aren't supported.
+
+use image::{get_max_mip_levels, invalid_data, Image, ImageFormat};
+use std::io;
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+
+pub const MAGIC: &[u8] = b"DDS ";
+
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+
+// DDS_HEADER flags
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_DEPTH: u32 = 0x800000;
+
+// DDS_PIXELFORMAT flags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+// caps2
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+// DX10 header
+const RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+// D3D11_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION, anything bigger can't be a texture anyway.
+const MAX_ARRAY_SIZE: u32 = 2048;
+
+struct PixelFormat {
+    flags: u32,
+    four_cc: [u8; 4],
+    bit_count: u32,
+    masks: [u32; 4],
+}
+
+pub fn decode(data: &[u8]) -> io::Result<Image> {
+    let mut reader = Reader { data };
+    reader.read_bytes(MAGIC.len())?;
+
+    if reader.read_u32()? != HEADER_SIZE {
+        return Err(invalid_data("DDS header has the wrong size"));
+    }
+    let flags = reader.read_u32()?;
+    let height = reader.read_u32()?;
+    let width = reader.read_u32()?;
+    let _pitch_or_linear_size = reader.read_u32()?;
+    let _depth = reader.read_u32()?;
+    let mip_count = reader.read_u32()?;
+    reader.read_bytes(11 * 4)?;
+
+    if reader.read_u32()? != PIXEL_FORMAT_SIZE {
+        return Err(invalid_data("DDS pixel format has the wrong size"));
+    }
+    let pixel_format = PixelFormat {
+        flags: reader.read_u32()?,
+        four_cc: reader.read_array()?,
+        bit_count: reader.read_u32()?,
+        masks: [
+            reader.read_u32()?,
+            reader.read_u32()?,
+            reader.read_u32()?,
+            reader.read_u32()?,
+        ],
+    };
+
+    let _caps = reader.read_u32()?;
+    let caps2 = reader.read_u32()?;
+    reader.read_bytes(3 * 4)?;
+
+    if flags & DDSD_DEPTH != 0 || caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err(invalid_data("DDS volume textures aren't supported"));
+    }
+
+    // Some writers count mips past 1x1, those don't exist.
+    let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 {
+        mip_count.clamp(1, get_max_mip_levels(width, height))
+    } else {
+        1
+    };
+
+    let (format, srgb, array_size, is_cube_map) =
+        if pixel_format.flags & DDPF_FOURCC != 0 && &pixel_format.four_cc == b"DX10" {
+            let dxgi_format: DXGI_FORMAT = reader.read_u32()?;
+            let dimension = reader.read_u32()?;
+            let misc_flags = reader.read_u32()?;
+            let array_size = reader.read_u32()?.max(1);
+            let _misc_flags2 = reader.read_u32()?;
+
+            if dimension != RESOURCE_DIMENSION_TEXTURE2D {
+                return Err(invalid_data("only 2D DDS textures are supported"));
+            }
+            let (format, srgb) = ImageFormat::from_dxgi_format(dxgi_format)
+                .ok_or_else(|| invalid_data("unsupported DDS format"))?;
+            let is_cube_map = misc_flags & RESOURCE_MISC_TEXTURECUBE != 0;
+            let array_size = if is_cube_map {
+                array_size.checked_mul(6)
+            } else {
+                Some(array_size)
+            };
+            let array_size = array_size
+                .filter(|&size| size <= MAX_ARRAY_SIZE)
+                .ok_or_else(|| invalid_data("DDS texture array is too large"))?;
+            (format, srgb, array_size, is_cube_map)
+        } else {
+            let format = get_legacy_format(&pixel_format)
+                .ok_or_else(|| invalid_data("unsupported DDS pixel format"))?;
+
+            // Old files can have only some of the faces, D3D wants all of them.
+            let is_cube_map = caps2 & DDSCAPS2_CUBEMAP != 0;
+            if is_cube_map && caps2 & DDSCAPS2_CUBEMAP_ALLFACES != DDSCAPS2_CUBEMAP_ALLFACES {
+                return Err(invalid_data("DDS cube map is missing faces"));
+            }
+            (format, false, if is_cube_map { 6 } else { 1 }, is_cube_map)
+        };
+
+    if width == 0 || height == 0 {
+        return Err(invalid_data("DDS texture is empty"));
+    }
+
+    let mut image = Image {
+        width,
+        height,
+        array_size,
+        mip_levels,
+        format,
+        srgb,
+        is_cube_map,
+        data: Vec::new(),
+    };
+    let size = image.get_data_size()?;
+    image.data = reader.read_bytes(size)?.to_vec();
+    Ok(image)
+}
+
+// Formats from before the DX10 header, going by what the DirectXTK understands.
+fn get_legacy_format(pixel_format: &PixelFormat) -> Option<ImageFormat> {
+    let flags = pixel_format.flags;
+    let masks = pixel_format.masks;
+
+    if flags & DDPF_FOURCC != 0 {
+        let format = match &pixel_format.four_cc {
+            b"DXT1" => ImageFormat::Bc1,
+            b"DXT2" | b"DXT3" => ImageFormat::Bc2,
+            b"DXT4" | b"DXT5" => ImageFormat::Bc3,
+            b"ATI1" | b"BC4U" => ImageFormat::Bc4,
+            b"ATI2" | b"BC5U" => ImageFormat::Bc5,
+            // Some writers put a D3DFORMAT number where the four characters go.
+            four_cc => match u32::from_le_bytes(*four_cc) {
+                36 => ImageFormat::R16G16B16A16Unorm,
+                111 => ImageFormat::R16Float,
+                112 => ImageFormat::R16G16Float,
+                113 => ImageFormat::R16G16B16A16Float,
+                114 => ImageFormat::R32Float,
+                115 => ImageFormat::R32G32Float,
+                116 => ImageFormat::R32G32B32A32Float,
+                _ => return None,
+            },
+        };
+        return Some(format);
+    }
+
+    let has_masks = |r: u32, g: u32, b: u32, a: u32| masks == [r, g, b, a];
+    if flags & DDPF_RGB != 0 {
+        let alpha = |a: u32| if flags & DDPF_ALPHAPIXELS != 0 { a } else { 0 };
+        return match pixel_format.bit_count {
+            32 if has_masks(0xff, 0xff00, 0xff_0000, alpha(0xff00_0000)) => {
+                Some(ImageFormat::R8G8B8A8Unorm)
+            }
+            32 if has_masks(0xff_0000, 0xff00, 0xff, 0xff00_0000) => {
+                Some(ImageFormat::B8G8R8A8Unorm)
+            }
+            32 if has_masks(0xff_0000, 0xff00, 0xff, 0) => Some(ImageFormat::B8G8R8X8Unorm),
+            // The masks are backwards in files written by D3DX.
+            32 if has_masks(0x3ff00000, 0xffc00, 0x3ff, 0xc000_0000) => {
+                Some(ImageFormat::R10G10B10A2Unorm)
+            }
+            32 if has_masks(0xffff, 0xffff_0000, 0, 0) => Some(ImageFormat::R16G16Unorm),
+            16 if has_masks(0xf800, 0x7e0, 0x1f, 0) => Some(ImageFormat::B5G6R5Unorm),
+            16 if has_masks(0x7c00, 0x3e0, 0x1f, alpha(0x8000)) => Some(ImageFormat::B5G5R5A1Unorm),
+            16 if has_masks(0xf00, 0xf0, 0xf, alpha(0xf000)) => Some(ImageFormat::B4G4R4A4Unorm),
+            _ => None,
+        };
+    }
+
+    if flags & DDPF_LUMINANCE != 0 {
+        return match pixel_format.bit_count {
+            8 => Some(ImageFormat::R8Unorm),
+            16 if masks[0] == 0xffff => Some(ImageFormat::R16Unorm),
+            16 if has_masks(0xff, 0, 0, 0xff00) => Some(ImageFormat::R8G8Unorm),
+            _ => None,
+        };
+    }
+
+    if flags & DDPF_ALPHA != 0 && pixel_format.bit_count == 8 {
+        return Some(ImageFormat::A8Unorm);
+    }
+
+    None
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(invalid_data("DDS file is truncated"));
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.read_bytes(N)?);
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winapi::shared::dxgiformat::DXGI_FORMAT_BC1_UNORM;
+
+    const DDSD_CAPS_HEIGHT_WIDTH_PIXELFORMAT: u32 = 0x1007;
+
+    fn make_header(width: u32, height: u32, mip_count: u32, four_cc: &[u8; 4]) -> Vec<u8> {
+        let mut dds = MAGIC.to_vec();
+        let mut push = |value: u32| dds.extend_from_slice(&value.to_le_bytes());
+        push(HEADER_SIZE);
+        push(DDSD_CAPS_HEIGHT_WIDTH_PIXELFORMAT | DDSD_MIPMAPCOUNT);
+        push(height);
+        push(width);
+        push(0);
+        push(0);
+        push(mip_count);
+        for _ in 0..11 {
+            push(0);
+        }
+        push(PIXEL_FORMAT_SIZE);
+        push(DDPF_FOURCC);
+        push(u32::from_le_bytes(*four_cc));
+        for _ in 0..5 {
+            push(0);
+        }
+        for _ in 0..5 {
+            push(0);
+        }
+        dds
+    }
+
+    #[test]
+    fn mip_count_past_1x1() {
+        // BC1 is 8 bytes per 4x4 block, and a block at least for every mip.
+        let mut dds = make_header(8, 8, 40, b"DXT1");
+        dds.extend_from_slice(&[0; 32 + 8 + 8 + 8]);
+        let image = decode(&dds).unwrap();
+        assert_eq!(image.mip_levels, 4);
+        assert_eq!(image.data.len(), 56);
+    }
+
+    #[test]
+    fn huge_cube_array() {
+        let mut dds = make_header(4, 4, 1, b"DX10");
+        let dx10 = [
+            DXGI_FORMAT_BC1_UNORM,
+            RESOURCE_DIMENSION_TEXTURE2D,
+            RESOURCE_MISC_TEXTURECUBE,
+            0x3000_0000,
+            0,
+        ];
+        for value in dx10.iter() {
+            dds.extend_from_slice(&value.to_le_bytes());
+        }
+        dds.extend_from_slice(&[0; 8 * 6]);
+        assert!(decode(&dds).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut dds = make_header(8, 8, 1, b"DXT1");
+        dds.extend_from_slice(&[0; 31]);
+        assert!(decode(&dds).is_err());
+        assert!(decode(&dds[..100]).is_err());
+    }
+}
@@ -0,0 +1,533 @@
+// Immediate-mode debug drawing. Game code queues world-space shapes from update or render and
+// they're turned into lines here, on the CPU. Doesn't know anything about D3D, see
+// DebugDrawRenderer for the D3D11 side.
+//
+// Matrices are row major and points are multiplied from the left, like in the rest of the
+// renderer.
+
+use math::{Mat4, Vec3};
+
+// Segments in each circle of a sphere.
+pub const SPHERE_SEGMENTS: usize = 32;
+
+// The input layout is in debug_draw_renderer.rs.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+// How a shape is drawn and for how long.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DebugStyle {
+    pub color: [f32; 4],
+    // Seconds the shape stays around. Zero means it's drawn in the next flush only.
+    pub duration: f32,
+    // Whether the scene can hide the shape. If not it's drawn on top of everything.
+    pub depth_test: bool,
+}
+
+impl DebugStyle {
+    pub fn new(color: [f32; 4]) -> DebugStyle {
+        DebugStyle {
+            color,
+            duration: 0.0,
+            depth_test: true,
+        }
+    }
+
+    pub fn with_duration(mut self, duration: f32) -> DebugStyle {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_depth_test(mut self, depth_test: bool) -> DebugStyle {
+        self.depth_test = depth_test;
+        self
+    }
+}
+
+struct DebugLine {
+    start: [f32; 3],
+    end: [f32; 3],
+    color: [f32; 4],
+    remaining: f32,
+    depth_test: bool,
+}
+
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+}
+
+impl DebugDraw {
+    pub fn new() -> DebugDraw {
+        DebugDraw { lines: Vec::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn line(&mut self, start: [f32; 3], end: [f32; 3], style: &DebugStyle) {
+        self.lines.push(DebugLine {
+            start,
+            end,
+            color: style.color,
+            remaining: style.duration,
+            depth_test: style.depth_test,
+        });
+    }
+
+    // A line with a four-sided head at the end. head_size is the length of the head in world
+    // units.
+    pub fn arrow(&mut self, start: [f32; 3], end: [f32; 3], head_size: f32, style: &DebugStyle) {
+        self.line(start, end, style);
+
+        let (start, end) = (Vec3::from(start), Vec3::from(end));
+        let length = start.distance(end);
+        if length == 0.0 {
+            return;
+        }
+
+        let direction = (end - start) / length;
+        let (side, up) = get_perpendiculars(direction);
+        let base = end - direction * head_size;
+        let width = head_size * 0.5;
+        for &offset in [side, up, -side, -up].iter() {
+            self.line(end.into(), (base + offset * width).into(), style);
+        }
+    }
+
+    pub fn aabb(&mut self, min: [f32; 3], max: [f32; 3], style: &DebugStyle) {
+        let (min, max) = (Vec3::from(min), Vec3::from(max));
+        let center = (min + max) * 0.5;
+        let half_extents = (max - min) * 0.5;
+        self.obb(
+            center.into(),
+            half_extents.into(),
+            &IDENTITY_AXES.map(Vec3::to_array),
+            style,
+        );
+    }
+
+    // A box rotated so its x, y and z are the rows of axes, which should be unit length.
+    pub fn obb(
+        &mut self,
+        center: [f32; 3],
+        half_extents: [f32; 3],
+        axes: &[[f32; 3]; 3],
+        style: &DebugStyle,
+    ) {
+        let x = Vec3::from(axes[0]) * half_extents[0];
+        let y = Vec3::from(axes[1]) * half_extents[1];
+        let z = Vec3::from(axes[2]) * half_extents[2];
+
+        // Bit 0 picks the +x or -x side, bit 1 y and bit 2 z.
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let sign = |bit: usize| if i & bit != 0 { 1.0 } else { -1.0 };
+            *corner = Vec3::from(center) + (x * sign(1) + (y * sign(2) + z * sign(4)));
+        }
+        self.box_edges(&corners, style);
+    }
+
+    // Three circles around the center, one in each of the xy, xz and yz planes.
+    pub fn sphere(&mut self, center: [f32; 3], radius: f32, style: &DebugStyle) {
+        let center = Vec3::from(center);
+        let [x, y, z] = IDENTITY_AXES;
+        self.circle(center, x, y, radius, style);
+        self.circle(center, x, z, radius, style);
+        self.circle(center, y, z, radius, style);
+    }
+
+    // The x, y and z axes of the transform, drawn from its origin as red, green and blue lines of
+    // the given length. The style's color is multiplied in, so white leaves them as they are.
+    pub fn axes(&mut self, transform: &[[f32; 4]; 4], size: f32, style: &DebugStyle) {
+        let transform = Mat4::from(*transform);
+        let origin = transform.project_point(Vec3::ZERO).into();
+        for (&axis, color) in IDENTITY_AXES.iter().zip(AXIS_COLORS.iter()) {
+            let end = transform.project_point(axis * size).into();
+            let mut axis_style = *style;
+            for (c, tint) in axis_style.color.iter_mut().zip(color.iter()) {
+                *c *= tint;
+            }
+            self.line(origin, end, &axis_style);
+        }
+    }
+
+    // A grid spanning the parallelogram from origin along x_axis and y_axis, with the given
+    // number of cells in each direction.
+    pub fn grid(
+        &mut self,
+        origin: [f32; 3],
+        x_axis: [f32; 3],
+        y_axis: [f32; 3],
+        x_divisions: u32,
+        y_divisions: u32,
+        style: &DebugStyle,
+    ) {
+        let x_divisions = x_divisions.max(1);
+        let y_divisions = y_divisions.max(1);
+        let (origin, x_axis, y_axis) = (Vec3::from(origin), Vec3::from(x_axis), Vec3::from(y_axis));
+
+        for i in 0..=x_divisions {
+            let start = origin + x_axis * (i as f32 / x_divisions as f32);
+            self.line(start.into(), (start + y_axis).into(), style);
+        }
+        for i in 0..=y_divisions {
+            let start = origin + y_axis * (i as f32 / y_divisions as f32);
+            self.line(start.into(), (start + x_axis).into(), style);
+        }
+    }
+
+    // The volume a camera sees, from the inverse of its view-projection matrix. Assumes D3D clip
+    // space, i.e. depth from 0 to 1.
+    pub fn frustum(&mut self, inverse_view_projection: &[[f32; 4]; 4], style: &DebugStyle) {
+        // Same corner order as obb.
+        let inverse_view_projection = Mat4::from(*inverse_view_projection);
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 != 0 { 1.0 } else { -1.0 };
+            let y = if i & 2 != 0 { 1.0 } else { -1.0 };
+            let z = if i & 4 != 0 { 1.0 } else { 0.0 };
+            *corner = inverse_view_projection.project_point(Vec3::new(x, y, z));
+        }
+        self.box_edges(&corners, style);
+    }
+
+    // Line list vertices for everything queued with or without depth testing.
+    pub fn get_vertices(&self, depth_test: bool) -> Vec<DebugVertex> {
+        let mut vertices = Vec::new();
+        for line in self.lines.iter().filter(|l| l.depth_test == depth_test) {
+            vertices.push(DebugVertex {
+                position: line.start,
+                color: line.color,
+            });
+            vertices.push(DebugVertex {
+                position: line.end,
+                color: line.color,
+            });
+        }
+        vertices
+    }
+
+    // Call once per frame after drawing. Drops everything that has been around for its duration.
+    pub fn advance(&mut self, elapsed_seconds: f32) {
+        for line in self.lines.iter_mut() {
+            line.remaining -= elapsed_seconds;
+        }
+        self.lines.retain(|l| l.remaining > 0.0);
+    }
+
+    fn circle(
+        &mut self,
+        center: Vec3,
+        axis_a: Vec3,
+        axis_b: Vec3,
+        radius: f32,
+        style: &DebugStyle,
+    ) {
+        let get_point = |i: usize| {
+            let angle = i as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+            let (sin, cos) = angle.sin_cos();
+            (center + (axis_a * (cos * radius) + axis_b * (sin * radius))).into()
+        };
+
+        for i in 0..SPHERE_SEGMENTS {
+            self.line(get_point(i), get_point(i + 1), style);
+        }
+    }
+
+    // The twelve edges between corners ordered like in obb.
+    fn box_edges(&mut self, corners: &[Vec3; 8], style: &DebugStyle) {
+        for i in 0..8 {
+            for bit in [1, 2, 4].iter() {
+                if i & bit == 0 {
+                    self.line(corners[i].into(), corners[i | bit].into(), style);
+                }
+            }
+        }
+    }
+}
+
+const IDENTITY_AXES: [Vec3; 3] = [Vec3::X, Vec3::Y, Vec3::Z];
+
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 1.0],
+];
+
+// Two unit vectors at right angles to each other and to the given unit direction.
+fn get_perpendiculars(direction: Vec3) -> (Vec3, Vec3) {
+    // Any axis works as long as it isn't parallel to the direction.
+    let reference = if direction.y.abs() < 0.99 {
+        Vec3::Y
+    } else {
+        Vec3::X
+    };
+    let side = reference.cross(direction).normalize();
+    (side, direction.cross(side))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::DepthMode;
+
+    const WHITE: [f32; 4] = [1.0; 4];
+
+    // Start and end of every queued line, depth tested or not.
+    fn get_lines(debug_draw: &DebugDraw) -> Vec<([f32; 3], [f32; 3])> {
+        let mut vertices = debug_draw.get_vertices(true);
+        vertices.extend(debug_draw.get_vertices(false));
+        vertices
+            .chunks(2)
+            .map(|line| (line[0].position, line[1].position))
+            .collect()
+    }
+
+    fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+        Vec3::from(a).distance(b.into())
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(
+            distance(actual, expected) < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    // Every line should be one of the twelve edges of the box with the given corners, and every
+    // edge should be there.
+    fn assert_box_edges(lines: &[([f32; 3], [f32; 3])], min: [f32; 3], max: [f32; 3]) {
+        assert_eq!(lines.len(), 12);
+        let mut along = [0; 3];
+        for &(start, end) in lines {
+            let changed: Vec<usize> = (0..3).filter(|&i| start[i] != end[i]).collect();
+            assert_eq!(changed.len(), 1, "{:?} to {:?} isn't an edge", start, end);
+            along[changed[0]] += 1;
+            for point in [start, end].iter() {
+                for i in 0..3 {
+                    assert!(point[i] == min[i] || point[i] == max[i], "{:?}", point);
+                }
+            }
+        }
+        assert_eq!(along, [4, 4, 4]);
+    }
+
+    #[test]
+    fn line() {
+        let mut debug_draw = DebugDraw::new();
+        assert!(debug_draw.is_empty());
+
+        let red = [1.0, 0.0, 0.0, 1.0];
+        debug_draw.line([1.0, 2.0, 3.0], [4.0, 5.0, 6.0], &DebugStyle::new(red));
+        assert_eq!(
+            debug_draw.get_vertices(true),
+            vec![
+                DebugVertex {
+                    position: [1.0, 2.0, 3.0],
+                    color: red,
+                },
+                DebugVertex {
+                    position: [4.0, 5.0, 6.0],
+                    color: red,
+                },
+            ]
+        );
+        assert!(debug_draw.get_vertices(false).is_empty());
+
+        debug_draw.clear();
+        assert!(debug_draw.is_empty());
+    }
+
+    #[test]
+    fn aabb() {
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.aabb([-1.0, 0.0, 2.0], [3.0, 1.0, 4.0], &DebugStyle::new(WHITE));
+        assert_box_edges(&get_lines(&debug_draw), [-1.0, 0.0, 2.0], [3.0, 1.0, 4.0]);
+    }
+
+    #[test]
+    fn obb() {
+        // A quarter turn around z swaps which way x and y go.
+        let axes = [[0.0, 1.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.obb(
+            [1.0, 1.0, 1.0],
+            [2.0, 1.0, 0.5],
+            &axes,
+            &DebugStyle::new(WHITE),
+        );
+        assert_box_edges(&get_lines(&debug_draw), [0.0, -1.0, 0.5], [2.0, 3.0, 1.5]);
+    }
+
+    #[test]
+    fn sphere() {
+        let center = [1.0, 2.0, 3.0];
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.sphere(center, 2.0, &DebugStyle::new(WHITE));
+
+        let lines = get_lines(&debug_draw);
+        assert_eq!(lines.len(), SPHERE_SEGMENTS * 3);
+        for (i, &(start, end)) in lines.iter().enumerate() {
+            assert!((distance(start, center) - 2.0).abs() < 1e-4);
+            assert!((distance(end, center) - 2.0).abs() < 1e-4);
+
+            // Each circle is closed, so every segment starts where the last one ended.
+            let previous = if i % SPHERE_SEGMENTS == 0 {
+                i + SPHERE_SEGMENTS - 1
+            } else {
+                i - 1
+            };
+            assert_close(start, lines[previous].1);
+        }
+
+        // One circle in each plane through the center.
+        for (circle, flat_axis) in [2, 1, 0].iter().enumerate() {
+            for &(start, _) in &lines[circle * SPHERE_SEGMENTS..(circle + 1) * SPHERE_SEGMENTS] {
+                assert_eq!(start[*flat_axis], center[*flat_axis]);
+            }
+        }
+    }
+
+    #[test]
+    fn arrow() {
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.arrow([0.0; 3], [0.0, 0.0, 4.0], 1.0, &DebugStyle::new(WHITE));
+
+        let lines = get_lines(&debug_draw);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], ([0.0; 3], [0.0, 0.0, 4.0]));
+        // The head goes back from the tip to points around the shaft, half its length out.
+        for &(start, end) in &lines[1..] {
+            assert_eq!(start, [0.0, 0.0, 4.0]);
+            assert!((end[2] - 3.0).abs() < 1e-6);
+            assert!((end[0].hypot(end[1]) - 0.5).abs() < 1e-6);
+        }
+
+        // Without a direction there's no head, and no NaNs.
+        debug_draw.clear();
+        debug_draw.arrow([1.0; 3], [1.0; 3], 1.0, &DebugStyle::new(WHITE));
+        assert_eq!(get_lines(&debug_draw), vec![([1.0; 3], [1.0; 3])]);
+
+        // Straight up, where the usual reference axis is parallel to the arrow.
+        debug_draw.clear();
+        debug_draw.arrow([0.0; 3], [0.0, 2.0, 0.0], 1.0, &DebugStyle::new(WHITE));
+        for &(_, end) in &get_lines(&debug_draw)[1..] {
+            assert!(end.iter().all(|c| c.is_finite()));
+            assert!((end[1] - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn axes() {
+        let transform = Mat4::translation([1.0, 2.0, 3.0].into()).rows;
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.axes(&transform, 2.0, &DebugStyle::new([0.5, 0.5, 0.5, 1.0]));
+
+        let vertices = debug_draw.get_vertices(true);
+        assert_eq!(vertices.len(), 6);
+        let ends = [[3.0, 2.0, 3.0], [1.0, 4.0, 3.0], [1.0, 2.0, 5.0]];
+        for (i, line) in vertices.chunks(2).enumerate() {
+            assert_eq!(line[0].position, [1.0, 2.0, 3.0]);
+            assert_eq!(line[1].position, ends[i]);
+            let mut color = [0.0, 0.0, 0.0, 1.0];
+            color[i] = 0.5;
+            assert_eq!(line[0].color, color);
+        }
+    }
+
+    #[test]
+    fn grid() {
+        let mut debug_draw = DebugDraw::new();
+        let style = DebugStyle::new(WHITE);
+        debug_draw.grid([0.0; 3], [4.0, 0.0, 0.0], [0.0, 0.0, 2.0], 4, 2, &style);
+
+        let lines = get_lines(&debug_draw);
+        assert_eq!(lines.len(), 5 + 3);
+        for (i, &(start, end)) in lines[..5].iter().enumerate() {
+            assert_eq!(start, [i as f32, 0.0, 0.0]);
+            assert_eq!(end, [i as f32, 0.0, 2.0]);
+        }
+        for (i, &(start, end)) in lines[5..].iter().enumerate() {
+            assert_eq!(start, [0.0, 0.0, i as f32]);
+            assert_eq!(end, [4.0, 0.0, i as f32]);
+        }
+
+        // No divisions is the same as one, the outline.
+        debug_draw.clear();
+        debug_draw.grid([0.0; 3], [4.0, 0.0, 0.0], [0.0, 0.0, 2.0], 0, 0, &style);
+        assert_eq!(get_lines(&debug_draw).len(), 4);
+    }
+
+    #[test]
+    fn frustum() {
+        // Clip space itself, from -1 to 1 across and 0 to 1 deep.
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.frustum(&Mat4::IDENTITY.rows, &DebugStyle::new(WHITE));
+        assert_box_edges(&get_lines(&debug_draw), [-1.0, -1.0, 0.0], [1.0, 1.0, 1.0]);
+
+        // 90 degrees wide and high, so the planes are as wide as they are far away.
+        let projection = Mat4::perspective_lh(2.0, 2.0, 1.0, 10.0, DepthMode::Standard);
+        let inverse = projection.inverse().unwrap();
+        debug_draw.clear();
+        debug_draw.frustum(&inverse.rows, &DebugStyle::new(WHITE));
+
+        let lines = get_lines(&debug_draw);
+        assert_eq!(lines.len(), 12);
+        for &(start, end) in &lines {
+            for point in [start, end].iter() {
+                let z = if point[2] < 5.0 { 1.0 } else { 10.0 };
+                assert_close([point[0].abs(), point[1].abs(), point[2]], [z, z, z]);
+            }
+        }
+    }
+
+    #[test]
+    fn depth_test() {
+        let mut debug_draw = DebugDraw::new();
+        let style = DebugStyle::new(WHITE);
+        debug_draw.line([0.0; 3], [1.0; 3], &style);
+        debug_draw.line([0.0; 3], [2.0; 3], &style.with_depth_test(false));
+        debug_draw.line([0.0; 3], [3.0; 3], &style);
+
+        let tested = debug_draw.get_vertices(true);
+        assert_eq!(tested.len(), 4);
+        assert_eq!(tested[3].position, [3.0; 3]);
+        let on_top = debug_draw.get_vertices(false);
+        assert_eq!(on_top.len(), 2);
+        assert_eq!(on_top[1].position, [2.0; 3]);
+    }
+
+    #[test]
+    fn advance() {
+        let mut debug_draw = DebugDraw::new();
+        let style = DebugStyle::new(WHITE);
+        debug_draw.line([0.0; 3], [1.0; 3], &style);
+        debug_draw.line([0.0; 3], [2.0; 3], &style.with_duration(1.0));
+        debug_draw.line([0.0; 3], [3.0; 3], &style.with_duration(0.25));
+
+        // Zero duration lines are gone after the first frame.
+        debug_draw.advance(0.1);
+        let ends: Vec<[f32; 3]> = get_lines(&debug_draw).iter().map(|l| l.1).collect();
+        assert_eq!(ends, vec![[2.0; 3], [3.0; 3]]);
+
+        debug_draw.advance(0.2);
+        let ends: Vec<[f32; 3]> = get_lines(&debug_draw).iter().map(|l| l.1).collect();
+        assert_eq!(ends, vec![[2.0; 3]]);
+
+        debug_draw.advance(0.5);
+        assert!(!debug_draw.is_empty());
+        debug_draw.advance(0.5);
+        assert!(debug_draw.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use constant_buffer::ConstantBuffer;
+use debug_draw::{DebugDraw, DebugVertex};
+use render_states::RenderStates;
+use shader::{ShaderDesc, ShaderHandle, ShaderLibrary, ShaderStage};
+use vertex_buffer::{create_input_layout, VertexBuffer};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{ID3D11Device, ID3D11DeviceContext, ID3D11InputLayout};
+use winapi::um::d3dcommon::D3D11_PRIMITIVE_TOPOLOGY_LINELIST;
+use wio::com::ComPtr;
+
+// Vertices uploaded at once. Even, so a line never gets split between two uploads.
+const MAX_VERTICES_PER_DRAW: usize = 65536;
+
+impl_vertex! {
+    DebugVertex {
+        position: [f32; 3] => "POSITION",
+        color: [f32; 4] => "COLOR",
+    }
+}
+
+hlsl_struct! {
+    struct DebugConstants {
+        // Row major, so positions are multiplied from the left.
+        view_projection: [[f32; 4]; 4],
+    }
+}
+
+// Draws what has been queued in a DebugDraw as D3D11 lines, all in one pass after the scene.
+//
+// Holds device objects, so it has to be created again after the device is lost. The DebugDraw
+// doesn't, so nothing queued gets lost with it.
+pub struct DebugDrawRenderer {
+    vertex_shader: ShaderHandle,
+    pixel_shader: ShaderHandle,
+    input_layout: ComPtr<ID3D11InputLayout>,
+    vertex_buffer: VertexBuffer<DebugVertex>,
+    constants: ConstantBuffer<DebugConstants>,
+}
+
+impl DebugDrawRenderer {
+    pub unsafe fn new(
+        device: &ID3D11Device,
+        shaders: &mut ShaderLibrary,
+    ) -> Result<DebugDrawRenderer, String> {
+        let path = "shaders/debug_draw.hlsl";
+        let vertex_shader =
+            shaders.load(device, ShaderDesc::new(path, "VSMain", ShaderStage::Vertex));
+        let pixel_shader =
+            shaders.load(device, ShaderDesc::new(path, "PSMain", ShaderStage::Pixel));
+
+        let bytecode = shaders
+            .get_bytecode(vertex_shader)
+            .ok_or_else(|| "The debug draw vertex shader isn't available".to_string())?;
+        let input_layout = create_input_layout::<DebugVertex>(device, bytecode)?;
+
+        let to_error =
+            |hr: HRESULT| format!("Failed to create debug draw buffers, HRESULT {:x}", hr);
+        let vertex_buffer =
+            VertexBuffer::new_dynamic(device, MAX_VERTICES_PER_DRAW).map_err(to_error)?;
+        let constants = ConstantBuffer::new(device).map_err(to_error)?;
+
+        Ok(DebugDrawRenderer {
+            vertex_shader,
+            pixel_shader,
+            input_layout,
+            vertex_buffer,
+            constants,
+        })
+    }
+
+    // Draws everything queued in debug_draw into the current render target, depth tested lines
+    // first. Call after the scene so depth testing has something to test against. Leaves the
+    // pipeline state changed.
+    pub unsafe fn render(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        shaders: &ShaderLibrary,
+        states: &mut RenderStates,
+        debug_draw: &DebugDraw,
+        view_projection: &[[f32; 4]; 4],
+    ) -> Result<(), HRESULT> {
+        if debug_draw.is_empty() {
+            return Ok(());
+        }
+
+        // If a shader didn't compile the error has been printed already.
+        let (vertex_shader, pixel_shader) = match (
+            shaders.get_vertex_shader(self.vertex_shader),
+            shaders.get_pixel_shader(self.pixel_shader),
+        ) {
+            (Some(vertex_shader), Some(pixel_shader)) => (vertex_shader, pixel_shader),
+            _ => return Ok(()),
+        };
+
+        let constants = DebugConstants {
+            view_projection: *view_projection,
+        };
+        self.constants.set_data(context, &constants)?;
+
+        context.OMSetBlendState(states.alpha_blend()?.as_raw(), &[1.0; 4], 0xffff_ffff);
+        context.RSSetState(states.cull_none()?.as_raw());
+
+        context.IASetInputLayout(self.input_layout.as_raw());
+        context.IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_LINELIST);
+        self.vertex_buffer.bind(context, 0);
+        context.VSSetShader(vertex_shader.as_raw(), std::ptr::null(), 0);
+        context.VSSetConstantBuffers(0, 1, &self.constants.get_buffer().as_raw());
+        context.PSSetShader(pixel_shader.as_raw(), std::ptr::null(), 0);
+
+        for &depth_test in [true, false].iter() {
+            let vertices = debug_draw.get_vertices(depth_test);
+            if vertices.is_empty() {
+                continue;
+            }
+
+            // Lines read the depth buffer but don't write it, so they don't hide each other.
+            let depth_state = if depth_test {
+                states.depth_read()?
+            } else {
+                states.depth_none()?
+            };
+            context.OMSetDepthStencilState(depth_state.as_raw(), 0);
+
+            for chunk in vertices.chunks(MAX_VERTICES_PER_DRAW) {
+                self.vertex_buffer.set_data(context, chunk)?;
+                context.Draw(chunk.len() as u32, 0);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hlsl_layout::check_layout;
+
+    #[test]
+    fn constants_match_hlsl() {
+        assert_eq!(check_layout::<DebugConstants>(), Ok(()));
+    }
+}
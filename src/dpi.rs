@@ -0,0 +1,98 @@
+use winapi::shared::minwindef::{BOOL, DWORD, FARPROC, UINT};
+use winapi::shared::ntdef::HRESULT;
+use winapi::shared::windef::{DPI_AWARENESS_CONTEXT, HMONITOR, HWND, RECT};
+use winapi::um::libloaderapi::{GetModuleHandleW, GetProcAddress, LoadLibraryW};
+use winapi::um::winuser::{AdjustWindowRectEx, MonitorFromWindow, MONITOR_DEFAULTTONEAREST};
+
+pub const USER_DEFAULT_SCREEN_DPI: u32 = 96;
+
+// Not in the winapi version we're on, and only available since Windows 10 1703 anyway.
+const DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2: DPI_AWARENESS_CONTEXT =
+    -4isize as DPI_AWARENESS_CONTEXT;
+const PROCESS_PER_MONITOR_DPI_AWARE: u32 = 2;
+const MDT_EFFECTIVE_DPI: u32 = 0;
+
+type SetProcessDpiAwarenessContextFn = unsafe extern "system" fn(DPI_AWARENESS_CONTEXT) -> BOOL;
+type SetProcessDpiAwarenessFn = unsafe extern "system" fn(u32) -> HRESULT;
+type GetDpiForWindowFn = unsafe extern "system" fn(HWND) -> UINT;
+type GetDpiForMonitorFn = unsafe extern "system" fn(HMONITOR, u32, *mut UINT, *mut UINT) -> HRESULT;
+type AdjustWindowRectExForDpiFn =
+    unsafe extern "system" fn(*mut RECT, DWORD, BOOL, DWORD, UINT) -> BOOL;
+
+// All of the DPI functions are looked up at runtime so we still start on versions of Windows
+// that predate them. The fallback order mirrors what the SDK docs recommend.
+unsafe fn get_proc(module: &str, name: &[u8]) -> FARPROC {
+    let module_name = ::to_wide(module);
+    let mut handle = GetModuleHandleW(module_name.as_ptr());
+    if handle.is_null() {
+        handle = LoadLibraryW(module_name.as_ptr());
+    }
+    if handle.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    GetProcAddress(handle, name.as_ptr() as *const i8)
+}
+
+// Has to be called before any window is created.
+pub unsafe fn enable_per_monitor_dpi_awareness() {
+    let proc_addr = get_proc("user32.dll", b"SetProcessDpiAwarenessContext\0");
+    if !proc_addr.is_null() {
+        let set_context: SetProcessDpiAwarenessContextFn = std::mem::transmute(proc_addr);
+        if set_context(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) != 0 {
+            return;
+        }
+    }
+
+    // Windows 8.1 and older Windows 10 builds only know about per-monitor v1.
+    let proc_addr = get_proc("shcore.dll", b"SetProcessDpiAwareness\0");
+    if !proc_addr.is_null() {
+        let set_awareness: SetProcessDpiAwarenessFn = std::mem::transmute(proc_addr);
+        set_awareness(PROCESS_PER_MONITOR_DPI_AWARE);
+    }
+}
+
+pub unsafe fn get_dpi_for_window(hwnd: HWND) -> u32 {
+    let proc_addr = get_proc("user32.dll", b"GetDpiForWindow\0");
+    if !proc_addr.is_null() {
+        let get_dpi: GetDpiForWindowFn = std::mem::transmute(proc_addr);
+        let dpi = get_dpi(hwnd);
+        if dpi != 0 {
+            return dpi;
+        }
+    }
+
+    let proc_addr = get_proc("shcore.dll", b"GetDpiForMonitor\0");
+    if !proc_addr.is_null() {
+        let get_dpi: GetDpiForMonitorFn = std::mem::transmute(proc_addr);
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+        let mut dpi_x = 0;
+        let mut dpi_y = 0;
+        if !::failed(get_dpi(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y)) {
+            return dpi_x;
+        }
+    }
+
+    USER_DEFAULT_SCREEN_DPI
+}
+
+// Grows a client rect to the full window rect, taking the non-client scaling at the given dpi into account.
+pub unsafe fn adjust_window_rect_for_dpi(rect: &mut RECT, style: DWORD, ex_style: DWORD, dpi: u32) {
+    let proc_addr = get_proc("user32.dll", b"AdjustWindowRectExForDpi\0");
+    if !proc_addr.is_null() {
+        let adjust: AdjustWindowRectExForDpiFn = std::mem::transmute(proc_addr);
+        if adjust(rect, style, 0, ex_style, dpi) != 0 {
+            return;
+        }
+    }
+
+    AdjustWindowRectEx(rect, style, 0, ex_style);
+}
+
+pub fn dpi_to_scale(dpi: u32) -> f32 {
+    dpi as f32 / USER_DEFAULT_SCREEN_DPI as f32
+}
+
+pub fn scale_for_dpi(value: i32, dpi: u32) -> i32 {
+    (value as i64 * dpi as i64 / USER_DEFAULT_SCREEN_DPI as i64) as i32
+}
@@ -0,0 +1,40 @@
+use winapi::shared::minwindef::{TRUE, UINT};
+use winapi::shared::windef::{HWND, POINT};
+use winapi::um::shellapi::{DragAcceptFiles, DragFinish, DragQueryFileW, DragQueryPoint, HDROP};
+
+// Files dragged onto the window, e.g. from Explorer. The position is in client coordinates.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileDrop {
+    pub paths: Vec<String>,
+    pub x: i32,
+    pub y: i32,
+}
+
+// Without this the window never gets WM_DROPFILES.
+pub unsafe fn accept_files(window: HWND) {
+    DragAcceptFiles(window, TRUE);
+}
+
+// Reads the files out of the WM_DROPFILES handle and frees it.
+pub unsafe fn take_file_drop(drop: HDROP) -> FileDrop {
+    let count = DragQueryFileW(drop, UINT::MAX, std::ptr::null_mut(), 0);
+
+    let mut paths = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        // The length doesn't include the terminator, the buffer size does.
+        let length = DragQueryFileW(drop, index, std::ptr::null_mut(), 0);
+        let mut path = vec![0u16; length as usize + 1];
+        DragQueryFileW(drop, index, path.as_mut_ptr(), length + 1);
+        paths.push(::from_wide(&path));
+    }
+
+    let mut point = POINT { x: 0, y: 0 };
+    DragQueryPoint(drop, &mut point);
+    DragFinish(drop);
+
+    FileDrop {
+        paths,
+        x: point.x,
+        y: point.y,
+    }
+}
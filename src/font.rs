@@ -0,0 +1,786 @@
+// Bitmap fonts: glyphs in one or more texture pages plus the metrics to lay out text with them.
+// Reads AngelCode BMFont files, text or binary, and DirectXTK .spritefont files. Layout is done
+// here without touching D3D, see SpriteFont for drawing.
+
+use sprite_batch::SpriteRect;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const BMFONT_MAGIC: &[u8] = b"BMF";
+const BMFONT_VERSION: u8 = 3;
+const SPRITEFONT_MAGIC: &[u8] = b"DXTKfont";
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Where the pixels of a page come from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum FontPage {
+    // An image file next to the font, as BMFont has it.
+    File(PathBuf),
+    // Texel data inside the font file, as .spritefont has it. pitch is the size of a row of
+    // pixels, or of blocks for compressed formats, and format is a DXGI_FORMAT.
+    Image {
+        width: u32,
+        height: u32,
+        format: u32,
+        pitch: u32,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Glyph {
+    pub page: usize,
+    // Where the glyph is in its page, in pixels.
+    pub source: SpriteRect,
+    // From the pen position to the top left of the glyph.
+    pub offset: [f32; 2],
+    // How far the pen moves after the glyph.
+    pub advance: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TextOptions {
+    // Lines longer than this are wrapped between words, or inside a word that doesn't fit on a
+    // line by itself. Never wrapped if None.
+    pub max_width: Option<f32>,
+    // Lines are aligned within max_width if there is one, otherwise within the widest line.
+    pub align: TextAlign,
+}
+
+impl TextOptions {
+    pub fn new() -> TextOptions {
+        TextOptions::default()
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> TextOptions {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> TextOptions {
+        self.align = align;
+        self
+    }
+}
+
+// A glyph where it ends up, relative to the top left of the text.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PlacedGlyph {
+    pub page: usize,
+    pub source: SpriteRect,
+    pub position: [f32; 2],
+}
+
+#[derive(Clone)]
+pub struct BitmapFont {
+    line_height: f32,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+    // Used for characters the font doesn't have. Those are skipped if None.
+    default_char: Option<char>,
+    pages: Vec<FontPage>,
+}
+
+impl BitmapFont {
+    pub fn new(line_height: f32) -> BitmapFont {
+        BitmapFont {
+            line_height,
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+            default_char: None,
+            pages: Vec::new(),
+        }
+    }
+
+    // Page files are looked up relative to the font file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<BitmapFont> {
+        let path = path.as_ref();
+        let mut font = BitmapFont::from_bytes(&fs::read(path)?)?;
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for page in font.pages.iter_mut() {
+            if let FontPage::File(ref mut file) = *page {
+                *file = directory.join(&file);
+            }
+        }
+        Ok(font)
+    }
+
+    // Tells the formats apart by their first bytes, anything unknown is taken to be BMFont text.
+    pub fn from_bytes(data: &[u8]) -> io::Result<BitmapFont> {
+        if data.starts_with(SPRITEFONT_MAGIC) {
+            read_spritefont(data)
+        } else if data.starts_with(BMFONT_MAGIC) {
+            read_bmfont_binary(data)
+        } else {
+            let text = std::str::from_utf8(data)
+                .map_err(|_| invalid_data("font is neither text nor a known binary format"))?;
+            read_bmfont_text(text)
+        }
+    }
+
+    pub fn add_glyph(&mut self, c: char, glyph: Glyph) {
+        self.glyphs.insert(c, glyph);
+    }
+
+    // amount is added to the advance of first when second follows it.
+    pub fn add_kerning(&mut self, first: char, second: char, amount: f32) {
+        self.kerning.insert((first, second), amount);
+    }
+
+    pub fn add_page(&mut self, page: FontPage) {
+        self.pages.push(page);
+    }
+
+    // Replaces a page, or adds it if index is one past the last page.
+    pub fn set_page(&mut self, index: usize, page: FontPage) {
+        if index == self.pages.len() {
+            self.pages.push(page);
+        } else {
+            self.pages[index] = page;
+        }
+    }
+
+    pub fn set_default_char(&mut self, c: Option<char>) {
+        self.default_char = c;
+    }
+
+    pub fn get_line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    pub fn get_pages(&self) -> &[FontPage] {
+        &self.pages
+    }
+
+    pub fn get_glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.default_char.and_then(|d| self.glyphs.get(&d)))
+    }
+
+    pub fn get_kerning(&self, first: char, second: char) -> f32 {
+        self.kerning.get(&(first, second)).cloned().unwrap_or(0.0)
+    }
+
+    // The size of the text once laid out.
+    pub fn measure(&self, text: &str, options: &TextOptions) -> [f32; 2] {
+        let lines = self.get_lines(text, options.max_width);
+        let width = lines.iter().map(|l| l.width).fold(0.0, f32::max);
+        [width, lines.len() as f32 * self.line_height]
+    }
+
+    // Every visible glyph of the text with where it goes.
+    pub fn layout(&self, text: &str, options: &TextOptions) -> Vec<PlacedGlyph> {
+        let lines = self.get_lines(text, options.max_width);
+        let block_width = options
+            .max_width
+            .unwrap_or_else(|| lines.iter().map(|l| l.width).fold(0.0, f32::max));
+
+        let mut glyphs = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let mut x = match options.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (block_width - line.width) * 0.5,
+                TextAlign::Right => block_width - line.width,
+            };
+            let y = index as f32 * self.line_height;
+
+            let mut previous = None;
+            for &c in line.chars.iter() {
+                if let Some(previous) = previous {
+                    x += self.get_kerning(previous, c);
+                }
+                previous = Some(c);
+
+                let glyph = match self.get_glyph(c) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+                if glyph.source.width > 0.0 && glyph.source.height > 0.0 {
+                    glyphs.push(PlacedGlyph {
+                        page: glyph.page,
+                        source: glyph.source,
+                        position: [x + glyph.offset[0], y + glyph.offset[1]],
+                    });
+                }
+                x += glyph.advance;
+            }
+        }
+        glyphs
+    }
+
+    // Writes the font as a .spritefont that from_bytes can read back, e.g. to cook a TrueTypeFont
+    // into a file. That format has no kerning and a single page, which has to be in the font
+    // rather than a file.
+    pub fn to_spritefont_bytes(&self) -> io::Result<Vec<u8>> {
+        let (width, height, format, pitch, texels) = match self.pages.as_slice() {
+            [FontPage::Image {
+                width,
+                height,
+                format,
+                pitch,
+                data,
+            }] => (*width, *height, *format, *pitch, data),
+            _ => {
+                return Err(invalid_data(
+                    "only fonts with a single page in the font can be written as .spritefont",
+                ))
+            }
+        };
+
+        // Sorted, the DirectXTK looks glyphs up with a binary search.
+        let mut glyphs: Vec<(&char, &Glyph)> = self.glyphs.iter().collect();
+        glyphs.sort_by_key(|(c, _)| **c);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SPRITEFONT_MAGIC);
+        data.extend_from_slice(&(glyphs.len() as u32).to_le_bytes());
+        for (c, glyph) in glyphs {
+            let source = glyph.source;
+            data.extend_from_slice(&(*c as u32).to_le_bytes());
+            for edge in [
+                source.x,
+                source.y,
+                source.x + source.width,
+                source.y + source.height,
+            ]
+            .iter()
+            {
+                data.extend_from_slice(&(*edge as i32).to_le_bytes());
+            }
+            let x_advance = glyph.advance - glyph.offset[0] - source.width;
+            for value in [glyph.offset[0], glyph.offset[1], x_advance].iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        data.extend_from_slice(&self.line_height.to_le_bytes());
+        data.extend_from_slice(&self.default_char.map_or(0, |c| c as u32).to_le_bytes());
+        let rows = texels.len() as u32 / pitch.max(1);
+        for value in [width, height, format, pitch, rows].iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(texels);
+        Ok(data)
+    }
+
+    // How far the pen moves over the characters, kerning included.
+    fn get_width(&self, chars: &[char]) -> f32 {
+        let mut width = 0.0;
+        for (i, &c) in chars.iter().enumerate() {
+            if i > 0 {
+                width += self.get_kerning(chars[i - 1], c);
+            }
+            width += self.get_glyph(c).map_or(0.0, |g| g.advance);
+        }
+        width
+    }
+
+    // Splits the text at line breaks and, with a max width, wherever a line gets too long.
+    fn get_lines(&self, text: &str, max_width: Option<f32>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let chars: Vec<char> = paragraph.chars().filter(|&c| c != '\r').collect();
+            let max_width = match max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(Line {
+                        width: self.get_width(&chars),
+                        chars,
+                    });
+                    continue;
+                }
+            };
+
+            let mut start = 0;
+            loop {
+                // Take characters while they fit. Spaces always fit, they're dropped at the end
+                // of the line anyway.
+                let mut end = start;
+                let mut last_space = None;
+                while end < chars.len() {
+                    let c = chars[end];
+                    if c == ' ' {
+                        last_space = Some(end);
+                    } else if end > start && self.get_width(&chars[start..=end]) > max_width {
+                        break;
+                    }
+                    end += 1;
+                }
+
+                // Prefer wrapping between words.
+                let (line_end, next_start) = match last_space {
+                    Some(space) if end < chars.len() => (space, space + 1),
+                    _ => (end, end),
+                };
+                let mut line_chars = &chars[start..line_end];
+                while let Some((&' ', rest)) = line_chars.split_last() {
+                    line_chars = rest;
+                }
+                lines.push(Line {
+                    width: self.get_width(line_chars),
+                    chars: line_chars.to_vec(),
+                });
+
+                start = next_start;
+                while start < chars.len() && chars[start] == ' ' {
+                    start += 1;
+                }
+                if start >= chars.len() {
+                    break;
+                }
+            }
+        }
+        lines
+    }
+}
+
+struct Line {
+    chars: Vec<char>,
+    width: f32,
+}
+
+// Lines like `char id=65 x=10 y=0 width=12 ...`, with quotes around strings.
+fn read_bmfont_text(text: &str) -> io::Result<BitmapFont> {
+    let mut font = BitmapFont::new(0.0);
+    let mut pages: Vec<(usize, PathBuf)> = Vec::new();
+
+    for line in text.lines() {
+        let (tag, values) = parse_bmfont_line(line);
+        let get = |key: &str| -> io::Result<i64> {
+            values
+                .iter()
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, v)| v.parse().ok())
+                .ok_or_else(|| invalid_data(&format!("BMFont {} is missing {}", tag, key)))
+        };
+
+        match tag {
+            "common" => font.line_height = get("lineHeight")? as f32,
+            "page" => {
+                let file = values
+                    .iter()
+                    .find(|(k, _)| *k == "file")
+                    .map(|(_, v)| PathBuf::from(v))
+                    .ok_or_else(|| invalid_data("BMFont page is missing file"))?;
+                pages.push((get("id")? as usize, file));
+            }
+            "char" => {
+                let c = get_char(get("id")?)?;
+                let glyph = Glyph {
+                    page: get("page").unwrap_or(0) as usize,
+                    source: SpriteRect::new(
+                        get("x")? as f32,
+                        get("y")? as f32,
+                        get("width")? as f32,
+                        get("height")? as f32,
+                    ),
+                    offset: [get("xoffset")? as f32, get("yoffset")? as f32],
+                    advance: get("xadvance")? as f32,
+                };
+                font.add_glyph(c, glyph);
+            }
+            "kerning" => {
+                let first = get_char(get("first")?)?;
+                let second = get_char(get("second")?)?;
+                font.add_kerning(first, second, get("amount")? as f32);
+            }
+            _ => {}
+        }
+    }
+
+    pages.sort_by_key(|(id, _)| *id);
+    for (_, file) in pages {
+        font.add_page(FontPage::File(file));
+    }
+    font.default_char = get_default_char(&font);
+    Ok(font)
+}
+
+// The tag and key=value pairs of a line, with quotes taken off the values.
+fn parse_bmfont_line(line: &str) -> (&str, Vec<(&str, &str)>) {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(' ').unwrap_or((line, ""));
+
+    let mut values = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        let (key, after_key) = match rest.split_once('=') {
+            Some(pair) => pair,
+            None => break,
+        };
+
+        let (value, after_value) = if let Some(quoted) = after_key.strip_prefix('"') {
+            quoted.split_once('"').unwrap_or((quoted, ""))
+        } else {
+            after_key.split_once(' ').unwrap_or((after_key, ""))
+        };
+        values.push((key.trim(), value));
+        rest = after_value;
+    }
+    (tag, values)
+}
+
+fn get_char(id: i64) -> io::Result<char> {
+    std::char::from_u32(id as u32).ok_or_else(|| invalid_data("font has an invalid character"))
+}
+
+// BMFont doesn't name one, so use what's commonly there for missing characters.
+fn get_default_char(font: &BitmapFont) -> Option<char> {
+    ['\u{fffd}', '?']
+        .iter()
+        .cloned()
+        .find(|c| font.glyphs.contains_key(c))
+}
+
+// Blocks of a type byte and a size, see the BMFont documentation for the layout of each.
+fn read_bmfont_binary(data: &[u8]) -> io::Result<BitmapFont> {
+    let mut reader = Reader { data };
+    reader.read_bytes(3)?;
+    if reader.read_u8()? != BMFONT_VERSION {
+        return Err(invalid_data("unsupported BMFont version"));
+    }
+
+    let mut font = BitmapFont::new(0.0);
+    while !reader.data.is_empty() {
+        let block_type = reader.read_u8()?;
+        let size = reader.read_u32()? as usize;
+        let mut block = Reader {
+            data: reader.read_bytes(size)?,
+        };
+
+        match block_type {
+            // common
+            2 => font.line_height = block.read_u16()? as f32,
+            // pages, null terminated file names
+            3 => {
+                for name in block.data.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    font.add_page(FontPage::File(PathBuf::from(name)));
+                }
+            }
+            // chars, 20 bytes each
+            4 => {
+                while !block.data.is_empty() {
+                    let c = get_char(block.read_u32()? as i64)?;
+                    let x = block.read_u16()? as f32;
+                    let y = block.read_u16()? as f32;
+                    let width = block.read_u16()? as f32;
+                    let height = block.read_u16()? as f32;
+                    let offset = [block.read_i16()? as f32, block.read_i16()? as f32];
+                    let advance = block.read_i16()? as f32;
+                    let page = block.read_u8()? as usize;
+                    block.read_u8()?;
+                    font.add_glyph(
+                        c,
+                        Glyph {
+                            page,
+                            source: SpriteRect::new(x, y, width, height),
+                            offset,
+                            advance,
+                        },
+                    );
+                }
+            }
+            // kerning pairs, 10 bytes each
+            5 => {
+                while !block.data.is_empty() {
+                    let first = get_char(block.read_u32()? as i64)?;
+                    let second = get_char(block.read_u32()? as i64)?;
+                    font.add_kerning(first, second, block.read_i16()? as f32);
+                }
+            }
+            // info, and anything newer versions add
+            _ => {}
+        }
+    }
+
+    font.default_char = get_default_char(&font);
+    Ok(font)
+}
+
+// What MakeSpriteFont writes: the glyphs, the metrics and a single page with its texels.
+fn read_spritefont(data: &[u8]) -> io::Result<BitmapFont> {
+    let mut reader = Reader { data };
+    reader.read_bytes(SPRITEFONT_MAGIC.len())?;
+
+    let glyph_count = reader.read_u32()?;
+    let mut glyphs = Vec::new();
+    for _ in 0..glyph_count {
+        let c = get_char(reader.read_u32()? as i64)?;
+        let left = reader.read_i32()? as f32;
+        let top = reader.read_i32()? as f32;
+        let right = reader.read_i32()? as f32;
+        let bottom = reader.read_i32()? as f32;
+        let x_offset = reader.read_f32()?;
+        let y_offset = reader.read_f32()?;
+        let x_advance = reader.read_f32()?;
+
+        // The pen moves by the offset before the glyph and by the advance after it.
+        let width = right - left;
+        glyphs.push((
+            c,
+            Glyph {
+                page: 0,
+                source: SpriteRect::new(left, top, width, bottom - top),
+                offset: [x_offset, y_offset],
+                advance: x_offset + width + x_advance,
+            },
+        ));
+    }
+
+    let mut font = BitmapFont::new(reader.read_f32()?);
+    for (c, glyph) in glyphs {
+        font.add_glyph(c, glyph);
+    }
+
+    let default_char = reader.read_u32()?;
+    font.default_char = match default_char {
+        0 => None,
+        c => Some(get_char(c as i64)?),
+    };
+
+    let width = reader.read_u32()?;
+    let height = reader.read_u32()?;
+    let format = reader.read_u32()?;
+    let pitch = reader.read_u32()?;
+    let rows = reader.read_u32()?;
+    let data = reader.read_bytes(pitch as usize * rows as usize)?.to_vec();
+    font.add_page(FontPage::Image {
+        width,
+        height,
+        format,
+        pitch,
+        data,
+    });
+
+    Ok(font)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(invalid_data("font is truncated"));
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.read_bytes(N)?);
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A font with 8 pixel wide letters, a narrower space and a kerning pair.
+    fn create_font() -> BitmapFont {
+        let mut font = BitmapFont::new(10.0);
+        let glyph = |x: f32, width: f32, advance: f32| Glyph {
+            page: 0,
+            source: SpriteRect::new(x, 0.0, width, 9.0),
+            offset: [1.0, 2.0],
+            advance,
+        };
+        font.add_glyph('A', glyph(0.0, 6.0, 8.0));
+        font.add_glyph('V', glyph(8.0, 6.0, 8.0));
+        font.add_glyph('?', glyph(16.0, 5.0, 7.0));
+        font.add_glyph(' ', glyph(0.0, 0.0, 4.0));
+        font.add_kerning('A', 'V', -2.0);
+        font
+    }
+
+    fn get_positions(glyphs: &[PlacedGlyph]) -> Vec<[f32; 2]> {
+        glyphs.iter().map(|g| g.position).collect()
+    }
+
+    #[test]
+    fn measure_string() {
+        let font = create_font();
+        let options = TextOptions::new();
+        assert_eq!(font.measure("", &options), [0.0, 10.0]);
+        assert_eq!(font.measure("A", &options), [8.0, 10.0]);
+        // The kerning pair is 2 pixels closer than the advances add up to.
+        assert_eq!(font.measure("AV", &options), [14.0, 10.0]);
+        assert_eq!(font.measure("VA", &options), [16.0, 10.0]);
+        assert_eq!(font.measure("A A", &options), [20.0, 10.0]);
+    }
+
+    #[test]
+    fn advance_and_kerning() {
+        let font = create_font();
+        let glyphs = font.layout("AVA", &TextOptions::new());
+        // Each glyph is at the pen plus its offset.
+        assert_eq!(
+            get_positions(&glyphs),
+            [[1.0, 2.0], [7.0, 2.0], [15.0, 2.0]]
+        );
+        assert_eq!(glyphs[1].source, SpriteRect::new(8.0, 0.0, 6.0, 9.0));
+    }
+
+    #[test]
+    fn spaces_advance_without_a_glyph() {
+        let font = create_font();
+        let glyphs = font.layout("A A", &TextOptions::new());
+        assert_eq!(get_positions(&glyphs), [[1.0, 2.0], [13.0, 2.0]]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let font = create_font();
+        let options = TextOptions::new();
+        assert_eq!(font.measure("AV\nA", &options), [14.0, 20.0]);
+        assert_eq!(font.measure("A\r\nA\n", &options), [8.0, 30.0]);
+
+        let glyphs = font.layout("A\nV", &options);
+        assert_eq!(get_positions(&glyphs), [[1.0, 2.0], [1.0, 12.0]]);
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        let font = create_font();
+        let options = TextOptions::new().with_max_width(20.0);
+        // "AA A" would be 28 wide, so the second word goes on the next line and the space
+        // between them is dropped.
+        assert_eq!(font.measure("AA AA", &options), [16.0, 20.0]);
+        let glyphs = font.layout("AA AA", &options);
+        assert_eq!(
+            get_positions(&glyphs),
+            [[1.0, 2.0], [9.0, 2.0], [1.0, 12.0], [9.0, 12.0]]
+        );
+        // Exactly as wide as the limit still fits.
+        assert_eq!(font.measure("A A", &options), [20.0, 10.0]);
+    }
+
+    #[test]
+    fn wraps_inside_long_words() {
+        let font = create_font();
+        let options = TextOptions::new().with_max_width(20.0);
+        assert_eq!(font.measure("AAAAA", &options), [16.0, 30.0]);
+        // A single glyph wider than the limit still gets a line of its own.
+        let narrow = TextOptions::new().with_max_width(4.0);
+        assert_eq!(font.measure("AA", &narrow), [8.0, 20.0]);
+    }
+
+    #[test]
+    fn alignment() {
+        let font = create_font();
+        let centered = TextOptions::new().with_align(TextAlign::Center);
+        let glyphs = font.layout("AVA\nA", &centered);
+        // Centered within the widest line, which is 22 wide.
+        assert_eq!(glyphs[3].position, [8.0, 12.0]);
+
+        let right = TextOptions::new()
+            .with_max_width(30.0)
+            .with_align(TextAlign::Right);
+        let glyphs = font.layout("A", &right);
+        assert_eq!(glyphs[0].position, [23.0, 2.0]);
+    }
+
+    #[test]
+    fn default_character() {
+        let mut font = create_font();
+        // Missing characters take no space without a default.
+        assert_eq!(font.measure("AxA", &TextOptions::new()), [16.0, 10.0]);
+        assert_eq!(font.layout("x", &TextOptions::new()), []);
+
+        font.set_default_char(Some('?'));
+        assert_eq!(font.measure("AxA", &TextOptions::new()), [23.0, 10.0]);
+        let glyphs = font.layout("x", &TextOptions::new());
+        assert_eq!(glyphs.len(), 1);
+        assert_eq!(glyphs[0].source, SpriteRect::new(16.0, 0.0, 5.0, 9.0));
+    }
+
+    #[test]
+    fn bmfont_text() {
+        let text = "info face=\"Test Font\" size=12\n\
+                    common lineHeight=14 base=11 pages=1\n\
+                    page id=0 file=\"test font_0.png\"\n\
+                    chars count=2\n\
+                    char id=65 x=1 y=2 width=7 height=9 xoffset=0 yoffset=3 xadvance=8 page=0\n\
+                    char id=63 x=9 y=2 width=6 height=9 xoffset=1 yoffset=3 xadvance=7 page=0\n\
+                    kernings count=1\n\
+                    kerning first=65 second=65 amount=-1\n";
+        let font = BitmapFont::from_bytes(text.as_bytes()).unwrap();
+        assert_eq!(font.get_line_height(), 14.0);
+        assert_eq!(
+            font.get_pages(),
+            [FontPage::File(PathBuf::from("test font_0.png"))]
+        );
+        assert_eq!(font.get_kerning('A', 'A'), -1.0);
+        assert_eq!(font.measure("AA", &TextOptions::new()), [15.0, 14.0]);
+        // BMFont has no default character, '?' is taken if it's there.
+        assert_eq!(font.get_glyph('x').map(|g| g.advance), Some(7.0));
+    }
+
+    #[test]
+    fn spritefont_round_trip() {
+        let mut font = create_font();
+        font.set_default_char(Some('?'));
+        font.add_page(FontPage::Image {
+            width: 4,
+            height: 2,
+            format: 28,
+            pitch: 16,
+            data: vec![0xff; 32],
+        });
+        let bytes = font.to_spritefont_bytes().unwrap();
+        let read = BitmapFont::from_bytes(&bytes).unwrap();
+        assert_eq!(read.get_line_height(), 10.0);
+        assert_eq!(read.get_pages(), font.get_pages());
+        assert_eq!(read.get_glyph('V'), font.get_glyph('V'));
+        assert_eq!(read.get_glyph('x'), font.get_glyph('?'));
+        assert_eq!(
+            read.layout("VAx", &TextOptions::new()).len(),
+            font.layout("VAx", &TextOptions::new()).len()
+        );
+    }
+}
@@ -1,17 +1,36 @@
+use clipboard;
+use debug_draw::DebugDraw;
+use debug_draw_renderer::DebugDrawRenderer;
+use dpi;
+use file_drop::FileDrop;
+use input::{Input, InputFrame};
+use math::Mat4;
+use render_states::RenderStates;
+use replay::{InputRecorder, Replay};
+use sample_scene::SampleScene;
+use shader::ShaderLibrary;
+use state_snapshot::StateSnapshot;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
 use step_timer::StepTimer;
-use winapi::shared::dxgi::{IDXGIAdapter, IDXGIDevice1};
+use winapi::shared::dxgi::{IDXGIDevice1, DXGI_PRESENT_TEST};
 use winapi::shared::dxgi1_2::{
     IDXGIFactory2, IDXGISwapChain1, DXGI_SWAP_CHAIN_DESC1, DXGI_SWAP_CHAIN_FULLSCREEN_DESC,
 };
 use winapi::shared::dxgiformat::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_D24_UNORM_S8_UINT};
 use winapi::shared::dxgitype::DXGI_USAGE_RENDER_TARGET_OUTPUT;
-use winapi::shared::minwindef::{TRUE, UINT};
+use winapi::shared::minwindef::{LPARAM, LRESULT, TRUE, UINT, WPARAM};
 use winapi::shared::windef::HWND;
-use winapi::shared::winerror::{DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET};
+use winapi::shared::winerror::{
+    DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET, DXGI_STATUS_OCCLUDED,
+};
 use winapi::um::d3d11::{
     D3D11CreateDevice, ID3D11DepthStencilView, ID3D11Device, ID3D11DeviceContext,
-    ID3D11RenderTargetView, D3D11_CLEAR_DEPTH, D3D11_CLEAR_STENCIL, D3D11_CREATE_DEVICE_DEBUG,
-    D3D11_MAX_DEPTH, D3D11_MIN_DEPTH, D3D11_SDK_VERSION, D3D11_VIEWPORT,
+    ID3D11RenderTargetView, ID3D11Resource, ID3D11Texture2D, D3D11_BIND_DEPTH_STENCIL,
+    D3D11_CLEAR_DEPTH, D3D11_CLEAR_STENCIL, D3D11_CREATE_DEVICE_DEBUG,
+    D3D11_DEPTH_STENCIL_VIEW_DESC, D3D11_DSV_DIMENSION_TEXTURE2D, D3D11_MAX_DEPTH, D3D11_MIN_DEPTH,
+    D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, D3D11_VIEWPORT,
 };
 use winapi::um::d3d11_1::{ID3D11Device1, ID3D11DeviceContext1};
 use winapi::um::d3dcommon::{
@@ -25,10 +44,58 @@ use wio::com::ComPtr;
 
 //TODO: mark everything as unsafe
 
+// How often we check whether we're still occluded when the game would otherwise be paused.
+// Windows doesn't send a message when occlusion ends, so we have to poll.
+const OCCLUSION_POLL_FPS: u32 = 10;
+
+// Draws SampleScene's teapot and sprite, to check the renderer works before there's anything of
+// your own.
+const DRAW_SAMPLE_SCENE: bool = false;
+
+// What the game loop does while the window is inactive, minimized or occluded.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BackgroundMode {
+    // Tick at full speed as if nothing happened.
+    KeepRunning,
+    // Tick at most this many times per second.
+    Throttle(u32),
+    // Don't update or draw at all. Blocks in WaitMessage until something happens, except while
+    // occluded, which only ends up polling Present.
+    Pause,
+}
+
+// Turns the game state into bytes for a snapshot and back.
+pub type SaveStateFn = fn(&Game) -> Vec<u8>;
+pub type RestoreStateFn = fn(&mut Game, &[u8]);
+
+// Boils the game state down to a number that changes whenever the state does. Recordings store
+// it after every tick so a replay can tell when it stops matching.
+pub type StateHashFn = fn(&Game) -> u64;
+
+// Gets the files dragged onto the window, see set_files_dropped.
+pub type FilesDroppedFn = fn(&mut Game, FileDrop);
+
+// Gives up on the recording after the first error instead of complaining on every update.
+fn write_recording<F>(recorder: &mut Option<InputRecorder>, write: F)
+where
+    F: FnOnce(&mut InputRecorder) -> io::Result<()>,
+{
+    let result = match *recorder {
+        Some(ref mut recorder) => write(recorder),
+        None => return,
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to write input recording, stopping: {}", e);
+        *recorder = None;
+    }
+}
+
 pub struct Game {
     window: HWND,
     output_width: i32,
     output_height: i32,
+    dpi: u32,
     feature_level: D3D_FEATURE_LEVEL,
     d3d_device: Option<ComPtr<ID3D11Device1>>,
     d3d_context: Option<ComPtr<ID3D11DeviceContext1>>,
@@ -36,23 +103,59 @@ pub struct Game {
     render_target_view: Option<ComPtr<ID3D11RenderTargetView>>,
     depth_stencil_view: Option<ComPtr<ID3D11DepthStencilView>>,
     timer: StepTimer,
+    background_mode: BackgroundMode,
+    is_active: bool,
+    is_minimized: bool,
+    is_occluded: bool,
+    is_suspended: bool,
+    state_snapshot: Option<(StateSnapshot, SaveStateFn, RestoreStateFn)>,
+    input: Input,
+    recorder: Option<InputRecorder>,
+    state_hash: Option<StateHashFn>,
+    files_dropped: Option<FilesDroppedFn>,
+    shaders: ShaderLibrary,
+    states: RenderStates,
+    debug_draw: DebugDraw,
+    debug_draw_renderer: Option<DebugDrawRenderer>,
+    sample_scene: Option<SampleScene>,
+    // What the debug drawing is seen through, see set_view_projection.
+    view_projection: [[f32; 4]; 4],
 }
 
 impl Game {
     pub fn new() -> Game {
-        unsafe {
-            Game {
-                window: std::ptr::null_mut(),
-                output_width: 800,
-                output_height: 600,
-                feature_level: D3D_FEATURE_LEVEL_9_1,
-                d3d_device: None,
-                d3d_context: None,
-                swap_chain: None,
-                render_target_view: None,
-                depth_stencil_view: None,
-                timer: StepTimer::new(),
-            }
+        Game {
+            window: std::ptr::null_mut(),
+            output_width: 800,
+            output_height: 600,
+            dpi: dpi::USER_DEFAULT_SCREEN_DPI,
+            feature_level: D3D_FEATURE_LEVEL_9_1,
+            d3d_device: None,
+            d3d_context: None,
+            swap_chain: None,
+            render_target_view: None,
+            depth_stencil_view: None,
+            timer: StepTimer::new(),
+            background_mode: BackgroundMode::Throttle(10),
+            is_active: true,
+            is_minimized: false,
+            is_occluded: false,
+            is_suspended: false,
+            state_snapshot: None,
+            input: Input::new(),
+            recorder: None,
+            state_hash: None,
+            files_dropped: None,
+            shaders: ShaderLibrary::new(),
+            states: RenderStates::new(),
+            debug_draw: DebugDraw::new(),
+            debug_draw_renderer: None,
+            sample_scene: if DRAW_SAMPLE_SCENE {
+                Some(SampleScene::new())
+            } else {
+                None
+            },
+            view_projection: Mat4::IDENTITY.rows,
         }
     }
     pub unsafe fn initialize(&mut self, window: HWND, width: i32, height: i32) {
@@ -60,26 +163,78 @@ impl Game {
         self.output_width = std::cmp::max(width, 1);
         self.output_height = std::cmp::max(height, 1);
 
+        self.input.set_window(window);
+
         self.create_device();
         self.create_resources();
+
+        // Picks up where we left off if the last run was suspended and never came back.
+        self.restore_state_snapshot();
     }
 
     pub fn tick(&mut self) {
+        // Paused, so the main loop only comes here while occluded to find out when that's over.
+        // The time in between is dropped when it is, see set_background_state.
+        if self.background_mode == BackgroundMode::Pause && self.is_in_background() {
+            self.test_occlusion();
+            return;
+        }
+
         //this is kinda sucky to port from c++ and I don't know how to fix it.
         //we can't call self.update because self is already borrowed when we take the timer.
         //stackoverflow suggests borrowing only the fields you need instead of taking self as param:
         //https://stackoverflow.com/questions/29896672/can-you-control-borrowing-a-struct-vs-borrowing-a-field
         //for now let's just leave the update function without access to Game (avoid the borrow)
+        let time_delta = self.timer.read_time_delta();
         {
+            write_recording(&mut self.recorder, |r| r.write_tick(time_delta));
+
             let timer = &mut self.timer;
-            timer.tick(|t| Game::update(t));
+            let input = &mut self.input;
+            let recorder = &mut self.recorder;
+            let debug_draw = &mut self.debug_draw;
+            timer.tick_with_delta(time_delta, |t| {
+                // Input is sampled per update rather than per frame, so when the fixed timestep
+                // catches up with several updates in a row, only the first one sees the press.
+                let frame = input.sample();
+                write_recording(recorder, |r| r.write_update(&frame));
+                input.apply(&frame);
+
+                Game::update(t, input, debug_draw);
+            });
+        }
+
+        if let (Some(state_hash), true) = (self.state_hash, self.recorder.is_some()) {
+            let hash = state_hash(self);
+            write_recording(&mut self.recorder, |r| r.write_state_hash(hash));
+        }
+
+        // Picks up edits to shader sources while the game is running.
+        if let Some(ref device) = self.d3d_device {
+            unsafe {
+                self.shaders.reload_changed(device);
+            }
         }
 
-        self.render();
+        // Nobody can see what we'd draw, so only check whether that's still the case.
+        if self.is_occluded {
+            self.test_occlusion();
+        } else {
+            self.render();
+        }
+
+        // Whatever was queued for this frame has had its chance to be seen now.
+        self.debug_draw
+            .advance(StepTimer::ticks_to_seconds(time_delta) as f32);
     }
 
-    fn update(timer: &StepTimer) {
-        let elapsed_time = timer.get_elapsed_seconds() as f32;
+    fn update(timer: &StepTimer, input: &Input, debug_draw: &mut DebugDraw) {
+        let _elapsed_time = timer.get_elapsed_seconds() as f32;
+
+        // input.actions has whatever was bound with bind_action and bind_axis. Shapes queued on
+        // debug_draw are drawn in the next frame and then dropped, so queue them every update for
+        // as long as they should be seen.
+        let _ = (input, debug_draw);
 
         // TODO: Add your game logic here
     }
@@ -93,18 +248,62 @@ impl Game {
         self.clear();
 
         // TODO: Add your rendering code here.
+        self.render_sample_scene();
+
+        self.render_debug_draw();
 
         self.present();
     }
 
-    fn clear(&mut self) {
-        if self.render_target_view.is_some()
-            && self.depth_stencil_view.is_some()
-            && self.d3d_context.is_some()
+    fn render_sample_scene(&mut self) {
+        if let (Some(scene), Some(context)) =
+            (self.sample_scene.as_mut(), self.d3d_context.as_ref())
         {
-            let rtv = &mut self.render_target_view.as_ref().unwrap();
-            let dsv = &mut self.depth_stencil_view.as_ref().unwrap();
-            let context = self.d3d_context.as_ref().unwrap();
+            let aspect = self.output_width as f32 / self.output_height as f32;
+            let total_seconds = self.timer.get_total_seconds() as f32;
+            self.view_projection = (scene.get_view() * scene.get_projection(aspect)).rows;
+            scene.queue_debug_shapes(&mut self.debug_draw);
+            let result = unsafe {
+                scene.render(
+                    context,
+                    &self.shaders,
+                    &mut self.states,
+                    total_seconds,
+                    aspect,
+                )
+            };
+            if let Err(hr) = result {
+                eprintln!("Failed to draw the sample scene, HRESULT {:x}", hr);
+            }
+        }
+    }
+
+    // On top of the scene, so it can be depth tested against it.
+    fn render_debug_draw(&mut self) {
+        if let (Some(renderer), Some(context)) =
+            (self.debug_draw_renderer.as_mut(), self.d3d_context.as_ref())
+        {
+            let result = unsafe {
+                renderer.render(
+                    context,
+                    &self.shaders,
+                    &mut self.states,
+                    &self.debug_draw,
+                    &self.view_projection,
+                )
+            };
+            if let Err(hr) = result {
+                eprintln!("Failed to draw debug lines, HRESULT {:x}", hr);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        if let (Some(rtv), Some(dsv), Some(context)) = (
+            self.render_target_view.as_ref(),
+            self.depth_stencil_view.as_ref(),
+            self.d3d_context.as_ref(),
+        ) {
             unsafe {
                 context.ClearRenderTargetView(rtv.as_raw(), &[0.0, 0.0, 0.5, 1.0f32]);
                 context.ClearDepthStencilView(
@@ -139,7 +338,9 @@ impl Game {
             // If the device was reset we must completely reinitialize the renderer.
             if hr == DXGI_ERROR_DEVICE_REMOVED || hr == DXGI_ERROR_DEVICE_RESET {
                 self.on_device_lost();
-            } else {
+            } else if hr == DXGI_STATUS_OCCLUDED {
+                self.set_background_state(self.is_active, self.is_minimized, true);
+            } else if ::failed(hr) {
                 //not sure what to do about this one. In C++ you can theoretically catch it but
                 //would anyone do that in practice?
                 panic!("Present failed without device removed or reset error");
@@ -147,33 +348,338 @@ impl Game {
         }
     }
 
+    fn test_occlusion(&mut self) {
+        let hr = match self.swap_chain.as_ref() {
+            Some(swap_chain) => unsafe { swap_chain.Present(0, DXGI_PRESENT_TEST) },
+            None => return,
+        };
+
+        if hr != DXGI_STATUS_OCCLUDED {
+            self.set_background_state(self.is_active, self.is_minimized, false);
+        }
+    }
+
+    pub fn set_background_mode(&mut self, mode: BackgroundMode) {
+        self.background_mode = mode;
+    }
+
+    pub fn get_background_mode(&self) -> BackgroundMode {
+        self.background_mode
+    }
+
+    pub fn is_in_background(&self) -> bool {
+        !self.is_active || self.is_minimized || self.is_occluded
+    }
+
+    // True if the main loop should block until the next message instead of ticking.
+    // Occlusion never pauses completely since only polling Present tells us when it's over.
+    pub fn should_wait_for_messages(&self) -> bool {
+        self.background_mode == BackgroundMode::Pause && (!self.is_active || self.is_minimized)
+    }
+
+    // The minimum time between two ticks, if the main loop should throttle at all.
+    pub fn get_throttle_interval(&self) -> Option<Duration> {
+        if !self.is_in_background() {
+            return None;
+        }
+
+        let fps = match self.background_mode {
+            BackgroundMode::KeepRunning => return None,
+            BackgroundMode::Throttle(fps) => std::cmp::max(fps, 1),
+            // Only checking whether we're still occluded, see tick.
+            BackgroundMode::Pause => OCCLUSION_POLL_FPS,
+        };
+
+        Some(Duration::from_secs(1) / fps)
+    }
+
+    fn set_background_state(&mut self, active: bool, minimized: bool, occluded: bool) {
+        let was_in_background = self.is_in_background();
+
+        self.is_active = active;
+        self.is_minimized = minimized;
+        self.is_occluded = occluded;
+
+        // Coming back from a throttled or paused state, the time since the last tick is
+        // meaningless and would otherwise show up as one giant update.
+        if was_in_background
+            && !self.is_in_background()
+            && self.background_mode != BackgroundMode::KeepRunning
+        {
+            self.reset_elapsed_time();
+        }
+    }
+
+    // Changes what the next tick does, so a recording has to know about it.
+    fn reset_elapsed_time(&mut self) {
+        self.timer.reset_elapsed_time();
+        write_recording(&mut self.recorder, |r| r.write_reset_elapsed_time());
+    }
+
+    // Called from the window procedure for every message that might carry input.
+    pub unsafe fn process_input_message(
+        &mut self,
+        message: UINT,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) {
+        self.input.process_message(message, w_param, l_param);
+    }
+
+    pub unsafe fn process_text_input_message(
+        &mut self,
+        message: UINT,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> Option<LRESULT> {
+        self.input.process_text_message(message, w_param, l_param)
+    }
+
     pub fn on_activated(&mut self) {
+        self.set_background_state(true, self.is_minimized, self.is_occluded);
+
         // TODO: Game is becoming active window.
     }
 
     pub fn on_deactivated(&mut self) {
+        self.set_background_state(false, self.is_minimized, self.is_occluded);
+
         // TODO: Game is becoming background window.
     }
 
+    // Without a handler dropped files are ignored.
+    pub fn on_files_dropped(&mut self, file_drop: FileDrop) {
+        if let Some(files_dropped) = self.files_dropped {
+            files_dropped(self, file_drop);
+        }
+    }
+
+    pub fn set_files_dropped(&mut self, files_dropped: FilesDroppedFn) {
+        self.files_dropped = Some(files_dropped);
+    }
+
+    pub fn get_clipboard_text(&self) -> Option<String> {
+        unsafe { clipboard::get_text(self.window) }
+    }
+
+    pub fn set_clipboard_text(&self, text: &str) -> bool {
+        unsafe { clipboard::set_text(self.window, text) }
+    }
+
+    pub fn on_minimized(&mut self) {
+        self.set_background_state(self.is_active, true, self.is_occluded);
+    }
+
+    pub fn on_restored(&mut self) {
+        self.set_background_state(self.is_active, false, self.is_occluded);
+    }
+
     pub fn on_suspending(&mut self) {
+        // Minimizing and then suspending the machine both end up here.
+        if self.is_suspended {
+            return;
+        }
+        self.is_suspended = true;
+
         // TODO: Game is being power-suspended (or minimized).
     }
 
+    // The machine is going to sleep and might never wake up, e.g. if the battery runs out, so
+    // unlike minimizing this is worth a state snapshot. Already suspended if it was minimized.
+    pub fn on_power_suspending(&mut self) {
+        self.on_suspending();
+        self.save_state_snapshot();
+    }
+
     pub fn on_resuming(&mut self) {
-        self.timer.reset_elapsed_time();
+        if !self.is_suspended {
+            return;
+        }
+        self.is_suspended = false;
+
+        self.reset_elapsed_time();
+
+        // Nothing was lost, the game state is still all there. The snapshot was only in case we
+        // never came back, and restoring it at the next launch would go back in time.
+        self.discard_state_snapshot();
 
         // TODO: Game is being power-resumed (or returning from minimize).
     }
 
+    pub fn is_suspended(&self) -> bool {
+        self.is_suspended
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.is_minimized
+    }
+
+    // Has to be set before initialize to restore a snapshot left over from the last run.
+    pub fn set_state_snapshot<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        save: SaveStateFn,
+        restore: RestoreStateFn,
+    ) {
+        self.state_snapshot = Some((StateSnapshot::new(path), save, restore));
+    }
+
+    fn save_state_snapshot(&mut self) {
+        if let Some((ref snapshot, save, _)) = self.state_snapshot {
+            let data = save(self);
+            if let Err(e) = snapshot.save(&data) {
+                eprintln!(
+                    "Failed to write state snapshot to {}: {}",
+                    snapshot.get_path().display(),
+                    e
+                );
+            }
+        }
+    }
+
+    fn discard_state_snapshot(&mut self) {
+        if let Some((ref snapshot, _, _)) = self.state_snapshot {
+            if let Err(e) = snapshot.discard() {
+                eprintln!(
+                    "Failed to delete state snapshot {}: {}",
+                    snapshot.get_path().display(),
+                    e
+                );
+            }
+        }
+    }
+
+    // Only at launch, see initialize.
+    fn restore_state_snapshot(&mut self) {
+        let (data, restore) = match self.state_snapshot {
+            Some((ref snapshot, _, restore)) => match snapshot.take() {
+                Ok(Some(data)) => (data, restore),
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!(
+                        "Failed to read state snapshot from {}: {}",
+                        snapshot.get_path().display(),
+                        e
+                    );
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        write_recording(&mut self.recorder, |r| r.write_state_snapshot(&data));
+        restore(self, &data);
+    }
+
+    // Records the input of every update from now on. Call before initialize, a replay starts
+    // from scratch and wouldn't know about anything that happened before, like a state snapshot
+    // being restored.
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.recorder = Some(InputRecorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        write_recording(&mut self.recorder, |r| r.flush());
+        self.recorder = None;
+    }
+
+    pub fn set_state_hash(&mut self, state_hash: StateHashFn) {
+        self.state_hash = Some(state_hash);
+    }
+
+    // Runs a recording from start to end as fast as possible, without a window or a device.
+    // Returns the index of the first tick where the game went somewhere else than it did when
+    // recording, or None if the whole replay matched.
+    pub fn run_replay<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Option<usize>> {
+        let replay = Replay::load(path)?;
+
+        for (index, tick) in replay.get_ticks().iter().enumerate() {
+            if let Some(ref data) = tick.state_snapshot {
+                let restore = match self.state_snapshot {
+                    Some((_, _, restore)) => restore,
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "the recording restores a state snapshot, but there's no \
+                             set_state_snapshot to restore it with",
+                        ))
+                    }
+                };
+                restore(self, data);
+            }
+            if tick.reset_elapsed_time {
+                self.timer.reset_elapsed_time();
+            }
+
+            let mut updates = tick.updates.iter();
+            let mut missing_update = false;
+            {
+                let timer = &mut self.timer;
+                let input = &mut self.input;
+                let debug_draw = &mut self.debug_draw;
+                timer.tick_with_delta(tick.time_delta, |t| {
+                    match updates.next() {
+                        Some(frame) => input.apply(frame),
+                        None => {
+                            missing_update = true;
+                            input.apply(&InputFrame::default());
+                        }
+                    }
+
+                    Game::update(t, input, debug_draw);
+                });
+            }
+
+            // Nothing draws during a replay, so don't let the queue grow.
+            self.debug_draw.clear();
+
+            // A different number of updates means the timer isn't set up like it was.
+            if missing_update || updates.next().is_some() {
+                return Ok(Some(index));
+            }
+
+            if let (Some(expected), Some(state_hash)) = (tick.state_hash, self.state_hash) {
+                if state_hash(self) != expected {
+                    return Ok(Some(index));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     pub unsafe fn on_window_size_changed(&mut self, width: i32, height: i32) {
-        self.output_width = std::cmp::max(width, 1);
-        self.output_height = std::cmp::min(height, 1);
+        let width = std::cmp::max(width, 1);
+        let height = std::cmp::max(height, 1);
+        if width == self.output_width && height == self.output_height {
+            return;
+        }
+
+        self.output_width = width;
+        self.output_height = height;
 
         self.create_resources();
 
         // TODO: Game window is being resized.
     }
 
+    pub fn on_dpi_changed(&mut self, dpi: u32) {
+        self.dpi = dpi;
+
+        // TODO: Rebuild anything that depends on the UI scale (fonts, layout).
+    }
+
+    // 1.0 at 96 dpi. Multiply UI sizes given in 96 dpi units by this to get pixels.
+    pub fn get_dpi_scale(&self) -> f32 {
+        dpi::dpi_to_scale(self.dpi)
+    }
+
+    // The camera's view times its projection, row major. Debug drawing is seen through it.
+    pub fn set_view_projection(&mut self, view_projection: [[f32; 4]; 4]) {
+        self.view_projection = view_projection;
+    }
+
     pub fn get_default_size(&self, width: &mut i32, height: &mut i32) {
         // TODO: Change to desired default window size (note minimum size is 320x200).
         *width = 800;
@@ -228,6 +734,27 @@ impl Game {
             .unwrap();
         self.d3d_context = Some(context);
 
+        // Shaders and render states used before a device loss come back here. Shaders get
+        // compiled for the new feature level if it changed.
+        self.shaders
+            .on_device_restored(self.d3d_device.as_ref().unwrap(), self.feature_level);
+        let device = self.d3d_device.clone().unwrap().up();
+        self.states.on_device_restored(device, self.feature_level);
+
+        match DebugDrawRenderer::new(self.d3d_device.as_ref().unwrap(), &mut self.shaders) {
+            Ok(renderer) => self.debug_draw_renderer = Some(renderer),
+            Err(e) => eprintln!("{}", e),
+        }
+        if let Some(ref mut scene) = self.sample_scene {
+            if let Err(e) = scene.on_device_restored(
+                self.d3d_device.as_ref().unwrap(),
+                self.d3d_context.as_ref().unwrap(),
+                &mut self.shaders,
+            ) {
+                eprintln!("{}", e);
+            }
+        }
+
         // TODO: Initialize device dependent objects here (independent of window size).
     }
 
@@ -266,7 +793,7 @@ impl Game {
                 // Everything is set up now. Do not continue execution of this method. OnDeviceLost will reenter this method
                 // and correctly set up the new device.
                 return;
-            } else {
+            } else if ::failed(hr) {
                 panic!("Unexpected error: {}", hr);
             }
         } else {
@@ -322,11 +849,84 @@ impl Game {
             if ::failed(hr) {
                 panic!("Failed to create swap chain, HRESULT {}", hr);
             }
+            self.swap_chain = Some(ComPtr::from_raw(swap_chain_ptr));
+        }
+
+        let device = self.d3d_device.as_ref().unwrap();
+
+        // Obtain the backbuffer for this window which will be the final 3D rendertarget.
+        let mut back_buffer_ptr: *mut ID3D11Texture2D = std::ptr::null_mut();
+        let mut hr = self.swap_chain.as_ref().unwrap().GetBuffer(
+            0,
+            &ID3D11Texture2D::uuidof(),
+            &mut back_buffer_ptr as *mut *mut _ as *mut *mut winapi::ctypes::c_void,
+        );
+        if ::failed(hr) {
+            panic!("Couldn't get back buffer, HRESULT {}", hr);
+        }
+        let back_buffer = ComPtr::from_raw(back_buffer_ptr);
+
+        // Create a view interface on the rendertarget to use on bind.
+        let mut rtv_ptr: *mut ID3D11RenderTargetView = std::ptr::null_mut();
+        hr = device.CreateRenderTargetView(
+            back_buffer.as_raw() as *mut ID3D11Resource,
+            std::ptr::null(),
+            &mut rtv_ptr,
+        );
+        if ::failed(hr) {
+            panic!("Failed to create render target view, HRESULT {}", hr);
         }
+        self.render_target_view = Some(ComPtr::from_raw(rtv_ptr));
+
+        // Allocate a 2-D surface as the depth/stencil buffer and
+        // create a DepthStencil view on this surface to use on bind.
+        let mut depth_stencil_desc: D3D11_TEXTURE2D_DESC = std::mem::zeroed();
+        depth_stencil_desc.Width = back_buffer_width as u32;
+        depth_stencil_desc.Height = back_buffer_height as u32;
+        depth_stencil_desc.MipLevels = 1;
+        depth_stencil_desc.ArraySize = 1;
+        depth_stencil_desc.Format = depth_buffer_format;
+        depth_stencil_desc.SampleDesc.Count = 1;
+        depth_stencil_desc.Usage = D3D11_USAGE_DEFAULT;
+        depth_stencil_desc.BindFlags = D3D11_BIND_DEPTH_STENCIL;
+
+        let mut depth_stencil_ptr: *mut ID3D11Texture2D = std::ptr::null_mut();
+        hr = device.CreateTexture2D(
+            &depth_stencil_desc,
+            std::ptr::null(),
+            &mut depth_stencil_ptr,
+        );
+        if ::failed(hr) {
+            panic!("Failed to create depth stencil buffer, HRESULT {}", hr);
+        }
+        let depth_stencil = ComPtr::from_raw(depth_stencil_ptr);
+
+        let mut dsv_desc: D3D11_DEPTH_STENCIL_VIEW_DESC = std::mem::zeroed();
+        dsv_desc.Format = depth_buffer_format;
+        dsv_desc.ViewDimension = D3D11_DSV_DIMENSION_TEXTURE2D;
+
+        let mut dsv_ptr: *mut ID3D11DepthStencilView = std::ptr::null_mut();
+        hr = device.CreateDepthStencilView(
+            depth_stencil.as_raw() as *mut ID3D11Resource,
+            &dsv_desc,
+            &mut dsv_ptr,
+        );
+        if ::failed(hr) {
+            panic!("Failed to create depth stencil view, HRESULT {}", hr);
+        }
+        self.depth_stencil_view = Some(ComPtr::from_raw(dsv_ptr));
+
+        // TODO: Initialize windows-size dependent objects here.
     }
 
     unsafe fn on_device_lost(&mut self) {
         // TODO: Add Direct3D resource cleanup here.
+        self.shaders.on_device_lost();
+        self.states.on_device_lost();
+        self.debug_draw_renderer = None;
+        if let Some(ref mut scene) = self.sample_scene {
+            scene.on_device_lost();
+        }
 
         self.depth_stencil_view = None;
         self.render_target_view = None;
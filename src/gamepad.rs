@@ -0,0 +1,545 @@
+use mouse::ButtonState;
+use std::time::{Duration, Instant};
+use winapi::shared::winerror::{ERROR_DEVICE_NOT_CONNECTED, ERROR_SUCCESS};
+use winapi::um::xinput::{
+    XInputGetState, XInputSetState, XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE,
+    XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE, XINPUT_GAMEPAD_TRIGGER_THRESHOLD, XINPUT_STATE,
+    XINPUT_VIBRATION,
+};
+
+pub const MAX_PLAYER_COUNT: usize = 4;
+
+const THUMB_MAX: f32 = 32767.0;
+const TRIGGER_MAX: f32 = 255.0;
+
+// Polling a disconnected pad is expensive, so only look for new ones every so often.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+// Values are the XINPUT_GAMEPAD_* bits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamePadButton {
+    DPadUp = 0x0001,
+    DPadDown = 0x0002,
+    DPadLeft = 0x0004,
+    DPadRight = 0x0008,
+    Start = 0x0010,
+    Back = 0x0020,
+    LeftStick = 0x0040,
+    RightStick = 0x0080,
+    LeftShoulder = 0x0100,
+    RightShoulder = 0x0200,
+    A = 0x1000,
+    B = 0x2000,
+    X = 0x4000,
+    Y = 0x8000,
+}
+
+impl GamePadButton {
+    pub fn from_bits(bits: u16) -> Option<GamePadButton> {
+        match bits {
+            0x0001 => Some(GamePadButton::DPadUp),
+            0x0002 => Some(GamePadButton::DPadDown),
+            0x0004 => Some(GamePadButton::DPadLeft),
+            0x0008 => Some(GamePadButton::DPadRight),
+            0x0010 => Some(GamePadButton::Start),
+            0x0020 => Some(GamePadButton::Back),
+            0x0040 => Some(GamePadButton::LeftStick),
+            0x0080 => Some(GamePadButton::RightStick),
+            0x0100 => Some(GamePadButton::LeftShoulder),
+            0x0200 => Some(GamePadButton::RightShoulder),
+            0x1000 => Some(GamePadButton::A),
+            0x2000 => Some(GamePadButton::B),
+            0x4000 => Some(GamePadButton::X),
+            0x8000 => Some(GamePadButton::Y),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GamePadButton> {
+        match name {
+            "DPadUp" => Some(GamePadButton::DPadUp),
+            "DPadDown" => Some(GamePadButton::DPadDown),
+            "DPadLeft" => Some(GamePadButton::DPadLeft),
+            "DPadRight" => Some(GamePadButton::DPadRight),
+            "Start" => Some(GamePadButton::Start),
+            "Back" => Some(GamePadButton::Back),
+            "LeftStick" => Some(GamePadButton::LeftStick),
+            "RightStick" => Some(GamePadButton::RightStick),
+            "LeftShoulder" => Some(GamePadButton::LeftShoulder),
+            "RightShoulder" => Some(GamePadButton::RightShoulder),
+            "A" => Some(GamePadButton::A),
+            "B" => Some(GamePadButton::B),
+            "X" => Some(GamePadButton::X),
+            "Y" => Some(GamePadButton::Y),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match *self {
+            GamePadButton::DPadUp => "DPadUp",
+            GamePadButton::DPadDown => "DPadDown",
+            GamePadButton::DPadLeft => "DPadLeft",
+            GamePadButton::DPadRight => "DPadRight",
+            GamePadButton::Start => "Start",
+            GamePadButton::Back => "Back",
+            GamePadButton::LeftStick => "LeftStick",
+            GamePadButton::RightStick => "RightStick",
+            GamePadButton::LeftShoulder => "LeftShoulder",
+            GamePadButton::RightShoulder => "RightShoulder",
+            GamePadButton::A => "A",
+            GamePadButton::B => "B",
+            GamePadButton::X => "X",
+            GamePadButton::Y => "Y",
+        }
+    }
+}
+
+// The analog controls. Sticks go from -1 to 1, triggers from 0 to 1.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamePadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamePadAxis {
+    pub fn from_name(name: &str) -> Option<GamePadAxis> {
+        match name {
+            "LeftStickX" => Some(GamePadAxis::LeftStickX),
+            "LeftStickY" => Some(GamePadAxis::LeftStickY),
+            "RightStickX" => Some(GamePadAxis::RightStickX),
+            "RightStickY" => Some(GamePadAxis::RightStickY),
+            "LeftTrigger" => Some(GamePadAxis::LeftTrigger),
+            "RightTrigger" => Some(GamePadAxis::RightTrigger),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match *self {
+            GamePadAxis::LeftStickX => "LeftStickX",
+            GamePadAxis::LeftStickY => "LeftStickY",
+            GamePadAxis::RightStickX => "RightStickX",
+            GamePadAxis::RightStickY => "RightStickY",
+            GamePadAxis::LeftTrigger => "LeftTrigger",
+            GamePadAxis::RightTrigger => "RightTrigger",
+        }
+    }
+}
+
+// How stick input near the center is filtered out. Same options as the DirectXTK GamePad.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DeadZone {
+    // Each axis gets its own dead zone. Makes it easy to move along exactly one axis.
+    IndependentAxes,
+    // The dead zone is a circle around the center. Best for free movement.
+    Circular,
+    // Raw values, just normalized.
+    None,
+}
+
+// Dead zones are in raw XInput units (0..32767 for sticks, 0..255 for triggers).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GamePadSettings {
+    pub dead_zone: DeadZone,
+    pub left_thumb_dead_zone: f32,
+    pub right_thumb_dead_zone: f32,
+    pub trigger_threshold: f32,
+}
+
+impl Default for GamePadSettings {
+    fn default() -> GamePadSettings {
+        GamePadSettings {
+            dead_zone: DeadZone::IndependentAxes,
+            left_thumb_dead_zone: XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE as f32,
+            right_thumb_dead_zone: XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE as f32,
+            trigger_threshold: XINPUT_GAMEPAD_TRIGGER_THRESHOLD as f32,
+        }
+    }
+}
+
+// Removes the dead zone from a single axis and rescales the rest to -1..1, so the output
+// starts at 0 right at the edge of the dead zone instead of jumping.
+pub fn apply_linear_dead_zone(value: f32, max_value: f32, dead_zone_size: f32) -> f32 {
+    let value = if value < -dead_zone_size {
+        value + dead_zone_size
+    } else if value > dead_zone_size {
+        value - dead_zone_size
+    } else {
+        return 0.0;
+    };
+
+    let scaled_value = value / (max_value - dead_zone_size);
+    scaled_value.clamp(-1.0, 1.0)
+}
+
+// Each axis on its own, so a stick held close to an axis snaps onto it.
+pub fn apply_axial_dead_zone(x: f32, y: f32, max_value: f32, dead_zone_size: f32) -> (f32, f32) {
+    (
+        apply_linear_dead_zone(x, max_value, dead_zone_size),
+        apply_linear_dead_zone(y, max_value, dead_zone_size),
+    )
+}
+
+// Applies the dead zone to the distance from the center and keeps the direction.
+pub fn apply_radial_dead_zone(x: f32, y: f32, max_value: f32, dead_zone_size: f32) -> (f32, f32) {
+    let distance = (x * x + y * y).sqrt();
+    let wanted_distance = apply_linear_dead_zone(distance, max_value, dead_zone_size);
+    let scale = if wanted_distance > 0.0 {
+        wanted_distance / distance
+    } else {
+        0.0
+    };
+
+    ((x * scale).clamp(-1.0, 1.0), (y * scale).clamp(-1.0, 1.0))
+}
+
+pub fn apply_stick_dead_zone(
+    x: f32,
+    y: f32,
+    dead_zone: DeadZone,
+    max_value: f32,
+    dead_zone_size: f32,
+) -> (f32, f32) {
+    match dead_zone {
+        DeadZone::IndependentAxes => apply_axial_dead_zone(x, y, max_value, dead_zone_size),
+        DeadZone::Circular => apply_radial_dead_zone(x, y, max_value, dead_zone_size),
+        DeadZone::None => apply_axial_dead_zone(x, y, max_value, 0.0),
+    }
+}
+
+pub fn apply_trigger_dead_zone(value: f32, dead_zone: DeadZone, threshold: f32) -> f32 {
+    match dead_zone {
+        DeadZone::None => apply_linear_dead_zone(value, TRIGGER_MAX, 0.0),
+        _ => apply_linear_dead_zone(value, TRIGGER_MAX, threshold),
+    }
+}
+
+// Sticks are -1..1 with y pointing up, triggers are 0..1.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct GamePadState {
+    pub connected: bool,
+    // Changes whenever XInput has new data for the pad.
+    pub packet: u32,
+    pub buttons: u16,
+    pub left_stick_x: f32,
+    pub left_stick_y: f32,
+    pub right_stick_x: f32,
+    pub right_stick_y: f32,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+impl GamePadState {
+    pub fn is_button_down(&self, button: GamePadButton) -> bool {
+        self.buttons & button as u16 != 0
+    }
+
+    pub fn get_axis(&self, axis: GamePadAxis) -> f32 {
+        match axis {
+            GamePadAxis::LeftStickX => self.left_stick_x,
+            GamePadAxis::LeftStickY => self.left_stick_y,
+            GamePadAxis::RightStickX => self.right_stick_x,
+            GamePadAxis::RightStickY => self.right_stick_y,
+            GamePadAxis::LeftTrigger => self.left_trigger,
+            GamePadAxis::RightTrigger => self.right_trigger,
+        }
+    }
+
+    fn from_xinput(state: &XINPUT_STATE, settings: &GamePadSettings) -> GamePadState {
+        let pad = &state.Gamepad;
+        let (left_stick_x, left_stick_y) = apply_stick_dead_zone(
+            pad.sThumbLX as f32,
+            pad.sThumbLY as f32,
+            settings.dead_zone,
+            THUMB_MAX,
+            settings.left_thumb_dead_zone,
+        );
+        let (right_stick_x, right_stick_y) = apply_stick_dead_zone(
+            pad.sThumbRX as f32,
+            pad.sThumbRY as f32,
+            settings.dead_zone,
+            THUMB_MAX,
+            settings.right_thumb_dead_zone,
+        );
+
+        GamePadState {
+            connected: true,
+            packet: state.dwPacketNumber,
+            buttons: pad.wButtons,
+            left_stick_x,
+            left_stick_y,
+            right_stick_x,
+            right_stick_y,
+            left_trigger: apply_trigger_dead_zone(
+                pad.bLeftTrigger as f32,
+                settings.dead_zone,
+                settings.trigger_threshold,
+            ),
+            right_trigger: apply_trigger_dead_zone(
+                pad.bRightTrigger as f32,
+                settings.dead_zone,
+                settings.trigger_threshold,
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamePadEvent {
+    Connected(usize),
+    Disconnected(usize),
+}
+
+// Polls up to four XInput pads. Unlike keyboard and mouse there are no messages to listen to,
+// so poll has to be called once per update.
+pub struct GamePad {
+    settings: GamePadSettings,
+    states: [GamePadState; MAX_PLAYER_COUNT],
+    last_connect_attempt: [Option<Instant>; MAX_PLAYER_COUNT],
+    events: Vec<GamePadEvent>,
+}
+
+impl GamePad {
+    pub fn new() -> GamePad {
+        GamePad {
+            settings: GamePadSettings::default(),
+            states: [GamePadState::default(); MAX_PLAYER_COUNT],
+            last_connect_attempt: [None; MAX_PLAYER_COUNT],
+            events: Vec::new(),
+        }
+    }
+
+    pub fn get_settings(&self) -> &GamePadSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: GamePadSettings) {
+        self.settings = settings;
+    }
+
+    pub fn poll(&mut self) {
+        let now = Instant::now();
+
+        for player in 0..MAX_PLAYER_COUNT {
+            let was_connected = self.states[player].connected;
+            if !was_connected {
+                if let Some(last_attempt) = self.last_connect_attempt[player] {
+                    if now.duration_since(last_attempt) < RECONNECT_INTERVAL {
+                        continue;
+                    }
+                }
+            }
+
+            let mut xinput_state: XINPUT_STATE = unsafe { std::mem::zeroed() };
+            let result = unsafe { XInputGetState(player as u32, &mut xinput_state) };
+
+            if result == ERROR_SUCCESS {
+                self.states[player] = GamePadState::from_xinput(&xinput_state, &self.settings);
+                self.last_connect_attempt[player] = None;
+                if !was_connected {
+                    self.events.push(GamePadEvent::Connected(player));
+                }
+            } else {
+                // Anything other than "not connected" is unexpected, but treat it the same. It's
+                // retried at the same slow rate, so this doesn't log every frame.
+                if result != ERROR_DEVICE_NOT_CONNECTED {
+                    eprintln!("XInputGetState failed for player {}: {}", player, result);
+                }
+                self.states[player] = GamePadState::default();
+                self.last_connect_attempt[player] = Some(now);
+                if was_connected {
+                    self.events.push(GamePadEvent::Disconnected(player));
+                }
+            }
+        }
+    }
+
+    pub fn get_state(&self, player: usize) -> GamePadState {
+        self.states[player]
+    }
+
+    // Connection changes since the last call, in the order poll saw them.
+    pub fn take_events(&mut self) -> Vec<GamePadEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Motor speeds are 0..1. The left motor is the low-frequency one. Returns false if the
+    // pad isn't connected.
+    pub fn set_vibration(&mut self, player: usize, left_motor: f32, right_motor: f32) -> bool {
+        if player >= MAX_PLAYER_COUNT {
+            return false;
+        }
+
+        let mut vibration = XINPUT_VIBRATION {
+            wLeftMotorSpeed: (left_motor.clamp(0.0, 1.0) * 65535.0) as u16,
+            wRightMotorSpeed: (right_motor.clamp(0.0, 1.0) * 65535.0) as u16,
+        };
+        unsafe { XInputSetState(player as u32, &mut vibration) == ERROR_SUCCESS }
+    }
+
+    pub fn stop_all_vibration(&mut self) {
+        for player in 0..MAX_PLAYER_COUNT {
+            if self.states[player].connected {
+                self.set_vibration(player, 0.0, 0.0);
+            }
+        }
+    }
+}
+
+// Same idea as the KeyboardStateTracker: update once per update tick.
+#[derive(Default)]
+pub struct GamePadButtonStateTracker {
+    pressed: u16,
+    released: u16,
+    last_state: GamePadState,
+}
+
+impl GamePadButtonStateTracker {
+    pub fn new() -> GamePadButtonStateTracker {
+        GamePadButtonStateTracker::default()
+    }
+
+    pub fn update(&mut self, state: &GamePadState) {
+        self.pressed = state.buttons & !self.last_state.buttons;
+        self.released = self.last_state.buttons & !state.buttons;
+        self.last_state = *state;
+    }
+
+    pub fn reset(&mut self) {
+        *self = GamePadButtonStateTracker::default();
+    }
+
+    pub fn is_button_pressed(&self, button: GamePadButton) -> bool {
+        self.pressed & button as u16 != 0
+    }
+
+    pub fn is_button_released(&self, button: GamePadButton) -> bool {
+        self.released & button as u16 != 0
+    }
+
+    pub fn get_button_state(&self, button: GamePadButton) -> ButtonState {
+        if self.is_button_pressed(button) {
+            ButtonState::Pressed
+        } else if self.is_button_released(button) {
+            ButtonState::Released
+        } else if self.last_state.is_button_down(button) {
+            ButtonState::Held
+        } else {
+            ButtonState::Up
+        }
+    }
+
+    pub fn get_last_state(&self) -> &GamePadState {
+        &self.last_state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEAD_ZONE: f32 = 7849.0;
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-5 && (actual.1 - expected.1).abs() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn linear() {
+        assert_eq!(apply_linear_dead_zone(0.0, THUMB_MAX, DEAD_ZONE), 0.0);
+        assert_eq!(
+            apply_linear_dead_zone(-DEAD_ZONE, THUMB_MAX, DEAD_ZONE),
+            0.0
+        );
+        // Starts from zero right at the edge rather than jumping.
+        let just_outside = apply_linear_dead_zone(DEAD_ZONE + 1.0, THUMB_MAX, DEAD_ZONE);
+        assert!(just_outside > 0.0 && just_outside < 1e-4);
+        let halfway = DEAD_ZONE + (THUMB_MAX - DEAD_ZONE) / 2.0;
+        assert_eq!(apply_linear_dead_zone(halfway, THUMB_MAX, DEAD_ZONE), 0.5);
+        assert_eq!(apply_linear_dead_zone(-halfway, THUMB_MAX, DEAD_ZONE), -0.5);
+        assert_eq!(apply_linear_dead_zone(THUMB_MAX, THUMB_MAX, DEAD_ZONE), 1.0);
+        // XInput goes one further down than up.
+        assert_eq!(apply_linear_dead_zone(-32768.0, THUMB_MAX, DEAD_ZONE), -1.0);
+    }
+
+    #[test]
+    fn axial() {
+        assert_eq!(
+            apply_axial_dead_zone(1000.0, -1000.0, THUMB_MAX, DEAD_ZONE),
+            (0.0, 0.0)
+        );
+        // Close to an axis snaps onto it.
+        let (x, y) = apply_axial_dead_zone(THUMB_MAX, DEAD_ZONE - 1.0, THUMB_MAX, DEAD_ZONE);
+        assert_eq!((x, y), (1.0, 0.0));
+        // Diagonals go all the way to the corner.
+        let corner = apply_axial_dead_zone(-32768.0, THUMB_MAX, THUMB_MAX, DEAD_ZONE);
+        assert_eq!(corner, (-1.0, 1.0));
+    }
+
+    #[test]
+    fn radial() {
+        // Both axes are inside their own dead zone, and so is the distance.
+        let inside = DEAD_ZONE * 0.7;
+        assert_eq!(
+            apply_radial_dead_zone(inside, inside, THUMB_MAX, DEAD_ZONE),
+            (0.0, 0.0)
+        );
+        // Same axes, but now the distance is outside.
+        let (x, y) = apply_radial_dead_zone(DEAD_ZONE, DEAD_ZONE, THUMB_MAX, DEAD_ZONE);
+        assert!(x > 0.0 && x == y);
+
+        assert_eq!(
+            apply_radial_dead_zone(DEAD_ZONE, 0.0, THUMB_MAX, DEAD_ZONE),
+            (0.0, 0.0)
+        );
+        let halfway = DEAD_ZONE + (THUMB_MAX - DEAD_ZONE) / 2.0;
+        assert_close(
+            apply_radial_dead_zone(0.0, -halfway, THUMB_MAX, DEAD_ZONE),
+            (0.0, -0.5),
+        );
+
+        // The direction stays the same, and the length saturates at 1.
+        let diagonal = apply_radial_dead_zone(THUMB_MAX, THUMB_MAX, THUMB_MAX, DEAD_ZONE);
+        let half_sqrt_2 = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(diagonal, (half_sqrt_2, half_sqrt_2));
+        let off_axis = apply_radial_dead_zone(THUMB_MAX, THUMB_MAX / 2.0, THUMB_MAX, DEAD_ZONE);
+        assert_close(
+            (off_axis.1 / off_axis.0, off_axis.0.hypot(off_axis.1)),
+            (0.5, 1.0),
+        );
+    }
+
+    #[test]
+    fn none() {
+        let (x, y) = apply_stick_dead_zone(100.0, -THUMB_MAX, DeadZone::None, THUMB_MAX, DEAD_ZONE);
+        assert_eq!((x, y), (100.0 / THUMB_MAX, -1.0));
+        assert_eq!(
+            apply_trigger_dead_zone(10.0, DeadZone::None, 30.0),
+            10.0 / TRIGGER_MAX
+        );
+    }
+
+    #[test]
+    fn triggers() {
+        assert_eq!(apply_trigger_dead_zone(30.0, DeadZone::Circular, 30.0), 0.0);
+        let halfway = 30.0 + (TRIGGER_MAX - 30.0) / 2.0;
+        assert_eq!(
+            apply_trigger_dead_zone(halfway, DeadZone::IndependentAxes, 30.0),
+            0.5
+        );
+        assert_eq!(
+            apply_trigger_dead_zone(TRIGGER_MAX, DeadZone::Circular, 30.0),
+            1.0
+        );
+    }
+}
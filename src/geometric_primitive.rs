@@ -0,0 +1,902 @@
+// Meshes generated from a few numbers, like DirectXTK's GeometricPrimitive: boxes, spheres,
+// cylinders, a teapot and so on, centered on the origin with +y up. They come out as Mesh, with
+// normals, tangents and texture coordinates and a single part without a material, so GpuMesh can
+// draw them like a loaded model.
+//
+// Front faces wind clockwise as seen from outside, which is what D3D culls by, when the positions
+// are read in the handedness given in the options. tessellation is how many segments go around,
+// or for the geosphere how many times an octahedron is subdivided.
+
+use math::Vec3;
+use mesh::{compute_tangents, Mesh, MeshPart, MeshVertex};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Handedness {
+    #[default]
+    RightHanded,
+    LeftHanded,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PrimitiveOptions {
+    pub handedness: Handedness,
+    // Normals point in and the winding is reversed, for seeing the shape from inside, like a
+    // sky box or sky sphere.
+    pub inside_out: bool,
+}
+
+impl PrimitiveOptions {
+    pub fn new() -> PrimitiveOptions {
+        PrimitiveOptions::default()
+    }
+
+    pub fn with_handedness(mut self, handedness: Handedness) -> PrimitiveOptions {
+        self.handedness = handedness;
+        self
+    }
+
+    pub fn with_inside_out(mut self, inside_out: bool) -> PrimitiveOptions {
+        self.inside_out = inside_out;
+        self
+    }
+}
+
+pub fn create_cube(size: f32, options: &PrimitiveOptions) -> Mesh {
+    let mut mesh = create_box([size; 3], options);
+    mesh.name = "cube".to_string();
+    mesh
+}
+
+// Every face has the whole texture on it.
+pub fn create_box(size: [f32; 3], options: &PrimitiveOptions) -> Mesh {
+    const FACE_NORMALS: [Vec3; 6] = [
+        Vec3::Z,
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::X,
+        Vec3::new(-1.0, 0.0, 0.0),
+        Vec3::Y,
+        Vec3::new(0.0, -1.0, 0.0),
+    ];
+    let half_size = Vec3::from(size) * 0.5;
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (face, &normal) in FACE_NORMALS.iter().enumerate() {
+        // Two directions along the face, right and down as seen from outside.
+        let basis = if face >= 4 { Vec3::Z } else { Vec3::Y };
+        let right = basis.cross(normal);
+        let down = right.cross(normal);
+
+        let base = vertices.len() as u32;
+        for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            let direction = normal + right * x + down * y;
+            vertices.push(vertex(
+                direction * half_size,
+                normal,
+                [(x + 1.0) * 0.5, (y + 1.0) * 0.5],
+            ));
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base + 2, base + 1, base + 3]);
+    }
+
+    finish("box", vertices, indices, options)
+}
+
+// Rings of latitude from the bottom to the top, twice as many segments around as up.
+pub fn create_sphere(diameter: f32, tessellation: u32, options: &PrimitiveOptions) -> Mesh {
+    assert!(tessellation >= 3);
+    let rows = tessellation;
+    let columns = tessellation * 2;
+    let radius = diameter * 0.5;
+
+    let mut vertices = Vec::new();
+    for i in 0..=rows {
+        let latitude = i as f32 * PI / rows as f32 - PI * 0.5;
+        let (y, ring_radius) = latitude.sin_cos();
+        for j in 0..=columns {
+            let u = j as f32 / columns as f32;
+            let circle = get_circle_vector(u);
+            let normal = Vec3::new(circle.x * ring_radius, y, circle.z * ring_radius);
+            vertices.push(vertex(
+                normal * radius,
+                normal,
+                [u, 1.0 - i as f32 / rows as f32],
+            ));
+        }
+    }
+
+    let mut indices = Vec::new();
+    push_grid(&mut indices, 0, rows, columns);
+    finish("sphere", vertices, indices, options)
+}
+
+// A subdivided octahedron pushed out onto the sphere, so its triangles are all about the same
+// size instead of bunching up at the poles.
+pub fn create_geosphere(diameter: f32, tessellation: u32, options: &PrimitiveOptions) -> Mesh {
+    let mut positions: Vec<Vec3> = vec![
+        Vec3::Y,
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::Z,
+        Vec3::X,
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(-1.0, 0.0, 0.0),
+    ];
+    let mut indices: Vec<u32> = Vec::new();
+    for k in 0..4 {
+        let (a, b) = (2 + k, 2 + (k + 1) % 4);
+        indices.extend_from_slice(&[a, 0, b, 1, a, b]);
+    }
+
+    for _ in 0..tessellation {
+        let mut midpoints = HashMap::new();
+        let mut get_midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let midpoint = positions[a as usize] + positions[b as usize];
+                positions.push(midpoint.normalize());
+                positions.len() as u32 - 1
+            })
+        };
+
+        let mut subdivided = Vec::with_capacity(indices.len() * 4);
+        for triangle in indices.chunks_exact(3) {
+            let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+            let ab = get_midpoint(a, b, &mut positions);
+            let bc = get_midpoint(b, c, &mut positions);
+            let ca = get_midpoint(c, a, &mut positions);
+            subdivided.extend_from_slice(&[a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+        }
+        indices = subdivided;
+    }
+
+    // Texture coordinates like the sphere's, with u from 0 at +z going towards +x.
+    let mut vertices: Vec<MeshVertex> = positions
+        .iter()
+        .map(|&p| {
+            let u = p.x.atan2(p.z) / (2.0 * PI);
+            let u = if u < 0.0 { u + 1.0 } else { u };
+            let v = p.y.clamp(-1.0, 1.0).acos() / PI;
+            vertex(p * (diameter * 0.5), p, [u, v])
+        })
+        .collect();
+
+    // Triangles across the seam where u wraps around would stretch the whole texture across
+    // themselves. Their vertices on the low side get a copy with u past 1 instead.
+    let mut seam_copies = HashMap::new();
+    for triangle in indices.chunks_exact_mut(3) {
+        let us = [triangle[0], triangle[1], triangle[2]].map(|i| vertices[i as usize].texcoord[0]);
+        let max_u = us[0].max(us[1]).max(us[2]);
+        for (index, &u) in triangle.iter_mut().zip(us.iter()) {
+            if max_u - u > 0.5 {
+                *index = *seam_copies.entry(*index).or_insert_with(|| {
+                    let mut copy = vertices[*index as usize];
+                    copy.texcoord[0] += 1.0;
+                    vertices.push(copy);
+                    vertices.len() as u32 - 1
+                });
+            }
+        }
+    }
+
+    // u means nothing at the poles, each triangle there gets its own pole vertex with the u of
+    // the middle of the triangle's other side.
+    for triangle in indices.chunks_exact_mut(3) {
+        for corner in 0..3 {
+            let pole = vertices[triangle[corner] as usize];
+            if pole.normal[1].abs() < 1.0 - 1e-6 {
+                continue;
+            }
+            let others = [triangle[(corner + 1) % 3], triangle[(corner + 2) % 3]];
+            let mut copy = pole;
+            copy.texcoord[0] = (vertices[others[0] as usize].texcoord[0]
+                + vertices[others[1] as usize].texcoord[0])
+                * 0.5;
+            vertices.push(copy);
+            triangle[corner] = vertices.len() as u32 - 1;
+        }
+    }
+
+    finish("geosphere", vertices, indices, options)
+}
+
+// Standing on the y axis, with caps.
+pub fn create_cylinder(
+    height: f32,
+    diameter: f32,
+    tessellation: u32,
+    options: &PrimitiveOptions,
+) -> Mesh {
+    assert!(tessellation >= 3);
+    let radius = diameter * 0.5;
+    let half_height = height * 0.5;
+
+    let mut vertices = Vec::new();
+    for &(y, v) in &[(-half_height, 1.0), (half_height, 0.0)] {
+        for j in 0..=tessellation {
+            let u = j as f32 / tessellation as f32;
+            let normal = get_circle_vector(u);
+            vertices.push(vertex(
+                normal * radius + Vec3::new(0.0, y, 0.0),
+                normal,
+                [u, v],
+            ));
+        }
+    }
+    let mut indices = Vec::new();
+    push_grid(&mut indices, 0, 1, tessellation);
+
+    push_cap(
+        &mut vertices,
+        &mut indices,
+        tessellation,
+        radius,
+        half_height,
+        true,
+    );
+    push_cap(
+        &mut vertices,
+        &mut indices,
+        tessellation,
+        radius,
+        -half_height,
+        false,
+    );
+    finish("cylinder", vertices, indices, options)
+}
+
+// Point up, with a cap on the bottom.
+pub fn create_cone(
+    diameter: f32,
+    height: f32,
+    tessellation: u32,
+    options: &PrimitiveOptions,
+) -> Mesh {
+    assert!(tessellation >= 3);
+    let radius = diameter * 0.5;
+    let half_height = height * 0.5;
+
+    // The tip gets a vertex per segment, so each side has its own normal there.
+    let mut vertices = Vec::new();
+    for &(is_tip, v) in &[(false, 1.0), (true, 0.0)] {
+        for j in 0..=tessellation {
+            let u = j as f32 / tessellation as f32;
+            let direction = get_circle_vector(u);
+            let base = direction * radius + Vec3::new(0.0, -half_height, 0.0);
+            let normal = (direction * height + Vec3::new(0.0, radius, 0.0)).normalize();
+            let position = if is_tip {
+                Vec3::new(0.0, half_height, 0.0)
+            } else {
+                base
+            };
+            vertices.push(vertex(position, normal, [u, v]));
+        }
+    }
+    let mut indices = Vec::new();
+    push_grid(&mut indices, 0, 1, tessellation);
+
+    push_cap(
+        &mut vertices,
+        &mut indices,
+        tessellation,
+        radius,
+        -half_height,
+        false,
+    );
+    finish("cone", vertices, indices, options)
+}
+
+// Lying flat around the y axis. diameter is to the middle of the tube, thickness is the tube's.
+pub fn create_torus(
+    diameter: f32,
+    thickness: f32,
+    tessellation: u32,
+    options: &PrimitiveOptions,
+) -> Mesh {
+    assert!(tessellation >= 3);
+    let radius = diameter * 0.5;
+    let tube_radius = thickness * 0.5;
+
+    // Rows go around the tube, starting from the outside, columns around the y axis.
+    let mut vertices = Vec::new();
+    for i in 0..=tessellation {
+        let v = i as f32 / tessellation as f32;
+        let (y, out) = (v * 2.0 * PI).sin_cos();
+        for j in 0..=tessellation {
+            let u = j as f32 / tessellation as f32;
+            let direction = get_circle_vector(u);
+            let normal = direction * out + Vec3::new(0.0, y, 0.0);
+            vertices.push(vertex(
+                direction * radius + normal * tube_radius,
+                normal,
+                [u, 1.0 - v],
+            ));
+        }
+    }
+
+    let mut indices = Vec::new();
+    push_grid(&mut indices, 0, tessellation, tessellation);
+    finish("torus", vertices, indices, options)
+}
+
+// Flat on the xz plane facing +y, split into a grid of quads. The texture covers it once, with
+// its top towards -z.
+pub fn create_plane(
+    width: f32,
+    depth: f32,
+    x_divisions: u32,
+    z_divisions: u32,
+    options: &PrimitiveOptions,
+) -> Mesh {
+    assert!(x_divisions >= 1 && z_divisions >= 1);
+    let mut vertices = Vec::new();
+    for i in 0..=z_divisions {
+        let v = 1.0 - i as f32 / z_divisions as f32;
+        for j in 0..=x_divisions {
+            let u = j as f32 / x_divisions as f32;
+            vertices.push(vertex(
+                Vec3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth),
+                Vec3::Y,
+                [u, v],
+            ));
+        }
+    }
+
+    let mut indices = Vec::new();
+    push_grid(&mut indices, 0, z_divisions, x_divisions);
+    finish("plane", vertices, indices, options)
+}
+
+// A cylinder with a half sphere on each end, standing on the y axis. height is from end to end
+// and can't be less than the diameter.
+pub fn create_capsule(
+    height: f32,
+    diameter: f32,
+    tessellation: u32,
+    options: &PrimitiveOptions,
+) -> Mesh {
+    assert!(tessellation >= 3);
+    assert!(height >= diameter);
+    let radius = diameter * 0.5;
+    let half_length = (height - diameter) * 0.5;
+    let hemisphere_rows = tessellation.div_ceil(2);
+    let columns = tessellation * 2;
+
+    // The rings of a sphere split at the equator, with the ring there twice, once for each end.
+    // v goes down the outline evenly, so the texture isn't squashed on the straight part.
+    let arc = PI * 0.5 * radius;
+    let outline = 2.0 * arc + 2.0 * half_length;
+    let mut vertices = Vec::new();
+    for &(y_offset, first_latitude, distance) in &[
+        (-half_length, -PI * 0.5, 0.0),
+        (half_length, 0.0, arc + 2.0 * half_length),
+    ] {
+        for i in 0..=hemisphere_rows {
+            let step = i as f32 / hemisphere_rows as f32;
+            let latitude = first_latitude + step * PI * 0.5;
+            let v = 1.0 - (distance + step * arc) / outline;
+            let (y, ring_radius) = latitude.sin_cos();
+            for j in 0..=columns {
+                let u = j as f32 / columns as f32;
+                let circle = get_circle_vector(u);
+                let normal = Vec3::new(circle.x * ring_radius, y, circle.z * ring_radius);
+                vertices.push(vertex(
+                    normal * radius + Vec3::new(0.0, y_offset, 0.0),
+                    normal,
+                    [u, v],
+                ));
+            }
+        }
+    }
+
+    let mut indices = Vec::new();
+    push_grid(&mut indices, 0, hemisphere_rows * 2 + 1, columns);
+    finish("capsule", vertices, indices, options)
+}
+
+// The Utah teapot, from Bezier patches. size is the height without the lid's knob, the spout to
+// the handle is about twice that. tessellation is the segments along each side of a patch.
+pub fn create_teapot(size: f32, tessellation: u32, options: &PrimitiveOptions) -> Mesh {
+    assert!(tessellation >= 1);
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    // The patches of the original data are in z up coordinates and this turns them y up, with
+    // the lid at the height of size.
+    let to_y_up = |p: [f32; 3]| (Vec3::new(p[0], p[2] - 1.2, -p[1]) * (size / 2.4)).into();
+
+    for profile in TEAPOT_PROFILES.iter() {
+        let quarter = get_revolved_patch(profile);
+        for turn in 0..4 {
+            // Quarter turns around the z axis of the original data.
+            let rotate = |p: [f32; 3]| match turn {
+                0 => p,
+                1 => [p[1], -p[0], p[2]],
+                2 => [-p[0], -p[1], p[2]],
+                _ => [-p[1], p[0], p[2]],
+            };
+            let patch = quarter.map(|row| row.map(|p| to_y_up(rotate(p))));
+            push_patch(&mut vertices, &mut indices, &patch, tessellation, false);
+        }
+    }
+
+    // The handle and the spout are made of two patches each, which are also mirrored to get
+    // the other side.
+    for patch in TEAPOT_PATCHES.iter() {
+        for &mirror in &[false, true] {
+            let patch = patch.map(|row| {
+                row.map(|p| {
+                    let p = if mirror { [p[0], -p[1], p[2]] } else { p };
+                    to_y_up(p)
+                })
+            });
+            push_patch(&mut vertices, &mut indices, &patch, tessellation, mirror);
+        }
+    }
+
+    finish("teapot", vertices, indices, options)
+}
+
+// Outlines of the round parts of the teapot as (radius, height) points, each the control points of
+// a Bezier curve that gets turned around the z axis.
+const TEAPOT_PROFILES: [[[f32; 2]; 4]; 6] = [
+    // Rim.
+    [[1.4, 2.4], [1.3375, 2.53125], [1.4375, 2.53125], [1.5, 2.4]],
+    // Body.
+    [[1.5, 2.4], [1.75, 1.875], [2.0, 1.35], [2.0, 0.9]],
+    [[2.0, 0.9], [2.0, 0.45], [1.5, 0.225], [1.5, 0.15]],
+    // Lid.
+    [[0.0, 3.15], [0.8, 3.15], [0.0, 2.85], [0.2, 2.7]],
+    [[0.2, 2.7], [0.4, 2.55], [1.3, 2.55], [1.3, 2.4]],
+    // Bottom.
+    [[1.5, 0.15], [1.5, 0.075], [1.425, 0.0], [0.0, 0.0]],
+];
+
+// The handle and the spout, for the y <= 0 side.
+const TEAPOT_PATCHES: [[[[f32; 3]; 4]; 4]; 4] = [
+    // Handle.
+    [
+        [
+            [-1.6, 0.0, 2.025],
+            [-1.6, -0.3, 2.025],
+            [-1.5, -0.3, 2.25],
+            [-1.5, 0.0, 2.25],
+        ],
+        [
+            [-2.3, 0.0, 2.025],
+            [-2.3, -0.3, 2.025],
+            [-2.5, -0.3, 2.25],
+            [-2.5, 0.0, 2.25],
+        ],
+        [
+            [-2.7, 0.0, 2.025],
+            [-2.7, -0.3, 2.025],
+            [-3.0, -0.3, 2.25],
+            [-3.0, 0.0, 2.25],
+        ],
+        [
+            [-2.7, 0.0, 1.8],
+            [-2.7, -0.3, 1.8],
+            [-3.0, -0.3, 1.8],
+            [-3.0, 0.0, 1.8],
+        ],
+    ],
+    [
+        [
+            [-2.7, 0.0, 1.8],
+            [-2.7, -0.3, 1.8],
+            [-3.0, -0.3, 1.8],
+            [-3.0, 0.0, 1.8],
+        ],
+        [
+            [-2.7, 0.0, 1.575],
+            [-2.7, -0.3, 1.575],
+            [-3.0, -0.3, 1.35],
+            [-3.0, 0.0, 1.35],
+        ],
+        [
+            [-2.5, 0.0, 1.125],
+            [-2.5, -0.3, 1.125],
+            [-2.65, -0.3, 0.9375],
+            [-2.65, 0.0, 0.9375],
+        ],
+        [
+            [-2.0, 0.0, 0.9],
+            [-2.0, -0.3, 0.9],
+            [-1.9, -0.3, 0.6],
+            [-1.9, 0.0, 0.6],
+        ],
+    ],
+    // Spout.
+    [
+        [
+            [1.7, 0.0, 1.425],
+            [1.7, -0.66, 1.425],
+            [1.7, -0.66, 0.6],
+            [1.7, 0.0, 0.6],
+        ],
+        [
+            [2.6, 0.0, 1.425],
+            [2.6, -0.66, 1.425],
+            [3.1, -0.66, 0.825],
+            [3.1, 0.0, 0.825],
+        ],
+        [
+            [2.3, 0.0, 2.1],
+            [2.3, -0.25, 2.1],
+            [2.4, -0.25, 2.025],
+            [2.4, 0.0, 2.025],
+        ],
+        [
+            [2.7, 0.0, 2.4],
+            [2.7, -0.25, 2.4],
+            [3.3, -0.25, 2.4],
+            [3.3, 0.0, 2.4],
+        ],
+    ],
+    [
+        [
+            [2.7, 0.0, 2.4],
+            [2.7, -0.25, 2.4],
+            [3.3, -0.25, 2.4],
+            [3.3, 0.0, 2.4],
+        ],
+        [
+            [2.8, 0.0, 2.475],
+            [2.8, -0.25, 2.475],
+            [3.525, -0.25, 2.49375],
+            [3.525, 0.0, 2.49375],
+        ],
+        [
+            [2.9, 0.0, 2.475],
+            [2.9, -0.15, 2.475],
+            [3.45, -0.15, 2.5125],
+            [3.45, 0.0, 2.5125],
+        ],
+        [
+            [2.8, 0.0, 2.4],
+            [2.8, -0.15, 2.4],
+            [3.2, -0.15, 2.4],
+            [3.2, 0.0, 2.4],
+        ],
+    ],
+];
+
+// A quarter of a surface of revolution as a patch, from +x to -y, with the circle approximated
+// the way the original data does it.
+fn get_revolved_patch(profile: &[[f32; 2]; 4]) -> [[[f32; 3]; 4]; 4] {
+    const K: f32 = 0.56;
+    profile.map(|[r, z]| [[r, 0.0, z], [r, -K * r, z], [K * r, -r, z], [0.0, -r, z]])
+}
+
+// Tessellates a bicubic Bezier patch into a grid. Rows follow the first index of the control
+// points and columns the second, which has the derivatives crossing inwards on every patch of the
+// original data. Mirroring turns that around.
+fn push_patch(
+    vertices: &mut Vec<MeshVertex>,
+    indices: &mut Vec<u32>,
+    patch: &[[[f32; 3]; 4]; 4],
+    tessellation: u32,
+    mirrored: bool,
+) {
+    let base = vertices.len() as u32;
+    for i in 0..=tessellation {
+        let s = i as f32 / tessellation as f32;
+        for j in 0..=tessellation {
+            let t = j as f32 / tessellation as f32;
+            let position = evaluate_patch(patch, s, t);
+
+            // At the top of the lid a whole row of control points is in one place and the
+            // derivatives there say nothing, so the normal is taken from a little way off.
+            let mut normal = Vec3::ZERO;
+            for &nudge in &[0.0, 1e-3, -1e-3] {
+                let s = (s + nudge).clamp(0.0, 1.0);
+                let t = (t + nudge).clamp(0.0, 1.0);
+                let ds = evaluate_patch(patch, s + 1e-3, t) - evaluate_patch(patch, s, t);
+                let dt = evaluate_patch(patch, s, t + 1e-3) - evaluate_patch(patch, s, t);
+                normal = ds.cross(dt);
+                if normal.length_squared() > 1e-20 {
+                    break;
+                }
+            }
+            let normal = if mirrored { normal } else { -normal };
+            vertices.push(vertex(position, normal.normalize(), [t, s]));
+        }
+    }
+
+    let start = indices.len();
+    push_grid(indices, base, tessellation, tessellation);
+    if mirrored {
+        reverse_winding(&mut indices[start..]);
+    }
+}
+
+fn evaluate_patch(patch: &[[[f32; 3]; 4]; 4], s: f32, t: f32) -> Vec3 {
+    let rows = patch.map(|row| evaluate_bezier(row.map(Vec3::from), t));
+    evaluate_bezier(rows, s)
+}
+
+fn evaluate_bezier(points: [Vec3; 4], t: f32) -> Vec3 {
+    let u = 1.0 - t;
+    let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+    let mut result = Vec3::ZERO;
+    for (&point, &weight) in points.iter().zip(weights.iter()) {
+        result += point * weight;
+    }
+    result
+}
+
+// A disk of a cylinder or cone at height y, facing up if it's the top.
+fn push_cap(
+    vertices: &mut Vec<MeshVertex>,
+    indices: &mut Vec<u32>,
+    tessellation: u32,
+    radius: f32,
+    y: f32,
+    is_top: bool,
+) {
+    let base = vertices.len() as u32;
+    let normal = if is_top {
+        Vec3::Y
+    } else {
+        Vec3::new(0.0, -1.0, 0.0)
+    };
+    for j in 0..tessellation {
+        let direction = get_circle_vector(j as f32 / tessellation as f32);
+        // The texture's top is towards -z and it reads the right way from outside.
+        let u = if is_top { direction.x } else { -direction.x };
+        vertices.push(vertex(
+            direction * radius + Vec3::new(0.0, y, 0.0),
+            normal,
+            [u * 0.5 + 0.5, direction.z * 0.5 + 0.5],
+        ));
+    }
+
+    for j in 1..tessellation - 1 {
+        if is_top {
+            indices.extend_from_slice(&[base, base + j + 1, base + j]);
+        } else {
+            indices.extend_from_slice(&[base, base + j, base + j + 1]);
+        }
+    }
+}
+
+// Two triangles for each cell of a grid of vertices, rows of columns + 1 starting at base. Seen
+// from outside, rows should go up and columns right.
+fn push_grid(indices: &mut Vec<u32>, base: u32, rows: u32, columns: u32) {
+    let stride = columns + 1;
+    for i in 0..rows {
+        for j in 0..columns {
+            let bottom_left = base + i * stride + j;
+            let top_left = bottom_left + stride;
+            indices.extend_from_slice(&[
+                bottom_left,
+                top_left,
+                bottom_left + 1,
+                bottom_left + 1,
+                top_left,
+                top_left + 1,
+            ]);
+        }
+    }
+}
+
+fn reverse_winding(indices: &mut [u32]) {
+    for triangle in indices.chunks_exact_mut(3) {
+        triangle.swap(0, 2);
+    }
+}
+
+fn finish(
+    name: &str,
+    mut vertices: Vec<MeshVertex>,
+    mut indices: Vec<u32>,
+    options: &PrimitiveOptions,
+) -> Mesh {
+    // The shapes are built right handed. Read left handed everything is mirrored, so the winding
+    // is reversed, and so is u to keep the textures reading the right way.
+    if options.handedness == Handedness::LeftHanded {
+        reverse_winding(&mut indices);
+        for vertex in &mut vertices {
+            vertex.texcoord[0] = 1.0 - vertex.texcoord[0];
+        }
+    }
+    if options.inside_out {
+        reverse_winding(&mut indices);
+        for vertex in &mut vertices {
+            vertex.normal = vertex.normal.map(|n| -n);
+        }
+    }
+    compute_tangents(&mut vertices, &indices);
+
+    Mesh {
+        name: name.to_string(),
+        parts: vec![MeshPart {
+            material: None,
+            start_index: 0,
+            index_count: indices.len() as u32,
+        }],
+        vertices,
+        indices,
+    }
+}
+
+fn vertex(position: Vec3, normal: Vec3, texcoord: [f32; 2]) -> MeshVertex {
+    MeshVertex {
+        position: position.into(),
+        normal: normal.into(),
+        texcoord,
+        ..MeshVertex::default()
+    }
+}
+
+// On the xz plane, from +z at 0 towards +x at a quarter.
+fn get_circle_vector(turns: f32) -> Vec3 {
+    let (x, z) = (turns * 2.0 * PI).sin_cos();
+    Vec3::new(x, 0.0, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_all(options: &PrimitiveOptions) -> Vec<Mesh> {
+        vec![
+            create_cube(1.0, options),
+            create_box([1.0, 2.0, 3.0], options),
+            create_sphere(1.0, 8, options),
+            create_geosphere(1.0, 2, options),
+            create_cylinder(1.0, 1.0, 8, options),
+            create_cone(1.0, 1.0, 8, options),
+            create_torus(1.0, 0.3, 8, options),
+            create_plane(2.0, 1.0, 3, 2, options),
+            create_capsule(2.0, 1.0, 8, options),
+            create_teapot(1.0, 4, options),
+        ]
+    }
+
+    fn get_all_options() -> [PrimitiveOptions; 4] {
+        let right_handed = PrimitiveOptions::new();
+        let left_handed = right_handed.with_handedness(Handedness::LeftHanded);
+        [
+            right_handed,
+            left_handed,
+            right_handed.with_inside_out(true),
+            left_handed.with_inside_out(true),
+        ]
+    }
+
+    #[test]
+    fn indices_are_in_bounds() {
+        for options in get_all_options().iter() {
+            for mesh in create_all(options) {
+                assert_eq!(mesh.indices.len() % 3, 0, "{}", mesh.name);
+                assert_eq!(mesh.parts.len(), 1);
+                assert_eq!(mesh.parts[0].index_count as usize, mesh.indices.len());
+                assert!(
+                    mesh.indices
+                        .iter()
+                        .all(|&i| (i as usize) < mesh.vertices.len()),
+                    "{}",
+                    mesh.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn counts() {
+        let options = PrimitiveOptions::new();
+        let get_counts = |mesh: Mesh| (mesh.vertices.len(), mesh.indices.len());
+
+        assert_eq!(get_counts(create_cube(1.0, &options)), (24, 36));
+        assert_eq!(get_counts(create_box([1.0, 2.0, 3.0], &options)), (24, 36));
+        // 9 rings of 17, the seam has a vertex at both ends.
+        assert_eq!(
+            get_counts(create_sphere(1.0, 8, &options)),
+            (153, 8 * 16 * 6)
+        );
+        // The sides, then a fan for each cap.
+        assert_eq!(
+            get_counts(create_cylinder(1.0, 1.0, 8, &options)),
+            (2 * 9 + 2 * 8, 8 * 6 + 2 * 6 * 3)
+        );
+        assert_eq!(
+            get_counts(create_cone(1.0, 1.0, 8, &options)),
+            (2 * 9 + 8, 8 * 6 + 6 * 3)
+        );
+        assert_eq!(
+            get_counts(create_torus(1.0, 0.3, 8, &options)),
+            (81, 8 * 8 * 6)
+        );
+        assert_eq!(
+            get_counts(create_plane(2.0, 1.0, 3, 2, &options)),
+            (4 * 3, 3 * 2 * 6)
+        );
+        // Two hemispheres of 4 rows around 16 segments, and the straight part between them.
+        assert_eq!(
+            get_counts(create_capsule(2.0, 1.0, 8, &options)),
+            (2 * 5 * 17, 9 * 16 * 6)
+        );
+        // 32 patches.
+        assert_eq!(
+            get_counts(create_teapot(1.0, 4, &options)),
+            (32 * 25, 32 * 4 * 4 * 6)
+        );
+
+        // Each subdivision splits every triangle in four, starting from an octahedron. Vertices
+        // on the seam and at the poles are copied, so there's only a lower bound on those.
+        for tessellation in 0..3 {
+            let (vertex_count, index_count) =
+                get_counts(create_geosphere(1.0, tessellation, &options));
+            let triangle_count = 8 * 4usize.pow(tessellation);
+            assert_eq!(index_count, triangle_count * 3);
+            assert!(vertex_count >= triangle_count / 2 + 2);
+        }
+    }
+
+    // The winding as a right handed cross product, compared to the way the vertex normals point.
+    fn get_winding_signs(mesh: &Mesh) -> Vec<f32> {
+        let mut signs = Vec::new();
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] =
+                [triangle[0], triangle[1], triangle[2]].map(|i| mesh.vertices[i as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|v| Vec3::from(v.position));
+            let face_normal = (pb - pa).cross(pc - pa);
+            // Triangles collapsed onto the poles and such don't have a winding.
+            if face_normal.length() < 1e-6 {
+                continue;
+            }
+            let normal = Vec3::from(a.normal) + Vec3::from(b.normal) + Vec3::from(c.normal);
+            signs.push(face_normal.dot(normal).signum());
+        }
+        signs
+    }
+
+    #[test]
+    fn winding_matches_normals() {
+        // Clockwise from the side the normals are on, which as a right handed cross product
+        // points away from them, and the other way around when the same numbers are read left
+        // handed. Turning a shape inside out flips both the normals and the winding.
+        for options in get_all_options().iter() {
+            let expected = match options.handedness {
+                Handedness::RightHanded => -1.0,
+                Handedness::LeftHanded => 1.0,
+            };
+            for mesh in create_all(options) {
+                let signs = get_winding_signs(&mesh);
+                assert!(!signs.is_empty());
+                assert!(
+                    signs.iter().all(|&sign| sign == expected),
+                    "{} {:?}",
+                    mesh.name,
+                    options
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inside_out_normals_point_in() {
+        let outside = create_sphere(1.0, 8, &PrimitiveOptions::new());
+        let inside = create_sphere(1.0, 8, &PrimitiveOptions::new().with_inside_out(true));
+        for (a, b) in outside.vertices.iter().zip(inside.vertices.iter()) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.normal, b.normal.map(|n| -n));
+            assert!(Vec3::from(b.normal).dot(Vec3::from(b.position)) < 0.0);
+        }
+    }
+
+    #[test]
+    fn left_handed_mirrors_u() {
+        let right = create_cube(1.0, &PrimitiveOptions::new());
+        let left = create_cube(
+            1.0,
+            &PrimitiveOptions::new().with_handedness(Handedness::LeftHanded),
+        );
+        for (a, b) in right.vertices.iter().zip(left.vertices.iter()) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.texcoord, [1.0 - b.texcoord[0], b.texcoord[1]]);
+        }
+    }
+}
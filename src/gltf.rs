@@ -0,0 +1,922 @@
+// glTF 2.0, as JSON with its buffers in other files or data URIs, or as a binary .glb. Every mesh
+// becomes one Mesh with a part per primitive, the default scene's nodes become the node hierarchy.
+// Skins, morph targets, animations and cameras are left out, and so are texture coordinate sets
+// past the first.
+
+use json::Json;
+use math::Mat4;
+use mesh::{
+    compute_flat_normals, compute_tangents, invalid_data, AlphaMode, Material, Mesh, MeshVertex,
+    Model, Node, TextureSource,
+};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const GLB_MAGIC: &[u8] = b"glTF";
+
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4e4f_534a;
+const GLB_CHUNK_BIN: u32 = 0x004e_4942;
+
+// Accessor component types
+const BYTE: u32 = 5120;
+const UNSIGNED_BYTE: u32 = 5121;
+const SHORT: u32 = 5122;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+// Primitive modes
+const TRIANGLES: usize = 4;
+const TRIANGLE_STRIP: usize = 5;
+const TRIANGLE_FAN: usize = 6;
+
+// data is a .gltf or a .glb file, directory is where it was, for finding the files it refers to.
+pub fn decode(data: &[u8], directory: &Path) -> io::Result<Model> {
+    let (text, bin) = if data.starts_with(GLB_MAGIC) {
+        read_glb(data)?
+    } else {
+        (data, None)
+    };
+
+    let text = std::str::from_utf8(text).map_err(|_| invalid_data("glTF JSON isn't UTF-8"))?;
+    let root = Json::parse(text).map_err(|e| invalid_data(&format!("glTF JSON: {}", e)))?;
+
+    let version = root
+        .get("asset")
+        .and_then(|a| a.get("version"))
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid_data("glTF has no asset version"))?;
+    if !version.starts_with("2.") {
+        return Err(invalid_data("only glTF 2.0 is supported"));
+    }
+
+    let buffers = get_array(&root, "buffers")
+        .iter()
+        .enumerate()
+        .map(|(i, buffer)| read_buffer(buffer, i, bin, directory))
+        .collect::<io::Result<Vec<_>>>()?;
+    let document = Document {
+        root: &root,
+        buffers,
+    };
+
+    let mut model = Model::default();
+    for image in get_array(&root, "images") {
+        model.textures.push(document.read_image(image, directory)?);
+    }
+    for material in get_array(&root, "materials") {
+        model.materials.push(document.read_material(material)?);
+    }
+    for mesh in get_array(&root, "meshes") {
+        model
+            .meshes
+            .push(document.read_mesh(mesh, model.materials.len())?);
+    }
+    for node in get_array(&root, "nodes") {
+        model.nodes.push(read_node(node, &model)?);
+    }
+    model.roots = get_roots(&root, &model.nodes)?;
+    Ok(model)
+}
+
+// The JSON chunk and the binary chunk if there is one.
+fn read_glb(data: &[u8]) -> io::Result<(&[u8], Option<&[u8]>)> {
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid_data("glb file is truncated"))
+    };
+    if read_u32(4)? != GLB_VERSION {
+        return Err(invalid_data("only glb version 2 is supported"));
+    }
+    let length = (read_u32(8)? as usize).min(data.len());
+
+    let mut text = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset < length {
+        let chunk_length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let chunk = data
+            .get(offset + 8..offset + 8 + chunk_length)
+            .ok_or_else(|| invalid_data("glb file is truncated"))?;
+        match chunk_type {
+            GLB_CHUNK_JSON if text.is_none() => text = Some(chunk),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+        offset += 8 + chunk_length.next_multiple_of(4);
+    }
+
+    let text = text.ok_or_else(|| invalid_data("glb file has no JSON chunk"))?;
+    Ok((text, bin))
+}
+
+fn read_buffer(
+    buffer: &Json,
+    index: usize,
+    bin: Option<&[u8]>,
+    directory: &Path,
+) -> io::Result<Vec<u8>> {
+    let length = get_usize(buffer, "byteLength")?;
+    let data = match buffer.get("uri").and_then(Json::as_str) {
+        Some(uri) => read_uri(uri, directory)?,
+        // Only the first buffer of a glb can leave out the URI, it's the binary chunk.
+        None if index == 0 => bin
+            .ok_or_else(|| invalid_data("glTF buffer has no data"))?
+            .to_vec(),
+        None => return Err(invalid_data("glTF buffer has no data")),
+    };
+
+    if data.len() < length {
+        return Err(invalid_data("glTF buffer is shorter than its byteLength"));
+    }
+    Ok(data)
+}
+
+fn read_uri(uri: &str, directory: &Path) -> io::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        let (_, data) = uri
+            .split_once(";base64,")
+            .ok_or_else(|| invalid_data("only base64 data URIs are supported"))?;
+        return decode_base64(data);
+    }
+    fs::read(directory.join(decode_percent(uri)))
+}
+
+// Relative URIs can have %20 and the like in them.
+fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn decode_base64(text: &str) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return Err(invalid_data("invalid base64 in data URI")),
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            decoded.push((bits >> bit_count) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+fn get_array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).and_then(Json::as_array).unwrap_or(&[])
+}
+
+fn get_usize(json: &Json, key: &str) -> io::Result<usize> {
+    json.get(key)
+        .and_then(Json::as_usize)
+        .ok_or_else(|| invalid_data(&format!("glTF is missing {}", key)))
+}
+
+fn get_optional_usize(json: &Json, key: &str) -> io::Result<Option<usize>> {
+    match json.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_usize()
+            .map(Some)
+            .ok_or_else(|| invalid_data(&format!("glTF {} isn't an index", key))),
+    }
+}
+
+fn get_f32(json: &Json, key: &str, default: f32) -> f32 {
+    json.get(key).and_then(Json::as_f32).unwrap_or(default)
+}
+
+fn get_floats<const N: usize>(json: &Json, key: &str, default: [f32; N]) -> io::Result<[f32; N]> {
+    let Some(values) = json.get(key) else {
+        return Ok(default);
+    };
+    let values = values
+        .as_array()
+        .filter(|v| v.len() == N)
+        .ok_or_else(|| invalid_data(&format!("glTF {} has the wrong length", key)))?;
+    let mut result = default;
+    for (r, v) in result.iter_mut().zip(values) {
+        *r = v
+            .as_f32()
+            .ok_or_else(|| invalid_data(&format!("glTF {} isn't numbers", key)))?;
+    }
+    Ok(result)
+}
+
+struct Document<'a> {
+    root: &'a Json,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<'a> Document<'a> {
+    fn get(&self, kind: &str, index: usize) -> io::Result<&'a Json> {
+        get_array(self.root, kind)
+            .get(index)
+            .ok_or_else(|| invalid_data(&format!("glTF refers to a missing item in {}", kind)))
+    }
+
+    // The bytes of a buffer view and its stride, which is None if the elements are packed.
+    fn get_buffer_view(&self, index: usize) -> io::Result<(&[u8], Option<usize>)> {
+        let view = self.get("bufferViews", index)?;
+        let buffer = self
+            .buffers
+            .get(get_usize(view, "buffer")?)
+            .ok_or_else(|| invalid_data("glTF buffer view refers to a missing buffer"))?;
+        let offset = get_optional_usize(view, "byteOffset")?.unwrap_or(0);
+        let length = get_usize(view, "byteLength")?;
+        let data = buffer
+            .get(offset..offset + length)
+            .ok_or_else(|| invalid_data("glTF buffer view is out of range"))?;
+        Ok((data, get_optional_usize(view, "byteStride")?))
+    }
+
+    fn read_image(&self, image: &Json, directory: &Path) -> io::Result<TextureSource> {
+        if let Some(uri) = image.get("uri").and_then(Json::as_str) {
+            return if uri.starts_with("data:") {
+                read_uri(uri, directory).map(TextureSource::Data)
+            } else {
+                Ok(TextureSource::File(directory.join(decode_percent(uri))))
+            };
+        }
+
+        let view = get_usize(image, "bufferView")?;
+        let (data, _) = self.get_buffer_view(view)?;
+        Ok(TextureSource::Data(data.to_vec()))
+    }
+
+    // Material textures go through a texture to an image, Model::textures has the images.
+    fn get_texture(&self, json: &Json, key: &str) -> io::Result<Option<usize>> {
+        let Some(info) = json.get(key) else {
+            return Ok(None);
+        };
+        let texture = self.get("textures", get_usize(info, "index")?)?;
+        let image = get_optional_usize(texture, "source")?;
+        if image.is_some_and(|i| i >= get_array(self.root, "images").len()) {
+            return Err(invalid_data("glTF texture refers to a missing image"));
+        }
+        Ok(image)
+    }
+
+    fn read_material(&self, json: &Json) -> io::Result<Material> {
+        let mut material = Material {
+            name: json
+                .get("name")
+                .and_then(Json::as_str)
+                .unwrap_or("")
+                .to_string(),
+            normal_texture: self.get_texture(json, "normalTexture")?,
+            normal_scale: json
+                .get("normalTexture")
+                .map_or(1.0, |t| get_f32(t, "scale", 1.0)),
+            emissive: get_floats(json, "emissiveFactor", [0.0; 3])?,
+            emissive_texture: self.get_texture(json, "emissiveTexture")?,
+            double_sided: json
+                .get("doubleSided")
+                .and_then(Json::as_bool)
+                .unwrap_or(false),
+            ..Material::default()
+        };
+
+        if let Some(pbr) = json.get("pbrMetallicRoughness") {
+            material.base_color = get_floats(pbr, "baseColorFactor", [1.0; 4])?;
+            material.base_color_texture = self.get_texture(pbr, "baseColorTexture")?;
+            material.metallic = get_f32(pbr, "metallicFactor", 1.0);
+            material.roughness = get_f32(pbr, "roughnessFactor", 1.0);
+            material.metallic_roughness_texture =
+                self.get_texture(pbr, "metallicRoughnessTexture")?;
+        }
+
+        material.alpha_mode = match json.get("alphaMode").and_then(Json::as_str) {
+            None | Some("OPAQUE") => AlphaMode::Opaque,
+            Some("MASK") => AlphaMode::Mask(get_f32(json, "alphaCutoff", 0.5)),
+            Some("BLEND") => AlphaMode::Blend,
+            Some(_) => return Err(invalid_data("glTF material has an unknown alphaMode")),
+        };
+        Ok(material)
+    }
+
+    fn read_mesh(&self, json: &Json, material_count: usize) -> io::Result<Mesh> {
+        let mut mesh = Mesh {
+            name: json
+                .get("name")
+                .and_then(Json::as_str)
+                .unwrap_or("")
+                .to_string(),
+            ..Mesh::default()
+        };
+
+        for primitive in get_array(json, "primitives") {
+            let material = get_optional_usize(primitive, "material")?;
+            if material.is_some_and(|m| m >= material_count) {
+                return Err(invalid_data("glTF primitive refers to a missing material"));
+            }
+            let (vertices, indices) = self.read_primitive(primitive)?;
+            mesh.append(&vertices, &indices, material);
+        }
+
+        if mesh.parts.is_empty() {
+            return Err(invalid_data("glTF mesh has no primitives"));
+        }
+        Ok(mesh)
+    }
+
+    fn read_primitive(&self, primitive: &Json) -> io::Result<(Vec<MeshVertex>, Vec<u32>)> {
+        let attributes = primitive
+            .get("attributes")
+            .ok_or_else(|| invalid_data("glTF primitive has no attributes"))?;
+        let attribute = |name: &str| get_optional_usize(attributes, name);
+
+        let positions = self.read_accessor::<3>(
+            attribute("POSITION")?
+                .ok_or_else(|| invalid_data("glTF primitive has no positions"))?,
+        )?;
+        let mut vertices = vec![MeshVertex::default(); positions.len()];
+        for (vertex, position) in vertices.iter_mut().zip(positions) {
+            vertex.position = position;
+        }
+
+        let normals = match attribute("NORMAL")? {
+            Some(accessor) => Some(self.read_accessor::<3>(accessor)?),
+            None => None,
+        };
+        if let Some(ref normals) = normals {
+            fill(&mut vertices, normals, |v, n| v.normal = n)?;
+        }
+        let tangents = match attribute("TANGENT")? {
+            Some(accessor) => Some(self.read_accessor::<4>(accessor)?),
+            None => None,
+        };
+        if let Some(ref tangents) = tangents {
+            fill(&mut vertices, tangents, |v, t| v.tangent = t)?;
+        }
+        if let Some(accessor) = attribute("TEXCOORD_0")? {
+            let texcoords = self.read_accessor::<2>(accessor)?;
+            fill(&mut vertices, &texcoords, |v, t| v.texcoord = t)?;
+        }
+        if let Some(accessor) = attribute("COLOR_0")? {
+            // RGB or RGBA, missing alpha reads as 1.
+            let colors = self.read_accessor_with_default(accessor, [1.0; 4])?;
+            fill(&mut vertices, &colors, |v, c| v.color = c)?;
+        }
+
+        let indices = match get_optional_usize(primitive, "indices")? {
+            Some(accessor) => self.read_indices(accessor)?,
+            None => (0..vertices.len() as u32).collect(),
+        };
+        if indices.iter().any(|&i| i as usize >= vertices.len()) {
+            return Err(invalid_data("glTF index is out of range"));
+        }
+        let mut indices = match get_optional_usize(primitive, "mode")?.unwrap_or(TRIANGLES) {
+            TRIANGLES => indices,
+            TRIANGLE_STRIP => get_strip_triangles(&indices),
+            TRIANGLE_FAN => get_fan_triangles(&indices),
+            _ => return Err(invalid_data("only glTF triangle primitives are supported")),
+        };
+        indices.truncate(indices.len() / 3 * 3);
+
+        // The spec says missing normals are flat, and missing tangents are up to us.
+        if normals.is_none() {
+            let (flat_vertices, flat_indices) = compute_flat_normals(&vertices, &indices);
+            vertices = flat_vertices;
+            indices = flat_indices;
+        }
+        if tangents.is_none() {
+            compute_tangents(&mut vertices, &indices);
+        }
+        Ok((vertices, indices))
+    }
+
+    fn read_accessor<const N: usize>(&self, index: usize) -> io::Result<Vec<[f32; N]>> {
+        self.read_accessor_with_default(index, [0.0; N])
+    }
+
+    // Every element as floats, normalized integers scaled to 0 to 1 or -1 to 1. Elements with
+    // fewer than N components get the rest from default, ones with more are an error.
+    fn read_accessor_with_default<const N: usize>(
+        &self,
+        index: usize,
+        default: [f32; N],
+    ) -> io::Result<Vec<[f32; N]>> {
+        let accessor = self.get("accessors", index)?;
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(invalid_data("unsupported glTF accessor type")),
+        };
+        if components > N {
+            return Err(invalid_data("glTF accessor has too many components"));
+        }
+        let format = ElementFormat {
+            component_type: get_usize(accessor, "componentType")? as u32,
+            components,
+            normalized: accessor
+                .get("normalized")
+                .and_then(Json::as_bool)
+                .unwrap_or(false),
+        };
+        self.read_elements(accessor, &format, default, |data, offset, element| {
+            format.read(data, offset, element)
+        })
+    }
+
+    // Indices stay integers all the way, floats would lose the ones past 2^24.
+    fn read_indices(&self, index: usize) -> io::Result<Vec<u32>> {
+        let accessor = self.get("accessors", index)?;
+        if accessor.get("type").and_then(Json::as_str) != Some("SCALAR") {
+            return Err(invalid_data("glTF indices have to be scalars"));
+        }
+        let format = ElementFormat {
+            component_type: get_usize(accessor, "componentType")? as u32,
+            components: 1,
+            normalized: false,
+        };
+        self.read_elements(accessor, &format, 0, |data, offset, element| {
+            *element = format.read_index(data, offset)?;
+            Ok(())
+        })
+    }
+
+    // What the accessors have in common: the elements from the buffer view, or default without
+    // one, with the sparse ones replaced after. read reads one element at an offset.
+    fn read_elements<T, F>(
+        &self,
+        accessor: &Json,
+        format: &ElementFormat,
+        default: T,
+        read: F,
+    ) -> io::Result<Vec<T>>
+    where
+        T: Clone,
+        F: Fn(&[u8], usize, &mut T) -> io::Result<()>,
+    {
+        let count = get_usize(accessor, "count")?;
+        let size = format.get_size()?;
+
+        let mut elements = match get_optional_usize(accessor, "bufferView")? {
+            Some(view) => {
+                let offset = get_optional_usize(accessor, "byteOffset")?.unwrap_or(0);
+                let (data, stride) = self.get_buffer_view(view)?;
+                let data = data
+                    .get(offset..)
+                    .ok_or_else(|| invalid_data("glTF accessor is out of range"))?;
+                let stride = stride.unwrap_or(size);
+                // Checked before allocating, so a made up count can't ask for all the memory.
+                let end = match count.checked_sub(1) {
+                    Some(last) => last.checked_mul(stride).and_then(|o| o.checked_add(size)),
+                    None => Some(0),
+                };
+                if end.is_none_or(|end| end > data.len()) {
+                    return Err(invalid_data("glTF accessor is out of range"));
+                }
+
+                let mut elements = vec![default; count];
+                for (i, element) in elements.iter_mut().enumerate() {
+                    read(data, i * stride, element)?;
+                }
+                elements
+            }
+            None => vec![default; count],
+        };
+
+        // Sparse accessors replace some of the elements, or give all of them if there's no
+        // buffer view.
+        if let Some(sparse) = accessor.get("sparse") {
+            let sparse_count = get_usize(sparse, "count")?;
+            let indices = sparse
+                .get("indices")
+                .ok_or_else(|| invalid_data("glTF sparse accessor has no indices"))?;
+            let values = sparse
+                .get("values")
+                .ok_or_else(|| invalid_data("glTF sparse accessor has no values"))?;
+
+            let index_format = ElementFormat {
+                component_type: get_usize(indices, "componentType")? as u32,
+                components: 1,
+                normalized: false,
+            };
+            let index_size = index_format.get_size()?;
+            let (index_data, _) = self.get_buffer_view(get_usize(indices, "bufferView")?)?;
+            let index_offset = get_optional_usize(indices, "byteOffset")?.unwrap_or(0);
+            let (value_data, _) = self.get_buffer_view(get_usize(values, "bufferView")?)?;
+            let value_offset = get_optional_usize(values, "byteOffset")?.unwrap_or(0);
+            if sparse_count > count
+                || sparse_count > index_data.len() / index_size
+                || sparse_count > value_data.len() / size
+            {
+                return Err(invalid_data("glTF sparse accessor is out of range"));
+            }
+
+            for i in 0..sparse_count {
+                let target = index_format.read_index(index_data, index_offset + i * index_size)?;
+                let element = elements
+                    .get_mut(target as usize)
+                    .ok_or_else(|| invalid_data("glTF sparse index is out of range"))?;
+                read(value_data, value_offset + i * size, element)?;
+            }
+        }
+
+        Ok(elements)
+    }
+}
+
+fn fill<T: Copy, F: Fn(&mut MeshVertex, T)>(
+    vertices: &mut [MeshVertex],
+    values: &[T],
+    set: F,
+) -> io::Result<()> {
+    if values.len() != vertices.len() {
+        return Err(invalid_data(
+            "glTF primitive attributes have different counts",
+        ));
+    }
+    for (vertex, &value) in vertices.iter_mut().zip(values) {
+        set(vertex, value);
+    }
+    Ok(())
+}
+
+struct ElementFormat {
+    component_type: u32,
+    components: usize,
+    normalized: bool,
+}
+
+impl ElementFormat {
+    fn get_component_size(&self) -> io::Result<usize> {
+        match self.component_type {
+            BYTE | UNSIGNED_BYTE => Ok(1),
+            SHORT | UNSIGNED_SHORT => Ok(2),
+            UNSIGNED_INT | FLOAT => Ok(4),
+            _ => Err(invalid_data("unsupported glTF component type")),
+        }
+    }
+
+    fn get_size(&self) -> io::Result<usize> {
+        Ok(self.get_component_size()? * self.components)
+    }
+
+    // An unsigned integer element, which is all indices can be.
+    fn read_index(&self, data: &[u8], offset: usize) -> io::Result<u32> {
+        let size = self.get_component_size()?;
+        let b = offset
+            .checked_add(size)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(|| invalid_data("glTF accessor is out of range"))?;
+        match self.component_type {
+            UNSIGNED_BYTE => Ok(b[0] as u32),
+            UNSIGNED_SHORT => Ok(u16::from_le_bytes([b[0], b[1]]) as u32),
+            UNSIGNED_INT => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            _ => Err(invalid_data("glTF indices have to be unsigned integers")),
+        }
+    }
+
+    // Reads the element at offset into the first components of element.
+    fn read(&self, data: &[u8], offset: usize, element: &mut [f32]) -> io::Result<()> {
+        let size = self.get_component_size()?;
+        let bytes = offset
+            .checked_add(size * self.components)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(|| invalid_data("glTF accessor is out of range"))?;
+
+        for (value, b) in element.iter_mut().zip(bytes.chunks_exact(size)) {
+            *value = match self.component_type {
+                BYTE => {
+                    let v = b[0] as i8 as f32;
+                    if self.normalized {
+                        (v / 127.0).max(-1.0)
+                    } else {
+                        v
+                    }
+                }
+                UNSIGNED_BYTE => {
+                    let v = b[0] as f32;
+                    if self.normalized {
+                        v / 255.0
+                    } else {
+                        v
+                    }
+                }
+                SHORT => {
+                    let v = i16::from_le_bytes([b[0], b[1]]) as f32;
+                    if self.normalized {
+                        (v / 32767.0).max(-1.0)
+                    } else {
+                        v
+                    }
+                }
+                UNSIGNED_SHORT => {
+                    let v = u16::from_le_bytes([b[0], b[1]]) as f32;
+                    if self.normalized {
+                        v / 65535.0
+                    } else {
+                        v
+                    }
+                }
+                UNSIGNED_INT => {
+                    let v = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                    if self.normalized {
+                        (v as f64 / u32::MAX as f64) as f32
+                    } else {
+                        v as f32
+                    }
+                }
+                _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            };
+        }
+        Ok(())
+    }
+}
+
+// Every other triangle of a strip is flipped so they all wind the same way.
+fn get_strip_triangles(indices: &[u32]) -> Vec<u32> {
+    let mut triangles = Vec::new();
+    for (i, window) in indices.windows(3).enumerate() {
+        if i % 2 == 0 {
+            triangles.extend_from_slice(window);
+        } else {
+            triangles.extend_from_slice(&[window[1], window[0], window[2]]);
+        }
+    }
+    triangles
+}
+
+fn get_fan_triangles(indices: &[u32]) -> Vec<u32> {
+    let mut triangles = Vec::new();
+    for window in indices.get(1..).unwrap_or(&[]).windows(2) {
+        triangles.extend_from_slice(&[indices[0], window[0], window[1]]);
+    }
+    triangles
+}
+
+fn read_node(json: &Json, model: &Model) -> io::Result<Node> {
+    let mesh = get_optional_usize(json, "mesh")?;
+    if mesh.is_some_and(|m| m >= model.meshes.len()) {
+        return Err(invalid_data("glTF node refers to a missing mesh"));
+    }
+    let children = get_array(json, "children")
+        .iter()
+        .map(|c| {
+            c.as_usize()
+                .ok_or_else(|| invalid_data("glTF node child isn't an index"))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(Node {
+        name: json
+            .get("name")
+            .and_then(Json::as_str)
+            .unwrap_or("")
+            .to_string(),
+        transform: get_node_transform(json)?,
+        mesh,
+        children,
+    })
+}
+
+fn get_node_transform(json: &Json) -> io::Result<[[f32; 4]; 4]> {
+    // glTF matrices are column major with column vectors, which is the same sixteen numbers as
+    // row major with row vectors.
+    if json.get("matrix").is_some() {
+        let m = get_floats(json, "matrix", [0.0; 16])?;
+        let mut transform = Mat4::IDENTITY.rows;
+        for (i, row) in transform.iter_mut().enumerate() {
+            row.copy_from_slice(&m[i * 4..i * 4 + 4]);
+        }
+        return Ok(transform);
+    }
+
+    let [tx, ty, tz] = get_floats(json, "translation", [0.0; 3])?;
+    let [x, y, z, w] = get_floats(json, "rotation", [0.0, 0.0, 0.0, 1.0])?;
+    let [sx, sy, sz] = get_floats(json, "scale", [1.0; 3])?;
+
+    // Scale, then rotate, then translate.
+    Ok([
+        [
+            (1.0 - 2.0 * (y * y + z * z)) * sx,
+            2.0 * (x * y + z * w) * sx,
+            2.0 * (x * z - y * w) * sx,
+            0.0,
+        ],
+        [
+            2.0 * (x * y - z * w) * sy,
+            (1.0 - 2.0 * (x * x + z * z)) * sy,
+            2.0 * (y * z + x * w) * sy,
+            0.0,
+        ],
+        [
+            2.0 * (x * z + y * w) * sz,
+            2.0 * (y * z - x * w) * sz,
+            (1.0 - 2.0 * (x * x + y * y)) * sz,
+            0.0,
+        ],
+        [tx, ty, tz, 1.0],
+    ])
+}
+
+// The nodes of the default scene, or of the first one. Without scenes every node that isn't a
+// child is a root. Also makes sure the nodes form trees.
+fn get_roots(root: &Json, nodes: &[Node]) -> io::Result<Vec<usize>> {
+    let mut parents = vec![None; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        for &child in &node.children {
+            let parent = parents
+                .get_mut(child)
+                .ok_or_else(|| invalid_data("glTF node refers to a missing child"))?;
+            if parent.is_some() {
+                return Err(invalid_data("glTF node has more than one parent"));
+            }
+            *parent = Some(i);
+        }
+    }
+    for start in 0..nodes.len() {
+        let mut node = start;
+        for _ in 0..nodes.len() {
+            match parents[node] {
+                Some(parent) => node = parent,
+                None => break,
+            }
+        }
+        if parents[node].is_some() {
+            return Err(invalid_data("glTF nodes form a cycle"));
+        }
+    }
+
+    let scenes = get_array(root, "scenes");
+    if scenes.is_empty() {
+        return Ok((0..nodes.len()).filter(|&i| parents[i].is_none()).collect());
+    }
+    let scene = get_optional_usize(root, "scene")?.unwrap_or(0);
+    let scene = scenes
+        .get(scene)
+        .ok_or_else(|| invalid_data("glTF default scene is missing"))?;
+    get_array(scene, "nodes")
+        .iter()
+        .map(|n| match n.as_usize() {
+            Some(n) if n < nodes.len() && parents[n].is_none() => Ok(n),
+            _ => Err(invalid_data("glTF scene has an invalid root node")),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mesh::MeshPart;
+    use std::path::PathBuf;
+
+    fn get_fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    fn decode_fixture(name: &str) -> io::Result<Model> {
+        let directory = get_fixtures();
+        decode(&fs::read(directory.join(name))?, &directory)
+    }
+
+    fn get_positions(mesh: &Mesh) -> Vec<[f32; 3]> {
+        mesh.vertices.iter().map(|v| v.position).collect()
+    }
+
+    #[test]
+    fn indexed_primitive() {
+        let model = decode_fixture("quad.gltf").unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.name, "quad");
+        assert_eq!(
+            get_positions(mesh),
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0]
+            ]
+        );
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, -1.0]));
+        assert_eq!(mesh.vertices[0].texcoord, [0.0, 1.0]);
+        assert_eq!(mesh.vertices[2].texcoord, [1.0, 0.0]);
+        assert_eq!(mesh.indices, [0, 2, 1, 0, 3, 2]);
+        assert_eq!(
+            mesh.parts,
+            [MeshPart {
+                material: Some(0),
+                start_index: 0,
+                index_count: 6,
+            }]
+        );
+
+        let material = &model.materials[0];
+        assert_eq!(material.name, "leaf");
+        assert!(material.double_sided);
+        assert_eq!(material.base_color, [0.5, 1.0, 0.5, 1.0]);
+        assert_eq!(material.metallic, 0.0);
+
+        assert_eq!(model.roots, [0]);
+        assert_eq!(model.nodes[0].mesh, Some(0));
+        assert_eq!(model.nodes[0].transform[3], [1.0, 2.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn glb_matches_gltf() {
+        // The same quad with 32-bit indices in the binary chunk.
+        let glb = decode_fixture("quad.glb").unwrap();
+        let gltf = decode_fixture("quad.gltf").unwrap();
+        assert_eq!(glb, gltf);
+    }
+
+    #[test]
+    fn primitive_without_indices_or_normals() {
+        let model = decode_fixture("triangle.gltf").unwrap();
+        let mesh = &model.meshes[0];
+        assert_eq!(
+            get_positions(mesh),
+            [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]
+        );
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        // Flat, along cross(b - a, c - a).
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, -1.0]));
+        assert_eq!(mesh.parts[0].material, None);
+        assert_eq!(model.roots, [0]);
+    }
+
+    #[test]
+    fn sparse_accessors() {
+        let model = decode_fixture("sparse.gltf").unwrap();
+        let mesh = &model.meshes[0];
+        // Vertices 1 and 2 are replaced.
+        assert_eq!(
+            get_positions(mesh),
+            [[0.0, 0.0, 0.0], [5.0, 5.0, 5.0], [6.0, 6.0, 6.0]]
+        );
+        // Without a buffer view everything but the sparse element is zero.
+        let texcoords: Vec<[f32; 2]> = mesh.vertices.iter().map(|v| v.texcoord).collect();
+        assert_eq!(texcoords, [[0.0, 0.0], [0.0, 0.0], [1.0, 1.0]]);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn accessor_past_its_buffer_view() {
+        let directory = get_fixtures();
+        let text = fs::read_to_string(directory.join("quad.gltf")).unwrap();
+        let text = text.replace("\"count\": 6", "\"count\": 1000000000000");
+        assert!(decode(text.as_bytes(), &directory).is_err());
+    }
+
+    #[test]
+    fn indices_stay_exact() {
+        // 2^24 + 1 is the first integer an f32 can't hold.
+        let format = ElementFormat {
+            component_type: UNSIGNED_INT,
+            components: 1,
+            normalized: false,
+        };
+        let data = 16_777_217u32.to_le_bytes();
+        assert_eq!(format.read_index(&data, 0).unwrap(), 16_777_217);
+
+        let float = ElementFormat {
+            component_type: FLOAT,
+            ..format
+        };
+        assert!(float.read_index(&data, 0).is_err());
+    }
+
+    #[test]
+    fn strips_and_fans() {
+        assert_eq!(get_strip_triangles(&[0, 1, 2, 3]), [0, 1, 2, 2, 1, 3]);
+        assert_eq!(get_fan_triangles(&[0, 1, 2, 3]), [0, 1, 2, 0, 2, 3]);
+    }
+}
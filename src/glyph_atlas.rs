@@ -0,0 +1,331 @@
+// Packs glyph bitmaps into texture pages as they're needed and turns coverage into signed
+// distance fields. Doesn't know where the glyphs come from, see TrueTypeFont for that.
+
+use font::{FontPage, Glyph};
+use sprite_batch::SpriteRect;
+
+// Empty pixels around every glyph, so filtering doesn't pick up its neighbours.
+const PADDING: u32 = 1;
+
+// DXGI_FORMAT_R8G8B8A8_UNORM, like font.rs the atlas only knows formats as numbers.
+const PAGE_FORMAT: u32 = 28;
+
+// A rasterized glyph with one byte of coverage per pixel, and where it goes relative to the pen
+// like in font::Glyph.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GlyphBitmap {
+    pub width: u32,
+    pub height: u32,
+    pub coverage: Vec<u8>,
+    pub offset: [f32; 2],
+    pub advance: f32,
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    width_used: u32,
+}
+
+// Places rectangles in rows from the top down. Simple, and good enough for glyphs as they're all
+// about the same height.
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u32, height: u32) -> ShelfPacker {
+        ShelfPacker {
+            width,
+            height,
+            shelves: Vec::new(),
+        }
+    }
+
+    // The top left of where a rectangle of the given size goes, None if there's no room left.
+    pub fn insert(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        // The row that wastes the least height.
+        let page_width = self.width;
+        let best = self
+            .shelves
+            .iter_mut()
+            .filter(|s| s.height >= height && page_width - s.width_used >= width)
+            .min_by_key(|s| s.height - height);
+        if let Some(shelf) = best {
+            let x = shelf.width_used;
+            shelf.width_used += width;
+            return Some([x, shelf.y]);
+        }
+
+        let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+        if width > self.width || y + height > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            width_used: width,
+        });
+        Some([0, y])
+    }
+}
+
+struct AtlasPage {
+    // Premultiplied white, so coverage is in every channel.
+    pixels: Vec<u8>,
+    packer: ShelfPacker,
+}
+
+pub struct GlyphAtlas {
+    page_size: u32,
+    distance_field_spread: Option<u32>,
+    pages: Vec<AtlasPage>,
+}
+
+impl GlyphAtlas {
+    // Pages are square. With a spread, glyphs are stored as distance fields reaching that many
+    // pixels out from their edges.
+    pub fn new(page_size: u32, distance_field_spread: Option<u32>) -> GlyphAtlas {
+        GlyphAtlas {
+            page_size,
+            distance_field_spread: distance_field_spread.map(|s| s.max(1)),
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn get_page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn get_page(&self, index: usize) -> FontPage {
+        FontPage::Image {
+            width: self.page_size,
+            height: self.page_size,
+            format: PAGE_FORMAT,
+            pitch: self.page_size * 4,
+            data: self.pages[index].pixels.clone(),
+        }
+    }
+
+    // Copies the glyph into a page, starting a new one when the last is full. None if the glyph
+    // doesn't fit in a page at all.
+    pub fn insert(&mut self, bitmap: &GlyphBitmap) -> Option<Glyph> {
+        // Spaces and the like only move the pen.
+        if bitmap.width == 0 || bitmap.height == 0 {
+            return Some(Glyph {
+                page: 0,
+                source: SpriteRect::new(0.0, 0.0, 0.0, 0.0),
+                offset: bitmap.offset,
+                advance: bitmap.advance,
+            });
+        }
+
+        let (width, height, pixels, offset) = match self.distance_field_spread {
+            Some(spread) => (
+                bitmap.width + spread * 2,
+                bitmap.height + spread * 2,
+                get_distance_field(&bitmap.coverage, bitmap.width, bitmap.height, spread),
+                [
+                    bitmap.offset[0] - spread as f32,
+                    bitmap.offset[1] - spread as f32,
+                ],
+            ),
+            None => (
+                bitmap.width,
+                bitmap.height,
+                bitmap.coverage.clone(),
+                bitmap.offset,
+            ),
+        };
+
+        let padded_width = width + PADDING * 2;
+        let padded_height = height + PADDING * 2;
+        let mut position = self
+            .pages
+            .last_mut()
+            .and_then(|p| p.packer.insert(padded_width, padded_height));
+        if position.is_none() {
+            let mut packer = ShelfPacker::new(self.page_size, self.page_size);
+            position = Some(packer.insert(padded_width, padded_height)?);
+            self.pages.push(AtlasPage {
+                pixels: vec![0; (self.page_size * self.page_size * 4) as usize],
+                packer,
+            });
+        }
+
+        let [x, y] = position?;
+        let (x, y) = (x + PADDING, y + PADDING);
+        let page_index = self.pages.len() - 1;
+        let page = &mut self.pages[page_index];
+        for row in 0..height {
+            for column in 0..width {
+                let value = pixels[(row * width + column) as usize];
+                let index = (((y + row) * self.page_size + x + column) * 4) as usize;
+                page.pixels[index..index + 4].copy_from_slice(&[value; 4]);
+            }
+        }
+
+        Some(Glyph {
+            page: page_index,
+            source: SpriteRect::new(x as f32, y as f32, width as f32, height as f32),
+            offset,
+            advance: bitmap.advance,
+        })
+    }
+}
+
+// A signed distance field of the coverage with spread pixels of room on every side. 0.5 is the
+// edge, going up to 1 inside the glyph and down to 0 outside, spread pixels away from it.
+pub fn get_distance_field(coverage: &[u8], width: u32, height: u32, spread: u32) -> Vec<u8> {
+    assert!(
+        spread > 0,
+        "a distance field needs a spread of at least a pixel"
+    );
+
+    let is_inside = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && x < width as i64
+            && y < height as i64
+            && coverage[(y * width as i64 + x) as usize] >= 128
+    };
+
+    let spread = spread as i64;
+    let field_width = width as i64 + spread * 2;
+    let field_height = height as i64 + spread * 2;
+    let mut field = Vec::with_capacity((field_width * field_height) as usize);
+    for y in -spread..height as i64 + spread {
+        for x in -spread..width as i64 + spread {
+            // The closest pixel on the other side of the edge, as far as spread reaches.
+            let inside = is_inside(x, y);
+            let mut closest = spread as f32 + 0.5;
+            for dy in -spread..=spread {
+                for dx in -spread..=spread {
+                    if is_inside(x + dx, y + dy) != inside {
+                        closest = closest.min(((dx * dx + dy * dy) as f32).sqrt());
+                    }
+                }
+            }
+
+            // The edge is halfway between the two pixels.
+            let distance = if inside { closest - 0.5 } else { 0.5 - closest };
+            let value = 0.5 + distance / (spread as f32 * 2.0);
+            field.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(size: u32) -> GlyphBitmap {
+        GlyphBitmap {
+            width: size,
+            height: size,
+            coverage: vec![255; (size * size) as usize],
+            offset: [0.0; 2],
+            advance: size as f32,
+        }
+    }
+
+    #[test]
+    fn shelves() {
+        let mut packer = ShelfPacker::new(16, 16);
+        assert_eq!(packer.insert(8, 4), Some([0, 0]));
+        assert_eq!(packer.insert(8, 8), Some([0, 4]));
+        // Back on the first shelf, it's the one that wastes the least.
+        assert_eq!(packer.insert(6, 3), Some([8, 0]));
+        assert_eq!(packer.insert(8, 8), Some([8, 4]));
+        assert_eq!(packer.insert(16, 4), Some([0, 12]));
+        // Full, apart from 2 pixels at the end of the first shelf.
+        assert_eq!(packer.insert(3, 1), None);
+        assert_eq!(packer.insert(2, 1), Some([14, 0]));
+    }
+
+    #[test]
+    fn too_large_for_the_packer() {
+        let mut packer = ShelfPacker::new(16, 16);
+        assert_eq!(packer.insert(17, 1), None);
+        assert_eq!(packer.insert(1, 17), None);
+        assert_eq!(packer.insert(16, 16), Some([0, 0]));
+    }
+
+    #[test]
+    fn full_page_starts_a_new_one() {
+        // Padded to 8 by 8, so four to a page.
+        let mut atlas = GlyphAtlas::new(16, None);
+        for i in 0..4 {
+            let glyph = atlas.insert(&square(6)).unwrap();
+            assert_eq!(glyph.page, 0);
+            let expected = [(i % 2) * 8 + 1, (i / 2) * 8 + 1];
+            assert_eq!([glyph.source.x, glyph.source.y], expected.map(|v| v as f32));
+        }
+        assert_eq!(atlas.get_page_count(), 1);
+
+        let glyph = atlas.insert(&square(6)).unwrap();
+        assert_eq!(glyph.page, 1);
+        assert_eq!([glyph.source.x, glyph.source.y], [1.0, 1.0]);
+        assert_eq!(atlas.get_page_count(), 2);
+
+        // The coverage is in every channel, inside the padding.
+        let FontPage::Image { data, .. } = atlas.get_page(1) else {
+            panic!("not an image page");
+        };
+        assert_eq!(&data[..4], &[0; 4]);
+        assert_eq!(&data[(16 + 1) * 4..(16 + 2) * 4], &[255; 4]);
+    }
+
+    #[test]
+    fn too_large_for_a_page() {
+        let mut atlas = GlyphAtlas::new(16, None);
+        assert!(atlas.insert(&square(6)).is_some());
+        // 17 by 17 with the padding.
+        assert!(atlas.insert(&square(15)).is_none());
+        assert_eq!(atlas.get_page_count(), 1);
+        // The spread counts towards the size too.
+        let mut atlas = GlyphAtlas::new(16, Some(2));
+        assert!(atlas.insert(&square(11)).is_none());
+        assert!(atlas.insert(&square(10)).is_some());
+    }
+
+    #[test]
+    fn empty_glyphs_take_no_room() {
+        let mut atlas = GlyphAtlas::new(16, None);
+        let glyph = atlas.insert(&square(0)).unwrap();
+        assert_eq!(glyph.source.width, 0.0);
+        assert_eq!(atlas.get_page_count(), 0);
+    }
+
+    #[test]
+    fn distance_field() {
+        // A 6 by 6 square with 2 pixels around it.
+        let spread = 2;
+        let field = get_distance_field(&[255; 36], 6, 6, spread);
+        let field_width = 6 + spread * 2;
+        assert_eq!(field.len(), (field_width * field_width) as usize);
+        let get = |x: u32, y: u32| field[(y * field_width + x) as usize];
+
+        // Either side of the left edge, half a pixel from it. 0.5 is right between them.
+        let inside = get(2, 5);
+        let outside = get(1, 5);
+        assert_eq!(inside, 159);
+        assert_eq!(outside, 96);
+        assert_eq!(inside as u32 + outside as u32, 255);
+
+        // Further in and out it goes up and down, until the spread runs out.
+        assert!(get(3, 5) > inside);
+        assert!(get(0, 5) < outside);
+        assert_eq!(get(5, 5), 255);
+        assert_eq!(get(0, 0), 0);
+    }
+
+    #[test]
+    fn distance_field_of_nothing() {
+        let field = get_distance_field(&[0; 4], 2, 2, 1);
+        assert!(field.iter().all(|&value| value == 0));
+    }
+}
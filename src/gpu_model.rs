@@ -0,0 +1,131 @@
+use image::Image;
+use mesh::{Mesh, MeshPart, MeshVertex, Model, TextureSource};
+use texture::{create_texture, load_texture, SrgbMode, Texture, TextureOptions};
+use vertex_buffer::{BufferUsage, IndexBuffer, VertexBuffer};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{ID3D11Device, ID3D11DeviceContext};
+use winapi::um::d3dcommon::D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST;
+
+impl_vertex! {
+    MeshVertex {
+        position: [f32; 3] => "POSITION",
+        normal: [f32; 3] => "NORMAL",
+        tangent: [f32; 4] => "TANGENT",
+        texcoord: [f32; 2] => "TEXCOORD",
+        color: [f32; 4] => "COLOR",
+    }
+}
+
+// A Mesh in vertex and index buffers. Indices are 16-bit when there are few enough vertices.
+pub struct GpuMesh {
+    vertex_buffer: VertexBuffer<MeshVertex>,
+    index_buffer: IndexBuffer,
+    parts: Vec<MeshPart>,
+}
+
+impl GpuMesh {
+    pub unsafe fn new(device: &ID3D11Device, mesh: &Mesh) -> Result<GpuMesh, HRESULT> {
+        let vertex_buffer = VertexBuffer::new(device, &mesh.vertices, BufferUsage::Immutable)?;
+        let index_buffer = if mesh.vertices.len() <= u16::MAX as usize + 1 {
+            let indices: Vec<u16> = mesh.indices.iter().map(|&i| i as u16).collect();
+            IndexBuffer::new(device, &indices, BufferUsage::Immutable)?
+        } else {
+            IndexBuffer::new(device, &mesh.indices, BufferUsage::Immutable)?
+        };
+
+        Ok(GpuMesh {
+            vertex_buffer,
+            index_buffer,
+            parts: mesh.parts.clone(),
+        })
+    }
+
+    // Sets the buffers and the topology, the input layout and shaders are up to the caller.
+    pub unsafe fn bind(&self, context: &ID3D11DeviceContext) {
+        self.vertex_buffer.bind(context, 0);
+        self.index_buffer.bind(context);
+        context.IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+    }
+
+    // Has to come after bind.
+    pub unsafe fn draw_part(&self, context: &ID3D11DeviceContext, part: usize) {
+        let part = &self.parts[part];
+        context.DrawIndexed(part.index_count, part.start_index, 0);
+    }
+
+    pub fn get_parts(&self) -> &[MeshPart] {
+        &self.parts
+    }
+}
+
+// The meshes and textures of a Model on the GPU, in the same order as in the model, so the
+// model's materials and nodes can be used with it as they are.
+//
+// Holds device objects, so it has to be created again after the device is lost. Keep the Model
+// around to do that without reading the files again.
+pub struct GpuModel {
+    pub meshes: Vec<GpuMesh>,
+    // None for textures no material uses.
+    pub textures: Vec<Option<Texture>>,
+}
+
+impl GpuModel {
+    // Textures get mips generated when they don't have them. Base color and emissive textures
+    // are sRGB, the others linear, whatever their files say.
+    pub unsafe fn new(
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        model: &Model,
+    ) -> Result<GpuModel, String> {
+        let meshes = model
+            .meshes
+            .iter()
+            .map(|mesh| {
+                GpuMesh::new(device, mesh).map_err(|hr| {
+                    format!(
+                        "Failed to create buffers for mesh {:?}, HRESULT {:x}",
+                        mesh.name, hr
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut srgb_modes = vec![None; model.textures.len()];
+        for material in &model.materials {
+            let uses = [
+                (material.base_color_texture, SrgbMode::Force),
+                (material.emissive_texture, SrgbMode::Force),
+                (material.metallic_roughness_texture, SrgbMode::Ignore),
+                (material.normal_texture, SrgbMode::Ignore),
+            ];
+            for &(texture, srgb) in &uses {
+                if let Some(texture) = texture {
+                    srgb_modes[texture] = Some(srgb);
+                }
+            }
+        }
+
+        let mut textures = Vec::with_capacity(model.textures.len());
+        for (source, srgb) in model.textures.iter().zip(srgb_modes) {
+            let Some(srgb) = srgb else {
+                textures.push(None);
+                continue;
+            };
+
+            let options = TextureOptions::new()
+                .with_generate_mips(true)
+                .with_srgb(srgb);
+            let texture = match *source {
+                TextureSource::File(ref path) => load_texture(device, context, path, &options)?,
+                TextureSource::Data(ref data) => {
+                    let image = Image::from_bytes(data)
+                        .map_err(|e| format!("Failed to load an embedded texture: {}", e))?;
+                    create_texture(device, context, &image, &options)?
+                }
+            };
+            textures.push(Some(texture));
+        }
+
+        Ok(GpuModel { meshes, textures })
+    }
+}
@@ -0,0 +1,303 @@
+// HLSL constant buffer packing, so we can tell whether a Rust struct has the same layout as the
+// cbuffer it gets copied into. Doesn't touch D3D at all.
+//
+// The rules: constants are packed into 16-byte registers, a scalar or vector can't straddle two
+// registers, and arrays, matrices and structs always start on a new register. Every array
+// element starts on a register too, so an array of floats has a 16-byte stride even though a
+// Rust array of f32 doesn't.
+//
+// A float4x4 takes four whole registers whether it's row or column major, which is exactly how
+// a float4[4] packs, so [[f32; 4]; 4] stands in for both. Smaller matrices would need padding
+// inside them and aren't supported.
+
+pub const REGISTER_SIZE: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HlslType {
+    // float, int or uint.
+    Scalar,
+    // float2 to float4 and friends.
+    Vector(usize),
+    // An array of scalars or vectors with the given component count.
+    Array { components: usize, count: usize },
+}
+
+impl HlslType {
+    pub fn get_size(&self) -> usize {
+        match *self {
+            HlslType::Scalar => 4,
+            HlslType::Vector(components) => components * 4,
+            // HLSL doesn't have these, check_layout rejects them.
+            HlslType::Array { count: 0, .. } => 0,
+            HlslType::Array { components, count } => (count - 1) * REGISTER_SIZE + components * 4,
+        }
+    }
+
+    fn starts_register(&self) -> bool {
+        match *self {
+            HlslType::Scalar | HlslType::Vector(_) => false,
+            HlslType::Array { .. } => true,
+        }
+    }
+}
+
+// Rust types that have an HLSL counterpart with the same layout. Arrays of float4 are the only
+// arrays that qualify, anything smaller would need padding after every element.
+pub trait HlslField {
+    fn get_hlsl_type() -> HlslType;
+}
+
+macro_rules! hlsl_fields {
+    ($($scalar:ty),*) => {
+        $(
+            impl HlslField for $scalar {
+                fn get_hlsl_type() -> HlslType {
+                    HlslType::Scalar
+                }
+            }
+
+            impl HlslField for [$scalar; 2] {
+                fn get_hlsl_type() -> HlslType {
+                    HlslType::Vector(2)
+                }
+            }
+
+            impl HlslField for [$scalar; 3] {
+                fn get_hlsl_type() -> HlslType {
+                    HlslType::Vector(3)
+                }
+            }
+
+            impl HlslField for [$scalar; 4] {
+                fn get_hlsl_type() -> HlslType {
+                    HlslType::Vector(4)
+                }
+            }
+
+            impl<const N: usize> HlslField for [[$scalar; 4]; N] {
+                fn get_hlsl_type() -> HlslType {
+                    HlslType::Array {
+                        components: 4,
+                        count: N,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+// HLSL bools are 4 bytes, so use u32 for those.
+hlsl_fields!(f32, i32, u32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FieldLayout {
+    pub name: &'static str,
+    pub offset: usize,
+    pub hlsl_type: HlslType,
+}
+
+// Implemented by hlsl_struct!, which also makes sure the field list is right. A hand written
+// field list that's out of sync with the struct makes check_layout meaningless.
+pub trait HlslLayout: Copy {
+    fn get_fields() -> Vec<FieldLayout>;
+}
+
+// Where HLSL puts each field of a cbuffer with these types, in declaration order.
+pub fn get_hlsl_offsets(types: &[HlslType]) -> Vec<usize> {
+    let mut offset = 0;
+    let mut offsets = Vec::with_capacity(types.len());
+    for hlsl_type in types {
+        let size = hlsl_type.get_size();
+        let space_left = REGISTER_SIZE - offset % REGISTER_SIZE;
+        if hlsl_type.starts_register() || size > space_left {
+            offset = round_up_to_register(offset);
+        }
+
+        offsets.push(offset);
+        offset += size;
+    }
+    offsets
+}
+
+// The size of the cbuffer, which D3D wants in whole registers.
+pub fn get_buffer_size<T: HlslLayout>() -> usize {
+    round_up_to_register(std::mem::size_of::<T>())
+}
+
+fn round_up_to_register(offset: usize) -> usize {
+    offset.div_ceil(REGISTER_SIZE) * REGISTER_SIZE
+}
+
+// Compares T against the layout HLSL would give a cbuffer with the same fields. The error says
+// which field is off and where it should be, usually a padding field fixes it.
+pub fn check_layout<T: HlslLayout>() -> Result<(), String> {
+    let fields = T::get_fields();
+    let types: Vec<HlslType> = fields.iter().map(|f| f.hlsl_type).collect();
+    let offsets = get_hlsl_offsets(&types);
+
+    for (field, offset) in fields.iter().zip(offsets) {
+        if let HlslType::Array { count: 0, .. } = field.hlsl_type {
+            return Err(format!(
+                "{}.{} is an empty array, which HLSL doesn't have",
+                std::any::type_name::<T>(),
+                field.name
+            ));
+        }
+        if field.offset != offset {
+            return Err(format!(
+                "{}.{} is at offset {} but HLSL puts it at {}",
+                std::any::type_name::<T>(),
+                field.name,
+                field.offset,
+                offset
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Declares a #[repr(C)] struct that can be checked against HLSL packing. Padding fields count
+// too, so declare them like any other field, e.g. `_pad: f32`.
+//
+// Only the D3D modules have constant buffers, so elsewhere it's just the tests using it.
+#[cfg_attr(not(windows), allow(unused_macros))]
+macro_rules! hlsl_struct {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $field_type:ty,)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        $vis struct $name {
+            $($field_vis $field: $field_type,)*
+        }
+
+        impl $crate::hlsl_layout::HlslLayout for $name {
+            fn get_fields() -> Vec<$crate::hlsl_layout::FieldLayout> {
+                vec![
+                    $($crate::hlsl_layout::FieldLayout {
+                        name: stringify!($field),
+                        offset: std::mem::offset_of!($name, $field),
+                        hlsl_type:
+                            <$field_type as $crate::hlsl_layout::HlslField>::get_hlsl_type(),
+                    },)*
+                ]
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    hlsl_struct! {
+        struct Packed {
+            color: [f32; 3],
+            // Fits in the rest of the register.
+            alpha: f32,
+            scale: [f32; 2],
+            offset: [f32; 2],
+        }
+    }
+
+    hlsl_struct! {
+        struct Straddling {
+            scale: [f32; 2],
+            // Would straddle two registers, so HLSL moves it to the next one.
+            position: [f32; 3],
+        }
+    }
+
+    hlsl_struct! {
+        struct Padded {
+            scale: [f32; 2],
+            _pad: [f32; 2],
+            position: [f32; 3],
+            alpha: f32,
+        }
+    }
+
+    hlsl_struct! {
+        struct AfterArray {
+            lights: [[f32; 4]; 2],
+            intensity: f32,
+            _pad: [f32; 3],
+            transform: [[f32; 4]; 4],
+        }
+    }
+
+    hlsl_struct! {
+        struct Empty {
+            value: f32,
+            _pad: [f32; 3],
+            nothing: [[f32; 4]; 0],
+            more: [f32; 4],
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(HlslType::Scalar.get_size(), 4);
+        assert_eq!(HlslType::Vector(3).get_size(), 12);
+        let array = HlslType::Array {
+            components: 2,
+            count: 3,
+        };
+        assert_eq!(array.get_size(), 40);
+        let empty = HlslType::Array {
+            components: 4,
+            count: 0,
+        };
+        assert_eq!(empty.get_size(), 0);
+    }
+
+    #[test]
+    fn offsets() {
+        let types = [
+            HlslType::Scalar,
+            HlslType::Vector(3),
+            HlslType::Vector(2),
+            HlslType::Scalar,
+            HlslType::Array {
+                components: 1,
+                count: 2,
+            },
+            HlslType::Scalar,
+            HlslType::Vector(4),
+        ];
+        assert_eq!(get_hlsl_offsets(&types), vec![0, 4, 16, 24, 32, 52, 64]);
+    }
+
+    #[test]
+    fn packed() {
+        assert_eq!(check_layout::<Packed>(), Ok(()));
+        assert_eq!(get_buffer_size::<Packed>(), 32);
+    }
+
+    #[test]
+    fn straddling() {
+        let error = check_layout::<Straddling>().unwrap_err();
+        assert!(error.contains(".position is at offset 8 but HLSL puts it at 16"));
+        assert_eq!(check_layout::<Padded>(), Ok(()));
+    }
+
+    #[test]
+    fn arrays_and_matrices() {
+        assert_eq!(check_layout::<AfterArray>(), Ok(()));
+        let fields = AfterArray::get_fields();
+        assert_eq!(fields[1].offset, 32);
+        assert_eq!(fields[3].offset, 48);
+        assert_eq!(get_buffer_size::<AfterArray>(), 112);
+    }
+
+    #[test]
+    fn empty_arrays() {
+        let error = check_layout::<Empty>().unwrap_err();
+        assert!(error.contains(".nothing is an empty array"));
+    }
+}
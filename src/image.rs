@@ -0,0 +1,347 @@
+// Decoded images: the texels of every array slice and mip level plus what they are, ready to go
+// into a texture. Doesn't know anything about D3D beyond naming formats, see texture.rs for that.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use winapi::shared::dxgiformat::*;
+
+use bmp;
+use dds;
+use png;
+use tga;
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    R8Unorm,
+    A8Unorm,
+    R8G8Unorm,
+    R8G8B8A8Unorm,
+    B8G8R8A8Unorm,
+    B8G8R8X8Unorm,
+    B5G6R5Unorm,
+    B5G5R5A1Unorm,
+    B4G4R4A4Unorm,
+    R10G10B10A2Unorm,
+    R16Unorm,
+    R16G16Unorm,
+    R16G16B16A16Unorm,
+    R16Float,
+    R16G16Float,
+    R16G16B16A16Float,
+    R32Float,
+    R32G32Float,
+    R32G32B32A32Float,
+    R11G11B10Float,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6hUf16,
+    Bc6hSf16,
+    Bc7,
+}
+
+struct FormatInfo {
+    format: ImageFormat,
+    dxgi_format: DXGI_FORMAT,
+    // The same format with sRGB encoded color, if there is one.
+    dxgi_srgb_format: Option<DXGI_FORMAT>,
+    // Per pixel, or per 4x4 block for compressed formats.
+    bytes: u32,
+    is_compressed: bool,
+}
+
+macro_rules! formats {
+    ($($format:ident => $dxgi:ident, $srgb:expr, $bytes:expr, $compressed:expr;)*) => {
+        const FORMATS: &[FormatInfo] = &[
+            $(FormatInfo {
+                format: ImageFormat::$format,
+                dxgi_format: $dxgi,
+                dxgi_srgb_format: $srgb,
+                bytes: $bytes,
+                is_compressed: $compressed,
+            },)*
+        ];
+    };
+}
+
+formats! {
+    R8Unorm => DXGI_FORMAT_R8_UNORM, None, 1, false;
+    A8Unorm => DXGI_FORMAT_A8_UNORM, None, 1, false;
+    R8G8Unorm => DXGI_FORMAT_R8G8_UNORM, None, 2, false;
+    R8G8B8A8Unorm => DXGI_FORMAT_R8G8B8A8_UNORM, Some(DXGI_FORMAT_R8G8B8A8_UNORM_SRGB), 4, false;
+    B8G8R8A8Unorm => DXGI_FORMAT_B8G8R8A8_UNORM, Some(DXGI_FORMAT_B8G8R8A8_UNORM_SRGB), 4, false;
+    B8G8R8X8Unorm => DXGI_FORMAT_B8G8R8X8_UNORM, Some(DXGI_FORMAT_B8G8R8X8_UNORM_SRGB), 4, false;
+    B5G6R5Unorm => DXGI_FORMAT_B5G6R5_UNORM, None, 2, false;
+    B5G5R5A1Unorm => DXGI_FORMAT_B5G5R5A1_UNORM, None, 2, false;
+    B4G4R4A4Unorm => DXGI_FORMAT_B4G4R4A4_UNORM, None, 2, false;
+    R10G10B10A2Unorm => DXGI_FORMAT_R10G10B10A2_UNORM, None, 4, false;
+    R16Unorm => DXGI_FORMAT_R16_UNORM, None, 2, false;
+    R16G16Unorm => DXGI_FORMAT_R16G16_UNORM, None, 4, false;
+    R16G16B16A16Unorm => DXGI_FORMAT_R16G16B16A16_UNORM, None, 8, false;
+    R16Float => DXGI_FORMAT_R16_FLOAT, None, 2, false;
+    R16G16Float => DXGI_FORMAT_R16G16_FLOAT, None, 4, false;
+    R16G16B16A16Float => DXGI_FORMAT_R16G16B16A16_FLOAT, None, 8, false;
+    R32Float => DXGI_FORMAT_R32_FLOAT, None, 4, false;
+    R32G32Float => DXGI_FORMAT_R32G32_FLOAT, None, 8, false;
+    R32G32B32A32Float => DXGI_FORMAT_R32G32B32A32_FLOAT, None, 16, false;
+    R11G11B10Float => DXGI_FORMAT_R11G11B10_FLOAT, None, 4, false;
+    Bc1 => DXGI_FORMAT_BC1_UNORM, Some(DXGI_FORMAT_BC1_UNORM_SRGB), 8, true;
+    Bc2 => DXGI_FORMAT_BC2_UNORM, Some(DXGI_FORMAT_BC2_UNORM_SRGB), 16, true;
+    Bc3 => DXGI_FORMAT_BC3_UNORM, Some(DXGI_FORMAT_BC3_UNORM_SRGB), 16, true;
+    Bc4 => DXGI_FORMAT_BC4_UNORM, None, 8, true;
+    Bc5 => DXGI_FORMAT_BC5_UNORM, None, 16, true;
+    Bc6hUf16 => DXGI_FORMAT_BC6H_UF16, None, 16, true;
+    Bc6hSf16 => DXGI_FORMAT_BC6H_SF16, None, 16, true;
+    Bc7 => DXGI_FORMAT_BC7_UNORM, Some(DXGI_FORMAT_BC7_UNORM_SRGB), 16, true;
+}
+
+impl ImageFormat {
+    fn get_info(self) -> &'static FormatInfo {
+        FORMATS.iter().find(|f| f.format == self).unwrap()
+    }
+
+    // The format and whether it's the sRGB variant.
+    pub fn from_dxgi_format(dxgi_format: DXGI_FORMAT) -> Option<(ImageFormat, bool)> {
+        FORMATS.iter().find_map(|f| {
+            if f.dxgi_format == dxgi_format {
+                Some((f.format, false))
+            } else if f.dxgi_srgb_format == Some(dxgi_format) {
+                Some((f.format, true))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Falls back to the plain format if there's no sRGB one.
+    pub fn get_dxgi_format(self, srgb: bool) -> DXGI_FORMAT {
+        let info = self.get_info();
+        match info.dxgi_srgb_format {
+            Some(format) if srgb => format,
+            _ => info.dxgi_format,
+        }
+    }
+
+    pub fn has_srgb(self) -> bool {
+        self.get_info().dxgi_srgb_format.is_some()
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self.get_info().is_compressed
+    }
+
+    // The bytes in a row of pixels, or of blocks, and the bytes in the whole surface. None if the
+    // row doesn't fit in the u32 D3D wants or the surface doesn't fit in memory.
+    pub fn get_surface_size(self, width: u32, height: u32) -> Option<(u32, usize)> {
+        let info = self.get_info();
+        let (columns, rows) = if info.is_compressed {
+            (width.div_ceil(4).max(1), height.div_ceil(4).max(1))
+        } else {
+            (width, height)
+        };
+        let row_pitch = columns.checked_mul(info.bytes)?;
+        Some((row_pitch, (row_pitch as usize).checked_mul(rows as usize)?))
+    }
+}
+
+// A full mip chain goes down to 1x1, halving the larger side each time.
+pub fn get_max_mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// Where one mip level of one array slice is in Image::data.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Subresource {
+    pub width: u32,
+    pub height: u32,
+    pub offset: usize,
+    pub row_pitch: u32,
+    pub size: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    // Six per cube for cube maps, in the order +x, -x, +y, -y, +z, -z.
+    pub array_size: u32,
+    pub mip_levels: u32,
+    pub format: ImageFormat,
+    // Whether the color is sRGB encoded, as far as the file says.
+    pub srgb: bool,
+    pub is_cube_map: bool,
+    // Every subresource tightly packed, all mips of the first slice first.
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    // A single RGBA image with 8 bits per channel.
+    pub fn new_rgba8(width: u32, height: u32, srgb: bool, data: Vec<u8>) -> Image {
+        assert_eq!(data.len(), width as usize * height as usize * 4);
+        Image {
+            width,
+            height,
+            array_size: 1,
+            mip_levels: 1,
+            format: ImageFormat::R8G8B8A8Unorm,
+            srgb,
+            is_cube_map: false,
+            data,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        Image::from_bytes(&fs::read(path)?)
+    }
+
+    // Tells the formats apart by their first bytes. TGA has nothing to tell it by, so that's what
+    // anything else is taken to be.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Image> {
+        if data.starts_with(dds::MAGIC) {
+            dds::decode(data)
+        } else if data.starts_with(png::SIGNATURE) {
+            png::decode(data)
+        } else if data.starts_with(bmp::MAGIC) {
+            bmp::decode(data)
+        } else {
+            tga::decode(data)
+        }
+    }
+
+    // The mips of a single array slice, with offsets from the start of the slice, and the size of
+    // the slice. Fails if there are more mips than the size allows or they don't fit in memory.
+    fn get_slice_mips(&self) -> io::Result<(Vec<Subresource>, usize)> {
+        if self.mip_levels > get_max_mip_levels(self.width, self.height) {
+            return Err(invalid_data("image has more mips than its size allows"));
+        }
+
+        let mut mips = Vec::with_capacity(self.mip_levels as usize);
+        let mut offset: usize = 0;
+        for mip in 0..self.mip_levels {
+            let width = (self.width >> mip).max(1);
+            let height = (self.height >> mip).max(1);
+            let (row_pitch, size) = self
+                .format
+                .get_surface_size(width, height)
+                .ok_or_else(too_large)?;
+            mips.push(Subresource {
+                width,
+                height,
+                offset,
+                row_pitch,
+                size,
+            });
+            offset = offset.checked_add(size).ok_or_else(too_large)?;
+        }
+        Ok((mips, offset))
+    }
+
+    // Every subresource in the order D3D numbers them. Check get_data_size against the data
+    // first, the array size could be anything.
+    pub fn get_subresources(&self) -> io::Result<Vec<Subresource>> {
+        self.get_data_size()?;
+        let (mips, slice_size) = self.get_slice_mips()?;
+
+        let mut subresources = Vec::with_capacity(self.array_size as usize * mips.len());
+        for slice in 0..self.array_size as usize {
+            for mip in mips.iter() {
+                subresources.push(Subresource {
+                    offset: slice * slice_size + mip.offset,
+                    ..*mip
+                });
+            }
+        }
+        Ok(subresources)
+    }
+
+    // What data should hold, for checking decoders.
+    pub fn get_data_size(&self) -> io::Result<usize> {
+        let (_, slice_size) = self.get_slice_mips()?;
+        slice_size
+            .checked_mul(self.array_size as usize)
+            .ok_or_else(too_large)
+    }
+}
+
+fn too_large() -> io::Error {
+    invalid_data("image is too large")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_image(width: u32, height: u32, array_size: u32, mip_levels: u32) -> Image {
+        Image {
+            width,
+            height,
+            array_size,
+            mip_levels,
+            format: ImageFormat::R8G8B8A8Unorm,
+            srgb: false,
+            is_cube_map: false,
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn max_mip_levels() {
+        assert_eq!(get_max_mip_levels(1, 1), 1);
+        assert_eq!(get_max_mip_levels(256, 256), 9);
+        assert_eq!(get_max_mip_levels(255, 3), 8);
+        assert_eq!(get_max_mip_levels(1, 1024), 11);
+        assert_eq!(get_max_mip_levels(u32::MAX, 1), 32);
+    }
+
+    #[test]
+    fn surface_size() {
+        let rgba = ImageFormat::R8G8B8A8Unorm;
+        assert_eq!(rgba.get_surface_size(3, 2), Some((12, 24)));
+        assert_eq!(rgba.get_surface_size(u32::MAX / 2, 1), None);
+        // Blocks, with at least one even for the smallest mips.
+        assert_eq!(ImageFormat::Bc1.get_surface_size(5, 1), Some((16, 16)));
+        assert_eq!(ImageFormat::Bc3.get_surface_size(8, 8), Some((32, 64)));
+    }
+
+    #[test]
+    fn subresources() {
+        let subresources = make_image(4, 2, 2, 3).get_subresources().unwrap();
+        let sizes: Vec<(u32, u32, usize)> = subresources
+            .iter()
+            .map(|s| (s.width, s.height, s.offset))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (4, 2, 0),
+                (2, 1, 32),
+                (1, 1, 40),
+                (4, 2, 44),
+                (2, 1, 76),
+                (1, 1, 84)
+            ]
+        );
+        assert_eq!(make_image(4, 2, 2, 3).get_data_size().unwrap(), 88);
+    }
+
+    #[test]
+    fn too_many_mips() {
+        assert!(make_image(4, 2, 1, 4).get_subresources().is_err());
+        assert!(make_image(1, 1, 1, 40).get_data_size().is_err());
+    }
+
+    #[test]
+    fn too_large() {
+        assert!(make_image(0x4000_0000, 1, 1, 1).get_subresources().is_err());
+        assert!(make_image(0x10000, 0x10000, u32::MAX, 1)
+            .get_subresources()
+            .is_err());
+    }
+}
@@ -0,0 +1,337 @@
+// Decompresses zlib streams (RFC 1950 wrapping RFC 1951 deflate data), which is what PNG stores
+// its pixels in. Decodes a bit at a time like zlib's puff, slow but small.
+
+use std::io;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// The order code length code lengths come in, in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// The Adler-32 at the end isn't checked, PNG has its own checks.
+pub fn zlib_decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 2 {
+        return Err(invalid_data("zlib stream is truncated"));
+    }
+
+    let (method, flags) = (data[0], data[1]);
+    if method & 0xf != 8 || (u16::from(method) << 8 | u16::from(flags)) % 31 != 0 {
+        return Err(invalid_data("not a deflate zlib stream"));
+    }
+    if flags & 0x20 != 0 {
+        return Err(invalid_data("zlib preset dictionaries aren't supported"));
+    }
+    inflate(&data[2..])
+}
+
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader {
+        data,
+        position: 0,
+        bits: 0,
+        bit_count: 0,
+    };
+    let mut output = Vec::new();
+
+    loop {
+        let is_last = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => read_stored_block(&mut reader, &mut output)?,
+            1 => {
+                let (lengths, distances) = get_fixed_codes();
+                read_compressed_block(&mut reader, &mut output, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = read_dynamic_codes(&mut reader)?;
+                read_compressed_block(&mut reader, &mut output, &lengths, &distances)?;
+            }
+            _ => return Err(invalid_data("invalid deflate block type")),
+        }
+
+        if is_last {
+            return Ok(output);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    // Least significant bit first, as deflate packs them.
+    fn read_bits(&mut self, count: u32) -> io::Result<u32> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| invalid_data("deflate data is truncated"))?;
+            self.position += 1;
+            self.bits |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = self.bits & ((1 << count) - 1);
+        self.bits >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn read_aligned_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        self.bits = 0;
+        self.bit_count = 0;
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or_else(|| invalid_data("deflate data is truncated"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+}
+
+// A canonical Huffman code as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths.iter() {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        // Codes of each length are consecutive, starting at first.
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid deflate code"))
+    }
+}
+
+fn read_stored_block(reader: &mut BitReader, output: &mut Vec<u8>) -> io::Result<()> {
+    let header = reader.read_aligned_bytes(4)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if length != !complement {
+        return Err(invalid_data("deflate stored block length is corrupted"));
+    }
+
+    output.extend_from_slice(reader.read_aligned_bytes(length as usize)?);
+    Ok(())
+}
+
+fn get_fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let length_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.read_bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    // Literal/length and distance code lengths come as one run, repeats can span both.
+    let mut lengths = Vec::with_capacity(length_count + distance_count);
+    while lengths.len() < length_count + distance_count {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid_data("deflate repeats a length before the first"))?;
+                (previous, 3 + reader.read_bits(2)?)
+            }
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > length_count + distance_count {
+        return Err(invalid_data("deflate code lengths overrun"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..length_count]),
+        Huffman::new(&lengths[length_count..]),
+    ))
+}
+
+fn read_compressed_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASES.len() {
+            return Err(invalid_data("invalid deflate length"));
+        }
+        let length = LENGTH_BASES[index] as usize
+            + reader.read_bits(LENGTH_EXTRA_BITS[index] as u32)? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        if index >= DISTANCE_BASES.len() {
+            return Err(invalid_data("invalid deflate distance"));
+        }
+        let distance = DISTANCE_BASES[index] as usize
+            + reader.read_bits(DISTANCE_EXTRA_BITS[index] as u32)? as usize;
+        if distance > output.len() {
+            return Err(invalid_data("deflate distance is too far back"));
+        }
+
+        // The copy can overlap what it's writing, so go a byte at a time.
+        let start = output.len() - distance;
+        for i in 0..length {
+            let byte = output[start + i];
+            output.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Made with Python's zlib.compress, at level 0 for the stored block and 9 for the others.
+    const STORED: [u8; 17] = [
+        120, 1, 1, 6, 0, 249, 255, 115, 116, 111, 114, 101, 100, 9, 60, 2, 146,
+    ];
+    const FIXED: [u8; 19] = [
+        120, 218, 75, 76, 74, 78, 132, 33, 133, 180, 204, 138, 212, 20, 0, 64, 110, 6, 201,
+    ];
+    // Random letters without repeats worth referencing, so zlib builds its own codes.
+    const DYNAMIC: [u8; 40] = [
+        120, 218, 69, 137, 129, 9, 0, 48, 12, 194, 110, 53, 249, 255, 135, 205, 118, 48, 17, 140,
+        68, 33, 9, 153, 41, 252, 92, 150, 182, 142, 231, 150, 197, 153, 94, 14, 125, 201, 25, 232,
+    ];
+    const DYNAMIC_TEXT: &[u8] =
+        b"ccbbaaababbaaaaabaaaaaaaaaaabaacbacbabbaabaabaaaabbaabacbcaabacabbab";
+
+    fn get_block_type(stream: &[u8]) -> u8 {
+        (stream[2] >> 1) & 3
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(get_block_type(&STORED), 0);
+        assert_eq!(zlib_decompress(&STORED).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(get_block_type(&FIXED), 1);
+        assert_eq!(zlib_decompress(&FIXED).unwrap(), b"abcabcabcabc fixed");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(get_block_type(&DYNAMIC), 2);
+        assert_eq!(zlib_decompress(&DYNAMIC).unwrap(), DYNAMIC_TEXT);
+    }
+
+    #[test]
+    fn corrupted_stored_length() {
+        let mut stream = STORED;
+        stream[5] = 0;
+        assert!(zlib_decompress(&stream).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        // Anything short of the end of the last block, the checksum after it isn't needed.
+        for stream in [&STORED[..], &FIXED[..], &DYNAMIC[..]].iter() {
+            for length in 0..stream.len() - 4 {
+                assert!(
+                    zlib_decompress(&stream[..length]).is_err(),
+                    "{} of {} bytes",
+                    length,
+                    stream.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn not_zlib() {
+        assert!(zlib_decompress(&[0x78, 0x02, 0x03, 0x00]).is_err());
+        assert!(zlib_decompress(&[0x79, 0x9c, 0x03, 0x00]).is_err());
+    }
+}
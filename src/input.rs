@@ -0,0 +1,121 @@
+use actions::ActionMap;
+use gamepad::{GamePad, GamePadButtonStateTracker, GamePadEvent, GamePadState, MAX_PLAYER_COUNT};
+use keyboard::{Keyboard, KeyboardState, KeyboardStateTracker};
+use mouse::{Mouse, MouseButtonStateTracker, MouseState};
+use text_input::{TextInput, TextInputEvent};
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::WM_ACTIVATEAPP;
+
+// Everything an update tick gets to see of the devices. Feeding the same frames back in
+// reproduces the same input, which is what recordings are made of.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct InputFrame {
+    pub keyboard: KeyboardState,
+    pub mouse: MouseState,
+    pub gamepads: [GamePadState; MAX_PLAYER_COUNT],
+    pub gamepad_events: Vec<GamePadEvent>,
+    pub text_events: Vec<TextInputEvent>,
+}
+
+// Everything the window procedure tells us about input, plus the per-update view of it.
+// The devices are fed from messages at any time, the trackers only change in update.
+pub struct Input {
+    pub keyboard: Keyboard,
+    pub keys: KeyboardStateTracker,
+    pub mouse: Mouse,
+    pub mouse_buttons: MouseButtonStateTracker,
+    pub gamepad: GamePad,
+    pub gamepad_buttons: [GamePadButtonStateTracker; MAX_PLAYER_COUNT],
+    // Pads that were plugged in or out right before this update.
+    pub gamepad_events: Vec<GamePadEvent>,
+    pub text: TextInput,
+    // Characters and IME composition changes since the last update, while text input is on.
+    pub text_events: Vec<TextInputEvent>,
+    // Named bindings on top of all of the above.
+    pub actions: ActionMap,
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Input {
+            keyboard: Keyboard::new(),
+            keys: KeyboardStateTracker::new(),
+            mouse: Mouse::new(),
+            mouse_buttons: MouseButtonStateTracker::new(),
+            gamepad: GamePad::new(),
+            gamepad_buttons: Default::default(),
+            gamepad_events: Vec::new(),
+            text: TextInput::new(),
+            text_events: Vec::new(),
+            actions: ActionMap::new(),
+        }
+    }
+
+    pub unsafe fn set_window(&mut self, window: HWND) {
+        self.mouse.set_window(window);
+        self.text.set_window(window);
+    }
+
+    pub unsafe fn process_message(&mut self, message: UINT, w_param: WPARAM, l_param: LPARAM) {
+        self.keyboard.process_message(message, w_param, l_param);
+        self.mouse.process_message(message, w_param, l_param);
+
+        // Nobody wants a controller that keeps rumbling while they're in another window.
+        if message == WM_ACTIVATEAPP && w_param == 0 {
+            self.gamepad.stop_all_vibration();
+        }
+    }
+
+    // Text input has its own entry point since some of its messages must not reach
+    // DefWindowProc. Returns the result for those.
+    pub unsafe fn process_text_message(
+        &mut self,
+        message: UINT,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> Option<LRESULT> {
+        self.text.process_message(message, w_param, l_param)
+    }
+
+    // Samples the devices. Call once per update tick, before the game logic runs.
+    pub fn update(&mut self) {
+        let frame = self.sample();
+        self.apply(&frame);
+    }
+
+    // Takes what the devices saw since the last call, without touching the trackers.
+    pub fn sample(&mut self) -> InputFrame {
+        self.gamepad.poll();
+
+        let mut gamepads = [GamePadState::default(); MAX_PLAYER_COUNT];
+        for (player, state) in gamepads.iter_mut().enumerate() {
+            *state = self.gamepad.get_state(player);
+        }
+
+        InputFrame {
+            keyboard: self.keyboard.take_state(),
+            mouse: self.mouse.take_state(),
+            gamepads,
+            gamepad_events: self.gamepad.take_events(),
+            text_events: self.text.take_events(),
+        }
+    }
+
+    // Updates the trackers and actions from a frame, either just sampled or from a recording.
+    pub fn apply(&mut self, frame: &InputFrame) {
+        self.keys.update(&frame.keyboard);
+        self.mouse_buttons.update(&frame.mouse);
+        for (tracker, state) in self.gamepad_buttons.iter_mut().zip(frame.gamepads.iter()) {
+            tracker.update(state);
+        }
+        self.gamepad_events = frame.gamepad_events.clone();
+        self.text_events = frame.text_events.clone();
+
+        // Uses the same states the trackers saw, so an action is pressed exactly when its
+        // control is.
+        let player = self.actions.get_player();
+        self.actions
+            .update(&frame.keyboard, &frame.mouse, &frame.gamepads[player]);
+    }
+}
@@ -0,0 +1,493 @@
+use winapi::shared::minwindef::{LPARAM, UINT, WPARAM};
+use winapi::um::winuser::{
+    VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_MENU, VK_RCONTROL, VK_RMENU, VK_RSHIFT,
+    VK_SHIFT, WM_ACTIVATEAPP, WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+};
+
+// Declares the Key enum along with its names, so bindings can be written to and read from
+// config files.
+macro_rules! keys {
+    ($($name:ident = $value:literal,)*) => {
+        // Pretty much a port of the DirectXTK Keyboard. Values are the win32 virtual-key codes.
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum Key {
+            $($name = $value,)*
+        }
+
+        impl Key {
+            pub fn from_vk(vk: u8) -> Option<Key> {
+                match vk {
+                    $($value => Some(Key::$name),)*
+                    _ => None,
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Key> {
+                match name {
+                    $(stringify!($name) => Some(Key::$name),)*
+                    _ => None,
+                }
+            }
+
+            pub fn get_name(&self) -> &'static str {
+                match *self {
+                    $(Key::$name => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    Back = 0x08,
+    Tab = 0x09,
+    Enter = 0x0d,
+    Pause = 0x13,
+    CapsLock = 0x14,
+    Kana = 0x15,
+    Kanji = 0x19,
+    Escape = 0x1b,
+    ImeConvert = 0x1c,
+    ImeNoConvert = 0x1d,
+    Space = 0x20,
+    PageUp = 0x21,
+    PageDown = 0x22,
+    End = 0x23,
+    Home = 0x24,
+    Left = 0x25,
+    Up = 0x26,
+    Right = 0x27,
+    Down = 0x28,
+    Select = 0x29,
+    Print = 0x2a,
+    Execute = 0x2b,
+    PrintScreen = 0x2c,
+    Insert = 0x2d,
+    Delete = 0x2e,
+    Help = 0x2f,
+    D0 = 0x30,
+    D1 = 0x31,
+    D2 = 0x32,
+    D3 = 0x33,
+    D4 = 0x34,
+    D5 = 0x35,
+    D6 = 0x36,
+    D7 = 0x37,
+    D8 = 0x38,
+    D9 = 0x39,
+    A = 0x41,
+    B = 0x42,
+    C = 0x43,
+    D = 0x44,
+    E = 0x45,
+    F = 0x46,
+    G = 0x47,
+    H = 0x48,
+    I = 0x49,
+    J = 0x4a,
+    K = 0x4b,
+    L = 0x4c,
+    M = 0x4d,
+    N = 0x4e,
+    O = 0x4f,
+    P = 0x50,
+    Q = 0x51,
+    R = 0x52,
+    S = 0x53,
+    T = 0x54,
+    U = 0x55,
+    V = 0x56,
+    W = 0x57,
+    X = 0x58,
+    Y = 0x59,
+    Z = 0x5a,
+    LeftWindows = 0x5b,
+    RightWindows = 0x5c,
+    Apps = 0x5d,
+    Sleep = 0x5f,
+    NumPad0 = 0x60,
+    NumPad1 = 0x61,
+    NumPad2 = 0x62,
+    NumPad3 = 0x63,
+    NumPad4 = 0x64,
+    NumPad5 = 0x65,
+    NumPad6 = 0x66,
+    NumPad7 = 0x67,
+    NumPad8 = 0x68,
+    NumPad9 = 0x69,
+    Multiply = 0x6a,
+    Add = 0x6b,
+    Separator = 0x6c,
+    Subtract = 0x6d,
+    Decimal = 0x6e,
+    Divide = 0x6f,
+    F1 = 0x70,
+    F2 = 0x71,
+    F3 = 0x72,
+    F4 = 0x73,
+    F5 = 0x74,
+    F6 = 0x75,
+    F7 = 0x76,
+    F8 = 0x77,
+    F9 = 0x78,
+    F10 = 0x79,
+    F11 = 0x7a,
+    F12 = 0x7b,
+    F13 = 0x7c,
+    F14 = 0x7d,
+    F15 = 0x7e,
+    F16 = 0x7f,
+    F17 = 0x80,
+    F18 = 0x81,
+    F19 = 0x82,
+    F20 = 0x83,
+    F21 = 0x84,
+    F22 = 0x85,
+    F23 = 0x86,
+    F24 = 0x87,
+    NumLock = 0x90,
+    Scroll = 0x91,
+    LeftShift = 0xa0,
+    RightShift = 0xa1,
+    LeftControl = 0xa2,
+    RightControl = 0xa3,
+    LeftAlt = 0xa4,
+    RightAlt = 0xa5,
+    BrowserBack = 0xa6,
+    BrowserForward = 0xa7,
+    BrowserRefresh = 0xa8,
+    BrowserStop = 0xa9,
+    BrowserSearch = 0xaa,
+    BrowserFavorites = 0xab,
+    BrowserHome = 0xac,
+    VolumeMute = 0xad,
+    VolumeDown = 0xae,
+    VolumeUp = 0xaf,
+    MediaNextTrack = 0xb0,
+    MediaPreviousTrack = 0xb1,
+    MediaStop = 0xb2,
+    MediaPlayPause = 0xb3,
+    LaunchMail = 0xb4,
+    SelectMedia = 0xb5,
+    LaunchApplication1 = 0xb6,
+    LaunchApplication2 = 0xb7,
+    OemSemicolon = 0xba,
+    OemPlus = 0xbb,
+    OemComma = 0xbc,
+    OemMinus = 0xbd,
+    OemPeriod = 0xbe,
+    OemQuestion = 0xbf,
+    OemTilde = 0xc0,
+    OemOpenBrackets = 0xdb,
+    OemPipe = 0xdc,
+    OemCloseBrackets = 0xdd,
+    OemQuotes = 0xde,
+    Oem8 = 0xdf,
+    OemBackslash = 0xe2,
+    ImeProcess = 0xe5,
+    Attn = 0xf6,
+    Crsel = 0xf7,
+    Exsel = 0xf8,
+    EraseEof = 0xf9,
+    Play = 0xfa,
+    Zoom = 0xfb,
+    Pa1 = 0xfd,
+    OemClear = 0xfe,
+}
+
+// One bit per virtual-key code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct KeyboardState {
+    bits: [u32; 8],
+}
+
+impl KeyboardState {
+    pub fn from_bits(bits: [u32; 8]) -> KeyboardState {
+        KeyboardState { bits }
+    }
+
+    pub fn get_bits(&self) -> [u32; 8] {
+        self.bits
+    }
+
+    pub fn is_key_down(&self, key: Key) -> bool {
+        self.is_vk_down(key as u8)
+    }
+
+    pub fn is_key_up(&self, key: Key) -> bool {
+        !self.is_vk_down(key as u8)
+    }
+
+    pub fn is_vk_down(&self, vk: u8) -> bool {
+        self.bits[(vk >> 5) as usize] & (1 << (vk & 0x1f)) != 0
+    }
+
+    pub fn set_vk(&mut self, vk: u8, down: bool) {
+        let mask = 1 << (vk & 0x1f);
+        if down {
+            self.bits[(vk >> 5) as usize] |= mask;
+        } else {
+            self.bits[(vk >> 5) as usize] &= !mask;
+        }
+    }
+
+    // Keys that are down in self but not in other.
+    fn difference(&self, other: &KeyboardState) -> KeyboardState {
+        let mut bits = self.bits;
+        for (bit, other_bit) in bits.iter_mut().zip(other.bits.iter()) {
+            *bit &= !other_bit;
+        }
+        KeyboardState { bits }
+    }
+
+    fn union(&self, other: &KeyboardState) -> KeyboardState {
+        let mut bits = self.bits;
+        for (bit, other_bit) in bits.iter_mut().zip(other.bits.iter()) {
+            *bit |= other_bit;
+        }
+        KeyboardState { bits }
+    }
+}
+
+// Fed from the window procedure. Game code should look at the state through a
+// KeyboardStateTracker that gets updated once per update tick.
+#[derive(Default)]
+pub struct Keyboard {
+    state: KeyboardState,
+    // Keys that went down since the last take_state, so a press and release that both happen
+    // between two updates still shows up as one update worth of key down.
+    pressed_since_take: KeyboardState,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+
+    pub fn process_message(&mut self, message: UINT, w_param: WPARAM, l_param: LPARAM) {
+        let down = match message {
+            WM_ACTIVATEAPP => {
+                // Nobody tells us about keys released while we're in the background.
+                self.reset();
+                return;
+            }
+            WM_KEYDOWN | WM_SYSKEYDOWN => true,
+            WM_KEYUP | WM_SYSKEYUP => false,
+            _ => return,
+        };
+
+        let vk = w_param as i32;
+        if vk <= 0 || vk > 0xfe {
+            return;
+        }
+
+        let scan_code = ((l_param >> 16) & 0xff) as u32;
+        let is_extended = l_param & 0x0100_0000 != 0;
+
+        // The generic modifier codes don't tell left and right apart, so look at the scan code
+        // and extended key flag instead.
+        let vk = match vk {
+            VK_SHIFT => {
+                if !down {
+                    // When both shifts are held Windows only sends one key up, so clear both.
+                    self.set_key(VK_LSHIFT as u8, false);
+                    self.set_key(VK_RSHIFT as u8, false);
+                }
+                if scan_code == 0x36 {
+                    VK_RSHIFT
+                } else {
+                    VK_LSHIFT
+                }
+            }
+            VK_CONTROL => {
+                if is_extended {
+                    VK_RCONTROL
+                } else {
+                    VK_LCONTROL
+                }
+            }
+            VK_MENU => {
+                if is_extended {
+                    VK_RMENU
+                } else {
+                    VK_LMENU
+                }
+            }
+            _ => vk,
+        };
+
+        self.set_key(vk as u8, down);
+    }
+
+    fn set_key(&mut self, vk: u8, down: bool) {
+        self.state.set_vk(vk, down);
+        if down {
+            self.pressed_since_take.set_vk(vk, true);
+        }
+    }
+
+    // The live state, as of the last processed message.
+    pub fn get_state(&self) -> KeyboardState {
+        self.state
+    }
+
+    // Like get_state, but also reports keys that were pressed and released again since the
+    // last call. Meant to be called exactly once per update.
+    pub fn take_state(&mut self) -> KeyboardState {
+        let state = self.state.union(&self.pressed_since_take);
+        self.pressed_since_take = KeyboardState::default();
+        state
+    }
+
+    pub fn reset(&mut self) {
+        self.state = KeyboardState::default();
+        self.pressed_since_take = KeyboardState::default();
+    }
+}
+
+// Turns consecutive states into pressed and released transitions. Update it once per update
+// tick (not once per frame) so with a fixed timestep every transition is seen by exactly one
+// update, no matter how many updates run in a single frame.
+#[derive(Default)]
+pub struct KeyboardStateTracker {
+    pressed: KeyboardState,
+    released: KeyboardState,
+    last_state: KeyboardState,
+}
+
+impl KeyboardStateTracker {
+    pub fn new() -> KeyboardStateTracker {
+        KeyboardStateTracker::default()
+    }
+
+    pub fn update(&mut self, state: &KeyboardState) {
+        self.pressed = state.difference(&self.last_state);
+        self.released = self.last_state.difference(state);
+        self.last_state = *state;
+    }
+
+    pub fn reset(&mut self) {
+        *self = KeyboardStateTracker::default();
+    }
+
+    pub fn is_key_pressed(&self, key: Key) -> bool {
+        self.pressed.is_key_down(key)
+    }
+
+    pub fn is_key_released(&self, key: Key) -> bool {
+        self.released.is_key_down(key)
+    }
+
+    pub fn get_last_state(&self) -> &KeyboardState {
+        &self.last_state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(keyboard: &mut Keyboard, message: UINT, vk: i32, scan_code: isize) {
+        keyboard.process_message(message, vk as WPARAM, scan_code << 16);
+    }
+
+    #[test]
+    fn state_bits() {
+        let mut state = KeyboardState::default();
+        state.set_vk(Key::A as u8, true);
+        state.set_vk(Key::OemClear as u8, true);
+        assert_eq!(state.get_bits(), [0, 0, 1 << 1, 0, 0, 0, 0, 1 << 30]);
+        assert!(state.is_key_down(Key::A));
+        assert!(state.is_key_up(Key::Space));
+
+        state.set_vk(Key::A as u8, false);
+        assert!(state.is_key_up(Key::A));
+        assert!(state.is_key_down(Key::OemClear));
+        assert_eq!(KeyboardState::from_bits(state.get_bits()), state);
+    }
+
+    #[test]
+    fn tracker_edges() {
+        let mut down = KeyboardState::default();
+        down.set_vk(Key::Space as u8, true);
+
+        let mut tracker = KeyboardStateTracker::new();
+        tracker.update(&down);
+        assert!(tracker.is_key_pressed(Key::Space));
+        assert!(!tracker.is_key_released(Key::Space));
+
+        tracker.update(&down);
+        assert!(!tracker.is_key_pressed(Key::Space));
+        assert!(tracker.get_last_state().is_key_down(Key::Space));
+
+        tracker.update(&KeyboardState::default());
+        assert!(tracker.is_key_released(Key::Space));
+        tracker.update(&KeyboardState::default());
+        assert!(!tracker.is_key_released(Key::Space));
+    }
+
+    // With a fixed timestep a slow frame runs several updates back to back. A key held through
+    // all of them is pressed in the first only.
+    #[test]
+    fn held_through_catch_up() {
+        let mut keyboard = Keyboard::new();
+        let mut tracker = KeyboardStateTracker::new();
+        send(&mut keyboard, WM_KEYDOWN, Key::A as i32, 0);
+
+        let mut pressed = Vec::new();
+        for _ in 0..3 {
+            tracker.update(&keyboard.take_state());
+            pressed.push(tracker.is_key_pressed(Key::A));
+        }
+        assert_eq!(pressed, [true, false, false]);
+    }
+
+    #[test]
+    fn tap_between_updates() {
+        let mut keyboard = Keyboard::new();
+        let mut tracker = KeyboardStateTracker::new();
+        send(&mut keyboard, WM_KEYDOWN, Key::A as i32, 0);
+        send(&mut keyboard, WM_KEYUP, Key::A as i32, 0);
+        assert!(keyboard.get_state().is_key_up(Key::A));
+
+        tracker.update(&keyboard.take_state());
+        assert!(tracker.is_key_pressed(Key::A));
+        tracker.update(&keyboard.take_state());
+        assert!(tracker.is_key_released(Key::A));
+        tracker.update(&keyboard.take_state());
+        assert!(!tracker.is_key_pressed(Key::A) && !tracker.is_key_released(Key::A));
+    }
+
+    #[test]
+    fn focus_loss_resets() {
+        let mut keyboard = Keyboard::new();
+        let mut tracker = KeyboardStateTracker::new();
+        send(&mut keyboard, WM_KEYDOWN, Key::A as i32, 0);
+        tracker.update(&keyboard.take_state());
+        send(&mut keyboard, WM_KEYDOWN, Key::Space as i32, 0);
+
+        // Neither the held key nor the one pressed since the last update survives.
+        keyboard.process_message(WM_ACTIVATEAPP, 0, 0);
+        assert_eq!(keyboard.get_state(), KeyboardState::default());
+        tracker.update(&keyboard.take_state());
+        assert!(tracker.is_key_released(Key::A));
+        assert!(!tracker.is_key_pressed(Key::Space));
+
+        tracker.reset();
+        assert_eq!(*tracker.get_last_state(), KeyboardState::default());
+    }
+
+    #[test]
+    fn shift_sides() {
+        let mut keyboard = Keyboard::new();
+        send(&mut keyboard, WM_KEYDOWN, VK_SHIFT, 0x2a);
+        send(&mut keyboard, WM_KEYDOWN, VK_SHIFT, 0x36);
+        let state = keyboard.get_state();
+        assert!(state.is_key_down(Key::LeftShift) && state.is_key_down(Key::RightShift));
+
+        // Only one key up for both.
+        send(&mut keyboard, WM_KEYUP, VK_SHIFT, 0x36);
+        let state = keyboard.get_state();
+        assert!(state.is_key_up(Key::LeftShift) && state.is_key_up(Key::RightShift));
+    }
+}
@@ -0,0 +1,119 @@
+// Everything but the window, which main.rs creates and hands to Game. The file formats, the math
+// and the CPU side of the renderer don't touch Windows, so they and their tests build anywhere.
+
+// Nearly everything that talks to D3D is unsafe for the same reason, the caller owns the device
+// and the context, so there's nothing to say per function. Types with a new() are created once
+// by their owner rather than defaulted.
+#![allow(clippy::missing_safety_doc, clippy::new_without_default)]
+
+extern crate winapi;
+extern crate wio;
+
+// Come first so hlsl_struct! and impl_vertex! are visible in the other modules.
+#[macro_use]
+pub mod hlsl_layout;
+#[cfg(windows)]
+#[macro_use]
+pub mod vertex;
+
+#[cfg(windows)]
+pub mod actions;
+#[cfg(windows)]
+pub mod basic_effect;
+#[cfg(windows)]
+pub mod bmp;
+#[cfg(windows)]
+pub mod clipboard;
+#[cfg(windows)]
+pub mod constant_buffer;
+pub mod cpu_features;
+#[cfg(windows)]
+pub mod dds;
+pub mod debug_draw;
+#[cfg(windows)]
+pub mod debug_draw_renderer;
+#[cfg(windows)]
+pub mod dpi;
+#[cfg(windows)]
+pub mod file_drop;
+pub mod font;
+#[cfg(windows)]
+pub mod game;
+#[cfg(windows)]
+pub mod gamepad;
+pub mod geometric_primitive;
+pub mod gltf;
+pub mod glyph_atlas;
+#[cfg(windows)]
+pub mod gpu_model;
+#[cfg(windows)]
+pub mod image;
+pub mod inflate;
+#[cfg(windows)]
+pub mod input;
+pub mod json;
+#[cfg(windows)]
+pub mod keyboard;
+pub mod math;
+pub mod mesh;
+#[cfg(windows)]
+pub mod mouse;
+pub mod obj;
+#[cfg(windows)]
+pub mod png;
+#[cfg(windows)]
+pub mod render_states;
+#[cfg(windows)]
+pub mod replay;
+#[cfg(windows)]
+pub mod sample_scene;
+#[cfg(windows)]
+pub mod shader;
+#[cfg(windows)]
+pub mod shader_compiler;
+#[cfg(windows)]
+pub mod shader_pack;
+pub mod sprite_batch;
+#[cfg(windows)]
+pub mod sprite_font;
+#[cfg(windows)]
+pub mod sprite_renderer;
+pub mod state_snapshot;
+#[cfg(windows)]
+pub mod step_timer;
+#[cfg(windows)]
+pub mod text_input;
+#[cfg(windows)]
+pub mod texture;
+#[cfg(windows)]
+pub mod tga;
+#[cfg(windows)]
+pub mod truetype;
+#[cfg(windows)]
+pub mod vertex_buffer;
+
+#[cfg(windows)]
+use std::ffi::OsStr;
+#[cfg(windows)]
+use std::iter::once;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+#[cfg(windows)]
+use winapi::shared::ntdef::HRESULT;
+
+#[cfg(windows)]
+pub fn failed(hr: HRESULT) -> bool {
+    hr < 0
+}
+
+#[cfg(windows)]
+pub fn to_wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(once(0)).collect()
+}
+
+// The other direction, stopping at the terminator if there is one. Anything that isn't valid
+// UTF-16 becomes U+FFFD.
+pub fn from_wide(s: &[u16]) -> String {
+    let length = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+    String::from_utf16_lossy(&s[..length])
+}
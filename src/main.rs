@@ -1,31 +1,46 @@
+extern crate win32_d3d_template;
 extern crate winapi;
-extern crate wio;
-
-use game::Game;
-use std::ffi::OsStr;
-use std::iter::once;
-use std::os::windows::ffi::OsStrExt;
-use winapi::shared::minwindef::{HINSTANCE, LPARAM, LRESULT, WPARAM};
-use winapi::shared::ntdef::HRESULT;
-use winapi::shared::windef::{HBRUSH, HMENU, HWND};
+
+use std::time::Instant;
+use win32_d3d_template::cpu_features::CpuFeatures;
+use win32_d3d_template::game::Game;
+use win32_d3d_template::{dpi, failed, file_drop, math, to_wide};
+use winapi::shared::minwindef::{FALSE, HINSTANCE, HIWORD, LOWORD, LPARAM, LRESULT, TRUE, WPARAM};
+use winapi::shared::windef::{HBRUSH, HMENU, HWND, RECT};
 use winapi::um::combaseapi::{CoInitializeEx, CoUninitialize, COINITBASE_MULTITHREADED};
 use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::shellapi::HDROP;
 use winapi::um::winuser::{
-    CreateWindowExW, DefWindowProcW, DispatchMessageW, LoadCursorW, LoadIconW, PeekMessageW,
-    PostQuitMessage, RegisterClassExW, ShowWindow, TranslateMessage, COLOR_WINDOW, CS_HREDRAW,
-    CS_VREDRAW, CW_USEDEFAULT, IDC_ARROW, MSG, PM_REMOVE, SW_SHOW, WM_ACTIVATEAPP, WM_DESTROY,
-    WM_ENTERSIZEMOVE, WM_EXITSIZEMOVE, WM_GETMINMAXINFO, WM_MENUCHAR, WM_PAINT, WM_POWERBROADCAST,
-    WM_QUIT, WM_SIZE, WM_SYSKEYDOWN, WNDCLASSEXW, WS_OVERLAPPEDWINDOW,
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, GetClientRect, GetWindowLongPtrW,
+    LoadCursorW, LoadIconW, MessageBoxW, MsgWaitForMultipleObjects, PeekMessageW, PostQuitMessage,
+    RegisterClassExW, SetWindowLongPtrW, SetWindowPos, ShowWindow, TranslateMessage, WaitMessage,
+    COLOR_WINDOW, CS_HREDRAW, CS_VREDRAW, CW_USEDEFAULT, GWLP_USERDATA, IDC_ARROW, MB_ICONERROR,
+    MB_OK, MSG, PBT_APMRESUMEAUTOMATIC, PBT_APMRESUMESUSPEND, PBT_APMSUSPEND, PM_REMOVE,
+    QS_ALLINPUT, SIZE_MINIMIZED, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOZORDER, SW_SHOW, WM_ACTIVATEAPP,
+    WM_CHAR, WM_DESTROY, WM_DPICHANGED, WM_DROPFILES, WM_ENTERSIZEMOVE, WM_EXITSIZEMOVE,
+    WM_GETMINMAXINFO, WM_IME_COMPOSITION, WM_IME_ENDCOMPOSITION, WM_IME_SETCONTEXT,
+    WM_IME_STARTCOMPOSITION, WM_INPUT, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP,
+    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MENUCHAR, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_MOVE, WM_PAINT,
+    WM_POWERBROADCAST, WM_QUIT, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SIZE, WM_SYSKEYDOWN, WM_SYSKEYUP,
+    WM_XBUTTONDOWN, WM_XBUTTONUP, WNDCLASSEXW, WS_OVERLAPPEDWINDOW,
 };
 
-mod game;
-mod step_timer;
-
 //TODO: mark everything as unsafe
 
 fn main() {
     unsafe {
-        //TODO: XMVerifyCPUSupport is missing. There are no bindings since it's c++.
+        // --replay <file> plays back a recording without ever opening a window. The exit code
+        // tells whether the game did the same thing as when it was recorded.
+        let args: Vec<String> = std::env::args().collect();
+        let replay_path = get_arg_value(&args, "--replay");
+
+        // A replay can run where nobody is around to close a dialog.
+        if !verify_cpu_support(replay_path.is_none()) {
+            std::process::exit(1);
+        }
+
+        // Has to happen before the first window is created, otherwise Windows bitmap-stretches us.
+        dpi::enable_per_monitor_dpi_awareness();
 
         let hr = CoInitializeEx(std::ptr::null_mut(), COINITBASE_MULTITHREADED);
         if failed(hr) {
@@ -34,18 +49,27 @@ fn main() {
 
         let mut game = Game::new();
 
+        if let Some(path) = replay_path {
+            let code = match game.run_replay(path) {
+                Ok(None) => 0,
+                Ok(Some(tick)) => {
+                    eprintln!("Replay diverged from the recording at tick {}", tick);
+                    2
+                }
+                Err(e) => {
+                    eprintln!("Failed to replay {}: {}", path, e);
+                    1
+                }
+            };
+            CoUninitialize();
+            std::process::exit(code);
+        }
+
         //https://stackoverflow.com/questions/1749972/determine-the-current-hinstance
         let hinstance = GetModuleHandleW(std::ptr::null_mut());
 
-        let wndclass_name: Vec<u16> = OsStr::new("testclassname")
-            .encode_wide()
-            .chain(once(0))
-            .collect();
-
-        let idi_icon: Vec<u16> = OsStr::new("IDI_ICON")
-            .encode_wide()
-            .chain(once(0))
-            .collect();
+        let wndclass_name = to_wide("testclassname");
+        let idi_icon = to_wide("IDI_ICON");
 
         //window class registration
         let wnd_class = WNDCLASSEXW {
@@ -72,7 +96,7 @@ fn main() {
 
             game.get_default_size(&mut width, &mut height);
 
-            let wnd_name: Vec<u16> = OsStr::new("window").encode_wide().chain(once(0)).collect();
+            let wnd_name = to_wide("window");
             let hwnd = CreateWindowExW(
                 0,
                 wndclass_name.as_ptr(),
@@ -80,29 +104,84 @@ fn main() {
                 WS_OVERLAPPEDWINDOW,
                 CW_USEDEFAULT,
                 CW_USEDEFAULT,
-                800,
-                600,
+                width,
+                height,
                 0 as HWND,
                 0 as HMENU,
                 0 as HINSTANCE,
                 std::ptr::null_mut(),
             );
 
-            if hwnd != std::ptr::null_mut() {
+            if !hwnd.is_null() {
+                // The default size is in 96 dpi units, so scale it for the monitor we ended up on
+                // and grow it by the (also scaled) window frame.
+                let dpi = dpi::get_dpi_for_window(hwnd);
+                let mut rc = RECT {
+                    left: 0,
+                    top: 0,
+                    right: dpi::scale_for_dpi(width, dpi),
+                    bottom: dpi::scale_for_dpi(height, dpi),
+                };
+                dpi::adjust_window_rect_for_dpi(&mut rc, WS_OVERLAPPEDWINDOW, 0, dpi);
+                SetWindowPos(
+                    hwnd,
+                    std::ptr::null_mut(),
+                    0,
+                    0,
+                    rc.right - rc.left,
+                    rc.bottom - rc.top,
+                    SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE,
+                );
+
+                file_drop::accept_files(hwnd);
+
                 ShowWindow(hwnd, SW_SHOW);
-                //TODO: setwindowlongptr and getclientrect for width and height
-                game.initialize(hwnd, 800, 600);
+                SetWindowLongPtrW(hwnd, GWLP_USERDATA, &mut game as *mut Game as isize);
+
+                let mut rc: RECT = std::mem::zeroed();
+                GetClientRect(hwnd, &mut rc);
+
+                // Before initialize, which might restore a state snapshot the replay needs.
+                if let Some(path) = get_arg_value(&args, "--record") {
+                    if let Err(e) = game.start_recording(path) {
+                        eprintln!("Failed to start recording to {}: {}", path, e);
+                    }
+                }
+
+                game.on_dpi_changed(dpi);
+                game.initialize(hwnd, rc.right - rc.left, rc.bottom - rc.top);
 
                 let mut msg: MSG = std::mem::zeroed();
                 while WM_QUIT != msg.message {
                     if PeekMessageW(&mut msg, std::ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
                         TranslateMessage(&msg);
                         DispatchMessageW(&msg);
+                    } else if game.should_wait_for_messages() {
+                        // Nothing to update or draw, so sleep until the window gets a message.
+                        WaitMessage();
                     } else {
+                        let tick_start = Instant::now();
                         game.tick();
+
+                        if let Some(interval) = game.get_throttle_interval() {
+                            // Wait out the rest of the interval, but wake up early for messages
+                            // so the window stays responsive.
+                            let elapsed = tick_start.elapsed();
+                            if elapsed < interval {
+                                let remaining_ms = (interval - elapsed).as_millis() as u32;
+                                MsgWaitForMultipleObjects(
+                                    0,
+                                    std::ptr::null(),
+                                    FALSE,
+                                    remaining_ms,
+                                    QS_ALLINPUT,
+                                );
+                            }
+                        }
                     }
                 }
 
+                game.stop_recording();
                 CoUninitialize();
 
                 //not sure what to do about msg.wparam.
@@ -118,18 +197,102 @@ unsafe extern "system" fn wnd_proc(
     w_param: WPARAM,
     l_param: LPARAM,
 ) -> LRESULT {
+    // Null until main has finished creating the window, so everything below has to check.
+    let game = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Game;
+
     match message {
         WM_PAINT => {}
-        WM_SIZE => {}
+        WM_SIZE if !game.is_null() => {
+            (*game).process_input_message(message, w_param, l_param);
+
+            if w_param == SIZE_MINIMIZED {
+                (*game).on_minimized();
+                (*game).on_suspending();
+            } else {
+                if (*game).is_minimized() {
+                    (*game).on_restored();
+                    (*game).on_resuming();
+                }
+
+                let width = LOWORD(l_param as u32) as i32;
+                let height = HIWORD(l_param as u32) as i32;
+                (*game).on_window_size_changed(width, height);
+            }
+        }
+        WM_DPICHANGED => {
+            // x and y dpi are always identical for windows, so the low word is enough.
+            let dpi = LOWORD(w_param as u32) as u32;
+            if !game.is_null() {
+                (*game).on_dpi_changed(dpi);
+            }
+
+            // Windows suggests a rect that keeps the window at the same physical size on the
+            // new monitor. The resulting WM_SIZE takes care of resizing the swap chain.
+            let suggested = &*(l_param as *const RECT);
+            SetWindowPos(
+                hwnd,
+                std::ptr::null_mut(),
+                suggested.left,
+                suggested.top,
+                suggested.right - suggested.left,
+                suggested.bottom - suggested.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+            return 0;
+        }
         WM_ENTERSIZEMOVE => {}
         WM_EXITSIZEMOVE => {}
         WM_GETMINMAXINFO => {}
-        WM_ACTIVATEAPP => {}
-        WM_POWERBROADCAST => {}
+        WM_ACTIVATEAPP if !game.is_null() => {
+            (*game).process_input_message(message, w_param, l_param);
+
+            if w_param != 0 {
+                (*game).on_activated();
+            } else {
+                (*game).on_deactivated();
+            }
+        }
+        WM_POWERBROADCAST if !game.is_null() => match w_param {
+            PBT_APMSUSPEND => {
+                (*game).on_power_suspending();
+                return TRUE as LRESULT;
+            }
+            // Automatic resume is always sent, the other one only if a user woke the
+            // machine up. on_resuming ignores whichever arrives second.
+            PBT_APMRESUMESUSPEND | PBT_APMRESUMEAUTOMATIC => {
+                // Still minimized, so keep sleeping until the window is restored.
+                if !(*game).is_minimized() {
+                    (*game).on_resuming();
+                }
+                return TRUE as LRESULT;
+            }
+            _ => {}
+        },
         WM_DESTROY => {
             PostQuitMessage(0);
         }
-        WM_SYSKEYDOWN => {}
+        WM_KEYDOWN | WM_KEYUP | WM_SYSKEYDOWN | WM_SYSKEYUP | WM_INPUT | WM_MOUSEMOVE
+        | WM_LBUTTONDOWN | WM_LBUTTONUP | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN
+        | WM_MBUTTONUP | WM_XBUTTONDOWN | WM_XBUTTONUP | WM_MOUSEWHEEL | WM_MOVE
+            if !game.is_null() =>
+        {
+            (*game).process_input_message(message, w_param, l_param);
+        }
+        WM_CHAR
+        | WM_IME_SETCONTEXT
+        | WM_IME_STARTCOMPOSITION
+        | WM_IME_COMPOSITION
+        | WM_IME_ENDCOMPOSITION
+            if !game.is_null() =>
+        {
+            if let Some(result) = (*game).process_text_input_message(message, w_param, l_param) {
+                return result;
+            }
+        }
+        WM_DROPFILES if !game.is_null() => {
+            (*game).on_files_dropped(file_drop::take_file_drop(w_param as HDROP));
+            return 0;
+        }
         WM_MENUCHAR => {}
         _ => {}
     };
@@ -137,6 +300,38 @@ unsafe extern "system" fn wnd_proc(
     DefWindowProcW(hwnd, message, w_param, l_param)
 }
 
-fn failed(hr: HRESULT) -> bool {
-    hr < 0
+// The argument following name, e.g. the path in "--record path".
+fn get_arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .map(|value| value.as_str())
+}
+
+// Checks the CPU has the instruction sets the game was built for or configured to require, see
+// cpu_features::REQUIRED_FEATURES, and hands what it has on to the math module. Missing ones are reported rather than crashing on the first one used.
+unsafe fn verify_cpu_support(show_dialog: bool) -> bool {
+    let features = CpuFeatures::detect();
+    let missing = features.get_missing(&CpuFeatures::get_required());
+    if !missing.is_empty() {
+        let message = format!(
+            "This game needs a processor that supports {}.",
+            missing.join(", ")
+        );
+        eprintln!("{}", message);
+        if show_dialog {
+            let text = to_wide(&message);
+            let caption = to_wide("Unsupported processor");
+            MessageBoxW(
+                std::ptr::null_mut(),
+                text.as_ptr(),
+                caption.as_ptr(),
+                MB_OK | MB_ICONERROR,
+            );
+        }
+        return false;
+    }
+
+    math::set_cpu_features(features);
+    true
 }
@@ -0,0 +1,1634 @@
+// Vectors, matrices and quaternions in the DirectXMath conventions. Vectors are rows multiplied
+// on the left, p * M, so transforms apply from left to right: scale * rotation * translation.
+// The world is left-handed with x right, y up and the camera looking down +z, and projections
+// map depth to [0, 1] the way D3D wants it, or to [1, 0] for reversed-Z.
+//
+// Mat4 has the same layout as the [[f32; 4]; 4] matrices the renderer takes, and goes into
+// row_major HLSL matrices as it is. Vec4 and Mat4 arithmetic is done with SSE on x86, and with
+// SSE4.1 where the CPU has it. FMA is used too, if it has that and set_fma_allowed said so.
+
+use cpu_features::CpuFeatures;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+static CPU_FEATURES: OnceLock<CpuFeatures> = OnceLock::new();
+
+// main passes on what it detected at startup, otherwise the features are detected the first
+// time they're needed. Only the first call counts, so it can't change halfway through a frame.
+pub fn set_cpu_features(features: CpuFeatures) {
+    let _ = CPU_FEATURES.set(features);
+}
+
+pub fn get_cpu_features() -> CpuFeatures {
+    *CPU_FEATURES.get_or_init(CpuFeatures::detect)
+}
+
+static FMA_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+// FMA rounds once where separate multiplies and adds round twice, so results can differ in the
+// last bit between machines, and a replay recorded on one would drift when played on another.
+// So it's off unless a game that never records or replays input turns it on.
+pub fn set_fma_allowed(allowed: bool) {
+    FMA_ALLOWED.store(allowed, Ordering::Relaxed);
+}
+
+pub fn is_fma_allowed() -> bool {
+    FMA_ALLOWED.load(Ordering::Relaxed)
+}
+
+// Plain per component arithmetic, which is what the vectors use where there's no SSE. The tests
+// check the SSE paths against it.
+#[cfg(any(
+    test,
+    not(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ))
+))]
+mod scalar {
+    pub fn add(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|i| a[i] + b[i])
+    }
+
+    pub fn sub(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|i| a[i] - b[i])
+    }
+
+    pub fn mul(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|i| a[i] * b[i])
+    }
+
+    pub fn div(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|i| a[i] / b[i])
+    }
+
+    pub fn dot(a: [f32; 4], b: [f32; 4]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3]
+    }
+
+    // Adds up in the same order as the SSE version without FMA, so the two agree exactly.
+    pub fn transform(v: [f32; 4], m: &[[f32; 4]; 4]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|column| (0..4).fold(0.0, |sum, k| sum + v[k] * m[k][column]))
+    }
+}
+
+#[cfg(not(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+)))]
+use self::scalar as simd;
+
+// Four lanes at a time. Every x86_64 CPU has SSE, and so does anything 32-bit this builds for
+// unless the target was told otherwise.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+mod simd {
+    use super::{get_cpu_features, is_fma_allowed};
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    // The intrinsics are only unsafe on CPUs without SSE, which this module isn't built for.
+    unsafe fn load(v: &[f32; 4]) -> __m128 {
+        _mm_loadu_ps(v.as_ptr())
+    }
+
+    unsafe fn store(v: __m128) -> [f32; 4] {
+        let mut result = [0.0; 4];
+        _mm_storeu_ps(result.as_mut_ptr(), v);
+        result
+    }
+
+    pub fn add(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_add_ps(load(&a), load(&b))) }
+    }
+
+    pub fn sub(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_sub_ps(load(&a), load(&b))) }
+    }
+
+    pub fn mul(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_mul_ps(load(&a), load(&b))) }
+    }
+
+    pub fn div(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_div_ps(load(&a), load(&b))) }
+    }
+
+    pub fn dot(a: [f32; 4], b: [f32; 4]) -> f32 {
+        if get_cpu_features().sse4_1 {
+            unsafe { dot_sse4_1(a, b) }
+        } else {
+            dot_sse(a, b)
+        }
+    }
+
+    fn dot_sse(a: [f32; 4], b: [f32; 4]) -> f32 {
+        unsafe {
+            let products = _mm_mul_ps(load(&a), load(&b));
+            // (x + y, _, z + w, _), then the two halves added together.
+            let pairs = _mm_add_ps(products, _mm_shuffle_ps(products, products, 0b10_11_00_01));
+            _mm_cvtss_f32(_mm_add_ss(pairs, _mm_movehl_ps(pairs, pairs)))
+        }
+    }
+
+    // All four products summed into the first lane in one go.
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn dot_sse4_1(a: [f32; 4], b: [f32; 4]) -> f32 {
+        _mm_cvtss_f32(_mm_dp_ps(load(&a), load(&b), 0xf1))
+    }
+
+    // v * m, as a sum of the rows weighted by the components of v.
+    pub fn transform(v: [f32; 4], m: &[[f32; 4]; 4]) -> [f32; 4] {
+        if get_cpu_features().fma && is_fma_allowed() {
+            unsafe { transform_fma(v, m) }
+        } else {
+            transform_sse(v, m)
+        }
+    }
+
+    fn transform_sse(v: [f32; 4], m: &[[f32; 4]; 4]) -> [f32; 4] {
+        unsafe {
+            let mut result = _mm_setzero_ps();
+            for (&component, row) in v.iter().zip(m) {
+                result = _mm_add_ps(result, _mm_mul_ps(_mm_set1_ps(component), load(row)));
+            }
+            store(result)
+        }
+    }
+
+    #[target_feature(enable = "fma")]
+    unsafe fn transform_fma(v: [f32; 4], m: &[[f32; 4]; 4]) -> [f32; 4] {
+        let mut result = _mm_setzero_ps();
+        for (&component, row) in v.iter().zip(m) {
+            result = _mm_fmadd_ps(_mm_set1_ps(component), load(row), result);
+        }
+        store(result)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::scalar;
+        use super::*;
+
+        const A: [f32; 4] = [1.5, -2.25, 3.125, 0.1];
+        const B: [f32; 4] = [-0.7, 4.0, 0.3, -9.5];
+        const M: [[f32; 4]; 4] = [
+            [0.3, -1.2, 2.5, 0.0],
+            [1.1, 0.4, -0.6, 0.0],
+            [-2.0, 0.9, 0.7, 0.0],
+            [5.0, -3.0, 1.25, 1.0],
+        ];
+
+        fn assert_close(a: f32, b: f32) {
+            assert!((a - b).abs() <= 1e-6 * a.abs().max(1.0), "{} != {}", a, b);
+        }
+
+        #[test]
+        fn component_wise_matches_scalar() {
+            assert_eq!(add(A, B), scalar::add(A, B));
+            assert_eq!(sub(A, B), scalar::sub(A, B));
+            assert_eq!(mul(A, B), scalar::mul(A, B));
+            assert_eq!(div(A, B), scalar::div(A, B));
+        }
+
+        #[test]
+        fn dot_matches_scalar() {
+            assert_close(dot_sse(A, B), scalar::dot(A, B));
+            if is_x86_feature_detected!("sse4.1") {
+                assert_close(unsafe { dot_sse4_1(A, B) }, scalar::dot(A, B));
+            }
+        }
+
+        #[test]
+        fn transform_matches_scalar() {
+            assert_eq!(transform_sse(A, &M), scalar::transform(A, &M));
+            // FMA is off unless allowed, so replays see the same bits everywhere.
+            assert_eq!(transform(A, &M), scalar::transform(A, &M));
+            // Only close, since FMA rounds once per row instead of twice.
+            if is_x86_feature_detected!("fma") {
+                let fused = unsafe { transform_fma(A, &M) };
+                for (&a, b) in fused.iter().zip(scalar::transform(A, &M)) {
+                    assert_close(a, b);
+                }
+            }
+        }
+    }
+}
+
+// What the vector types have in common. Arithmetic is per component, apart from multiplying
+// or dividing by a scalar.
+macro_rules! vector {
+    ($name:ident, $count:expr, $($field:ident: $index:expr),+) => {
+        #[repr(C)]
+        #[derive(Clone, Copy, PartialEq, Debug, Default)]
+        pub struct $name {
+            $(pub $field: f32,)+
+        }
+
+        impl $name {
+            pub const ZERO: $name = $name { $($field: 0.0,)+ };
+            pub const ONE: $name = $name { $($field: 1.0,)+ };
+
+            pub const fn new($($field: f32),+) -> $name {
+                $name { $($field,)+ }
+            }
+
+            pub const fn splat(value: f32) -> $name {
+                $name { $($field: value,)+ }
+            }
+
+            pub fn length_squared(self) -> f32 {
+                self.dot(self)
+            }
+
+            pub fn length(self) -> f32 {
+                self.dot(self).sqrt()
+            }
+
+            // A zero vector stays zero, like in DirectXMath.
+            pub fn normalize(self) -> $name {
+                let length = self.length();
+                if length > 0.0 {
+                    self / length
+                } else {
+                    $name::ZERO
+                }
+            }
+
+            pub fn distance(self, other: $name) -> f32 {
+                (other - self).length()
+            }
+
+            pub fn lerp(self, other: $name, t: f32) -> $name {
+                self + (other - self) * t
+            }
+
+            pub fn min(self, other: $name) -> $name {
+                $name { $($field: self.$field.min(other.$field),)+ }
+            }
+
+            pub fn max(self, other: $name) -> $name {
+                $name { $($field: self.$field.max(other.$field),)+ }
+            }
+
+            pub fn abs(self) -> $name {
+                $name { $($field: self.$field.abs(),)+ }
+            }
+
+            pub fn to_array(self) -> [f32; $count] {
+                [$(self.$field),+]
+            }
+        }
+
+        impl From<[f32; $count]> for $name {
+            fn from(a: [f32; $count]) -> $name {
+                $name { $($field: a[$index],)+ }
+            }
+        }
+
+        impl From<$name> for [f32; $count] {
+            fn from(v: $name) -> [f32; $count] {
+                v.to_array()
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+            fn neg(self) -> $name {
+                $name { $($field: -self.$field,)+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+            fn mul(self, s: f32) -> $name {
+                self * $name::splat(s)
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+            fn mul(self, v: $name) -> $name {
+                v * $name::splat(self)
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = $name;
+            fn div(self, s: f32) -> $name {
+                self / $name::splat(s)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                *self = *self + other;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                *self = *self - other;
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, s: f32) {
+                *self = *self * s;
+            }
+        }
+
+        impl DivAssign<f32> for $name {
+            fn div_assign(&mut self, s: f32) {
+                *self = *self / s;
+            }
+        }
+    };
+}
+
+// Per component arithmetic one field at a time, for the vectors too small to bother with SSE.
+macro_rules! vector_arithmetic {
+    ($name:ident, $($field:ident),+) => {
+        impl $name {
+            pub fn dot(self, other: $name) -> f32 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, other: $name) -> $name {
+                $name { $($field: self.$field + other.$field,)+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, other: $name) -> $name {
+                $name { $($field: self.$field - other.$field,)+ }
+            }
+        }
+
+        impl Mul for $name {
+            type Output = $name;
+            fn mul(self, other: $name) -> $name {
+                $name { $($field: self.$field * other.$field,)+ }
+            }
+        }
+
+        impl Div for $name {
+            type Output = $name;
+            fn div(self, other: $name) -> $name {
+                $name { $($field: self.$field / other.$field,)+ }
+            }
+        }
+    };
+}
+
+vector!(Vec2, 2, x: 0, y: 1);
+vector_arithmetic!(Vec2, x, y);
+vector!(Vec3, 3, x: 0, y: 1, z: 2);
+vector_arithmetic!(Vec3, x, y, z);
+vector!(Vec4, 4, x: 0, y: 1, z: 2, w: 3);
+
+impl Vec3 {
+    pub const X: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+    pub const Y: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    pub const Z: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+    // In a left-handed world, cross(X, Y) is Z like in a right-handed one, it's just that Z
+    // points away from the viewer.
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+}
+
+impl Vec4 {
+    pub fn dot(self, other: Vec4) -> f32 {
+        simd::dot(self.to_array(), other.to_array())
+    }
+
+    // Drops w.
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+impl Add for Vec4 {
+    type Output = Vec4;
+    fn add(self, other: Vec4) -> Vec4 {
+        simd::add(self.to_array(), other.to_array()).into()
+    }
+}
+
+impl Sub for Vec4 {
+    type Output = Vec4;
+    fn sub(self, other: Vec4) -> Vec4 {
+        simd::sub(self.to_array(), other.to_array()).into()
+    }
+}
+
+impl Mul for Vec4 {
+    type Output = Vec4;
+    fn mul(self, other: Vec4) -> Vec4 {
+        simd::mul(self.to_array(), other.to_array()).into()
+    }
+}
+
+impl Div for Vec4 {
+    type Output = Vec4;
+    fn div(self, other: Vec4) -> Vec4 {
+        simd::div(self.to_array(), other.to_array()).into()
+    }
+}
+
+// Where a projection puts depth. Reversed puts the near plane at 1 and the far plane at 0,
+// which spreads the precision of a float depth buffer much more evenly; it needs a GREATER
+// depth test and the depth buffer cleared to 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DepthMode {
+    #[default]
+    Standard,
+    Reversed,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mat4 {
+    pub rows: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        rows: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn from_rows(x: Vec4, y: Vec4, z: Vec4, w: Vec4) -> Mat4 {
+        Mat4 {
+            rows: [x.into(), y.into(), z.into(), w.into()],
+        }
+    }
+
+    pub fn get_row(&self, i: usize) -> Vec4 {
+        self.rows[i].into()
+    }
+
+    pub fn get_column(&self, i: usize) -> Vec4 {
+        Vec4::new(
+            self.rows[0][i],
+            self.rows[1][i],
+            self.rows[2][i],
+            self.rows[3][i],
+        )
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        m.rows[3] = offset.extend(1.0).into();
+        m
+    }
+
+    pub fn scaling(scale: Vec3) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        m.rows[0][0] = scale.x;
+        m.rows[1][1] = scale.y;
+        m.rows[2][2] = scale.z;
+        m
+    }
+
+    // Rotations are clockwise looking down the axis toward the origin, as left-handed rotations
+    // go, so rotation_y with a positive angle turns +z toward +x.
+    pub fn rotation_x(angle: f32) -> Mat4 {
+        let (s, c) = angle.sin_cos();
+        let mut m = Mat4::IDENTITY;
+        m.rows[1] = [0.0, c, s, 0.0];
+        m.rows[2] = [0.0, -s, c, 0.0];
+        m
+    }
+
+    pub fn rotation_y(angle: f32) -> Mat4 {
+        let (s, c) = angle.sin_cos();
+        let mut m = Mat4::IDENTITY;
+        m.rows[0] = [c, 0.0, -s, 0.0];
+        m.rows[2] = [s, 0.0, c, 0.0];
+        m
+    }
+
+    pub fn rotation_z(angle: f32) -> Mat4 {
+        let (s, c) = angle.sin_cos();
+        let mut m = Mat4::IDENTITY;
+        m.rows[0] = [c, s, 0.0, 0.0];
+        m.rows[1] = [-s, c, 0.0, 0.0];
+        m
+    }
+
+    pub fn rotation_axis(axis: Vec3, angle: f32) -> Mat4 {
+        Mat4::from_quat(Quat::from_axis_angle(axis, angle))
+    }
+
+    // q has to be normalized.
+    pub fn from_quat(q: Quat) -> Mat4 {
+        let Quat { x, y, z, w } = q;
+        Mat4 {
+            rows: [
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y + w * z),
+                    2.0 * (x * z - w * y),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * y - w * z),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z + w * x),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * z + w * y),
+                    2.0 * (y * z - w * x),
+                    1.0 - 2.0 * (x * x + y * y),
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Scales, then rotates, then translates, the usual transform of a node or an object.
+    pub fn from_scale_rotation_translation(scale: Vec3, rotation: Quat, translation: Vec3) -> Mat4 {
+        let mut m = Mat4::from_quat(rotation);
+        for (row, s) in m.rows.iter_mut().zip(scale.to_array()) {
+            *row = simd::mul(*row, [s; 4]);
+        }
+        m.rows[3] = translation.extend(1.0).into();
+        m
+    }
+
+    // A view from eye toward target. up only has to be somewhere above the view direction, not
+    // perpendicular to it.
+    pub fn look_at_lh(eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+        Mat4::look_to_lh(eye, target - eye, up)
+    }
+
+    pub fn look_to_lh(eye: Vec3, direction: Vec3, up: Vec3) -> Mat4 {
+        let forward = direction.normalize();
+        let right = up.cross(forward).normalize();
+        let up = forward.cross(right);
+        Mat4 {
+            rows: [
+                [right.x, up.x, forward.x, 0.0],
+                [right.y, up.y, forward.y, 0.0],
+                [right.z, up.z, forward.z, 0.0],
+                [-right.dot(eye), -up.dot(eye), -forward.dot(eye), 1.0],
+            ],
+        }
+    }
+
+    // fov_y is in radians and aspect is width over height. far can be f32::INFINITY, which
+    // with reversed depth is the usual choice.
+    pub fn perspective_fov_lh(
+        fov_y: f32,
+        aspect: f32,
+        near: f32,
+        far: f32,
+        depth: DepthMode,
+    ) -> Mat4 {
+        let height = 1.0 / (fov_y * 0.5).tan();
+        Mat4::perspective_scaled(height / aspect, height, near, far, depth)
+    }
+
+    // width and height are the size of the view at the near plane.
+    pub fn perspective_lh(width: f32, height: f32, near: f32, far: f32, depth: DepthMode) -> Mat4 {
+        Mat4::perspective_scaled(2.0 * near / width, 2.0 * near / height, near, far, depth)
+    }
+
+    fn perspective_scaled(
+        x_scale: f32,
+        y_scale: f32,
+        near: f32,
+        far: f32,
+        depth: DepthMode,
+    ) -> Mat4 {
+        // Depth comes out as (a * z + b) / z.
+        let (a, b) = match (depth, far.is_infinite()) {
+            (DepthMode::Standard, false) => (far / (far - near), -near * far / (far - near)),
+            (DepthMode::Standard, true) => (1.0, -near),
+            (DepthMode::Reversed, false) => (near / (near - far), near * far / (far - near)),
+            (DepthMode::Reversed, true) => (0.0, near),
+        };
+        Mat4 {
+            rows: [
+                [x_scale, 0.0, 0.0, 0.0],
+                [0.0, y_scale, 0.0, 0.0],
+                [0.0, 0.0, a, 1.0],
+                [0.0, 0.0, b, 0.0],
+            ],
+        }
+    }
+
+    // A view width by height in size, centered on the view direction.
+    pub fn orthographic_lh(width: f32, height: f32, near: f32, far: f32, depth: DepthMode) -> Mat4 {
+        let (x, y) = (width * 0.5, height * 0.5);
+        Mat4::orthographic_off_center_lh(-x, x, -y, y, near, far, depth)
+    }
+
+    pub fn orthographic_off_center_lh(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+        depth: DepthMode,
+    ) -> Mat4 {
+        // Depth comes out as a * z + b.
+        let (a, b) = match depth {
+            DepthMode::Standard => (1.0 / (far - near), -near / (far - near)),
+            DepthMode::Reversed => (1.0 / (near - far), far / (far - near)),
+        };
+        Mat4 {
+            rows: [
+                [2.0 / (right - left), 0.0, 0.0, 0.0],
+                [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+                [0.0, 0.0, a, 0.0],
+                [
+                    (left + right) / (left - right),
+                    (top + bottom) / (bottom - top),
+                    b,
+                    1.0,
+                ],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        Mat4 {
+            rows: [0, 1, 2, 3].map(|i| self.get_column(i).into()),
+        }
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.get_minors();
+        get_determinant(&s, &c)
+    }
+
+    // None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let m = &self.rows;
+        let (s, c) = self.get_minors();
+        let determinant = get_determinant(&s, &c);
+        if determinant.abs() < f32::MIN_POSITIVE || !determinant.is_finite() {
+            return None;
+        }
+
+        let r = 1.0 / determinant;
+        let inverse = [
+            [
+                (m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3]) * r,
+                (-m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3]) * r,
+                (m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3]) * r,
+                (-m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3]) * r,
+            ],
+            [
+                (-m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1]) * r,
+                (m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1]) * r,
+                (-m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1]) * r,
+                (m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1]) * r,
+            ],
+            [
+                (m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0]) * r,
+                (-m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0]) * r,
+                (m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0]) * r,
+                (-m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0]) * r,
+            ],
+            [
+                (-m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0]) * r,
+                (m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0]) * r,
+                (-m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0]) * r,
+                (m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0]) * r,
+            ],
+        ];
+        Some(Mat4 { rows: inverse })
+    }
+
+    // The 2x2 determinants of the top two rows and of the bottom two, which both the
+    // determinant and the inverse are built from.
+    fn get_minors(&self) -> ([f32; 6], [f32; 6]) {
+        let m = &self.rows;
+        let minors = |a: usize, b: usize| {
+            [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]
+                .map(|(i, j)| m[a][i] * m[b][j] - m[b][i] * m[a][j])
+        };
+        (minors(0, 1), minors(2, 3))
+    }
+
+    pub fn transform(&self, v: Vec4) -> Vec4 {
+        simd::transform(v.into(), &self.rows).into()
+    }
+
+    // Takes w to be 1 and ignores the w that comes out, which is right for anything but a
+    // projection.
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform(p.extend(1.0)).truncate()
+    }
+
+    // Takes w to be 0, so translation doesn't apply. For normals, use the inverse transpose
+    // unless the matrix is only rotation and uniform scale.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        self.transform(v.extend(0.0)).truncate()
+    }
+
+    // Transforms and divides by w, e.g. to take a point to normalized device coordinates.
+    pub fn project_point(&self, p: Vec3) -> Vec3 {
+        let v = self.transform(p.extend(1.0));
+        v.truncate() / v.w
+    }
+
+    pub fn get_translation(&self) -> Vec3 {
+        self.get_row(3).truncate()
+    }
+}
+
+// Laplace expansion along the top two rows, from the minors get_minors gives.
+fn get_determinant(s: &[f32; 6], c: &[f32; 6]) -> f32 {
+    s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    // self first, then other.
+    fn mul(self, other: Mat4) -> Mat4 {
+        Mat4 {
+            rows: self.rows.map(|row| simd::transform(row, &other.rows)),
+        }
+    }
+}
+
+impl MulAssign for Mat4 {
+    fn mul_assign(&mut self, other: Mat4) {
+        *self = *self * other;
+    }
+}
+
+impl Mul<Mat4> for Vec4 {
+    type Output = Vec4;
+    fn mul(self, m: Mat4) -> Vec4 {
+        m.transform(self)
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Mat4 {
+    fn from(rows: [[f32; 4]; 4]) -> Mat4 {
+        Mat4 { rows }
+    }
+}
+
+impl From<Mat4> for [[f32; 4]; 4] {
+    fn from(m: Mat4) -> [[f32; 4]; 4] {
+        m.rows
+    }
+}
+
+// A rotation. Multiplying applies the left one first, like with matrices, so
+// Mat4::from_quat(a * b) is Mat4::from_quat(a) * Mat4::from_quat(b).
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat::new(0.0, 0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Quat {
+        Quat { x, y, z, w }
+    }
+
+    // Turns the same way as Mat4::rotation_axis.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+        let (s, c) = (angle * 0.5).sin_cos();
+        let axis = axis.normalize() * s;
+        Quat::new(axis.x, axis.y, axis.z, c)
+    }
+
+    // Roll around z first, then pitch around x, then yaw around y, like
+    // XMQuaternionRotationRollPitchYaw.
+    pub fn from_pitch_yaw_roll(pitch: f32, yaw: f32, roll: f32) -> Quat {
+        Quat::from_axis_angle(Vec3::Z, roll)
+            * Quat::from_axis_angle(Vec3::X, pitch)
+            * Quat::from_axis_angle(Vec3::Y, yaw)
+    }
+
+    // From the upper 3x3 of m, which has to be a rotation without any scale.
+    pub fn from_rotation_matrix(m: &Mat4) -> Quat {
+        let m = &m.rows;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        // Divide by whichever component is largest, so it's nowhere near zero.
+        let q = if trace > 0.0 {
+            let s = 0.5 / (trace + 1.0).sqrt();
+            Quat::new(
+                (m[1][2] - m[2][1]) * s,
+                (m[2][0] - m[0][2]) * s,
+                (m[0][1] - m[1][0]) * s,
+                0.25 / s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quat::new(
+                0.25 * s,
+                (m[0][1] + m[1][0]) / s,
+                (m[2][0] + m[0][2]) / s,
+                (m[1][2] - m[2][1]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quat::new(
+                (m[0][1] + m[1][0]) / s,
+                0.25 * s,
+                (m[1][2] + m[2][1]) / s,
+                (m[2][0] - m[0][2]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quat::new(
+                (m[2][0] + m[0][2]) / s,
+                (m[1][2] + m[2][1]) / s,
+                0.25 * s,
+                (m[0][1] - m[1][0]) / s,
+            )
+        };
+        q.normalize()
+    }
+
+    fn to_vec4(self) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, self.w)
+    }
+
+    fn from_vec4(v: Vec4) -> Quat {
+        Quat::new(v.x, v.y, v.z, v.w)
+    }
+
+    pub fn dot(self, other: Quat) -> f32 {
+        self.to_vec4().dot(other.to_vec4())
+    }
+
+    pub fn length(self) -> f32 {
+        self.to_vec4().length()
+    }
+
+    pub fn normalize(self) -> Quat {
+        Quat::from_vec4(self.to_vec4().normalize())
+    }
+
+    // The inverse of a normalized quaternion.
+    pub fn conjugate(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(self) -> Quat {
+        Quat::from_vec4(self.conjugate().to_vec4() / self.dot(self))
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = axis.cross(v) * 2.0;
+        v + t * self.w + axis.cross(t)
+    }
+
+    // Takes the shorter way around, and falls back to a normalized lerp when the rotations
+    // are too close together for the angle between them to mean much.
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut cos = self.dot(other);
+        let mut other = other.to_vec4();
+        if cos < 0.0 {
+            cos = -cos;
+            other = -other;
+        }
+
+        let from = self.to_vec4();
+        if cos > 0.9995 {
+            return Quat::from_vec4(from.lerp(other, t).normalize());
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+        let a = ((1.0 - t) * angle).sin() / sin;
+        let b = (t * angle).sin() / sin;
+        Quat::from_vec4(from * a + other * b)
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+    // self first, then other, which is the Hamilton product other * self.
+    fn mul(self, other: Quat) -> Quat {
+        let (a, b) = (other, self);
+        Quat::new(
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+        )
+    }
+}
+
+// The points p where dot(normal, p) + d is zero. The side the normal points to is in front,
+// where the distance is positive.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vec3, d: f32) -> Plane {
+        Plane { normal, d }
+    }
+
+    pub fn from_point_normal(point: Vec3, normal: Vec3) -> Plane {
+        let normal = normal.normalize();
+        Plane::new(normal, -normal.dot(point))
+    }
+
+    // The normal is cross(b - a, c - a), so it faces the side the points look clockwise from
+    // in a left-handed world, the same as a D3D front face.
+    pub fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Plane {
+        Plane::from_point_normal(a, (b - a).cross(c - a))
+    }
+
+    // Scales the plane so the normal has unit length, which makes get_distance a distance.
+    // A plane without a normal is left alone.
+    pub fn normalize(self) -> Plane {
+        let length = self.normal.length();
+        if length > 0.0 {
+            Plane::new(self.normal / length, self.d / length)
+        } else {
+            self
+        }
+    }
+
+    // Signed, and only in world units when the plane is normalized.
+    pub fn get_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+impl From<Vec4> for Plane {
+    fn from(v: Vec4) -> Plane {
+        Plane::new(v.truncate(), v.w)
+    }
+}
+
+// direction doesn't have to be normalized. Intersections give t, the hit being at get_point(t),
+// and only count in front of the origin.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    // The ray through a point on the screen, e.g. the mouse, for picking. x and y are pixels in
+    // a viewport width by height in size, the matrix is the camera's view * projection and the
+    // ray starts on the near plane. None if the matrix can't be inverted.
+    pub fn from_screen_point(
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        view_projection: &Mat4,
+        depth: DepthMode,
+    ) -> Option<Ray> {
+        let inverse = view_projection.inverse()?;
+        let (ndc_x, ndc_y) = (2.0 * x / width - 1.0, 1.0 - 2.0 * y / height);
+        // Anything between the near and far plane works for the direction, and halfway is
+        // still a finite distance away when the far plane isn't.
+        let near_z = match depth {
+            DepthMode::Standard => 0.0,
+            DepthMode::Reversed => 1.0,
+        };
+        let near = inverse.project_point(Vec3::new(ndc_x, ndc_y, near_z));
+        let middle = inverse.project_point(Vec3::new(ndc_x, ndc_y, 0.5));
+        Some(Ray::new(near, middle - near))
+    }
+
+    pub fn get_point(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // From either side of the plane. None if the ray runs parallel to it.
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let speed = plane.normal.dot(self.direction);
+        if speed == 0.0 {
+            return None;
+        }
+        let t = -plane.get_distance(self.origin) / speed;
+        (t >= 0.0).then_some(t)
+    }
+
+    // 0 if the ray starts inside the box.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0_f32;
+        let mut far = f32::INFINITY;
+        let origin = self.origin.to_array();
+        let direction = self.direction.to_array();
+        let (min, max) = (aabb.min.to_array(), aabb.max.to_array());
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                // Parallel to this pair of sides, so it has to be between them already.
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inverse = 1.0 / direction[axis];
+            let a = (min[axis] - origin[axis]) * inverse;
+            let b = (max[axis] - origin[axis]) * inverse;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    // 0 if the ray starts inside the sphere.
+    pub fn intersect_sphere(&self, center: Vec3, radius: f32) -> Option<f32> {
+        let offset = self.origin - center;
+        let a = self.direction.length_squared();
+        let b = offset.dot(self.direction);
+        let c = offset.length_squared() - radius * radius;
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+        let discriminant = b * b - a * c;
+        if a == 0.0 || discriminant < 0.0 {
+            return None;
+        }
+        let t = (-b - discriminant.sqrt()) / a;
+        (t >= 0.0).then_some(t)
+    }
+
+    // Hits the triangle from either side.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let (ab, ac) = (b - a, c - a);
+        let p = self.direction.cross(ac);
+        let determinant = ab.dot(p);
+        if determinant.abs() <= f32::EPSILON * ab.length() * ac.length() {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let offset = self.origin - a;
+        let u = offset.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = ac.dot(q) * inverse;
+        (t >= 0.0).then_some(t)
+    }
+}
+
+// An axis aligned box. One made from no points has min above max, contains nothing and turns
+// into the other box when joined with one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::splat(f32::INFINITY),
+        max: Vec3::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // extents are half the size.
+    pub fn from_center_extents(center: Vec3, extents: Vec3) -> Aabb {
+        Aabb::new(center - extents, center + extents)
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, &point| aabb.add_point(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn get_center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn get_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    // The corners that have x at min come first, then in the same way for y and z.
+    pub fn get_corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Vec3::new(
+                if i & 4 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 1 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    pub fn add_point(&self, point: Vec3) -> Aabb {
+        Aabb::new(self.min.min(point), self.max.max(point))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        point.x >= self.min.x
+            && point.y >= self.min.y
+            && point.z >= self.min.z
+            && point.x <= self.max.x
+            && point.y <= self.max.y
+            && point.z <= self.max.z
+    }
+
+    // Touching counts.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.min.y <= other.max.y
+            && self.min.z <= other.max.z
+            && other.min.x <= self.max.x
+            && other.min.y <= self.max.y
+            && other.min.z <= self.max.z
+    }
+
+    // The box around the transformed box, which is bigger than the box around the transformed
+    // contents unless the transform keeps the axes lined up.
+    pub fn transform(&self, m: &Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = m.transform_point(self.get_center());
+        let extents = self.get_extents();
+        let absolute = |i: usize| Vec3::from([0, 1, 2].map(|j| m.rows[i][j].abs()));
+        let extents = absolute(0) * extents.x + absolute(1) * extents.y + absolute(2) * extents.z;
+        Aabb::from_center_extents(center, extents)
+    }
+}
+
+// The volume a camera sees, as six planes facing inward.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Frustum {
+    // Left, right, bottom and top, then the planes where depth is 0 and 1, normalized. With an
+    // infinite far plane that one has no normal and everything is in front of it.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // From the camera's view * projection, or just the projection for a frustum in view space.
+    // Works with either DepthMode since both keep depth in [0, 1].
+    pub fn from_matrix(m: &Mat4) -> Frustum {
+        // Clip space has -w <= x <= w, -w <= y <= w and 0 <= z <= w, and each clip coordinate
+        // is a point dotted with a column.
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| m.get_column(i));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|p| Plane::from(p).normalize());
+        Frustum { planes }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|p| p.get_distance(point) >= 0.0)
+    }
+
+    // Can say a sphere near a corner intersects when it doesn't, which is fine for culling.
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|p| p.get_distance(center) >= -radius)
+    }
+
+    // Like intersects_sphere this errs on the side of intersecting.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.get_center();
+        let extents = aabb.get_extents();
+        self.planes.iter().all(|p| {
+            // How far the box reaches toward the plane's normal.
+            let reach = p.normal.abs().dot(extents);
+            p.get_distance(center) >= -reach
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        let close = (a - b).to_array().iter().all(|d| d.abs() <= EPSILON);
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_mat4_eq(a: &Mat4, b: &Mat4) {
+        for (row_a, row_b) in a.rows.iter().zip(&b.rows) {
+            for (&x, &y) in row_a.iter().zip(row_b) {
+                assert!((x - y).abs() <= EPSILON, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    // q and -q are the same rotation.
+    fn assert_quat_eq(a: Quat, b: Quat) {
+        assert!(a.dot(b).abs() >= 1.0 - EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn rows(rows: [[f32; 4]; 4]) -> Mat4 {
+        Mat4::from(rows)
+    }
+
+    #[test]
+    fn look_at_matches_directxmath() {
+        // XMMatrixLookAtLH({0, 0, -5}, {0, 0, 0}, {0, 1, 0})
+        let m = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        let expected = rows([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 5.0, 1.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+
+        // XMMatrixLookAtLH({3, 4, 5}, {0, 4, 5}, {0, 1, 0}), looking down -x.
+        let m = Mat4::look_at_lh(Vec3::new(3.0, 4.0, 5.0), Vec3::new(0.0, 4.0, 5.0), Vec3::Y);
+        let expected = rows([
+            [0.0, 0.0, -1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [-5.0, -4.0, 3.0, 1.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+
+        // The eye ends up at the origin and the target straight ahead.
+        let eye = Vec3::new(1.0, 2.0, 3.0);
+        let target = Vec3::new(-4.0, 0.5, 7.0);
+        let m = Mat4::look_at_lh(eye, target, Vec3::Y);
+        assert_vec3_eq(m.transform_point(eye), Vec3::ZERO);
+        assert_vec3_eq(
+            m.transform_point(target),
+            Vec3::new(0.0, 0.0, eye.distance(target)),
+        );
+    }
+
+    #[test]
+    fn perspective_matches_directxmath() {
+        // XMMatrixPerspectiveFovLH(XM_PIDIV2, 1, 1, 101)
+        let m = Mat4::perspective_fov_lh(FRAC_PI_2, 1.0, 1.0, 101.0, DepthMode::Standard);
+        let expected = rows([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.01, 1.0],
+            [0.0, 0.0, -1.01, 0.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+
+        // XMMatrixPerspectiveFovLH(XM_PIDIV2, 2, 101, 1), reversed by swapping near and far.
+        let m = Mat4::perspective_fov_lh(FRAC_PI_2, 2.0, 1.0, 101.0, DepthMode::Reversed);
+        let expected = rows([
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, -0.01, 1.0],
+            [0.0, 0.0, 1.01, 0.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+
+        // XMMatrixPerspectiveLH(2, 1, 1, 11)
+        let m = Mat4::perspective_lh(2.0, 1.0, 1.0, 11.0, DepthMode::Standard);
+        let expected = rows([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 0.0],
+            [0.0, 0.0, 1.1, 1.0],
+            [0.0, 0.0, -1.1, 0.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+    }
+
+    #[test]
+    fn perspective_depth_range() {
+        let (near, far) = (0.5, 200.0);
+        for &(depth, near_depth, far_depth) in &[
+            (DepthMode::Standard, 0.0, 1.0),
+            (DepthMode::Reversed, 1.0, 0.0),
+        ] {
+            let m = Mat4::perspective_fov_lh(FRAC_PI_4, 1.5, near, far, depth);
+            assert!((m.project_point(Vec3::new(0.0, 0.0, near)).z - near_depth).abs() < EPSILON);
+            assert!((m.project_point(Vec3::new(0.0, 0.0, far)).z - far_depth).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn infinite_perspective() {
+        let near = 0.1;
+        let m = Mat4::perspective_fov_lh(FRAC_PI_2, 1.0, near, f32::INFINITY, DepthMode::Standard);
+        assert_eq!(m.rows[2], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(m.rows[3], [0.0, 0.0, -near, 0.0]);
+        assert!(m.project_point(Vec3::new(0.0, 0.0, near)).z.abs() < EPSILON);
+        assert!(m.project_point(Vec3::new(0.0, 0.0, 1e6)).z < 1.0);
+
+        let m = Mat4::perspective_fov_lh(FRAC_PI_2, 1.0, near, f32::INFINITY, DepthMode::Reversed);
+        assert_eq!(m.rows[2], [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(m.rows[3], [0.0, 0.0, near, 0.0]);
+        assert!((m.project_point(Vec3::new(0.0, 0.0, near)).z - 1.0).abs() < EPSILON);
+        assert!(m.project_point(Vec3::new(0.0, 0.0, 1e6)).z > 0.0);
+    }
+
+    #[test]
+    fn orthographic_matches_directxmath() {
+        // XMMatrixOrthographicLH(4, 2, 1, 11)
+        let m = Mat4::orthographic_lh(4.0, 2.0, 1.0, 11.0, DepthMode::Standard);
+        let expected = rows([
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.1, 0.0],
+            [0.0, 0.0, -0.1, 1.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+
+        // XMMatrixOrthographicLH(4, 2, 11, 1)
+        let m = Mat4::orthographic_lh(4.0, 2.0, 1.0, 11.0, DepthMode::Reversed);
+        let expected = rows([
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, -0.1, 0.0],
+            [0.0, 0.0, 1.1, 1.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+
+        // XMMatrixOrthographicOffCenterLH(0, 800, 600, 0, 0, 1), the usual 2D one with y down.
+        let m =
+            Mat4::orthographic_off_center_lh(0.0, 800.0, 600.0, 0.0, 0.0, 1.0, DepthMode::Standard);
+        let expected = rows([
+            [0.0025, 0.0, 0.0, 0.0],
+            [0.0, -1.0 / 300.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-1.0, 1.0, 0.0, 1.0],
+        ]);
+        assert_mat4_eq(&m, &expected);
+    }
+
+    #[test]
+    fn rotations_are_left_handed() {
+        // XMMatrixRotationY(XM_PIDIV2) turns +z toward +x.
+        assert_vec3_eq(
+            Mat4::rotation_y(FRAC_PI_2).transform_vector(Vec3::Z),
+            Vec3::X,
+        );
+        assert_vec3_eq(
+            Mat4::rotation_x(FRAC_PI_2).transform_vector(Vec3::Y),
+            Vec3::Z,
+        );
+        assert_vec3_eq(
+            Mat4::rotation_z(FRAC_PI_2).transform_vector(Vec3::X),
+            Vec3::Y,
+        );
+        assert_mat4_eq(&Mat4::rotation_axis(Vec3::Y, 0.7), &Mat4::rotation_y(0.7));
+    }
+
+    #[test]
+    fn inverse() {
+        let m = Mat4::from_scale_rotation_translation(
+            Vec3::new(2.0, 0.5, 4.0),
+            Quat::from_pitch_yaw_roll(0.3, -1.1, 0.6),
+            Vec3::new(10.0, -3.0, 7.0),
+        );
+        let inverse = m.inverse().unwrap();
+        assert_mat4_eq(&(m * inverse), &Mat4::IDENTITY);
+        assert_mat4_eq(&(inverse * m), &Mat4::IDENTITY);
+
+        let m = Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::scaling(Vec3::splat(2.0));
+        let expected = rows([
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 0.5, 0.0, 0.0],
+            [0.0, 0.0, 0.5, 0.0],
+            [-1.0, -2.0, -3.0, 1.0],
+        ]);
+        assert_mat4_eq(&m.inverse().unwrap(), &expected);
+        assert!((m.determinant() - 8.0).abs() < EPSILON);
+
+        let projection = Mat4::perspective_fov_lh(1.0, 1.5, 0.1, 100.0, DepthMode::Standard);
+        assert_mat4_eq(
+            &(projection * projection.inverse().unwrap()),
+            &Mat4::IDENTITY,
+        );
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        assert_eq!(Mat4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+        let mut m = Mat4::IDENTITY;
+        m.rows[2] = m.rows[1];
+        assert_eq!(m.inverse(), None);
+    }
+
+    #[test]
+    fn quat_from_pitch_yaw_roll_matches_directxmath() {
+        // XMQuaternionRotationRollPitchYaw(XM_PIDIV2, 0, 0)
+        let (s, c) = FRAC_PI_4.sin_cos();
+        assert_quat_eq(
+            Quat::from_pitch_yaw_roll(FRAC_PI_2, 0.0, 0.0),
+            Quat::new(s, 0.0, 0.0, c),
+        );
+        // XMQuaternionRotationRollPitchYaw(0.1, 0.2, 0.3)
+        assert_quat_eq(
+            Quat::from_pitch_yaw_roll(0.1, 0.2, 0.3),
+            Quat::new(0.064071, 0.091158, 0.143572, 0.983347),
+        );
+
+        // XMMatrixRotationRollPitchYaw is roll * pitch * yaw.
+        let (pitch, yaw, roll) = (0.4, -1.3, 2.2);
+        assert_mat4_eq(
+            &Mat4::from_quat(Quat::from_pitch_yaw_roll(pitch, yaw, roll)),
+            &(Mat4::rotation_z(roll) * Mat4::rotation_x(pitch) * Mat4::rotation_y(yaw)),
+        );
+    }
+
+    #[test]
+    fn quat_from_rotation_matrix() {
+        // Each of the four branches, depending on which component is largest.
+        let rotations = [
+            Quat::from_pitch_yaw_roll(0.2, 0.3, -0.1),
+            Quat::from_axis_angle(Vec3::X, PI),
+            Quat::from_axis_angle(Vec3::Y, PI),
+            Quat::from_axis_angle(Vec3::Z, PI),
+            Quat::from_axis_angle(Vec3::new(1.0, 2.0, -3.0), 2.5),
+        ];
+        for &q in &rotations {
+            assert_quat_eq(Quat::from_rotation_matrix(&Mat4::from_quat(q)), q);
+        }
+    }
+
+    #[test]
+    fn quat_rotates_like_its_matrix() {
+        let q = Quat::from_axis_angle(Vec3::new(0.3, -1.0, 0.5), 1.2);
+        let v = Vec3::new(2.0, -1.0, 0.5);
+        assert_vec3_eq(q.rotate(v), Mat4::from_quat(q).transform_vector(v));
+
+        let r = Quat::from_axis_angle(Vec3::X, 0.4);
+        assert_mat4_eq(
+            &Mat4::from_quat(q * r),
+            &(Mat4::from_quat(q) * Mat4::from_quat(r)),
+        );
+    }
+
+    #[test]
+    fn frustum_from_matrix() {
+        let view = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO, Vec3::Y);
+        for &depth in &[DepthMode::Standard, DepthMode::Reversed] {
+            let projection = Mat4::perspective_fov_lh(FRAC_PI_2, 1.0, 1.0, 100.0, depth);
+            let frustum = Frustum::from_matrix(&(view * projection));
+
+            for plane in &frustum.planes {
+                assert!((plane.normal.length() - 1.0).abs() < EPSILON);
+            }
+            // The left plane of a 90 degree frustum faces right and in at 45 degrees.
+            let left = frustum.planes[0];
+            let (s, _) = FRAC_PI_4.sin_cos();
+            assert_vec3_eq(left.normal, Vec3::new(s, 0.0, s));
+
+            assert!(frustum.contains_point(Vec3::ZERO));
+            assert!(frustum.contains_point(Vec3::new(0.0, 0.0, 89.0)));
+            assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 91.0)));
+            assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -9.5)));
+            assert!(!frustum.contains_point(Vec3::new(11.0, 0.0, 0.0)));
+            assert!(!frustum.contains_point(Vec3::new(0.0, -11.0, 0.0)));
+            assert!(frustum.intersects_sphere(Vec3::new(11.0, 0.0, 0.0), 1.0));
+            assert!(!frustum.intersects_sphere(Vec3::new(20.0, 0.0, 0.0), 1.0));
+            assert!(frustum.intersects_aabb(&Aabb::from_center_extents(
+                Vec3::new(11.0, 0.0, 0.0),
+                Vec3::ONE,
+            )));
+            assert!(!frustum.intersects_aabb(&Aabb::from_center_extents(
+                Vec3::new(0.0, 0.0, -20.0),
+                Vec3::ONE,
+            )));
+        }
+    }
+
+    #[test]
+    fn infinite_frustum_has_no_far_plane() {
+        let projection =
+            Mat4::perspective_fov_lh(FRAC_PI_2, 1.0, 1.0, f32::INFINITY, DepthMode::Reversed);
+        let frustum = Frustum::from_matrix(&projection);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, 1e9)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 0.5)));
+    }
+
+    #[test]
+    fn ray_from_screen_point() {
+        let eye = Vec3::new(0.0, 0.0, -10.0);
+        let view = Mat4::look_at_lh(eye, Vec3::ZERO, Vec3::Y);
+        let cases = [
+            (100.0, DepthMode::Standard),
+            (100.0, DepthMode::Reversed),
+            (f32::INFINITY, DepthMode::Reversed),
+        ];
+        for &(far, depth) in &cases {
+            let projection = Mat4::perspective_fov_lh(FRAC_PI_2, 2.0, 1.0, far, depth);
+            let view_projection = view * projection;
+
+            // The middle of the screen looks straight ahead from the near plane.
+            let ray = Ray::from_screen_point(400.0, 200.0, 800.0, 400.0, &view_projection, depth)
+                .unwrap();
+            assert_vec3_eq(ray.origin, Vec3::new(0.0, 0.0, -9.0));
+            assert_vec3_eq(ray.direction.normalize(), Vec3::Z);
+
+            // The top left corner, with x twice as far out as y for the aspect ratio.
+            let ray =
+                Ray::from_screen_point(0.0, 0.0, 800.0, 400.0, &view_projection, depth).unwrap();
+            assert_vec3_eq(ray.origin, Vec3::new(-2.0, 1.0, -9.0));
+            assert_vec3_eq(
+                ray.direction.normalize(),
+                Vec3::new(-2.0, 1.0, 1.0).normalize(),
+            );
+        }
+
+        let singular = Mat4::scaling(Vec3::ZERO);
+        assert_eq!(
+            Ray::from_screen_point(0.0, 0.0, 1.0, 1.0, &singular, DepthMode::Standard),
+            None
+        );
+    }
+
+    #[test]
+    fn ray_intersections() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 2.0));
+        let plane = Plane::from_point_normal(Vec3::ZERO, -Vec3::Z);
+        assert_eq!(ray.intersect_plane(&plane), Some(2.5));
+        let aabb = Aabb::from_center_extents(Vec3::ZERO, Vec3::ONE);
+        assert_eq!(ray.intersect_aabb(&aabb), Some(2.0));
+        assert_eq!(ray.intersect_sphere(Vec3::ZERO, 1.0), Some(2.0));
+        let t = ray
+            .intersect_triangle(
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+            )
+            .unwrap();
+        assert!((t - 2.5).abs() < EPSILON);
+
+        let away = Ray::new(ray.origin, -ray.direction);
+        assert_eq!(away.intersect_plane(&plane), None);
+        assert_eq!(away.intersect_aabb(&aabb), None);
+        assert_eq!(away.intersect_sphere(Vec3::ZERO, 1.0), None);
+    }
+}
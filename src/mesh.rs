@@ -0,0 +1,443 @@
+// Models as the engine keeps them: meshes split into parts by material, the materials and the
+// textures they use, and a hierarchy of nodes placing the meshes. Read from glTF 2.0 and Wavefront
+// OBJ files without touching D3D, see GpuModel for the buffers and textures.
+//
+// Positions are as the file has them, which for both formats is right handed with +y up.
+// Texture coordinates have (0, 0) at the top left of the image. Matrices are row major and
+// points are multiplied from the left, like in the rest of the renderer.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gltf;
+use math::{Mat4, Vec3};
+use obj;
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// The input layout is in gpu_model.rs.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    // xyz points along +u, w is 1 or -1 and the bitangent, along +v, is cross(normal, xyz) * w.
+    pub tangent: [f32; 4],
+    pub texcoord: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl Default for MeshVertex {
+    fn default() -> MeshVertex {
+        MeshVertex {
+            position: [0.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+            texcoord: [0.0; 2],
+            color: [1.0; 4],
+        }
+    }
+}
+
+// Where the bytes of an image file come from. Either way they're for Image::from_bytes.
+#[derive(Clone, PartialEq, Debug)]
+pub enum TextureSource {
+    File(PathBuf),
+    // Inside the model file, or in a data URI.
+    Data(Vec<u8>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    // Pixels with less alpha than the cutoff are discarded, the rest are opaque.
+    Mask(f32),
+    Blend,
+}
+
+// Metallic-roughness PBR parameters, as glTF has them. OBJ materials are converted as well as
+// they can be. The textures are indices into Model::textures and multiply the factors.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Material {
+    pub name: String,
+    pub base_color: [f32; 4],
+    // sRGB encoded.
+    pub base_color_texture: Option<usize>,
+    pub metallic: f32,
+    pub roughness: f32,
+    // Roughness in green and metalness in blue.
+    pub metallic_roughness_texture: Option<usize>,
+    // Tangent space, scaled in x and y by normal_scale.
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    pub emissive: [f32; 3],
+    // sRGB encoded.
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            name: String::new(),
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+// A range of a mesh's triangle list drawn with one material.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MeshPart {
+    // Index into Model::materials. None gets the default material.
+    pub material: Option<usize>,
+    pub start_index: u32,
+    pub index_count: u32,
+}
+
+// An indexed triangle list.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Mesh {
+    pub name: String,
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    pub parts: Vec<MeshPart>,
+}
+
+impl Mesh {
+    // Adds a part drawn with the given material, offsetting the indices past the vertices the
+    // mesh already has.
+    pub fn append(&mut self, vertices: &[MeshVertex], indices: &[u32], material: Option<usize>) {
+        let base_vertex = self.vertices.len() as u32;
+        self.parts.push(MeshPart {
+            material,
+            start_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+        });
+        self.vertices.extend_from_slice(vertices);
+        self.indices
+            .extend(indices.iter().map(|&i| i + base_vertex));
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Node {
+    pub name: String,
+    // Relative to the parent.
+    pub transform: [[f32; 4]; 4],
+    // Index into Model::meshes.
+    pub mesh: Option<usize>,
+    // Indices into Model::nodes.
+    pub children: Vec<usize>,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<TextureSource>,
+    pub nodes: Vec<Node>,
+    // The nodes without a parent, in Model::nodes.
+    pub roots: Vec<usize>,
+    // What the loader left out instead of failing, like an OBJ material library that isn't there.
+    pub warnings: Vec<String>,
+}
+
+impl Model {
+    // .obj files are read as OBJ, anything else as glTF, either .gltf or .glb. Files the model
+    // refers to are looked for relative to it.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Model> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let data = fs::read(path)?;
+
+        let is_obj = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("obj"));
+        if is_obj {
+            obj::decode(&data, directory)
+        } else {
+            gltf::decode(&data, directory)
+        }
+    }
+
+    // Where each node ends up, by multiplying down from the roots. Nodes that aren't reachable
+    // from a root keep their own transform.
+    pub fn get_world_transforms(&self) -> Vec<[[f32; 4]; 4]> {
+        let mut transforms: Vec<[[f32; 4]; 4]> = self.nodes.iter().map(|n| n.transform).collect();
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = self.roots.clone();
+        while let Some(index) = stack.pop() {
+            // Loaders check for cycles, this is for models put together by hand.
+            if std::mem::replace(&mut visited[index], true) {
+                continue;
+            }
+            for &child in &self.nodes[index].children {
+                let transform = Mat4::from(self.nodes[child].transform) * transforms[index].into();
+                transforms[child] = transform.into();
+                stack.push(child);
+            }
+        }
+        transforms
+    }
+}
+
+// Smooth normals weighted by triangle area. Vertices in the same place share a normal even if
+// they were split for other reasons, like texture seams, so the seams don't show in the shading.
+pub fn compute_smooth_normals(vertices: &mut [MeshVertex], indices: &[u32]) {
+    let key = |p: [f32; 3]| [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+    let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        // Twice the area long, which is the weight.
+        let normal = get_face_normal(&get_triangle(vertices, triangle));
+        for &i in triangle {
+            *sums
+                .entry(key(vertices[i as usize].position))
+                .or_insert(Vec3::ZERO) += normal;
+        }
+    }
+
+    for vertex in vertices.iter_mut() {
+        if let Some(&sum) = sums.get(&key(vertex.position)) {
+            vertex.normal = normalize_or(sum, Vec3::Z).into();
+        }
+    }
+}
+
+// Gives each triangle its own vertices with the triangle's normal, so the mesh looks faceted.
+pub fn compute_flat_normals(
+    vertices: &[MeshVertex],
+    indices: &[u32],
+) -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut flat_vertices = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let triangle = get_triangle(vertices, triangle);
+        let normal = normalize_or(get_face_normal(&triangle), Vec3::Z).into();
+        for &vertex in &triangle {
+            flat_vertices.push(MeshVertex { normal, ..vertex });
+        }
+    }
+    let flat_indices = (0..flat_vertices.len() as u32).collect();
+    (flat_vertices, flat_indices)
+}
+
+// Tangents from the texture coordinates, needs the normals to be there already. Vertices that
+// have no usable texture coordinates get some tangent perpendicular to the normal.
+pub fn compute_tangents(vertices: &mut [MeshVertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = get_triangle(vertices, triangle);
+        let edge1 = Vec3::from(b.position) - a.position.into();
+        let edge2 = Vec3::from(c.position) - a.position.into();
+        let du1 = b.texcoord[0] - a.texcoord[0];
+        let dv1 = b.texcoord[1] - a.texcoord[1];
+        let du2 = c.texcoord[0] - a.texcoord[0];
+        let dv2 = c.texcoord[1] - a.texcoord[1];
+
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() < 1e-12 {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * dv2 - edge2 * dv1) * r;
+        let bitangent = (edge2 * du1 - edge1 * du2) * r;
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+            bitangents[i as usize] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let n = Vec3::from(vertex.normal);
+        // Gram-Schmidt, so the tangent is perpendicular to the normal.
+        let t = normalize_or(tangents[i] - n * n.dot(tangents[i]), get_perpendicular(n));
+        let w = if n.cross(t).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = t.extend(w).into();
+    }
+}
+
+fn get_triangle(vertices: &[MeshVertex], triangle: &[u32]) -> [MeshVertex; 3] {
+    [
+        vertices[triangle[0] as usize],
+        vertices[triangle[1] as usize],
+        vertices[triangle[2] as usize],
+    ]
+}
+
+// Not normalized, as long as twice the area of the triangle.
+fn get_face_normal([a, b, c]: &[MeshVertex; 3]) -> Vec3 {
+    let a_position = Vec3::from(a.position);
+    (Vec3::from(b.position) - a_position).cross(Vec3::from(c.position) - a_position)
+}
+
+// Degenerate vectors become the fallback instead of NaNs, where Vec3::normalize gives zero.
+fn normalize_or(v: Vec3, fallback: Vec3) -> Vec3 {
+    let length = v.length();
+    if length > 1e-20 {
+        v * (1.0 / length)
+    } else {
+        fallback
+    }
+}
+
+fn get_perpendicular(n: Vec3) -> Vec3 {
+    let other = if n.x.abs() < 0.9 { Vec3::X } else { Vec3::Y };
+    normalize_or(other.cross(n), Vec3::X)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::Vec4;
+
+    fn get_fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn vertex(position: [f32; 3], texcoord: [f32; 2]) -> MeshVertex {
+        MeshVertex {
+            position,
+            texcoord,
+            ..MeshVertex::default()
+        }
+    }
+
+    #[test]
+    fn load_picks_the_format_by_extension() {
+        let obj = Model::load(get_fixture("quad.obj")).unwrap();
+        assert_eq!(obj.meshes.len(), 2);
+        let gltf = Model::load(get_fixture("quad.gltf")).unwrap();
+        assert_eq!(gltf.meshes.len(), 1);
+        assert!(Model::load(get_fixture("missing.gltf")).is_err());
+    }
+
+    #[test]
+    fn append_offsets_indices() {
+        let mut mesh = Mesh::default();
+        let triangle = [
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        mesh.append(&triangle, &[0, 1, 2], None);
+        mesh.append(&triangle, &[2, 1, 0], Some(3));
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, [0, 1, 2, 5, 4, 3]);
+        assert_eq!(
+            mesh.parts[1],
+            MeshPart {
+                material: Some(3),
+                start_index: 3,
+                index_count: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn world_transforms() {
+        let translation = |x: f32| {
+            let mut m = Mat4::IDENTITY.rows;
+            m[3][0] = x;
+            m
+        };
+        let node = |x: f32, children: Vec<usize>| Node {
+            name: String::new(),
+            transform: translation(x),
+            mesh: None,
+            children,
+        };
+        let model = Model {
+            nodes: vec![node(1.0, vec![1]), node(2.0, vec![2]), node(4.0, vec![])],
+            roots: vec![0],
+            ..Model::default()
+        };
+        let transforms = model.get_world_transforms();
+        let offsets: Vec<f32> = transforms.iter().map(|m| m[3][0]).collect();
+        assert_eq!(offsets, [1.0, 3.0, 7.0]);
+    }
+
+    #[test]
+    fn flat_normals() {
+        let vertices = [
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 0.0, 1.0], [0.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        let (flat_vertices, flat_indices) = compute_flat_normals(&vertices, &[0, 2, 1, 0, 1, 3]);
+        assert_eq!(flat_indices, [0, 1, 2, 3, 4, 5]);
+        let normals: Vec<[f32; 3]> = flat_vertices.iter().map(|v| v.normal).collect();
+        assert_eq!(normals[..3], [[0.0, 1.0, 0.0]; 3]);
+        assert_eq!(normals[3..], [[0.0, 0.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn smooth_normals_are_shared_across_seams() {
+        // Two triangles folded along x, with the vertices on the fold split.
+        let mut vertices = [
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 0.0, 1.0], [0.0, 1.0]),
+            vertex([0.0, 0.0, 0.0], [0.5, 0.0]),
+            vertex([1.0, 0.0, 0.0], [0.5, 1.0]),
+            vertex([0.0, -1.0, 0.0], [0.0, 1.0]),
+        ];
+        compute_smooth_normals(&mut vertices, &[0, 2, 1, 3, 4, 5]);
+        let s = 0.5f32.sqrt();
+        assert_eq!(vertices[0].normal, vertices[3].normal);
+        assert!((vertices[0].normal[1] - s).abs() < 1e-6);
+        assert!((vertices[0].normal[2] + s).abs() < 1e-6);
+        assert_eq!(vertices[2].normal, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn tangents_follow_texcoords() {
+        let mut vertices = [
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+        ];
+        for v in vertices.iter_mut() {
+            v.normal = [0.0, 0.0, -1.0];
+        }
+        compute_tangents(&mut vertices, &[0, 1, 2]);
+        for v in &vertices {
+            assert_eq!(v.tangent, [1.0, 0.0, 0.0, 1.0]);
+        }
+
+        // Without texture coordinates it's still perpendicular to the normal.
+        let mut vertices = [
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [0.0, 0.0]),
+        ];
+        compute_tangents(&mut vertices, &[0, 1, 2]);
+        for v in &vertices {
+            let t = Vec4::from(v.tangent).truncate();
+            assert!(t.dot(v.normal.into()).abs() < 1e-6);
+            assert!((t.length() - 1.0).abs() < 1e-6);
+        }
+    }
+}
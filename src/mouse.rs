@@ -0,0 +1,409 @@
+use winapi::shared::minwindef::{FALSE, LPARAM, TRUE, UINT, WPARAM};
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::shared::windowsx::{GET_X_LPARAM, GET_Y_LPARAM};
+use winapi::um::winuser::{
+    ClipCursor, GetClientRect, GetCursorPos, GetRawInputData, MapWindowPoints,
+    RegisterRawInputDevices, ScreenToClient, SetCursorPos, ShowCursor, GET_WHEEL_DELTA_WPARAM,
+    GET_XBUTTON_WPARAM, HRAWINPUT, MOUSE_MOVE_ABSOLUTE, RAWINPUT, RAWINPUTDEVICE, RAWINPUTHEADER,
+    RID_INPUT, RIM_TYPEMOUSE, WM_ACTIVATEAPP, WM_INPUT, WM_LBUTTONDOWN, WM_LBUTTONUP,
+    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_MOVE, WM_RBUTTONDOWN,
+    WM_RBUTTONUP, WM_SIZE, WM_XBUTTONDOWN, WM_XBUTTONUP, XBUTTON1,
+};
+
+const HID_USAGE_PAGE_GENERIC: u16 = 0x01;
+const HID_USAGE_GENERIC_MOUSE: u16 = 0x02;
+
+const LEFT_BUTTON: u8 = 0x01;
+const MIDDLE_BUTTON: u8 = 0x02;
+const RIGHT_BUTTON: u8 = 0x04;
+const X_BUTTON1: u8 = 0x08;
+const X_BUTTON2: u8 = 0x10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MouseMode {
+    // x and y are the cursor position in client coordinates.
+    #[default]
+    Absolute,
+    // x and y are the raw movement since the last update. The cursor is hidden and kept
+    // inside the window.
+    Relative,
+}
+
+// Pretty much a port of the DirectXTK Mouse.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MouseState {
+    pub left_button: bool,
+    pub middle_button: bool,
+    pub right_button: bool,
+    pub x_button1: bool,
+    pub x_button2: bool,
+    pub x: i32,
+    pub y: i32,
+    // Accumulated wheel movement in multiples of WHEEL_DELTA (120) per notch.
+    pub scroll_wheel_value: i32,
+    pub position_mode: MouseMode,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    X1,
+    X2,
+}
+
+impl MouseButton {
+    pub fn from_name(name: &str) -> Option<MouseButton> {
+        match name {
+            "Left" => Some(MouseButton::Left),
+            "Middle" => Some(MouseButton::Middle),
+            "Right" => Some(MouseButton::Right),
+            "X1" => Some(MouseButton::X1),
+            "X2" => Some(MouseButton::X2),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match *self {
+            MouseButton::Left => "Left",
+            MouseButton::Middle => "Middle",
+            MouseButton::Right => "Right",
+            MouseButton::X1 => "X1",
+            MouseButton::X2 => "X2",
+        }
+    }
+}
+
+impl MouseState {
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        match button {
+            MouseButton::Left => self.left_button,
+            MouseButton::Middle => self.middle_button,
+            MouseButton::Right => self.right_button,
+            MouseButton::X1 => self.x_button1,
+            MouseButton::X2 => self.x_button2,
+        }
+    }
+
+    fn from_buttons(buttons: u8) -> MouseState {
+        MouseState {
+            left_button: buttons & LEFT_BUTTON != 0,
+            middle_button: buttons & MIDDLE_BUTTON != 0,
+            right_button: buttons & RIGHT_BUTTON != 0,
+            x_button1: buttons & X_BUTTON1 != 0,
+            x_button2: buttons & X_BUTTON2 != 0,
+            ..MouseState::default()
+        }
+    }
+}
+
+pub struct Mouse {
+    window: HWND,
+    mode: MouseMode,
+    buttons: u8,
+    // Buttons that went down since the last take_state, so quick clicks aren't lost.
+    pressed_since_take: u8,
+    x: i32,
+    y: i32,
+    relative_x: i32,
+    relative_y: i32,
+    scroll_wheel_value: i32,
+    is_cursor_hidden: bool,
+    // Where the cursor was when we switched to relative mode, in client coordinates.
+    saved_cursor_pos: POINT,
+    // False if the window couldn't register for raw input, see set_window.
+    has_raw_input: bool,
+}
+
+impl Mouse {
+    pub fn new() -> Mouse {
+        Mouse {
+            window: std::ptr::null_mut(),
+            mode: MouseMode::Absolute,
+            buttons: 0,
+            pressed_since_take: 0,
+            x: 0,
+            y: 0,
+            relative_x: 0,
+            relative_y: 0,
+            scroll_wheel_value: 0,
+            is_cursor_hidden: false,
+            saved_cursor_pos: POINT { x: 0, y: 0 },
+            has_raw_input: false,
+        }
+    }
+
+    pub unsafe fn set_window(&mut self, window: HWND) {
+        self.window = window;
+
+        // Relative mode only listens to raw input, WM_MOUSEMOVE is subject to pointer ballistics
+        // and stops at the screen edge.
+        let device = RAWINPUTDEVICE {
+            usUsagePage: HID_USAGE_PAGE_GENERIC,
+            usUsage: HID_USAGE_GENERIC_MOUSE,
+            dwFlags: 0,
+            hwndTarget: window,
+        };
+        self.has_raw_input =
+            RegisterRawInputDevices(&device, 1, std::mem::size_of::<RAWINPUTDEVICE>() as u32) != 0;
+        if !self.has_raw_input {
+            eprintln!("Failed to register for raw mouse input, relative mode will use the cursor");
+        }
+    }
+
+    pub unsafe fn process_message(&mut self, message: UINT, w_param: WPARAM, l_param: LPARAM) {
+        match message {
+            WM_ACTIVATEAPP => {
+                if w_param != 0 {
+                    if self.mode == MouseMode::Relative {
+                        self.hide_and_clip_cursor();
+                    }
+                } else {
+                    // Give the cursor back while somebody else has focus, and forget about
+                    // buttons we won't see being released.
+                    self.show_and_release_cursor();
+                    self.buttons = 0;
+                    self.relative_x = 0;
+                    self.relative_y = 0;
+                }
+            }
+            // The clip rect is in screen coordinates so it goes stale when the window moves.
+            WM_SIZE | WM_MOVE if self.mode == MouseMode::Relative && self.is_cursor_hidden => {
+                self.clip_to_window();
+            }
+            WM_INPUT if self.mode == MouseMode::Relative => {
+                self.process_raw_input(l_param as HRAWINPUT);
+            }
+            WM_MOUSEMOVE => {
+                let x = GET_X_LPARAM(l_param);
+                let y = GET_Y_LPARAM(l_param);
+                // Without raw input, relative mode makes do with how far the cursor moved. That
+                // stops at the edges of the window the cursor is clipped to.
+                if self.mode == MouseMode::Relative && !self.has_raw_input {
+                    self.relative_x += x - self.x;
+                    self.relative_y += y - self.y;
+                }
+                self.x = x;
+                self.y = y;
+            }
+            WM_LBUTTONDOWN => self.set_button(LEFT_BUTTON, true),
+            WM_LBUTTONUP => self.set_button(LEFT_BUTTON, false),
+            WM_MBUTTONDOWN => self.set_button(MIDDLE_BUTTON, true),
+            WM_MBUTTONUP => self.set_button(MIDDLE_BUTTON, false),
+            WM_RBUTTONDOWN => self.set_button(RIGHT_BUTTON, true),
+            WM_RBUTTONUP => self.set_button(RIGHT_BUTTON, false),
+            WM_XBUTTONDOWN | WM_XBUTTONUP => {
+                let button = if GET_XBUTTON_WPARAM(w_param) == XBUTTON1 {
+                    X_BUTTON1
+                } else {
+                    X_BUTTON2
+                };
+                self.set_button(button, message == WM_XBUTTONDOWN);
+            }
+            WM_MOUSEWHEEL => {
+                self.scroll_wheel_value += GET_WHEEL_DELTA_WPARAM(w_param) as i32;
+            }
+            _ => {}
+        }
+    }
+
+    unsafe fn process_raw_input(&mut self, handle: HRAWINPUT) {
+        let mut raw: RAWINPUT = std::mem::zeroed();
+        let mut size = std::mem::size_of::<RAWINPUT>() as u32;
+        let read = GetRawInputData(
+            handle,
+            RID_INPUT,
+            &mut raw as *mut RAWINPUT as *mut _,
+            &mut size,
+            std::mem::size_of::<RAWINPUTHEADER>() as u32,
+        );
+        if read == !0 || raw.header.dwType != RIM_TYPEMOUSE {
+            return;
+        }
+
+        let mouse = raw.data.mouse();
+        // Absolute raw input comes from remote desktop and tablets, neither of which make sense
+        // for a relative camera, so we ignore it.
+        if mouse.usFlags & MOUSE_MOVE_ABSOLUTE == 0 {
+            self.relative_x += mouse.lLastX;
+            self.relative_y += mouse.lLastY;
+        }
+    }
+
+    fn set_button(&mut self, button: u8, down: bool) {
+        if down {
+            self.buttons |= button;
+            self.pressed_since_take |= button;
+        } else {
+            self.buttons &= !button;
+        }
+    }
+
+    // The live state. In relative mode x and y are the movement accumulated so far.
+    pub fn get_state(&self) -> MouseState {
+        let mut state = MouseState::from_buttons(self.buttons);
+        state.scroll_wheel_value = self.scroll_wheel_value;
+        state.position_mode = self.mode;
+        if self.mode == MouseMode::Relative {
+            state.x = self.relative_x;
+            state.y = self.relative_y;
+        } else {
+            state.x = self.x;
+            state.y = self.y;
+        }
+        state
+    }
+
+    // Like get_state, but consumes the relative movement and latched clicks. Meant to be
+    // called exactly once per update.
+    pub fn take_state(&mut self) -> MouseState {
+        let mut state = MouseState::from_buttons(self.buttons | self.pressed_since_take);
+        state.scroll_wheel_value = self.scroll_wheel_value;
+        state.position_mode = self.mode;
+        if self.mode == MouseMode::Relative {
+            state.x = self.relative_x;
+            state.y = self.relative_y;
+            self.relative_x = 0;
+            self.relative_y = 0;
+        } else {
+            state.x = self.x;
+            state.y = self.y;
+        }
+
+        self.pressed_since_take = 0;
+        state
+    }
+
+    pub fn reset_scroll_wheel_value(&mut self) {
+        self.scroll_wheel_value = 0;
+    }
+
+    pub fn get_mode(&self) -> MouseMode {
+        self.mode
+    }
+
+    pub unsafe fn set_mode(&mut self, mode: MouseMode) {
+        if self.mode == mode {
+            return;
+        }
+        self.mode = mode;
+        self.relative_x = 0;
+        self.relative_y = 0;
+
+        match mode {
+            MouseMode::Relative => {
+                let mut pos: POINT = std::mem::zeroed();
+                if GetCursorPos(&mut pos) != 0 {
+                    ScreenToClient(self.window, &mut pos);
+                    self.saved_cursor_pos = pos;
+                }
+                self.hide_and_clip_cursor();
+            }
+            MouseMode::Absolute => {
+                self.show_and_release_cursor();
+
+                // Put the cursor back where it was before it disappeared.
+                let mut pos = self.saved_cursor_pos;
+                MapWindowPoints(self.window, std::ptr::null_mut(), &mut pos, 1);
+                SetCursorPos(pos.x, pos.y);
+                self.x = self.saved_cursor_pos.x;
+                self.y = self.saved_cursor_pos.y;
+            }
+        }
+    }
+
+    unsafe fn hide_and_clip_cursor(&mut self) {
+        // ShowCursor keeps a counter, so only ever call it in pairs.
+        if !self.is_cursor_hidden {
+            ShowCursor(FALSE);
+            self.is_cursor_hidden = true;
+        }
+        self.clip_to_window();
+    }
+
+    unsafe fn show_and_release_cursor(&mut self) {
+        if self.is_cursor_hidden {
+            ShowCursor(TRUE);
+            self.is_cursor_hidden = false;
+        }
+        ClipCursor(std::ptr::null());
+    }
+
+    unsafe fn clip_to_window(&self) {
+        let mut rect: RECT = std::mem::zeroed();
+        GetClientRect(self.window, &mut rect);
+
+        // A RECT is two POINTs as far as MapWindowPoints is concerned.
+        MapWindowPoints(
+            self.window,
+            std::ptr::null_mut(),
+            &mut rect as *mut RECT as *mut POINT,
+            2,
+        );
+        ClipCursor(&rect);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ButtonState {
+    #[default]
+    Up,
+    // Down for at least two updates in a row.
+    Held,
+    // Went up this update.
+    Released,
+    // Went down this update.
+    Pressed,
+}
+
+impl ButtonState {
+    fn from_transition(was_down: bool, is_down: bool) -> ButtonState {
+        match (was_down, is_down) {
+            (false, false) => ButtonState::Up,
+            (true, true) => ButtonState::Held,
+            (true, false) => ButtonState::Released,
+            (false, true) => ButtonState::Pressed,
+        }
+    }
+}
+
+// Same idea as the KeyboardStateTracker: update once per update tick.
+#[derive(Default)]
+pub struct MouseButtonStateTracker {
+    pub left_button: ButtonState,
+    pub middle_button: ButtonState,
+    pub right_button: ButtonState,
+    pub x_button1: ButtonState,
+    pub x_button2: ButtonState,
+    // Wheel movement since the previous update.
+    pub scroll_delta: i32,
+    last_state: MouseState,
+}
+
+impl MouseButtonStateTracker {
+    pub fn new() -> MouseButtonStateTracker {
+        MouseButtonStateTracker::default()
+    }
+
+    pub fn update(&mut self, state: &MouseState) {
+        let last = &self.last_state;
+        self.left_button = ButtonState::from_transition(last.left_button, state.left_button);
+        self.middle_button = ButtonState::from_transition(last.middle_button, state.middle_button);
+        self.right_button = ButtonState::from_transition(last.right_button, state.right_button);
+        self.x_button1 = ButtonState::from_transition(last.x_button1, state.x_button1);
+        self.x_button2 = ButtonState::from_transition(last.x_button2, state.x_button2);
+        self.scroll_delta = state.scroll_wheel_value - last.scroll_wheel_value;
+
+        self.last_state = *state;
+    }
+
+    pub fn reset(&mut self) {
+        *self = MouseButtonStateTracker::default();
+    }
+
+    pub fn get_last_state(&self) -> &MouseState {
+        &self.last_state
+    }
+}
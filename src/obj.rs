@@ -0,0 +1,484 @@
+// Wavefront OBJ with its MTL material libraries. Every object, started by an "o" line, becomes a
+// mesh with a part per material and a node of its own. Groups, smoothing groups, lines and
+// points are ignored, and faces with more than three corners are split into fans.
+
+use math::Mat4;
+use mesh::{
+    compute_smooth_normals, compute_tangents, invalid_data, AlphaMode, Material, Mesh, MeshPart,
+    MeshVertex, Model, Node, TextureSource,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Indices into the positions, texture coordinates and normals of the file.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// directory is where the file was, for finding material libraries and textures.
+pub fn decode(data: &[u8], directory: &Path) -> io::Result<Model> {
+    let text = String::from_utf8_lossy(data);
+    let mut reader = ObjReader {
+        directory,
+        model: Model::default(),
+        material_names: HashMap::new(),
+        texture_paths: HashMap::new(),
+        positions: Vec::new(),
+        colors: Vec::new(),
+        texcoords: Vec::new(),
+        normals: Vec::new(),
+        object: Object::new(String::new()),
+        material: None,
+    };
+
+    for (number, line) in join_continued_lines(&text).iter().enumerate() {
+        reader
+            .read_line(line)
+            .map_err(|e| invalid_data(&format!("OBJ line {}: {}", number + 1, e)))?;
+    }
+
+    reader.finish_object();
+    Ok(reader.model)
+}
+
+// A backslash at the end of a line carries it on to the next one.
+fn join_continued_lines(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        match line.strip_suffix('\\') {
+            Some(start) => {
+                current.push_str(start);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn parse_floats(arguments: &[&str]) -> Result<Vec<f32>, String> {
+    arguments
+        .iter()
+        .map(|a| a.parse().map_err(|_| format!("{} isn't a number", a)))
+        .collect()
+}
+
+// The first N arguments, anything after them is left alone.
+fn parse_vector<const N: usize>(arguments: &[&str]) -> Result<[f32; N], String> {
+    if arguments.len() < N {
+        return Err(format!("expected {} numbers", N));
+    }
+    let mut vector = [0.0; N];
+    vector.copy_from_slice(&parse_floats(&arguments[..N])?);
+    Ok(vector)
+}
+
+// OBJ indices start at 1, negative ones count back from the last element so far.
+fn resolve_index(text: &str, count: usize) -> Result<usize, String> {
+    let index: i64 = text
+        .parse()
+        .map_err(|_| format!("{} isn't an index", text))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} is out of range", index));
+    }
+    Ok(resolved as usize)
+}
+
+// The mesh being read.
+struct Object {
+    name: String,
+    vertices: Vec<MeshVertex>,
+    corners: HashMap<Corner, u32>,
+    // Triangles by material, in the order the materials were first used.
+    parts: Vec<(Option<usize>, Vec<u32>)>,
+    missing_normals: bool,
+}
+
+impl Object {
+    fn new(name: String) -> Object {
+        Object {
+            name,
+            vertices: Vec::new(),
+            corners: HashMap::new(),
+            parts: Vec::new(),
+            missing_normals: false,
+        }
+    }
+}
+
+struct ObjReader<'a> {
+    directory: &'a Path,
+    model: Model,
+    material_names: HashMap<String, usize>,
+    texture_paths: HashMap<PathBuf, usize>,
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    texcoords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    object: Object,
+    material: Option<usize>,
+}
+
+impl<'a> ObjReader<'a> {
+    fn read_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return Ok(());
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        let rest = line.trim().get(command.len()..).unwrap_or("").trim();
+
+        match command {
+            "v" => {
+                let values = parse_floats(&arguments)?;
+                if values.len() < 3 {
+                    return Err("expected 3 numbers".to_string());
+                }
+                self.positions.push([values[0], values[1], values[2]]);
+                // Some writers put a color after the position, with or without a w before it.
+                let color = match values.len() {
+                    6 => [values[3], values[4], values[5], 1.0],
+                    7 => [values[4], values[5], values[6], 1.0],
+                    _ => [1.0; 4],
+                };
+                self.colors.push(color);
+            }
+            "vt" => {
+                let [u, v] = match arguments.len() {
+                    1 => [parse_vector::<1>(&arguments)?[0], 0.0],
+                    _ => parse_vector(&arguments)?,
+                };
+                // OBJ has v going up from the bottom of the image, D3D has it going down.
+                self.texcoords.push([u, 1.0 - v]);
+            }
+            "vn" => self.normals.push(parse_vector(&arguments)?),
+            "f" => self.read_face(&arguments)?,
+            "o" => {
+                self.finish_object();
+                self.object = Object::new(rest.to_string());
+            }
+            "usemtl" => {
+                // Unknown materials get the default one, like when there's no usemtl at all.
+                self.material = self.material_names.get(rest).cloned();
+            }
+            "mtllib" => {
+                for name in arguments {
+                    self.read_material_library(name)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn read_face(&mut self, arguments: &[&str]) -> Result<(), String> {
+        if arguments.len() < 3 {
+            return Err("a face needs at least 3 corners".to_string());
+        }
+
+        let mut indices = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            let mut parts = argument.split('/');
+            let position = resolve_index(parts.next().unwrap_or(""), self.positions.len())?;
+            let texcoord = match parts.next() {
+                None | Some("") => None,
+                Some(t) => Some(resolve_index(t, self.texcoords.len())?),
+            };
+            let normal = match parts.next() {
+                None | Some("") => None,
+                Some(n) => Some(resolve_index(n, self.normals.len())?),
+            };
+            indices.push(self.get_vertex((position, texcoord, normal)));
+        }
+
+        let material = self.material;
+        let part = match self.object.parts.iter().position(|p| p.0 == material) {
+            Some(part) => part,
+            None => {
+                self.object.parts.push((material, Vec::new()));
+                self.object.parts.len() - 1
+            }
+        };
+        let triangles = &mut self.object.parts[part].1;
+        for i in 1..indices.len() - 1 {
+            triangles.extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+        }
+        Ok(())
+    }
+
+    // The index of the mesh vertex for a corner, adding it if no face has used it yet.
+    fn get_vertex(&mut self, corner: Corner) -> u32 {
+        if let Some(&index) = self.object.corners.get(&corner) {
+            return index;
+        }
+
+        let (position, texcoord, normal) = corner;
+        let mut vertex = MeshVertex {
+            position: self.positions[position],
+            color: self.colors[position],
+            ..MeshVertex::default()
+        };
+        if let Some(texcoord) = texcoord {
+            vertex.texcoord = self.texcoords[texcoord];
+        }
+        match normal {
+            Some(normal) => vertex.normal = self.normals[normal],
+            None => self.object.missing_normals = true,
+        }
+
+        let index = self.object.vertices.len() as u32;
+        self.object.vertices.push(vertex);
+        self.object.corners.insert(corner, index);
+        index
+    }
+
+    fn finish_object(&mut self) {
+        let object = std::mem::replace(&mut self.object, Object::new(String::new()));
+        if object.parts.is_empty() {
+            return;
+        }
+
+        let mut mesh = Mesh {
+            name: object.name,
+            vertices: object.vertices,
+            ..Mesh::default()
+        };
+        for (material, indices) in object.parts {
+            mesh.parts.push(MeshPart {
+                material,
+                start_index: mesh.indices.len() as u32,
+                index_count: indices.len() as u32,
+            });
+            mesh.indices.extend(indices);
+        }
+
+        // Normals from the file are kept only if every vertex has one, it's rare for a file to
+        // have some but not all.
+        if object.missing_normals {
+            compute_smooth_normals(&mut mesh.vertices, &mesh.indices);
+        }
+        compute_tangents(&mut mesh.vertices, &mesh.indices);
+
+        self.model.roots.push(self.model.nodes.len());
+        self.model.nodes.push(Node {
+            name: mesh.name.clone(),
+            transform: Mat4::IDENTITY.rows,
+            mesh: Some(self.model.meshes.len()),
+            children: Vec::new(),
+        });
+        self.model.meshes.push(mesh);
+    }
+
+    // A library that isn't there leaves its materials out rather than failing the whole model.
+    fn read_material_library(&mut self, name: &str) -> Result<(), String> {
+        let path = self.directory.join(name);
+        let text = match fs::read(&path) {
+            Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+            Err(e) => {
+                self.model
+                    .warnings
+                    .push(format!("Failed to read material library {:?}: {}", path, e));
+                return Ok(());
+            }
+        };
+
+        let mut material: Option<Material> = None;
+        for (number, line) in join_continued_lines(&text).iter().enumerate() {
+            self.read_material_line(line, &mut material)
+                .map_err(|e| format!("{:?} line {}: {}", path, number + 1, e))?;
+        }
+        if let Some(material) = material {
+            self.add_material(material);
+        }
+        Ok(())
+    }
+
+    fn read_material_line(
+        &mut self,
+        line: &str,
+        material: &mut Option<Material>,
+    ) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return Ok(());
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        let rest = line.trim().get(command.len()..).unwrap_or("").trim();
+
+        if command == "newmtl" {
+            if let Some(previous) = material.take() {
+                self.add_material(previous);
+            }
+            *material = Some(Material {
+                name: rest.to_string(),
+                // Plain OBJ materials aren't metals, a Pm line says otherwise.
+                metallic: 0.0,
+                ..Material::default()
+            });
+            return Ok(());
+        }
+        let Some(material) = material.as_mut() else {
+            return Ok(());
+        };
+
+        match command {
+            "Kd" => {
+                let [r, g, b] = parse_vector(&arguments)?;
+                material.base_color = [r, g, b, material.base_color[3]];
+            }
+            "d" => {
+                material.base_color[3] = parse_vector::<1>(&arguments)?[0];
+            }
+            "Tr" => {
+                material.base_color[3] = 1.0 - parse_vector::<1>(&arguments)?[0];
+            }
+            "Ke" => material.emissive = parse_vector(&arguments)?,
+            // The usual conversion from a Blinn-Phong exponent.
+            "Ns" => {
+                let exponent = parse_vector::<1>(&arguments)?[0].max(0.0);
+                material.roughness = (2.0 / (exponent + 2.0)).sqrt();
+            }
+            "Pr" => material.roughness = parse_vector::<1>(&arguments)?[0],
+            "Pm" => material.metallic = parse_vector::<1>(&arguments)?[0],
+            "map_Kd" => material.base_color_texture = self.get_texture(&arguments),
+            "map_Ke" => material.emissive_texture = self.get_texture(&arguments),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                material.normal_texture = self.get_texture(&arguments);
+                if let Some(i) = arguments.iter().position(|&a| a == "-bm") {
+                    material.normal_scale = parse_vector::<1>(&arguments[i + 1..])?[0];
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn add_material(&mut self, mut material: Material) {
+        if material.base_color[3] < 1.0 {
+            material.alpha_mode = AlphaMode::Blend;
+        }
+        self.material_names
+            .insert(material.name.clone(), self.model.materials.len());
+        self.model.materials.push(material);
+    }
+
+    // Texture maps can have options before the file name, so the file name is taken to be the
+    // last argument. Textures used by more than one material are only added once.
+    fn get_texture(&mut self, arguments: &[&str]) -> Option<usize> {
+        let path = self.directory.join(arguments.last()?);
+        let model = &mut self.model;
+        let index = *self.texture_paths.entry(path.clone()).or_insert_with(|| {
+            model.textures.push(TextureSource::File(path));
+            model.textures.len() - 1
+        });
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    fn decode_fixture(name: &str) -> Model {
+        let directory = get_fixtures();
+        decode(&fs::read(directory.join(name)).unwrap(), &directory).unwrap()
+    }
+
+    #[test]
+    fn objects_become_meshes() {
+        let model = decode_fixture("quad.obj");
+        let names: Vec<&str> = model.meshes.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["quad", "triangle"]);
+        assert_eq!(model.roots, [0, 1]);
+        assert_eq!(model.nodes[1].mesh, Some(1));
+        assert_eq!(model.nodes[1].transform, Mat4::IDENTITY.rows);
+    }
+
+    #[test]
+    fn face_with_texcoords_and_normals() {
+        let model = decode_fixture("quad.obj");
+        let mesh = &model.meshes[0];
+        let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|v| v.position).collect();
+        assert_eq!(
+            positions,
+            [
+                [0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [1.0, 0.0, 0.0]
+            ]
+        );
+        // Split into a fan, and v flipped so it goes down the image.
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.vertices[0].texcoord, [0.0, 1.0]);
+        assert_eq!(mesh.vertices[1].texcoord, [0.0, 0.0]);
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, -1.0]));
+        assert_eq!(mesh.parts[0].material, Some(0));
+    }
+
+    #[test]
+    fn face_without_normals() {
+        let model = decode_fixture("quad.obj");
+        let mesh = &model.meshes[1];
+        // Negative indices count back from the last position.
+        let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|v| v.position).collect();
+        assert_eq!(
+            positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]
+        );
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+        // usemtl with a material the library doesn't have.
+        assert_eq!(mesh.parts[0].material, None);
+    }
+
+    #[test]
+    fn material_library() {
+        let model = decode_fixture("quad.obj");
+        assert_eq!(model.materials.len(), 1);
+        let material = &model.materials[0];
+        assert_eq!(material.name, "red");
+        assert_eq!(material.base_color, [1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(material.alpha_mode, AlphaMode::Blend);
+        assert_eq!(material.metallic, 0.0);
+        assert!((material.roughness - 0.141_421).abs() < 1e-5);
+    }
+
+    #[test]
+    fn missing_material_library() {
+        let data = b"mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        let model = decode(data, &get_fixtures()).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert!(model.materials.is_empty());
+        assert_eq!(model.warnings.len(), 1);
+        assert!(model.warnings[0].contains("missing.mtl"));
+
+        assert!(decode_fixture("quad.obj").warnings.is_empty());
+    }
+
+    #[test]
+    fn bad_lines() {
+        let directory = get_fixtures();
+        assert!(decode(b"v 0 0 0\nf 1 2 3\n", &directory).is_err());
+        assert!(decode(b"v 0 0\n", &directory).is_err());
+        assert!(decode(b"v 0 0 0\nv 1 0 0\nf 1 2\n", &directory).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+// PNG files of every color type and bit depth, interlaced or not. Always decodes to 8 bit RGBA,
+// 16 bit channels lose their low byte.
+
+use image::{invalid_data, Image};
+use inflate::zlib_decompress;
+use std::io;
+
+pub const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// Where each of the seven Adam7 passes starts and how far apart its pixels are.
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+// gAMA stores gamma times 100000, this one is the 1/2.2 that sRGB approximates.
+const SRGB_GAMMA: u32 = 45455;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorType {
+    Gray,
+    Rgb,
+    Indexed,
+    GrayAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    fn get_channels(self) -> u32 {
+        match self {
+            ColorType::Gray | ColorType::Indexed => 1,
+            ColorType::GrayAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u32,
+    color_type: ColorType,
+    is_interlaced: bool,
+}
+
+pub fn decode(data: &[u8]) -> io::Result<Image> {
+    let mut rest = data
+        .get(SIGNATURE.len()..)
+        .ok_or_else(|| invalid_data("PNG file is truncated"))?;
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    // The sample values, at the image's bit depth, that are transparent in gray and RGB images.
+    let mut transparent: Option<[u16; 3]> = None;
+    let mut compressed = Vec::new();
+    let mut srgb = false;
+
+    loop {
+        if rest.len() < 12 {
+            return Err(invalid_data("PNG file is truncated"));
+        }
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = &rest[4..8];
+        let chunk = rest
+            .get(8..8 + length)
+            .ok_or_else(|| invalid_data("PNG file is truncated"))?;
+        rest = rest
+            .get(12 + length..)
+            .ok_or_else(|| invalid_data("PNG file is truncated"))?;
+
+        match kind {
+            b"IHDR" => header = Some(read_header(chunk)?),
+            b"PLTE" => {
+                palette = chunk
+                    .chunks(3)
+                    .filter(|c| c.len() == 3)
+                    .map(|c| [c[0], c[1], c[2], 255])
+                    .collect();
+            }
+            b"tRNS" => {
+                let color_type = header.as_ref().map(|h: &Header| h.color_type);
+                let read = |i: usize| {
+                    chunk
+                        .get(i * 2..i * 2 + 2)
+                        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                };
+                match color_type {
+                    Some(ColorType::Indexed) => {
+                        for (entry, &alpha) in palette.iter_mut().zip(chunk.iter()) {
+                            entry[3] = alpha;
+                        }
+                    }
+                    Some(ColorType::Gray) => {
+                        let gray = read(0).ok_or_else(|| invalid_data("PNG tRNS is too short"))?;
+                        transparent = Some([gray, 0, 0]);
+                    }
+                    Some(ColorType::Rgb) => {
+                        let rgb = [read(0), read(1), read(2)];
+                        if let [Some(r), Some(g), Some(b)] = rgb {
+                            transparent = Some([r, g, b]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            b"sRGB" => srgb = true,
+            b"gAMA" if chunk.len() == 4 => {
+                let gamma = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                srgb |= gamma == SRGB_GAMMA;
+            }
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Chunks with a lowercase first letter can be skipped, the others can't.
+            _ if kind[0].is_ascii_uppercase() => {
+                return Err(invalid_data(
+                    "PNG has a critical chunk that isn't supported",
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| invalid_data("PNG has no header"))?;
+    if header.color_type == ColorType::Indexed && palette.is_empty() {
+        return Err(invalid_data("PNG has no palette"));
+    }
+    let filtered = zlib_decompress(&compressed)?;
+
+    // The header can claim any size, so make sure the data is there before allocating for it.
+    let too_large = || invalid_data("PNG image is too large");
+    let filtered_size = if header.is_interlaced {
+        ADAM7_PASSES
+            .iter()
+            .try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+                let width = (header.width.saturating_sub(x0)).div_ceil(dx);
+                let height = (header.height.saturating_sub(y0)).div_ceil(dy);
+                total.checked_add(get_filtered_size(&header, width, height)?)
+            })
+    } else {
+        get_filtered_size(&header, header.width, header.height)
+    };
+    if filtered.len() < filtered_size.ok_or_else(too_large)? {
+        return Err(invalid_data("PNG image data is truncated"));
+    }
+
+    let pixel_count = (header.width as usize)
+        .checked_mul(header.height as usize)
+        .ok_or_else(too_large)?;
+    let mut pixels = vec![0u8; pixel_count.checked_mul(4).ok_or_else(too_large)?];
+    let converter = Converter {
+        header: &header,
+        palette: &palette,
+        transparent,
+    };
+
+    if header.is_interlaced {
+        let mut rest = filtered.as_slice();
+        for &(x0, y0, dx, dy) in ADAM7_PASSES.iter() {
+            let width = (header.width.saturating_sub(x0)).div_ceil(dx);
+            let height = (header.height.saturating_sub(y0)).div_ceil(dy);
+            if width == 0 || height == 0 {
+                continue;
+            }
+
+            let size = get_filtered_size(&header, width, height).ok_or_else(too_large)?;
+            let pass = rest
+                .get(..size)
+                .ok_or_else(|| invalid_data("PNG image data is truncated"))?;
+            rest = &rest[size..];
+            let rows = unfilter(&header, pass, width, height)?;
+            converter.convert(&rows, width, height, &mut pixels, [x0, y0, dx, dy]);
+        }
+    } else {
+        let size = get_filtered_size(&header, header.width, header.height).ok_or_else(too_large)?;
+        let data = filtered
+            .get(..size)
+            .ok_or_else(|| invalid_data("PNG image data is truncated"))?;
+        let rows = unfilter(&header, data, header.width, header.height)?;
+        converter.convert(
+            &rows,
+            header.width,
+            header.height,
+            &mut pixels,
+            [0, 0, 1, 1],
+        );
+    }
+
+    Ok(Image::new_rgba8(header.width, header.height, srgb, pixels))
+}
+
+fn read_header(chunk: &[u8]) -> io::Result<Header> {
+    if chunk.len() < 13 {
+        return Err(invalid_data("PNG header is too short"));
+    }
+
+    let width = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    let height = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+    let bit_depth = chunk[8] as u32;
+    let color_type = match chunk[9] {
+        0 => ColorType::Gray,
+        2 => ColorType::Rgb,
+        3 => ColorType::Indexed,
+        4 => ColorType::GrayAlpha,
+        6 => ColorType::Rgba,
+        _ => return Err(invalid_data("PNG has an invalid color type")),
+    };
+
+    let valid_depth = match color_type {
+        ColorType::Gray => [1, 2, 4, 8, 16].contains(&bit_depth),
+        ColorType::Indexed => [1, 2, 4, 8].contains(&bit_depth),
+        _ => [8, 16].contains(&bit_depth),
+    };
+    if !valid_depth {
+        return Err(invalid_data("PNG has an invalid bit depth"));
+    }
+    if width == 0 || height == 0 {
+        return Err(invalid_data("PNG image is empty"));
+    }
+
+    Ok(Header {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        is_interlaced: chunk[12] == 1,
+    })
+}
+
+fn get_row_size(header: &Header, width: u32) -> usize {
+    (width as usize * (header.color_type.get_channels() * header.bit_depth) as usize).div_ceil(8)
+}
+
+// Every row starts with a byte saying which filter it uses. None if it doesn't fit in memory.
+fn get_filtered_size(header: &Header, width: u32, height: u32) -> Option<usize> {
+    (get_row_size(header, width) + 1).checked_mul(height as usize)
+}
+
+// Undoes the per row filters, returning the rows without their filter bytes.
+fn unfilter(header: &Header, data: &[u8], width: u32, height: u32) -> io::Result<Vec<u8>> {
+    let row_size = get_row_size(header, width);
+    // Filters work on bytes, comparing with the same byte of the pixel to the left.
+    let pixel_size = ((header.color_type.get_channels() * header.bit_depth) as usize).div_ceil(8);
+
+    let mut rows = vec![0u8; row_size * height as usize];
+    for y in 0..height as usize {
+        let filtered = &data[y * (row_size + 1)..(y + 1) * (row_size + 1)];
+        let (filter, filtered) = (filtered[0], &filtered[1..]);
+        let (previous_rows, current_rows) = rows.split_at_mut(y * row_size);
+        let previous = if y > 0 {
+            &previous_rows[(y - 1) * row_size..]
+        } else {
+            &[][..]
+        };
+        let row = &mut current_rows[..row_size];
+
+        for x in 0..row_size {
+            let left = if x >= pixel_size {
+                row[x - pixel_size]
+            } else {
+                0
+            };
+            let up = previous.get(x).cloned().unwrap_or(0);
+            let up_left = if x >= pixel_size {
+                previous.get(x - pixel_size).cloned().unwrap_or(0)
+            } else {
+                0
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => get_paeth(left, up, up_left),
+                _ => return Err(invalid_data("PNG has an invalid filter")),
+            };
+            row[x] = filtered[x].wrapping_add(predicted);
+        }
+    }
+    Ok(rows)
+}
+
+fn get_paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+struct Converter<'a> {
+    header: &'a Header,
+    palette: &'a [[u8; 4]],
+    transparent: Option<[u16; 3]>,
+}
+
+impl<'a> Converter<'a> {
+    // Writes unfiltered rows into the RGBA pixels. placement is where the first pixel goes and
+    // the step between pixels, for interlaced passes.
+    fn convert(
+        &self,
+        rows: &[u8],
+        width: u32,
+        height: u32,
+        pixels: &mut [u8],
+        placement: [u32; 4],
+    ) {
+        let header = self.header;
+        let row_size = get_row_size(header, width);
+        let channels = header.color_type.get_channels();
+        let depth = header.bit_depth;
+        let max_value = (1u32 << depth) - 1;
+
+        for y in 0..height {
+            let row = &rows[y as usize * row_size..(y as usize + 1) * row_size];
+            // The raw value of a sample at the image's bit depth.
+            let sample = |index: u32| -> u16 {
+                match depth {
+                    8 => row[index as usize] as u16,
+                    16 => {
+                        u16::from_be_bytes([row[index as usize * 2], row[index as usize * 2 + 1]])
+                    }
+                    _ => {
+                        let bit = index * depth;
+                        let byte = row[(bit / 8) as usize];
+                        let shift = 8 - depth - bit % 8;
+                        ((byte >> shift) as u32 & max_value) as u16
+                    }
+                }
+            };
+            let to_u8 = |value: u16| match depth {
+                16 => (value >> 8) as u8,
+                _ => (value as u32 * 255 / max_value) as u8,
+            };
+
+            for x in 0..width {
+                let first = x * channels;
+                let rgba = match header.color_type {
+                    ColorType::Indexed => self
+                        .palette
+                        .get(sample(first) as usize)
+                        .cloned()
+                        .unwrap_or([0, 0, 0, 255]),
+                    ColorType::Gray | ColorType::Rgb => {
+                        let raw = if channels == 1 {
+                            let gray = sample(first);
+                            [gray, gray, gray]
+                        } else {
+                            [sample(first), sample(first + 1), sample(first + 2)]
+                        };
+                        let is_transparent = match self.transparent {
+                            Some(color) if channels == 1 => raw[0] == color[0],
+                            Some(color) => raw == color,
+                            None => false,
+                        };
+                        let alpha = if is_transparent { 0 } else { 255 };
+                        [to_u8(raw[0]), to_u8(raw[1]), to_u8(raw[2]), alpha]
+                    }
+                    ColorType::GrayAlpha => {
+                        let gray = to_u8(sample(first));
+                        [gray, gray, gray, to_u8(sample(first + 1))]
+                    }
+                    ColorType::Rgba => [
+                        to_u8(sample(first)),
+                        to_u8(sample(first + 1)),
+                        to_u8(sample(first + 2)),
+                        to_u8(sample(first + 3)),
+                    ],
+                };
+
+                let pixel_x = placement[0] + x * placement[2];
+                let pixel_y = placement[1] + y * placement[3];
+                let index = (pixel_y as usize * header.width as usize + pixel_x as usize) * 4;
+                pixels[index..index + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // The CRC isn't checked.
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    // An 8 bit RGBA PNG with the filtered data in a single stored deflate block.
+    fn make_png(width: u32, height: u32, filtered: &[u8]) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let length = filtered.len() as u16;
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(filtered);
+        zlib.extend_from_slice(&[0; 4]);
+
+        let mut png = SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &header));
+        png.extend(chunk(b"IDAT", &zlib));
+        png.extend(chunk(b"IEND", &[]));
+        png
+    }
+
+    #[test]
+    fn decodes() {
+        let png = make_png(2, 1, &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn header_bigger_than_the_data() {
+        // 16 GB of pixels if the header were taken at its word.
+        let png = make_png(0x10000, 0x10000, &[0, 1, 2, 3, 4]);
+        assert!(decode(&png).is_err());
+        assert!(decode(&make_png(2, 1, &[0, 1, 2, 3, 4])).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(decode(&SIGNATURE[..4]).is_err());
+        let png = make_png(2, 1, &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(decode(&png[..png.len() - 20]).is_err());
+    }
+}
@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{
+    ID3D11BlendState, ID3D11DepthStencilState, ID3D11Device, ID3D11RasterizerState,
+    ID3D11SamplerState, D3D11_BLEND, D3D11_BLEND_DESC, D3D11_BLEND_INV_SRC_ALPHA, D3D11_BLEND_ONE,
+    D3D11_BLEND_OP_ADD, D3D11_BLEND_SRC_ALPHA, D3D11_BLEND_ZERO, D3D11_COLOR_WRITE_ENABLE_ALL,
+    D3D11_COMPARISON_ALWAYS, D3D11_COMPARISON_LESS_EQUAL, D3D11_COMPARISON_NEVER, D3D11_CULL_BACK,
+    D3D11_CULL_FRONT, D3D11_CULL_MODE, D3D11_CULL_NONE, D3D11_DEFAULT_STENCIL_READ_MASK,
+    D3D11_DEFAULT_STENCIL_WRITE_MASK, D3D11_DEPTH_STENCILOP_DESC, D3D11_DEPTH_STENCIL_DESC,
+    D3D11_DEPTH_WRITE_MASK_ALL, D3D11_DEPTH_WRITE_MASK_ZERO, D3D11_FILL_MODE, D3D11_FILL_SOLID,
+    D3D11_FILL_WIREFRAME, D3D11_FILTER, D3D11_FILTER_ANISOTROPIC, D3D11_FILTER_MIN_MAG_MIP_LINEAR,
+    D3D11_FILTER_MIN_MAG_MIP_POINT, D3D11_FLOAT32_MAX, D3D11_MAX_MAXANISOTROPY,
+    D3D11_RASTERIZER_DESC, D3D11_RENDER_TARGET_BLEND_DESC, D3D11_SAMPLER_DESC,
+    D3D11_STENCIL_OP_KEEP, D3D11_TEXTURE_ADDRESS_CLAMP, D3D11_TEXTURE_ADDRESS_MODE,
+    D3D11_TEXTURE_ADDRESS_WRAP,
+};
+use winapi::um::d3dcommon::{D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_9_1};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+// State objects are immutable and D3D hands out the same object for the same description
+// anyway, but only up to 4096 of them, and asking every frame isn't free. So we keep our own
+// cache, keyed on the description's fields. The padding inside the descriptions is garbage, so
+// they can't be compared as plain bytes.
+struct StateCache<D: Copy, S: Interface> {
+    indices: HashMap<Vec<u32>, usize>,
+    entries: Vec<(D, Option<ComPtr<S>>)>,
+    get_key: fn(&D) -> Vec<u32>,
+    create: unsafe fn(&ID3D11Device, &D, *mut *mut S) -> HRESULT,
+}
+
+impl<D: Copy, S: Interface> StateCache<D, S> {
+    fn new(
+        get_key: fn(&D) -> Vec<u32>,
+        create: unsafe fn(&ID3D11Device, &D, *mut *mut S) -> HRESULT,
+    ) -> StateCache<D, S> {
+        StateCache {
+            indices: HashMap::new(),
+            entries: Vec::new(),
+            get_key,
+            create,
+        }
+    }
+
+    unsafe fn get(&mut self, device: &ID3D11Device, desc: &D) -> Result<ComPtr<S>, HRESULT> {
+        let key = (self.get_key)(desc);
+        let index = match self.indices.get(&key) {
+            Some(&index) => index,
+            None => {
+                self.entries.push((*desc, None));
+                self.indices.insert(key, self.entries.len() - 1);
+                self.entries.len() - 1
+            }
+        };
+
+        let entry = &mut self.entries[index];
+        if let Some(ref state) = entry.1 {
+            return Ok(state.clone());
+        }
+
+        let state = create_state(self.create, device, &entry.0)?;
+        entry.1 = Some(state.clone());
+        Ok(state)
+    }
+
+    fn release(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.1 = None;
+        }
+    }
+
+    // Creates everything that was asked for before the device was lost. Failures are left for
+    // get to report.
+    unsafe fn recreate(&mut self, device: &ID3D11Device) {
+        for entry in self.entries.iter_mut() {
+            entry.1 = create_state(self.create, device, &entry.0).ok();
+        }
+    }
+}
+
+unsafe fn create_state<D, S: Interface>(
+    create: unsafe fn(&ID3D11Device, &D, *mut *mut S) -> HRESULT,
+    device: &ID3D11Device,
+    desc: &D,
+) -> Result<ComPtr<S>, HRESULT> {
+    let mut state_ptr: *mut S = std::ptr::null_mut();
+    let hr = create(device, desc, &mut state_ptr);
+    if ::failed(hr) {
+        return Err(hr);
+    }
+    Ok(ComPtr::from_raw(state_ptr))
+}
+
+fn get_blend_key(desc: &D3D11_BLEND_DESC) -> Vec<u32> {
+    let mut key = vec![
+        desc.AlphaToCoverageEnable as u32,
+        desc.IndependentBlendEnable as u32,
+    ];
+    for target in desc.RenderTarget.iter() {
+        key.extend_from_slice(&[
+            target.BlendEnable as u32,
+            target.SrcBlend,
+            target.DestBlend,
+            target.BlendOp,
+            target.SrcBlendAlpha,
+            target.DestBlendAlpha,
+            target.BlendOpAlpha,
+            u32::from(target.RenderTargetWriteMask),
+        ]);
+    }
+    key
+}
+
+fn get_depth_stencil_key(desc: &D3D11_DEPTH_STENCIL_DESC) -> Vec<u32> {
+    let mut key = vec![
+        desc.DepthEnable as u32,
+        desc.DepthWriteMask,
+        desc.DepthFunc,
+        desc.StencilEnable as u32,
+        u32::from(desc.StencilReadMask),
+        u32::from(desc.StencilWriteMask),
+    ];
+    for face in [desc.FrontFace, desc.BackFace].iter() {
+        key.extend_from_slice(&[
+            face.StencilFailOp,
+            face.StencilDepthFailOp,
+            face.StencilPassOp,
+            face.StencilFunc,
+        ]);
+    }
+    key
+}
+
+fn get_rasterizer_key(desc: &D3D11_RASTERIZER_DESC) -> Vec<u32> {
+    vec![
+        desc.FillMode,
+        desc.CullMode,
+        desc.FrontCounterClockwise as u32,
+        desc.DepthBias as u32,
+        desc.DepthBiasClamp.to_bits(),
+        desc.SlopeScaledDepthBias.to_bits(),
+        desc.DepthClipEnable as u32,
+        desc.ScissorEnable as u32,
+        desc.MultisampleEnable as u32,
+        desc.AntialiasedLineEnable as u32,
+    ]
+}
+
+fn get_sampler_key(desc: &D3D11_SAMPLER_DESC) -> Vec<u32> {
+    let mut key = vec![
+        desc.Filter,
+        desc.AddressU,
+        desc.AddressV,
+        desc.AddressW,
+        desc.MipLODBias.to_bits(),
+        desc.MaxAnisotropy,
+        desc.ComparisonFunc,
+    ];
+    key.extend(desc.BorderColor.iter().map(|c| c.to_bits()));
+    key.extend_from_slice(&[desc.MinLOD.to_bits(), desc.MaxLOD.to_bits()]);
+    key
+}
+
+unsafe fn create_blend_state(
+    device: &ID3D11Device,
+    desc: &D3D11_BLEND_DESC,
+    state: *mut *mut ID3D11BlendState,
+) -> HRESULT {
+    device.CreateBlendState(desc, state)
+}
+
+unsafe fn create_depth_stencil_state(
+    device: &ID3D11Device,
+    desc: &D3D11_DEPTH_STENCIL_DESC,
+    state: *mut *mut ID3D11DepthStencilState,
+) -> HRESULT {
+    device.CreateDepthStencilState(desc, state)
+}
+
+unsafe fn create_rasterizer_state(
+    device: &ID3D11Device,
+    desc: &D3D11_RASTERIZER_DESC,
+    state: *mut *mut ID3D11RasterizerState,
+) -> HRESULT {
+    device.CreateRasterizerState(desc, state)
+}
+
+unsafe fn create_sampler_state(
+    device: &ID3D11Device,
+    desc: &D3D11_SAMPLER_DESC,
+    state: *mut *mut ID3D11SamplerState,
+) -> HRESULT {
+    device.CreateSamplerState(desc, state)
+}
+
+// Pretty much a port of the DirectXTK CommonStates, plus a cache for custom descriptions.
+//
+// Don't hold on to the states across a device loss; get them again every frame, it's just a
+// lookup once they exist.
+pub struct RenderStates {
+    device: Option<ComPtr<ID3D11Device>>,
+    feature_level: D3D_FEATURE_LEVEL,
+    blend_states: StateCache<D3D11_BLEND_DESC, ID3D11BlendState>,
+    depth_stencil_states: StateCache<D3D11_DEPTH_STENCIL_DESC, ID3D11DepthStencilState>,
+    rasterizer_states: StateCache<D3D11_RASTERIZER_DESC, ID3D11RasterizerState>,
+    sampler_states: StateCache<D3D11_SAMPLER_DESC, ID3D11SamplerState>,
+}
+
+impl RenderStates {
+    pub fn new() -> RenderStates {
+        RenderStates {
+            device: None,
+            feature_level: D3D_FEATURE_LEVEL_9_1,
+            blend_states: StateCache::new(get_blend_key, create_blend_state),
+            depth_stencil_states: StateCache::new(
+                get_depth_stencil_key,
+                create_depth_stencil_state,
+            ),
+            rasterizer_states: StateCache::new(get_rasterizer_key, create_rasterizer_state),
+            sampler_states: StateCache::new(get_sampler_key, create_sampler_state),
+        }
+    }
+
+    pub fn on_device_lost(&mut self) {
+        self.blend_states.release();
+        self.depth_stencil_states.release();
+        self.rasterizer_states.release();
+        self.sampler_states.release();
+        self.device = None;
+    }
+
+    pub unsafe fn on_device_restored(
+        &mut self,
+        device: ComPtr<ID3D11Device>,
+        feature_level: D3D_FEATURE_LEVEL,
+    ) {
+        self.blend_states.recreate(&device);
+        self.depth_stencil_states.recreate(&device);
+        self.rasterizer_states.recreate(&device);
+        self.sampler_states.recreate(&device);
+        self.device = Some(device);
+        self.feature_level = feature_level;
+    }
+
+    pub fn get_blend_state(
+        &mut self,
+        desc: &D3D11_BLEND_DESC,
+    ) -> Result<ComPtr<ID3D11BlendState>, HRESULT> {
+        let device = self
+            .device
+            .as_ref()
+            .expect("render states used without a device");
+        unsafe { self.blend_states.get(device, desc) }
+    }
+
+    pub fn get_depth_stencil_state(
+        &mut self,
+        desc: &D3D11_DEPTH_STENCIL_DESC,
+    ) -> Result<ComPtr<ID3D11DepthStencilState>, HRESULT> {
+        let device = self
+            .device
+            .as_ref()
+            .expect("render states used without a device");
+        unsafe { self.depth_stencil_states.get(device, desc) }
+    }
+
+    pub fn get_rasterizer_state(
+        &mut self,
+        desc: &D3D11_RASTERIZER_DESC,
+    ) -> Result<ComPtr<ID3D11RasterizerState>, HRESULT> {
+        let device = self
+            .device
+            .as_ref()
+            .expect("render states used without a device");
+        unsafe { self.rasterizer_states.get(device, desc) }
+    }
+
+    pub fn get_sampler_state(
+        &mut self,
+        desc: &D3D11_SAMPLER_DESC,
+    ) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        let device = self
+            .device
+            .as_ref()
+            .expect("render states used without a device");
+        unsafe { self.sampler_states.get(device, desc) }
+    }
+
+    pub fn opaque(&mut self) -> Result<ComPtr<ID3D11BlendState>, HRESULT> {
+        self.get_blend_state(&get_blend_desc(D3D11_BLEND_ONE, D3D11_BLEND_ZERO))
+    }
+
+    // For colors that haven't been multiplied by their alpha.
+    pub fn alpha_blend(&mut self) -> Result<ComPtr<ID3D11BlendState>, HRESULT> {
+        self.get_blend_state(&get_blend_desc(
+            D3D11_BLEND_SRC_ALPHA,
+            D3D11_BLEND_INV_SRC_ALPHA,
+        ))
+    }
+
+    pub fn additive(&mut self) -> Result<ComPtr<ID3D11BlendState>, HRESULT> {
+        self.get_blend_state(&get_blend_desc(D3D11_BLEND_SRC_ALPHA, D3D11_BLEND_ONE))
+    }
+
+    // For colors that have been multiplied by their alpha, which is what the DirectXTK calls
+    // AlphaBlend.
+    pub fn premultiplied(&mut self) -> Result<ComPtr<ID3D11BlendState>, HRESULT> {
+        self.get_blend_state(&get_blend_desc(D3D11_BLEND_ONE, D3D11_BLEND_INV_SRC_ALPHA))
+    }
+
+    // Depth test and depth writes.
+    pub fn depth_default(&mut self) -> Result<ComPtr<ID3D11DepthStencilState>, HRESULT> {
+        self.get_depth_stencil_state(&get_depth_stencil_desc(true, true))
+    }
+
+    // Depth test without writes, for transparent things drawn after the opaque ones.
+    pub fn depth_read(&mut self) -> Result<ComPtr<ID3D11DepthStencilState>, HRESULT> {
+        self.get_depth_stencil_state(&get_depth_stencil_desc(true, false))
+    }
+
+    pub fn depth_none(&mut self) -> Result<ComPtr<ID3D11DepthStencilState>, HRESULT> {
+        self.get_depth_stencil_state(&get_depth_stencil_desc(false, false))
+    }
+
+    pub fn cull_none(&mut self) -> Result<ComPtr<ID3D11RasterizerState>, HRESULT> {
+        self.get_rasterizer_state(&get_rasterizer_desc(D3D11_CULL_NONE, D3D11_FILL_SOLID))
+    }
+
+    // Front faces are clockwise by default, so this culls them.
+    pub fn cull_clockwise(&mut self) -> Result<ComPtr<ID3D11RasterizerState>, HRESULT> {
+        self.get_rasterizer_state(&get_rasterizer_desc(D3D11_CULL_FRONT, D3D11_FILL_SOLID))
+    }
+
+    pub fn cull_counter_clockwise(&mut self) -> Result<ComPtr<ID3D11RasterizerState>, HRESULT> {
+        self.get_rasterizer_state(&get_rasterizer_desc(D3D11_CULL_BACK, D3D11_FILL_SOLID))
+    }
+
+    pub fn wireframe(&mut self) -> Result<ComPtr<ID3D11RasterizerState>, HRESULT> {
+        self.get_rasterizer_state(&get_rasterizer_desc(D3D11_CULL_NONE, D3D11_FILL_WIREFRAME))
+    }
+
+    pub fn point_wrap(&mut self) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        self.get_preset_sampler(D3D11_FILTER_MIN_MAG_MIP_POINT, D3D11_TEXTURE_ADDRESS_WRAP)
+    }
+
+    pub fn point_clamp(&mut self) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        self.get_preset_sampler(D3D11_FILTER_MIN_MAG_MIP_POINT, D3D11_TEXTURE_ADDRESS_CLAMP)
+    }
+
+    pub fn linear_wrap(&mut self) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        self.get_preset_sampler(D3D11_FILTER_MIN_MAG_MIP_LINEAR, D3D11_TEXTURE_ADDRESS_WRAP)
+    }
+
+    pub fn linear_clamp(&mut self) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        self.get_preset_sampler(D3D11_FILTER_MIN_MAG_MIP_LINEAR, D3D11_TEXTURE_ADDRESS_CLAMP)
+    }
+
+    pub fn anisotropic_wrap(&mut self) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        self.get_preset_sampler(D3D11_FILTER_ANISOTROPIC, D3D11_TEXTURE_ADDRESS_WRAP)
+    }
+
+    pub fn anisotropic_clamp(&mut self) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        self.get_preset_sampler(D3D11_FILTER_ANISOTROPIC, D3D11_TEXTURE_ADDRESS_CLAMP)
+    }
+
+    fn get_preset_sampler(
+        &mut self,
+        filter: D3D11_FILTER,
+        address: D3D11_TEXTURE_ADDRESS_MODE,
+    ) -> Result<ComPtr<ID3D11SamplerState>, HRESULT> {
+        // Feature level 9.1 only goes up to 2x.
+        let max_anisotropy = if self.feature_level > D3D_FEATURE_LEVEL_9_1 {
+            D3D11_MAX_MAXANISOTROPY
+        } else {
+            2
+        };
+
+        self.get_sampler_state(&get_sampler_desc(filter, address, max_anisotropy))
+    }
+}
+
+fn get_blend_desc(src_blend: D3D11_BLEND, dest_blend: D3D11_BLEND) -> D3D11_BLEND_DESC {
+    let is_enabled = src_blend != D3D11_BLEND_ONE || dest_blend != D3D11_BLEND_ZERO;
+    let target = D3D11_RENDER_TARGET_BLEND_DESC {
+        BlendEnable: if is_enabled { TRUE } else { FALSE },
+        SrcBlend: src_blend,
+        DestBlend: dest_blend,
+        BlendOp: D3D11_BLEND_OP_ADD,
+        SrcBlendAlpha: src_blend,
+        DestBlendAlpha: dest_blend,
+        BlendOpAlpha: D3D11_BLEND_OP_ADD,
+        RenderTargetWriteMask: D3D11_COLOR_WRITE_ENABLE_ALL as u8,
+    };
+
+    D3D11_BLEND_DESC {
+        AlphaToCoverageEnable: FALSE,
+        IndependentBlendEnable: FALSE,
+        RenderTarget: [target; 8],
+    }
+}
+
+fn get_depth_stencil_desc(enable: bool, write_enable: bool) -> D3D11_DEPTH_STENCIL_DESC {
+    let face = D3D11_DEPTH_STENCILOP_DESC {
+        StencilFailOp: D3D11_STENCIL_OP_KEEP,
+        StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
+        StencilPassOp: D3D11_STENCIL_OP_KEEP,
+        StencilFunc: D3D11_COMPARISON_ALWAYS,
+    };
+
+    D3D11_DEPTH_STENCIL_DESC {
+        DepthEnable: if enable { TRUE } else { FALSE },
+        DepthWriteMask: if write_enable {
+            D3D11_DEPTH_WRITE_MASK_ALL
+        } else {
+            D3D11_DEPTH_WRITE_MASK_ZERO
+        },
+        DepthFunc: D3D11_COMPARISON_LESS_EQUAL,
+        StencilEnable: FALSE,
+        StencilReadMask: D3D11_DEFAULT_STENCIL_READ_MASK as u8,
+        StencilWriteMask: D3D11_DEFAULT_STENCIL_WRITE_MASK as u8,
+        FrontFace: face,
+        BackFace: face,
+    }
+}
+
+fn get_rasterizer_desc(
+    cull_mode: D3D11_CULL_MODE,
+    fill_mode: D3D11_FILL_MODE,
+) -> D3D11_RASTERIZER_DESC {
+    D3D11_RASTERIZER_DESC {
+        FillMode: fill_mode,
+        CullMode: cull_mode,
+        FrontCounterClockwise: FALSE,
+        DepthBias: 0,
+        DepthBiasClamp: 0.0,
+        SlopeScaledDepthBias: 0.0,
+        DepthClipEnable: TRUE,
+        ScissorEnable: FALSE,
+        MultisampleEnable: TRUE,
+        AntialiasedLineEnable: FALSE,
+    }
+}
+
+fn get_sampler_desc(
+    filter: D3D11_FILTER,
+    address: D3D11_TEXTURE_ADDRESS_MODE,
+    max_anisotropy: u32,
+) -> D3D11_SAMPLER_DESC {
+    D3D11_SAMPLER_DESC {
+        Filter: filter,
+        AddressU: address,
+        AddressV: address,
+        AddressW: address,
+        MipLODBias: 0.0,
+        MaxAnisotropy: max_anisotropy,
+        ComparisonFunc: D3D11_COMPARISON_NEVER,
+        BorderColor: [0.0; 4],
+        MinLOD: 0.0,
+        MaxLOD: D3D11_FLOAT32_MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use winapi::um::d3d11::{
+        D3D11_BLEND_OP_SUBTRACT, D3D11_COMPARISON_LESS, D3D11_STENCIL_OP_ZERO,
+    };
+
+    // The base description's key, then the key after each change, all different from each other.
+    fn check_changes<D: Copy>(base: D, get_key: fn(&D) -> Vec<u32>, changes: &[fn(&mut D)]) {
+        let mut keys = HashSet::new();
+        keys.insert(get_key(&base));
+        for (i, change) in changes.iter().enumerate() {
+            let mut desc = base;
+            change(&mut desc);
+            assert!(
+                keys.insert(get_key(&desc)),
+                "change {} didn't change the key",
+                i
+            );
+        }
+    }
+
+    fn check_distinct<D>(presets: &[D], get_key: fn(&D) -> Vec<u32>) {
+        let keys: HashSet<_> = presets.iter().map(get_key).collect();
+        assert_eq!(keys.len(), presets.len());
+    }
+
+    #[test]
+    fn blend_key() {
+        let base = get_blend_desc(D3D11_BLEND_SRC_ALPHA, D3D11_BLEND_INV_SRC_ALPHA);
+        let same = get_blend_desc(D3D11_BLEND_SRC_ALPHA, D3D11_BLEND_INV_SRC_ALPHA);
+        assert_eq!(get_blend_key(&base), get_blend_key(&same));
+
+        // The padding after RenderTargetWriteMask doesn't count.
+        let mut garbage: D3D11_BLEND_DESC = unsafe { std::mem::zeroed() };
+        unsafe { std::ptr::write_bytes(&mut garbage, 0xab, 1) };
+        garbage.AlphaToCoverageEnable = base.AlphaToCoverageEnable;
+        garbage.IndependentBlendEnable = base.IndependentBlendEnable;
+        for (target, base_target) in garbage
+            .RenderTarget
+            .iter_mut()
+            .zip(base.RenderTarget.iter())
+        {
+            target.BlendEnable = base_target.BlendEnable;
+            target.SrcBlend = base_target.SrcBlend;
+            target.DestBlend = base_target.DestBlend;
+            target.BlendOp = base_target.BlendOp;
+            target.SrcBlendAlpha = base_target.SrcBlendAlpha;
+            target.DestBlendAlpha = base_target.DestBlendAlpha;
+            target.BlendOpAlpha = base_target.BlendOpAlpha;
+            target.RenderTargetWriteMask = base_target.RenderTargetWriteMask;
+        }
+        assert_eq!(get_blend_key(&garbage), get_blend_key(&base));
+
+        check_changes(
+            base,
+            get_blend_key,
+            &[
+                |d| d.AlphaToCoverageEnable = TRUE,
+                |d| d.IndependentBlendEnable = TRUE,
+                |d| d.RenderTarget[0].BlendEnable = FALSE,
+                |d| d.RenderTarget[0].SrcBlend = D3D11_BLEND_ONE,
+                |d| d.RenderTarget[0].DestBlend = D3D11_BLEND_ONE,
+                |d| d.RenderTarget[0].BlendOp = D3D11_BLEND_OP_SUBTRACT,
+                |d| d.RenderTarget[0].SrcBlendAlpha = D3D11_BLEND_ONE,
+                |d| d.RenderTarget[0].DestBlendAlpha = D3D11_BLEND_ONE,
+                |d| d.RenderTarget[0].BlendOpAlpha = D3D11_BLEND_OP_SUBTRACT,
+                |d| d.RenderTarget[0].RenderTargetWriteMask = 0,
+                |d| d.RenderTarget[7].RenderTargetWriteMask = 0,
+            ],
+        );
+    }
+
+    #[test]
+    fn depth_stencil_key() {
+        let base = get_depth_stencil_desc(true, true);
+        let same = get_depth_stencil_desc(true, true);
+        assert_eq!(get_depth_stencil_key(&base), get_depth_stencil_key(&same));
+
+        check_changes(
+            base,
+            get_depth_stencil_key,
+            &[
+                |d| d.DepthEnable = FALSE,
+                |d| d.DepthWriteMask = D3D11_DEPTH_WRITE_MASK_ZERO,
+                |d| d.DepthFunc = D3D11_COMPARISON_LESS,
+                |d| d.StencilEnable = TRUE,
+                |d| d.StencilReadMask = 0x0f,
+                |d| d.StencilWriteMask = 0x0f,
+                |d| d.FrontFace.StencilFailOp = D3D11_STENCIL_OP_ZERO,
+                |d| d.FrontFace.StencilDepthFailOp = D3D11_STENCIL_OP_ZERO,
+                |d| d.FrontFace.StencilPassOp = D3D11_STENCIL_OP_ZERO,
+                |d| d.FrontFace.StencilFunc = D3D11_COMPARISON_NEVER,
+                |d| d.BackFace.StencilFailOp = D3D11_STENCIL_OP_ZERO,
+                |d| d.BackFace.StencilDepthFailOp = D3D11_STENCIL_OP_ZERO,
+                |d| d.BackFace.StencilPassOp = D3D11_STENCIL_OP_ZERO,
+                |d| d.BackFace.StencilFunc = D3D11_COMPARISON_NEVER,
+            ],
+        );
+    }
+
+    #[test]
+    fn rasterizer_key() {
+        let base = get_rasterizer_desc(D3D11_CULL_BACK, D3D11_FILL_SOLID);
+        let same = get_rasterizer_desc(D3D11_CULL_BACK, D3D11_FILL_SOLID);
+        assert_eq!(get_rasterizer_key(&base), get_rasterizer_key(&same));
+
+        check_changes(
+            base,
+            get_rasterizer_key,
+            &[
+                |d| d.FillMode = D3D11_FILL_WIREFRAME,
+                |d| d.CullMode = D3D11_CULL_NONE,
+                |d| d.FrontCounterClockwise = TRUE,
+                |d| d.DepthBias = -1,
+                |d| d.DepthBiasClamp = 0.5,
+                |d| d.SlopeScaledDepthBias = 0.5,
+                |d| d.DepthClipEnable = FALSE,
+                |d| d.ScissorEnable = TRUE,
+                |d| d.MultisampleEnable = FALSE,
+                |d| d.AntialiasedLineEnable = TRUE,
+            ],
+        );
+    }
+
+    #[test]
+    fn sampler_key() {
+        let base = get_sampler_desc(D3D11_FILTER_ANISOTROPIC, D3D11_TEXTURE_ADDRESS_WRAP, 16);
+        let same = get_sampler_desc(D3D11_FILTER_ANISOTROPIC, D3D11_TEXTURE_ADDRESS_WRAP, 16);
+        assert_eq!(get_sampler_key(&base), get_sampler_key(&same));
+
+        check_changes(
+            base,
+            get_sampler_key,
+            &[
+                |d| d.Filter = D3D11_FILTER_MIN_MAG_MIP_POINT,
+                |d| d.AddressU = D3D11_TEXTURE_ADDRESS_CLAMP,
+                |d| d.AddressV = D3D11_TEXTURE_ADDRESS_CLAMP,
+                |d| d.AddressW = D3D11_TEXTURE_ADDRESS_CLAMP,
+                |d| d.MipLODBias = -0.5,
+                |d| d.MaxAnisotropy = 2,
+                |d| d.ComparisonFunc = D3D11_COMPARISON_LESS,
+                |d| d.BorderColor[0] = 1.0,
+                |d| d.BorderColor[3] = 1.0,
+                |d| d.MinLOD = 1.0,
+                |d| d.MaxLOD = 4.0,
+            ],
+        );
+    }
+
+    #[test]
+    fn presets_are_distinct() {
+        check_distinct(
+            &[
+                get_blend_desc(D3D11_BLEND_ONE, D3D11_BLEND_ZERO),
+                get_blend_desc(D3D11_BLEND_SRC_ALPHA, D3D11_BLEND_INV_SRC_ALPHA),
+                get_blend_desc(D3D11_BLEND_SRC_ALPHA, D3D11_BLEND_ONE),
+                get_blend_desc(D3D11_BLEND_ONE, D3D11_BLEND_INV_SRC_ALPHA),
+            ],
+            get_blend_key,
+        );
+        check_distinct(
+            &[
+                get_depth_stencil_desc(true, true),
+                get_depth_stencil_desc(true, false),
+                get_depth_stencil_desc(false, false),
+            ],
+            get_depth_stencil_key,
+        );
+        check_distinct(
+            &[
+                get_rasterizer_desc(D3D11_CULL_NONE, D3D11_FILL_SOLID),
+                get_rasterizer_desc(D3D11_CULL_FRONT, D3D11_FILL_SOLID),
+                get_rasterizer_desc(D3D11_CULL_BACK, D3D11_FILL_SOLID),
+                get_rasterizer_desc(D3D11_CULL_NONE, D3D11_FILL_WIREFRAME),
+            ],
+            get_rasterizer_key,
+        );
+
+        let mut samplers = Vec::new();
+        for &filter in [
+            D3D11_FILTER_MIN_MAG_MIP_POINT,
+            D3D11_FILTER_MIN_MAG_MIP_LINEAR,
+            D3D11_FILTER_ANISOTROPIC,
+        ]
+        .iter()
+        {
+            for &address in [D3D11_TEXTURE_ADDRESS_WRAP, D3D11_TEXTURE_ADDRESS_CLAMP].iter() {
+                samplers.push(get_sampler_desc(filter, address, D3D11_MAX_MAXANISOTROPY));
+            }
+        }
+        check_distinct(&samplers, get_sampler_key);
+    }
+}
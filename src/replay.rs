@@ -0,0 +1,462 @@
+use gamepad::{GamePadEvent, GamePadState, MAX_PLAYER_COUNT};
+use input::InputFrame;
+use keyboard::KeyboardState;
+use mouse::{MouseMode, MouseState};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use text_input::TextInputEvent;
+
+// A recording is the magic and version followed by a stream of records, each starting with a
+// tag byte. Every tick writes its time delta, then one record per update, then optionally the
+// state hash after the tick. Timer resets and restored state snapshots happen between ticks and
+// belong to the tick after them. Numbers are little endian.
+const MAGIC: &[u8; 4] = b"INPR";
+const VERSION: u32 = 2;
+
+const TAG_TICK: u8 = 1;
+const TAG_UPDATE: u8 = 2;
+const TAG_STATE_HASH: u8 = 3;
+const TAG_RESET_ELAPSED_TIME: u8 = 4;
+const TAG_STATE_SNAPSHOT: u8 = 5;
+
+// Update records only contain what changed since the previous update. These flags say which
+// parts are there.
+const KEYBOARD_CHANGED: u8 = 0x01;
+const MOUSE_CHANGED: u8 = 0x02;
+const GAMEPAD_EVENTS: u8 = 0x04;
+const TEXT_EVENTS: u8 = 0x08;
+// One bit per player, starting here.
+const GAMEPAD_CHANGED: u8 = 0x10;
+
+const LEFT_BUTTON: u8 = 0x01;
+const MIDDLE_BUTTON: u8 = 0x02;
+const RIGHT_BUTTON: u8 = 0x04;
+const X_BUTTON1: u8 = 0x08;
+const X_BUTTON2: u8 = 0x10;
+
+const EVENT_CONNECTED: u8 = 0x80;
+
+const TEXT_CHAR: u8 = 0;
+const TEXT_COMPOSITION_START: u8 = 1;
+const TEXT_COMPOSITION: u8 = 2;
+const TEXT_COMPOSITION_END: u8 = 3;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Writes a recording while the game runs. Recording has to start before the game is initialized,
+// since replaying starts from a fresh timer and game state.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    last_frame: InputFrame,
+}
+
+impl InputRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<InputRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(InputRecorder {
+            writer,
+            last_frame: InputFrame::default(),
+        })
+    }
+
+    pub fn write_tick(&mut self, time_delta: u64) -> io::Result<()> {
+        self.writer.write_all(&[TAG_TICK])?;
+        self.writer.write_all(&time_delta.to_le_bytes())
+    }
+
+    pub fn write_update(&mut self, frame: &InputFrame) -> io::Result<()> {
+        let mut flags = 0;
+        if frame.keyboard != self.last_frame.keyboard {
+            flags |= KEYBOARD_CHANGED;
+        }
+        if frame.mouse != self.last_frame.mouse {
+            flags |= MOUSE_CHANGED;
+        }
+        if !frame.gamepad_events.is_empty() {
+            flags |= GAMEPAD_EVENTS;
+        }
+        if !frame.text_events.is_empty() {
+            flags |= TEXT_EVENTS;
+        }
+        for player in 0..MAX_PLAYER_COUNT {
+            if frame.gamepads[player] != self.last_frame.gamepads[player] {
+                flags |= GAMEPAD_CHANGED << player;
+            }
+        }
+
+        let mut data = vec![TAG_UPDATE, flags];
+        if flags & KEYBOARD_CHANGED != 0 {
+            for bits in frame.keyboard.get_bits().iter() {
+                data.extend_from_slice(&bits.to_le_bytes());
+            }
+        }
+        if flags & MOUSE_CHANGED != 0 {
+            write_mouse_state(&mut data, &frame.mouse);
+        }
+        if flags & GAMEPAD_EVENTS != 0 {
+            data.push(frame.gamepad_events.len() as u8);
+            for event in frame.gamepad_events.iter() {
+                data.push(match *event {
+                    GamePadEvent::Connected(player) => player as u8 | EVENT_CONNECTED,
+                    GamePadEvent::Disconnected(player) => player as u8,
+                });
+            }
+        }
+        if flags & TEXT_EVENTS != 0 {
+            data.extend_from_slice(&(frame.text_events.len() as u32).to_le_bytes());
+            for event in frame.text_events.iter() {
+                write_text_event(&mut data, event);
+            }
+        }
+        for player in 0..MAX_PLAYER_COUNT {
+            if flags & (GAMEPAD_CHANGED << player) != 0 {
+                write_gamepad_state(&mut data, &frame.gamepads[player]);
+            }
+        }
+
+        self.last_frame = frame.clone();
+        self.writer.write_all(&data)
+    }
+
+    // The timer forgot its leftover time, which changes how many updates the next tick runs.
+    pub fn write_reset_elapsed_time(&mut self) -> io::Result<()> {
+        self.writer.write_all(&[TAG_RESET_ELAPSED_TIME])
+    }
+
+    // The game state was replaced with a snapshot, which the replay has to start from too.
+    pub fn write_state_snapshot(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(&[TAG_STATE_SNAPSHOT])?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)
+    }
+
+    pub fn write_state_hash(&mut self, hash: u64) -> io::Result<()> {
+        self.writer.write_all(&[TAG_STATE_HASH])?;
+        self.writer.write_all(&hash.to_le_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn write_mouse_state(data: &mut Vec<u8>, state: &MouseState) {
+    let mut buttons = 0;
+    let pairs = [
+        (state.left_button, LEFT_BUTTON),
+        (state.middle_button, MIDDLE_BUTTON),
+        (state.right_button, RIGHT_BUTTON),
+        (state.x_button1, X_BUTTON1),
+        (state.x_button2, X_BUTTON2),
+    ];
+    for &(down, bit) in pairs.iter() {
+        if down {
+            buttons |= bit;
+        }
+    }
+
+    data.push(buttons);
+    data.extend_from_slice(&state.x.to_le_bytes());
+    data.extend_from_slice(&state.y.to_le_bytes());
+    data.extend_from_slice(&state.scroll_wheel_value.to_le_bytes());
+    data.push(match state.position_mode {
+        MouseMode::Absolute => 0,
+        MouseMode::Relative => 1,
+    });
+}
+
+fn write_gamepad_state(data: &mut Vec<u8>, state: &GamePadState) {
+    data.push(state.connected as u8);
+    data.extend_from_slice(&state.packet.to_le_bytes());
+    data.extend_from_slice(&state.buttons.to_le_bytes());
+    let axes = [
+        state.left_stick_x,
+        state.left_stick_y,
+        state.right_stick_x,
+        state.right_stick_y,
+        state.left_trigger,
+        state.right_trigger,
+    ];
+    for axis in axes.iter() {
+        data.extend_from_slice(&axis.to_bits().to_le_bytes());
+    }
+}
+
+fn write_text_event(data: &mut Vec<u8>, event: &TextInputEvent) {
+    match *event {
+        TextInputEvent::Char(c) => {
+            data.push(TEXT_CHAR);
+            data.extend_from_slice(&(c as u32).to_le_bytes());
+        }
+        TextInputEvent::CompositionStart => data.push(TEXT_COMPOSITION_START),
+        TextInputEvent::Composition { ref text, cursor } => {
+            data.push(TEXT_COMPOSITION);
+            data.extend_from_slice(&(cursor as u32).to_le_bytes());
+            data.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            data.extend_from_slice(text.as_bytes());
+        }
+        TextInputEvent::CompositionEnd => data.push(TEXT_COMPOSITION_END),
+    }
+}
+
+// One tick of a recording: how far the clock moved and what each update in it saw.
+pub struct ReplayTick {
+    // Whether the timer was reset before the tick.
+    pub reset_elapsed_time: bool,
+    // A snapshot the game state was restored from before the tick.
+    pub state_snapshot: Option<Vec<u8>>,
+    pub time_delta: u64,
+    pub updates: Vec<InputFrame>,
+    pub state_hash: Option<u64>,
+}
+
+pub struct Replay {
+    ticks: Vec<ReplayTick>,
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        let data = fs::read(path)?;
+        let mut reader = Reader { data: &data };
+
+        if reader.read_bytes(4)? != MAGIC {
+            return Err(invalid_data("not an input recording"));
+        }
+        if reader.read_u32()? != VERSION {
+            return Err(invalid_data("unsupported input recording version"));
+        }
+
+        let mut ticks: Vec<ReplayTick> = Vec::new();
+        let mut last_frame = InputFrame::default();
+        let mut reset_elapsed_time = false;
+        let mut state_snapshot = None;
+        while !reader.data.is_empty() {
+            let tag = reader.read_u8()?;
+            match tag {
+                TAG_TICK => {
+                    ticks.push(ReplayTick {
+                        reset_elapsed_time,
+                        state_snapshot: state_snapshot.take(),
+                        time_delta: reader.read_u64()?,
+                        updates: Vec::new(),
+                        state_hash: None,
+                    });
+                    reset_elapsed_time = false;
+                    continue;
+                }
+                TAG_RESET_ELAPSED_TIME => {
+                    reset_elapsed_time = true;
+                    continue;
+                }
+                TAG_STATE_SNAPSHOT => {
+                    let length = reader.read_u32()? as usize;
+                    state_snapshot = Some(reader.read_bytes(length)?.to_vec());
+                    continue;
+                }
+                _ => {}
+            }
+
+            let tick = ticks
+                .last_mut()
+                .ok_or_else(|| invalid_data("input recording doesn't start with a tick"))?;
+            match tag {
+                TAG_UPDATE => {
+                    let frame = reader.read_update(&last_frame)?;
+                    tick.updates.push(frame.clone());
+                    last_frame = frame;
+                }
+                TAG_STATE_HASH => tick.state_hash = Some(reader.read_u64()?),
+                _ => return Err(invalid_data("unknown record in input recording")),
+            }
+        }
+
+        Ok(Replay { ticks })
+    }
+
+    pub fn get_ticks(&self) -> &[ReplayTick] {
+        &self.ticks
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(invalid_data("input recording is truncated"));
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.read_bytes(N)?);
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    fn read_update(&mut self, last_frame: &InputFrame) -> io::Result<InputFrame> {
+        let flags = self.read_u8()?;
+        let mut frame = InputFrame {
+            gamepad_events: Vec::new(),
+            text_events: Vec::new(),
+            ..last_frame.clone()
+        };
+
+        if flags & KEYBOARD_CHANGED != 0 {
+            let mut bits = [0; 8];
+            for b in bits.iter_mut() {
+                *b = self.read_u32()?;
+            }
+            frame.keyboard = KeyboardState::from_bits(bits);
+        }
+        if flags & MOUSE_CHANGED != 0 {
+            frame.mouse = self.read_mouse_state()?;
+        }
+        if flags & GAMEPAD_EVENTS != 0 {
+            let count = self.read_u8()?;
+            for _ in 0..count {
+                let event = self.read_u8()?;
+                let player = (event & !EVENT_CONNECTED) as usize;
+                frame.gamepad_events.push(if event & EVENT_CONNECTED != 0 {
+                    GamePadEvent::Connected(player)
+                } else {
+                    GamePadEvent::Disconnected(player)
+                });
+            }
+        }
+        if flags & TEXT_EVENTS != 0 {
+            let count = self.read_u32()?;
+            for _ in 0..count {
+                let event = self.read_text_event()?;
+                frame.text_events.push(event);
+            }
+        }
+        for player in 0..MAX_PLAYER_COUNT {
+            if flags & (GAMEPAD_CHANGED << player) != 0 {
+                frame.gamepads[player] = self.read_gamepad_state()?;
+            }
+        }
+
+        Ok(frame)
+    }
+
+    fn read_mouse_state(&mut self) -> io::Result<MouseState> {
+        let buttons = self.read_u8()?;
+        Ok(MouseState {
+            left_button: buttons & LEFT_BUTTON != 0,
+            middle_button: buttons & MIDDLE_BUTTON != 0,
+            right_button: buttons & RIGHT_BUTTON != 0,
+            x_button1: buttons & X_BUTTON1 != 0,
+            x_button2: buttons & X_BUTTON2 != 0,
+            x: self.read_i32()?,
+            y: self.read_i32()?,
+            scroll_wheel_value: self.read_i32()?,
+            position_mode: match self.read_u8()? {
+                0 => MouseMode::Absolute,
+                _ => MouseMode::Relative,
+            },
+        })
+    }
+
+    fn read_text_event(&mut self) -> io::Result<TextInputEvent> {
+        match self.read_u8()? {
+            TEXT_CHAR => std::char::from_u32(self.read_u32()?)
+                .map(TextInputEvent::Char)
+                .ok_or_else(|| invalid_data("invalid character in input recording")),
+            TEXT_COMPOSITION_START => Ok(TextInputEvent::CompositionStart),
+            TEXT_COMPOSITION => {
+                let cursor = self.read_u32()? as usize;
+                let length = self.read_u32()? as usize;
+                let text = String::from_utf8(self.read_bytes(length)?.to_vec())
+                    .map_err(|_| invalid_data("invalid text in input recording"))?;
+                Ok(TextInputEvent::Composition { text, cursor })
+            }
+            TEXT_COMPOSITION_END => Ok(TextInputEvent::CompositionEnd),
+            _ => Err(invalid_data("unknown text event in input recording")),
+        }
+    }
+
+    fn read_gamepad_state(&mut self) -> io::Result<GamePadState> {
+        Ok(GamePadState {
+            connected: self.read_u8()? != 0,
+            packet: self.read_u32()?,
+            buttons: self.read_u16()?,
+            left_stick_x: self.read_f32()?,
+            left_stick_y: self.read_f32()?,
+            right_stick_x: self.read_f32()?,
+            right_stick_y: self.read_f32()?,
+            left_trigger: self.read_f32()?,
+            right_trigger: self.read_f32()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resets_and_snapshots_belong_to_the_next_tick() {
+        let path = std::env::temp_dir().join("replay_resets_and_snapshots.inpr");
+        {
+            let mut recorder = InputRecorder::create(&path).unwrap();
+            recorder.write_state_snapshot(&[1, 2, 3]).unwrap();
+            recorder.write_tick(100).unwrap();
+            recorder.write_update(&InputFrame::default()).unwrap();
+            recorder.write_state_hash(7).unwrap();
+            recorder.write_reset_elapsed_time().unwrap();
+            recorder.write_tick(200).unwrap();
+            recorder.write_tick(300).unwrap();
+            recorder.flush().unwrap();
+        }
+        let replay = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let replay = replay.unwrap();
+        let ticks = replay.get_ticks();
+        assert_eq!(ticks.len(), 3);
+        assert_eq!(ticks[0].state_snapshot, Some(vec![1, 2, 3]));
+        assert!(!ticks[0].reset_elapsed_time);
+        assert_eq!(ticks[0].updates.len(), 1);
+        assert_eq!(ticks[0].state_hash, Some(7));
+        assert_eq!(ticks[1].time_delta, 200);
+        assert!(ticks[1].reset_elapsed_time);
+        assert_eq!(ticks[1].state_snapshot, None);
+        assert!(!ticks[2].reset_elapsed_time);
+    }
+}
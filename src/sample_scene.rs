@@ -0,0 +1,137 @@
+use basic_effect::{BasicEffect, BasicEffectOptions};
+use debug_draw::{DebugDraw, DebugStyle};
+use geometric_primitive::{create_teapot, Handedness, PrimitiveOptions};
+use gpu_model::GpuMesh;
+use image::Image;
+use math::{DepthMode, Mat4, Vec3};
+use mesh::Mesh;
+use render_states::RenderStates;
+use shader::ShaderLibrary;
+use sprite_batch::{Sprite, SpriteSortMode};
+use sprite_renderer::{SpriteRenderer, SpriteTexture};
+use std::f32::consts::PI;
+use texture::{create_texture, TextureOptions};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{ID3D11Device, ID3D11DeviceContext};
+
+// Size of the checkerboard sprite, and of its squares, in pixels.
+const CHECKER_SIZE: u32 = 32;
+const CHECKER_SQUARE: u32 = 8;
+
+// A teapot turning on a debug grid, lit by BasicEffect's default lights, and a checkerboard
+// sprite in the corner. Something to look at while finding out whether the renderer works,
+// before the game draws anything of its own.
+pub struct SampleScene {
+    teapot: Mesh,
+    gpu_teapot: Option<GpuMesh>,
+    effect: BasicEffect,
+    sprites: Option<SpriteRenderer>,
+    checker: Option<SpriteTexture>,
+}
+
+impl SampleScene {
+    pub fn new() -> SampleScene {
+        let mut effect = BasicEffect::new(BasicEffectOptions::new().with_lighting(true));
+        effect.set_default_lighting();
+        effect.set_diffuse_color([0.8, 0.5, 0.2]);
+
+        SampleScene {
+            // Left handed like the camera, so the faces D3D culls are the back ones.
+            teapot: create_teapot(
+                1.0,
+                8,
+                &PrimitiveOptions::new().with_handedness(Handedness::LeftHanded),
+            ),
+            gpu_teapot: None,
+            effect,
+            sprites: None,
+            checker: None,
+        }
+    }
+
+    pub fn on_device_lost(&mut self) {
+        self.gpu_teapot = None;
+        self.effect.on_device_lost();
+        self.sprites = None;
+        self.checker = None;
+    }
+
+    pub unsafe fn on_device_restored(
+        &mut self,
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        shaders: &mut ShaderLibrary,
+    ) -> Result<(), String> {
+        self.effect.on_device_restored(device, shaders)?;
+        let teapot = GpuMesh::new(device, &self.teapot)
+            .map_err(|hr| format!("Failed to create the teapot buffers, HRESULT {:x}", hr))?;
+        self.gpu_teapot = Some(teapot);
+
+        self.sprites = Some(SpriteRenderer::new(device, shaders)?);
+        let checker = create_texture(device, context, &create_checker(), &TextureOptions::new())?;
+        self.checker = Some(checker.get_sprite_texture());
+        Ok(())
+    }
+
+    // Looking down at the origin from a little way off. The depth buffer is cleared to 1.
+    pub fn get_view(&self) -> Mat4 {
+        Mat4::look_at_lh(Vec3::new(0.0, 1.2, -2.5), Vec3::new(0.0, 0.1, 0.0), Vec3::Y)
+    }
+
+    pub fn get_projection(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective_fov_lh(PI / 4.0, aspect, 0.1, 100.0, DepthMode::Standard)
+    }
+
+    // Marks out the ground the teapot stands on. Queue them before the debug drawing is rendered.
+    pub fn queue_debug_shapes(&self, debug_draw: &mut DebugDraw) {
+        debug_draw.grid(
+            [-2.0, -0.5, -2.0],
+            [4.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0],
+            8,
+            8,
+            &DebugStyle::new([0.5, 0.5, 0.5, 1.0]),
+        );
+    }
+
+    // total_seconds turns the teapot, aspect is the width of the view over its height.
+    pub unsafe fn render(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        shaders: &ShaderLibrary,
+        states: &mut RenderStates,
+        total_seconds: f32,
+        aspect: f32,
+    ) -> Result<(), HRESULT> {
+        if let Some(ref teapot) = self.gpu_teapot {
+            let (view, projection) = (self.get_view(), self.get_projection(aspect));
+            self.effect.set_matrices(
+                Mat4::rotation_y(total_seconds * 0.5).rows,
+                view.rows,
+                projection.rows,
+            );
+            self.effect.draw_mesh(context, shaders, states, teapot)?;
+        }
+
+        if let (Some(sprites), Some(checker)) = (self.sprites.as_mut(), self.checker.as_ref()) {
+            sprites.begin(SpriteSortMode::Deferred);
+            sprites.draw(checker, &Sprite::new(16.0, 16.0).with_scale(2.0, 2.0));
+            sprites.end(context, shaders, states)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Opaque white and gray squares, premultiplied like the sprite renderer expects.
+fn create_checker() -> Image {
+    let mut data = Vec::with_capacity((CHECKER_SIZE * CHECKER_SIZE * 4) as usize);
+    for y in 0..CHECKER_SIZE {
+        for x in 0..CHECKER_SIZE {
+            let is_white = (x / CHECKER_SQUARE + y / CHECKER_SQUARE).is_multiple_of(2);
+            let value = if is_white { 255 } else { 96 };
+            data.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+    Image::new_rgba8(CHECKER_SIZE, CHECKER_SIZE, false, data)
+}
@@ -0,0 +1,321 @@
+use shader_compiler::{compile, get_profile};
+pub use shader_compiler::{ShaderDesc, ShaderStage};
+use shader_pack::{get_shader_key, ShaderPack};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{
+    ID3D11ComputeShader, ID3D11Device, ID3D11DomainShader, ID3D11GeometryShader, ID3D11HullShader,
+    ID3D11PixelShader, ID3D11VertexShader,
+};
+use winapi::um::d3dcommon::{D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_9_3};
+use wio::com::ComPtr;
+
+// Cooked by build.rs from shaders/manifest.txt for release builds. Debug builds, and release
+// builds where the manifest is empty or the build machine couldn't compile shaders, don't get
+// one and compile from source instead.
+#[cfg(shader_pack)]
+static SHADER_PACK: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/shaders.pack"));
+
+#[cfg(shader_pack)]
+fn load_shader_pack() -> Option<ShaderPack> {
+    match ShaderPack::from_bytes(SHADER_PACK) {
+        Ok(pack) => Some(pack),
+        Err(e) => {
+            eprintln!(
+                "Failed to read the embedded shaders, compiling from source: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(shader_pack))]
+fn load_shader_pack() -> Option<ShaderPack> {
+    None
+}
+
+// Checking the timestamps of every source file each frame would be wasteful.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+pub enum ShaderObject {
+    Vertex(ComPtr<ID3D11VertexShader>),
+    Pixel(ComPtr<ID3D11PixelShader>),
+    Geometry(ComPtr<ID3D11GeometryShader>),
+    Hull(ComPtr<ID3D11HullShader>),
+    Domain(ComPtr<ID3D11DomainShader>),
+    Compute(ComPtr<ID3D11ComputeShader>),
+}
+
+unsafe fn create_shader_object(
+    device: &ID3D11Device,
+    stage: ShaderStage,
+    bytecode: &[u8],
+) -> Result<ShaderObject, HRESULT> {
+    macro_rules! create {
+        ($method:ident, $interface:ty, $variant:ident) => {{
+            let mut shader_ptr: *mut $interface = std::ptr::null_mut();
+            let hr = device.$method(
+                bytecode.as_ptr() as *const _,
+                bytecode.len(),
+                std::ptr::null_mut(),
+                &mut shader_ptr,
+            );
+            if ::failed(hr) {
+                return Err(hr);
+            }
+            ShaderObject::$variant(ComPtr::from_raw(shader_ptr))
+        }};
+    }
+
+    Ok(match stage {
+        ShaderStage::Vertex => create!(CreateVertexShader, ID3D11VertexShader, Vertex),
+        ShaderStage::Pixel => create!(CreatePixelShader, ID3D11PixelShader, Pixel),
+        ShaderStage::Geometry => create!(CreateGeometryShader, ID3D11GeometryShader, Geometry),
+        ShaderStage::Hull => create!(CreateHullShader, ID3D11HullShader, Hull),
+        ShaderStage::Domain => create!(CreateDomainShader, ID3D11DomainShader, Domain),
+        ShaderStage::Compute => create!(CreateComputeShader, ID3D11ComputeShader, Compute),
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShaderHandle(usize);
+
+struct ShaderEntry {
+    desc: ShaderDesc,
+    bytecode: Option<Vec<u8>>,
+    object: Option<ShaderObject>,
+    // Each file with the modification time it had when we last compiled.
+    dependencies: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+// Owns every shader the game loaded. Handles stay valid when a shader is recompiled or
+// recreated after device loss, so look the shader up through its handle every time it's used.
+pub struct ShaderLibrary {
+    feature_level: D3D_FEATURE_LEVEL,
+    include_dirs: Vec<PathBuf>,
+    entries: Vec<ShaderEntry>,
+    last_watch: Option<Instant>,
+    // Precompiled shaders in release builds. Without it everything is compiled from source.
+    pack: Option<ShaderPack>,
+}
+
+impl ShaderLibrary {
+    pub fn new() -> ShaderLibrary {
+        ShaderLibrary {
+            feature_level: D3D_FEATURE_LEVEL_9_3,
+            include_dirs: Vec::new(),
+            entries: Vec::new(),
+            last_watch: None,
+            pack: load_shader_pack(),
+        }
+    }
+
+    // Where #include <...> looks.
+    pub fn add_include_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+    }
+
+    // Compiles and creates the shader right away, or takes it from the pack in release builds.
+    // If that fails the diagnostics are printed and the handle stays empty until a fixed source
+    // compiles. Loading the same shader again, e.g. when device objects are recreated, gives
+    // back the handle it already has.
+    pub unsafe fn load(&mut self, device: &ID3D11Device, desc: ShaderDesc) -> ShaderHandle {
+        if let Some(index) = self.entries.iter().position(|e| e.desc == desc) {
+            return ShaderHandle(index);
+        }
+
+        self.entries.push(ShaderEntry {
+            desc,
+            bytecode: None,
+            object: None,
+            dependencies: Vec::new(),
+        });
+
+        let index = self.entries.len() - 1;
+        self.rebuild(index, device);
+        ShaderHandle(index)
+    }
+
+    fn get_entry(&self, handle: ShaderHandle) -> &ShaderEntry {
+        &self.entries[handle.0]
+    }
+
+    pub fn get(&self, handle: ShaderHandle) -> Option<&ShaderObject> {
+        self.get_entry(handle).object.as_ref()
+    }
+
+    // Input layouts are validated against this.
+    pub fn get_bytecode(&self, handle: ShaderHandle) -> Option<&[u8]> {
+        self.get_entry(handle).bytecode.as_deref()
+    }
+
+    pub fn get_vertex_shader(&self, handle: ShaderHandle) -> Option<&ComPtr<ID3D11VertexShader>> {
+        match self.get(handle) {
+            Some(ShaderObject::Vertex(shader)) => Some(shader),
+            _ => None,
+        }
+    }
+
+    pub fn get_pixel_shader(&self, handle: ShaderHandle) -> Option<&ComPtr<ID3D11PixelShader>> {
+        match self.get(handle) {
+            Some(ShaderObject::Pixel(shader)) => Some(shader),
+            _ => None,
+        }
+    }
+
+    // Recompiles whatever changed on disk since it was last compiled. Returns true if any
+    // shader was replaced, e.g. so cached input layouts can be rebuilt.
+    pub unsafe fn reload_changed(&mut self, device: &ID3D11Device) -> bool {
+        // Precompiled shaders don't change.
+        if self.pack.is_some() {
+            return false;
+        }
+
+        let now = Instant::now();
+        if let Some(last_watch) = self.last_watch {
+            if now.duration_since(last_watch) < WATCH_INTERVAL {
+                return false;
+            }
+        }
+        self.last_watch = Some(now);
+
+        let mut reloaded = false;
+        for index in 0..self.entries.len() {
+            let changed = self.entries[index]
+                .dependencies
+                .iter()
+                .any(|(path, modified)| get_modified_time(path) != *modified);
+            if changed && self.rebuild(index, device) {
+                reloaded = true;
+            }
+        }
+
+        reloaded
+    }
+
+    // Releases the device objects but keeps the bytecode around for on_device_restored.
+    pub fn on_device_lost(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.object = None;
+        }
+    }
+
+    // The new device might have a different feature level, in which case everything gets
+    // compiled again for the new profiles.
+    pub unsafe fn on_device_restored(
+        &mut self,
+        device: &ID3D11Device,
+        feature_level: D3D_FEATURE_LEVEL,
+    ) {
+        let level_changed = feature_level != self.feature_level;
+        self.feature_level = feature_level;
+
+        for index in 0..self.entries.len() {
+            if level_changed {
+                self.rebuild(index, device);
+                continue;
+            }
+
+            let entry = &mut self.entries[index];
+            if let Some(ref bytecode) = entry.bytecode {
+                match create_shader_object(device, entry.desc.stage, bytecode) {
+                    Ok(object) => entry.object = Some(object),
+                    Err(hr) => eprintln!(
+                        "{}: failed to create shader, HRESULT {:x}",
+                        entry.desc.path.display(),
+                        hr
+                    ),
+                }
+            }
+        }
+    }
+
+    // Compiles, or takes from the pack, and creates one shader. On failure the previous version,
+    // if any, stays in use.
+    unsafe fn rebuild(&mut self, index: usize, device: &ID3D11Device) -> bool {
+        let entry = &mut self.entries[index];
+        let path_name = entry.desc.path.display().to_string();
+        let profile = match get_profile(entry.desc.stage, self.feature_level) {
+            Some(profile) => profile,
+            None => {
+                eprintln!(
+                    "{}: {:?} shaders aren't supported at feature level {:x}",
+                    path_name, entry.desc.stage, self.feature_level
+                );
+                return false;
+            }
+        };
+
+        let bytecode = match self.pack {
+            Some(ref pack) => match pack.get(get_shader_key(&entry.desc, &profile)) {
+                Some(bytecode) => bytecode.to_vec(),
+                None => {
+                    eprintln!(
+                        "{}: {} ({}) isn't in the shader pack, add it to shaders/manifest.txt",
+                        path_name, entry.desc.entry_point, profile
+                    );
+                    return false;
+                }
+            },
+            None => match compile_and_watch(entry, &profile, &self.include_dirs) {
+                Some(bytecode) => bytecode,
+                None => return false,
+            },
+        };
+
+        match create_shader_object(device, entry.desc.stage, &bytecode) {
+            Ok(object) => {
+                entry.object = Some(object);
+                entry.bytecode = Some(bytecode);
+                true
+            }
+            Err(hr) => {
+                eprintln!("{}: failed to create shader, HRESULT {:x}", path_name, hr);
+                false
+            }
+        }
+    }
+}
+
+fn compile_and_watch(
+    entry: &mut ShaderEntry,
+    profile: &str,
+    include_dirs: &[PathBuf],
+) -> Option<Vec<u8>> {
+    let compiled = compile(&entry.desc, profile, include_dirs, cfg!(debug_assertions));
+
+    // Watch the files even if the compile failed, so fixing the error triggers a retry. If an
+    // include couldn't be found we only know about the main file, which is good enough.
+    let dependency_paths = match compiled {
+        Ok(ref compiled) => compiled.dependencies.clone(),
+        Err(_) if entry.dependencies.is_empty() => vec![entry.desc.path.clone()],
+        Err(_) => entry.dependencies.iter().map(|d| d.0.clone()).collect(),
+    };
+    entry.dependencies = dependency_paths
+        .into_iter()
+        .map(|path| {
+            let modified = get_modified_time(&path);
+            (path, modified)
+        })
+        .collect();
+
+    match compiled {
+        Ok(compiled) => {
+            if !compiled.warnings.is_empty() {
+                eprintln!("{}", compiled.warnings);
+            }
+            Some(compiled.bytecode)
+        }
+        Err(diagnostics) => {
+            eprintln!("{}", diagnostics);
+            None
+        }
+    }
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
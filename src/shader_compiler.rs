@@ -0,0 +1,301 @@
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use winapi::shared::minwindef::{LPCVOID, UINT};
+use winapi::shared::winerror::{E_FAIL, SUCCEEDED, S_OK};
+use winapi::um::d3dcommon::{
+    ID3DBlob, ID3DInclude, ID3DIncludeVtbl, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_10_0,
+    D3D_FEATURE_LEVEL_10_1, D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_9_3, D3D_INCLUDE_SYSTEM,
+    D3D_INCLUDE_TYPE, D3D_SHADER_MACRO,
+};
+use winapi::um::d3dcompiler::{
+    D3DCompile, D3DCOMPILE_DEBUG, D3DCOMPILE_ENABLE_STRICTNESS, D3DCOMPILE_OPTIMIZATION_LEVEL3,
+    D3DCOMPILE_SKIP_OPTIMIZATION,
+};
+use winapi::um::winnt::{HRESULT, LPCSTR};
+use wio::com::ComPtr;
+
+// This module is also compiled into build.rs to cook the release shaders, so it can't use
+// anything from the rest of the game.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShaderStage {
+    Vertex,
+    Pixel,
+    Geometry,
+    Hull,
+    Domain,
+    Compute,
+}
+
+impl ShaderStage {
+    pub fn from_name(name: &str) -> Option<ShaderStage> {
+        match name {
+            "Vertex" => Some(ShaderStage::Vertex),
+            "Pixel" => Some(ShaderStage::Pixel),
+            "Geometry" => Some(ShaderStage::Geometry),
+            "Hull" => Some(ShaderStage::Hull),
+            "Domain" => Some(ShaderStage::Domain),
+            "Compute" => Some(ShaderStage::Compute),
+            _ => None,
+        }
+    }
+
+    fn get_profile_prefix(&self) -> &'static str {
+        match *self {
+            ShaderStage::Vertex => "vs",
+            ShaderStage::Pixel => "ps",
+            ShaderStage::Geometry => "gs",
+            ShaderStage::Hull => "hs",
+            ShaderStage::Domain => "ds",
+            ShaderStage::Compute => "cs",
+        }
+    }
+}
+
+// The best profile the device can run for a stage, or None if the stage doesn't exist at that
+// feature level. Compute on 10.x only works if the driver opts in, so we don't count on it.
+pub fn get_profile(stage: ShaderStage, feature_level: D3D_FEATURE_LEVEL) -> Option<String> {
+    let version = if feature_level >= D3D_FEATURE_LEVEL_11_0 {
+        "5_0"
+    } else {
+        match stage {
+            ShaderStage::Hull | ShaderStage::Domain | ShaderStage::Compute => return None,
+            ShaderStage::Geometry if feature_level < D3D_FEATURE_LEVEL_10_0 => return None,
+            _ => {}
+        }
+
+        if feature_level >= D3D_FEATURE_LEVEL_10_1 {
+            "4_1"
+        } else if feature_level >= D3D_FEATURE_LEVEL_10_0 {
+            "4_0"
+        } else if feature_level >= D3D_FEATURE_LEVEL_9_3 {
+            "4_0_level_9_3"
+        } else {
+            "4_0_level_9_1"
+        }
+    };
+
+    Some(format!("{}_{}", stage.get_profile_prefix(), version))
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ShaderDesc {
+    pub path: PathBuf,
+    pub entry_point: String,
+    pub stage: ShaderStage,
+    pub defines: Vec<(String, String)>,
+}
+
+impl ShaderDesc {
+    pub fn new<P: AsRef<Path>>(path: P, entry_point: &str, stage: ShaderStage) -> ShaderDesc {
+        ShaderDesc {
+            path: path.as_ref().to_path_buf(),
+            entry_point: entry_point.to_string(),
+            stage,
+            defines: Vec::new(),
+        }
+    }
+
+    pub fn with_define(mut self, name: &str, value: &str) -> ShaderDesc {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// What a successful compile produces. The dependencies are the source file and everything it
+// included, which is what we have to watch for changes.
+pub struct CompiledShader {
+    pub bytecode: Vec<u8>,
+    pub dependencies: Vec<PathBuf>,
+    // Compiler warnings, empty if there were none.
+    pub warnings: String,
+}
+
+// Compiles one shader from source for the given profile. Debug builds skip optimization so
+// the shaders can be stepped through in a graphics debugger. Errors come back as the compiler's
+// diagnostics.
+pub fn compile(
+    desc: &ShaderDesc,
+    profile: &str,
+    include_dirs: &[PathBuf],
+    debug: bool,
+) -> Result<CompiledShader, String> {
+    let path_name = desc.path.display().to_string();
+    let source = fs::read(&desc.path).map_err(|e| format!("{}: {}", path_name, e))?;
+
+    let to_c_string =
+        |s: &str| CString::new(s).map_err(|_| format!("{}: {:?} contains a nul", path_name, s));
+    let source_name = to_c_string(&path_name)?;
+    let entry_point = to_c_string(&desc.entry_point)?;
+    let target = to_c_string(profile)?;
+    let mut define_strings = Vec::with_capacity(desc.defines.len());
+    for (name, value) in desc.defines.iter() {
+        define_strings.push((to_c_string(name)?, to_c_string(value)?));
+    }
+
+    // Terminated by an entry full of nulls.
+    let mut defines: Vec<D3D_SHADER_MACRO> = define_strings
+        .iter()
+        .map(|(name, value)| D3D_SHADER_MACRO {
+            Name: name.as_ptr(),
+            Definition: value.as_ptr(),
+        })
+        .collect();
+    defines.push(D3D_SHADER_MACRO {
+        Name: std::ptr::null(),
+        Definition: std::ptr::null(),
+    });
+
+    let mut flags = D3DCOMPILE_ENABLE_STRICTNESS;
+    if debug {
+        flags |= D3DCOMPILE_DEBUG | D3DCOMPILE_SKIP_OPTIMIZATION;
+    } else {
+        flags |= D3DCOMPILE_OPTIMIZATION_LEVEL3;
+    }
+
+    let root_dir = desc
+        .path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+    let mut include = IncludeHandler::new(root_dir, include_dirs);
+
+    let mut code_ptr: *mut ID3DBlob = std::ptr::null_mut();
+    let mut errors_ptr: *mut ID3DBlob = std::ptr::null_mut();
+    let hr = unsafe {
+        D3DCompile(
+            source.as_ptr() as LPCVOID,
+            source.len(),
+            source_name.as_ptr(),
+            defines.as_ptr(),
+            &mut include as *mut IncludeHandler as *mut ID3DInclude,
+            entry_point.as_ptr(),
+            target.as_ptr(),
+            flags,
+            0,
+            &mut code_ptr,
+            &mut errors_ptr,
+        )
+    };
+
+    let diagnostics = if errors_ptr.is_null() {
+        String::new()
+    } else {
+        unsafe { blob_to_string(ComPtr::from_raw(errors_ptr)) }
+    };
+
+    if !SUCCEEDED(hr) || code_ptr.is_null() {
+        if diagnostics.is_empty() {
+            return Err(format!(
+                "{}: D3DCompile failed with HRESULT {:x}",
+                path_name, hr
+            ));
+        }
+        return Err(diagnostics);
+    }
+    let bytecode = unsafe { blob_to_vec(ComPtr::from_raw(code_ptr)) };
+    let mut dependencies = vec![desc.path.clone()];
+    dependencies.extend(include.files.into_iter().map(|(path, _)| path));
+
+    Ok(CompiledShader {
+        bytecode,
+        dependencies,
+        warnings: diagnostics,
+    })
+}
+
+unsafe fn blob_to_vec(blob: ComPtr<ID3DBlob>) -> Vec<u8> {
+    std::slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize()).to_vec()
+}
+
+unsafe fn blob_to_string(blob: ComPtr<ID3DBlob>) -> String {
+    let text = blob_to_vec(blob);
+    String::from_utf8_lossy(&text)
+        .trim_end_matches('\0')
+        .trim_end()
+        .to_string()
+}
+
+// Resolves #include "x" relative to the including file and #include <x> in the include
+// directories, like the C preprocessor does. Keeps track of everything it opened so those
+// files can be watched too.
+#[repr(C)]
+struct IncludeHandler {
+    // Has to come first, D3DCompile only knows about this part.
+    base: ID3DInclude,
+    root_dir: PathBuf,
+    include_dirs: Vec<PathBuf>,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+static INCLUDE_HANDLER_VTBL: ID3DIncludeVtbl = ID3DIncludeVtbl {
+    Open: include_open,
+    Close: include_close,
+};
+
+impl IncludeHandler {
+    fn new(root_dir: PathBuf, include_dirs: &[PathBuf]) -> IncludeHandler {
+        IncludeHandler {
+            base: ID3DInclude {
+                lpVtbl: &INCLUDE_HANDLER_VTBL,
+            },
+            root_dir,
+            include_dirs: include_dirs.to_vec(),
+            files: Vec::new(),
+        }
+    }
+
+    fn find(&self, include_type: D3D_INCLUDE_TYPE, name: &str, parent: LPCVOID) -> Option<PathBuf> {
+        // The parent is one of the buffers we handed out earlier, or null for the main file.
+        let parent_dir = self
+            .files
+            .iter()
+            .find(|(_, data)| data.as_ptr() as LPCVOID == parent)
+            .and_then(|(path, _)| path.parent())
+            .unwrap_or(&self.root_dir);
+
+        let mut candidates: Vec<&Path> = self.include_dirs.iter().map(|d| d.as_path()).collect();
+        if include_type == D3D_INCLUDE_SYSTEM {
+            candidates.push(parent_dir);
+        } else {
+            candidates.insert(0, parent_dir);
+        }
+
+        candidates
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
+
+unsafe extern "system" fn include_open(
+    this: *mut ID3DInclude,
+    include_type: D3D_INCLUDE_TYPE,
+    file_name: LPCSTR,
+    parent_data: LPCVOID,
+    data: *mut LPCVOID,
+    bytes: *mut UINT,
+) -> HRESULT {
+    let handler = &mut *(this as *mut IncludeHandler);
+    let name = CStr::from_ptr(file_name).to_string_lossy();
+
+    let path = match handler.find(include_type, &name, parent_data) {
+        Some(path) => path,
+        None => return E_FAIL,
+    };
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(_) => return E_FAIL,
+    };
+
+    // The buffer lives until the handler is dropped, after D3DCompile returned.
+    *data = contents.as_ptr() as LPCVOID;
+    *bytes = contents.len() as UINT;
+    handler.files.push((path, contents));
+    S_OK
+}
+
+unsafe extern "system" fn include_close(_this: *mut ID3DInclude, _data: LPCVOID) -> HRESULT {
+    S_OK
+}
@@ -0,0 +1,237 @@
+use shader_compiler::ShaderDesc;
+use std::io;
+use std::path::Component;
+
+// Like shader_compiler, this is shared with build.rs.
+
+const MAGIC: &[u8; 4] = b"SHPK";
+const VERSION: u32 = 1;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// 64-bit FNV-1a. Good enough to tell shaders apart and to catch a damaged pack, and it gives the
+// same answer in the build script as in the game.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// Identifies one compiled variant of a shader. Paths are relative to the crate root, like in the
+// manifest, and "./" or backslashes don't make a difference.
+pub fn get_shader_key(desc: &ShaderDesc, profile: &str) -> u64 {
+    let path: Vec<String> = desc
+        .path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    let mut key = format!("{}|{}|{}", path.join("/"), desc.entry_point, profile);
+    for (name, value) in desc.defines.iter() {
+        key.push_str(&format!("|{}={}", name, value));
+    }
+    hash_bytes(key.as_bytes())
+}
+
+// Precompiled bytecode by shader key. Every blob is stored with the hash of its contents, which
+// is checked when the pack is read.
+#[derive(Default)]
+pub struct ShaderPack {
+    blobs: Vec<(u64, Vec<u8>)>,
+}
+
+impl ShaderPack {
+    pub fn new() -> ShaderPack {
+        ShaderPack::default()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<ShaderPack> {
+        let mut reader = Reader { data };
+        if reader.read_bytes(4)? != MAGIC {
+            return Err(invalid_data("not a shader pack"));
+        }
+        if reader.read_u32()? != VERSION {
+            return Err(invalid_data("unsupported shader pack version"));
+        }
+
+        let count = reader.read_u32()?;
+        let mut pack = ShaderPack::new();
+        for _ in 0..count {
+            let key = reader.read_u64()?;
+            let content_hash = reader.read_u64()?;
+            let size = reader.read_u32()? as usize;
+            let bytecode = reader.read_bytes(size)?;
+            if hash_bytes(bytecode) != content_hash {
+                return Err(invalid_data("shader pack is corrupted"));
+            }
+            pack.insert(key, bytecode.to_vec());
+        }
+
+        Ok(pack)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(self.blobs.len() as u32).to_le_bytes());
+        for (key, bytecode) in self.blobs.iter() {
+            data.extend_from_slice(&key.to_le_bytes());
+            data.extend_from_slice(&hash_bytes(bytecode).to_le_bytes());
+            data.extend_from_slice(&(bytecode.len() as u32).to_le_bytes());
+            data.extend_from_slice(bytecode);
+        }
+        data
+    }
+
+    // Replaces the blob if the key is already in the pack.
+    pub fn insert(&mut self, key: u64, bytecode: Vec<u8>) {
+        match self.blobs.iter_mut().find(|(k, _)| *k == key) {
+            Some(blob) => blob.1 = bytecode,
+            None => self.blobs.push((key, bytecode)),
+        }
+    }
+
+    pub fn get(&self, key: u64) -> Option<&[u8]> {
+        self.blobs
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, bytecode)| bytecode.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(invalid_data("shader pack is truncated"));
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.read_bytes(N)?);
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shader_compiler::ShaderStage;
+
+    fn basic_desc() -> ShaderDesc {
+        ShaderDesc::new("shaders/basic.hlsl", "main", ShaderStage::Vertex)
+            .with_define("A", "1")
+            .with_define("B", "2")
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut pack = ShaderPack::new();
+        assert!(pack.is_empty());
+        pack.insert(1, vec![1, 2, 3]);
+        pack.insert(2, Vec::new());
+        pack.insert(1, vec![4, 5]);
+
+        let data = pack.to_bytes();
+        assert_eq!(&data[..4], b"SHPK");
+
+        let pack = ShaderPack::from_bytes(&data).unwrap();
+        assert_eq!(pack.get(1), Some(&[4u8, 5][..]));
+        assert_eq!(pack.get(2), Some(&[][..]));
+        assert_eq!(pack.get(3), None);
+    }
+
+    #[test]
+    fn damaged_packs() {
+        let mut pack = ShaderPack::new();
+        pack.insert(1, vec![1, 2, 3]);
+        let data = pack.to_bytes();
+
+        for length in 0..data.len() {
+            assert!(ShaderPack::from_bytes(&data[..length]).is_err());
+        }
+
+        let mut corrupted = data.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert!(ShaderPack::from_bytes(&corrupted).is_err());
+
+        let mut wrong_magic = data.clone();
+        wrong_magic[0] = b'X';
+        assert!(ShaderPack::from_bytes(&wrong_magic).is_err());
+
+        let mut wrong_version = data;
+        wrong_version[4] = 2;
+        assert!(ShaderPack::from_bytes(&wrong_version).is_err());
+    }
+
+    #[test]
+    fn hash_is_fnv1a() {
+        assert_eq!(hash_bytes(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash_bytes(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    // Packs cooked by an older build have to keep working, so the key can't change.
+    #[test]
+    fn key_is_stable() {
+        assert_eq!(
+            get_shader_key(&basic_desc(), "vs_5_0"),
+            0xde9c_f4b7_7010_2212
+        );
+    }
+
+    #[test]
+    fn key_ignores_path_spelling() {
+        let key = get_shader_key(&basic_desc(), "vs_5_0");
+        let mut desc = basic_desc();
+        desc.path = "./shaders/basic.hlsl".into();
+        assert_eq!(get_shader_key(&desc, "vs_5_0"), key);
+        desc.path = "shaders\\basic.hlsl".into();
+        assert_eq!(get_shader_key(&desc, "vs_5_0"), key);
+    }
+
+    #[test]
+    fn key_depends_on_everything() {
+        let key = get_shader_key(&basic_desc(), "vs_5_0");
+        assert_ne!(get_shader_key(&basic_desc(), "vs_4_0"), key);
+
+        let mut desc = basic_desc();
+        desc.entry_point = "other".to_string();
+        assert_ne!(get_shader_key(&desc, "vs_5_0"), key);
+
+        let desc = basic_desc().with_define("C", "1");
+        assert_ne!(get_shader_key(&desc, "vs_5_0"), key);
+
+        // Defines can depend on the ones before them, so their order matters.
+        let desc = ShaderDesc::new("shaders/basic.hlsl", "main", ShaderStage::Vertex)
+            .with_define("B", "2")
+            .with_define("A", "1");
+        assert_ne!(get_shader_key(&desc, "vs_5_0"), key);
+    }
+}
@@ -0,0 +1,539 @@
+// Collects sprites between begin and end and turns them into quads, sorted and grouped into as
+// few draws as possible. Doesn't know anything about D3D; the texture is whatever the renderer
+// uses to tell textures apart, see SpriteRenderer for the D3D11 side.
+
+// Sprites per draw at most, so the indices fit in 16 bits.
+pub const MAX_SPRITES_PER_DRAW: usize = 2048;
+
+// The input layout is in sprite_renderer.rs.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpriteVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SpriteSortMode {
+    // In the order they were drawn.
+    #[default]
+    Deferred,
+    // Grouped by texture, for the fewest draws when nothing overlaps.
+    Texture,
+    // Highest depth first, for transparent sprites.
+    BackToFront,
+    // Lowest depth first.
+    FrontToBack,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpriteRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl SpriteRect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> SpriteRect {
+        SpriteRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+// Everything about a single sprite except its texture. Positions and sizes are in pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sprite {
+    pub position: [f32; 2],
+    // The part of the texture to draw, all of it if None.
+    pub source: Option<SpriteRect>,
+    pub color: [f32; 4],
+    // Radians, clockwise on screen.
+    pub rotation: f32,
+    // The point in the source rect that ends up at position, and that rotation and scale are
+    // around.
+    pub origin: [f32; 2],
+    pub scale: [f32; 2],
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // 0 to 1, used for sorting. Also ends up as the z of the vertices.
+    pub depth: f32,
+}
+
+impl Sprite {
+    pub fn new(x: f32, y: f32) -> Sprite {
+        Sprite {
+            position: [x, y],
+            source: None,
+            color: [1.0; 4],
+            rotation: 0.0,
+            origin: [0.0, 0.0],
+            scale: [1.0, 1.0],
+            flip_x: false,
+            flip_y: false,
+            depth: 0.0,
+        }
+    }
+
+    pub fn with_source(mut self, source: SpriteRect) -> Sprite {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Sprite {
+        self.color = color;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Sprite {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_origin(mut self, x: f32, y: f32) -> Sprite {
+        self.origin = [x, y];
+        self
+    }
+
+    pub fn with_scale(mut self, x: f32, y: f32) -> Sprite {
+        self.scale = [x, y];
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Sprite {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: f32) -> Sprite {
+        self.depth = depth;
+        self
+    }
+}
+
+// A run of consecutive sprites that share a texture, i.e. one draw.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpriteDraw<T> {
+    pub texture: T,
+    pub first_sprite: usize,
+    pub sprite_count: usize,
+}
+
+// Four vertices per sprite, in the order of the draws.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpriteBatchData<T> {
+    pub vertices: Vec<SpriteVertex>,
+    pub draws: Vec<SpriteDraw<T>>,
+}
+
+struct QueuedSprite {
+    texture_index: usize,
+    sprite: Sprite,
+}
+
+pub struct SpriteBatch<T> {
+    sort_mode: SpriteSortMode,
+    is_in_batch: bool,
+    // Each texture used in this batch with its size in pixels.
+    textures: Vec<(T, [f32; 2])>,
+    sprites: Vec<QueuedSprite>,
+}
+
+impl<T: Clone + PartialEq> SpriteBatch<T> {
+    pub fn new() -> SpriteBatch<T> {
+        SpriteBatch {
+            sort_mode: SpriteSortMode::Deferred,
+            is_in_batch: false,
+            textures: Vec::new(),
+            sprites: Vec::new(),
+        }
+    }
+
+    pub fn begin(&mut self, sort_mode: SpriteSortMode) {
+        assert!(!self.is_in_batch, "begin called twice without end");
+
+        self.sort_mode = sort_mode;
+        self.is_in_batch = true;
+        self.textures.clear();
+        self.sprites.clear();
+    }
+
+    pub fn draw(&mut self, texture: &T, texture_width: u32, texture_height: u32, sprite: &Sprite) {
+        assert!(self.is_in_batch, "draw called outside of begin and end");
+
+        // Consecutive sprites usually share a texture, so try the last one first.
+        let texture_index = match self.textures.last() {
+            Some((last, _)) if last == texture => self.textures.len() - 1,
+            _ => match self.textures.iter().position(|(t, _)| t == texture) {
+                Some(index) => index,
+                None => {
+                    let size = [texture_width as f32, texture_height as f32];
+                    self.textures.push((texture.clone(), size));
+                    self.textures.len() - 1
+                }
+            },
+        };
+
+        self.sprites.push(QueuedSprite {
+            texture_index,
+            sprite: *sprite,
+        });
+    }
+
+    // Sorts what was drawn since begin and builds the vertices.
+    pub fn end(&mut self) -> SpriteBatchData<T> {
+        assert!(self.is_in_batch, "end called without begin");
+        self.is_in_batch = false;
+
+        // Stable sorts, so sprites that compare equal stay in the order they were drawn.
+        match self.sort_mode {
+            SpriteSortMode::Deferred => {}
+            SpriteSortMode::Texture => self.sprites.sort_by_key(|s| s.texture_index),
+            SpriteSortMode::BackToFront => self
+                .sprites
+                .sort_by(|a, b| b.sprite.depth.total_cmp(&a.sprite.depth)),
+            SpriteSortMode::FrontToBack => self
+                .sprites
+                .sort_by(|a, b| a.sprite.depth.total_cmp(&b.sprite.depth)),
+        }
+
+        let mut vertices = Vec::with_capacity(self.sprites.len() * 4);
+        let mut draws: Vec<SpriteDraw<T>> = Vec::new();
+        for (i, queued) in self.sprites.iter().enumerate() {
+            let (ref texture, texture_size) = self.textures[queued.texture_index];
+            vertices.extend_from_slice(&get_sprite_vertices(&queued.sprite, texture_size));
+
+            match draws.last_mut() {
+                Some(draw) if draw.texture == *texture => draw.sprite_count += 1,
+                _ => draws.push(SpriteDraw {
+                    texture: texture.clone(),
+                    first_sprite: i,
+                    sprite_count: 1,
+                }),
+            }
+        }
+
+        self.sprites.clear();
+        self.textures.clear();
+        SpriteBatchData { vertices, draws }
+    }
+}
+
+// The corners in vertex order: top left, top right, bottom left, bottom right.
+const CORNERS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+
+pub fn get_sprite_vertices(sprite: &Sprite, texture_size: [f32; 2]) -> [SpriteVertex; 4] {
+    let source = sprite
+        .source
+        .unwrap_or_else(|| SpriteRect::new(0.0, 0.0, texture_size[0], texture_size[1]));
+    let (sin, cos) = sprite.rotation.sin_cos();
+
+    let get_vertex = |corner: [f32; 2]| {
+        let x = (corner[0] * source.width - sprite.origin[0]) * sprite.scale[0];
+        let y = (corner[1] * source.height - sprite.origin[1]) * sprite.scale[1];
+
+        // Flipping mirrors the texture, the quad stays where it is.
+        let u = if sprite.flip_x {
+            1.0 - corner[0]
+        } else {
+            corner[0]
+        };
+        let v = if sprite.flip_y {
+            1.0 - corner[1]
+        } else {
+            corner[1]
+        };
+
+        SpriteVertex {
+            position: [
+                sprite.position[0] + x * cos - y * sin,
+                sprite.position[1] + x * sin + y * cos,
+                sprite.depth,
+            ],
+            color: sprite.color,
+            uv: [
+                (source.x + u * source.width) / texture_size[0],
+                (source.y + v * source.height) / texture_size[1],
+            ],
+        }
+    };
+
+    [
+        get_vertex(CORNERS[0]),
+        get_vertex(CORNERS[1]),
+        get_vertex(CORNERS[2]),
+        get_vertex(CORNERS[3]),
+    ]
+}
+
+// Two clockwise triangles per sprite. The same for every batch, so this only has to be uploaded
+// once.
+pub fn get_sprite_indices(sprite_count: usize) -> Vec<u16> {
+    assert!(sprite_count <= MAX_SPRITES_PER_DRAW);
+
+    let mut indices = Vec::with_capacity(sprite_count * 6);
+    for i in 0..sprite_count as u16 {
+        let first = i * 4;
+        indices.extend_from_slice(&[first, first + 1, first + 2, first + 1, first + 3, first + 2]);
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (actual[0] - expected[0]).abs() < 1e-4 && (actual[1] - expected[1]).abs() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn get_positions(vertices: &[SpriteVertex]) -> Vec<[f32; 2]> {
+        vertices
+            .iter()
+            .map(|v| [v.position[0], v.position[1]])
+            .collect()
+    }
+
+    fn get_uvs(vertices: &[SpriteVertex]) -> Vec<[f32; 2]> {
+        vertices.iter().map(|v| v.uv).collect()
+    }
+
+    fn get_depths(data: &SpriteBatchData<&str>) -> Vec<f32> {
+        data.vertices
+            .iter()
+            .step_by(4)
+            .map(|v| v.position[2])
+            .collect()
+    }
+
+    #[test]
+    fn whole_texture() {
+        let sprite = Sprite::new(10.0, 20.0)
+            .with_color([1.0, 0.5, 0.25, 1.0])
+            .with_depth(0.5);
+        let vertices = get_sprite_vertices(&sprite, [32.0, 16.0]);
+
+        assert_eq!(
+            get_positions(&vertices),
+            vec![[10.0, 20.0], [42.0, 20.0], [10.0, 36.0], [42.0, 36.0]]
+        );
+        assert_eq!(
+            get_uvs(&vertices),
+            vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]
+        );
+        for vertex in &vertices {
+            assert_eq!(vertex.position[2], 0.5);
+            assert_eq!(vertex.color, [1.0, 0.5, 0.25, 1.0]);
+        }
+    }
+
+    #[test]
+    fn source_rect() {
+        let sprite = Sprite::new(0.0, 0.0).with_source(SpriteRect::new(8.0, 4.0, 16.0, 8.0));
+        let vertices = get_sprite_vertices(&sprite, [32.0, 16.0]);
+
+        // The quad is the size of the source rect, not of the texture.
+        assert_eq!(
+            get_positions(&vertices),
+            vec![[0.0, 0.0], [16.0, 0.0], [0.0, 8.0], [16.0, 8.0]]
+        );
+        assert_eq!(
+            get_uvs(&vertices),
+            vec![[0.25, 0.25], [0.75, 0.25], [0.25, 0.75], [0.75, 0.75]]
+        );
+    }
+
+    #[test]
+    fn origin_and_scale() {
+        let sprite = Sprite::new(100.0, 100.0)
+            .with_origin(8.0, 4.0)
+            .with_scale(2.0, 3.0);
+        let vertices = get_sprite_vertices(&sprite, [16.0, 8.0]);
+
+        // The origin ends up at the position, and scaling is around it.
+        assert_eq!(
+            get_positions(&vertices),
+            vec![[84.0, 88.0], [116.0, 88.0], [84.0, 112.0], [116.0, 112.0]]
+        );
+    }
+
+    #[test]
+    fn rotation() {
+        // A quarter turn clockwise on screen, with y going down.
+        let sprite = Sprite::new(10.0, 10.0).with_rotation(std::f32::consts::FRAC_PI_2);
+        let vertices = get_sprite_vertices(&sprite, [4.0, 2.0]);
+        let expected = [[10.0, 10.0], [10.0, 14.0], [8.0, 10.0], [8.0, 14.0]];
+        for (actual, expected) in get_positions(&vertices).into_iter().zip(expected) {
+            assert_close(actual, expected);
+        }
+
+        // Around the origin, which stays where it is.
+        let sprite = Sprite::new(10.0, 10.0)
+            .with_origin(2.0, 1.0)
+            .with_rotation(std::f32::consts::PI);
+        let vertices = get_sprite_vertices(&sprite, [4.0, 2.0]);
+        let expected = [[12.0, 11.0], [8.0, 11.0], [12.0, 9.0], [8.0, 9.0]];
+        for (actual, expected) in get_positions(&vertices).into_iter().zip(expected) {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn flips() {
+        let sprite = Sprite::new(0.0, 0.0).with_source(SpriteRect::new(0.0, 0.0, 2.0, 2.0));
+        let unflipped = get_sprite_vertices(&sprite, [4.0, 4.0]);
+
+        let flipped_x = get_sprite_vertices(&sprite.with_flip(true, false), [4.0, 4.0]);
+        assert_eq!(get_positions(&flipped_x), get_positions(&unflipped));
+        assert_eq!(
+            get_uvs(&flipped_x),
+            vec![[0.5, 0.0], [0.0, 0.0], [0.5, 0.5], [0.0, 0.5]]
+        );
+
+        let flipped_y = get_sprite_vertices(&sprite.with_flip(false, true), [4.0, 4.0]);
+        assert_eq!(
+            get_uvs(&flipped_y),
+            vec![[0.0, 0.5], [0.5, 0.5], [0.0, 0.0], [0.5, 0.0]]
+        );
+
+        let flipped_both = get_sprite_vertices(&sprite.with_flip(true, true), [4.0, 4.0]);
+        assert_eq!(
+            get_uvs(&flipped_both),
+            vec![[0.5, 0.5], [0.0, 0.5], [0.5, 0.0], [0.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn indices() {
+        assert!(get_sprite_indices(0).is_empty());
+        assert_eq!(
+            get_sprite_indices(2),
+            vec![0, 1, 2, 1, 3, 2, 4, 5, 6, 5, 7, 6]
+        );
+
+        let indices = get_sprite_indices(MAX_SPRITES_PER_DRAW);
+        assert_eq!(indices.len(), MAX_SPRITES_PER_DRAW * 6);
+        assert_eq!(
+            indices.iter().max(),
+            Some(&((MAX_SPRITES_PER_DRAW * 4 - 1) as u16))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_indices() {
+        get_sprite_indices(MAX_SPRITES_PER_DRAW + 1);
+    }
+
+    fn draw_sprites(sort_mode: SpriteSortMode) -> SpriteBatchData<&'static str> {
+        let mut batch = SpriteBatch::new();
+        batch.begin(sort_mode);
+        batch.draw(&"a", 8, 8, &Sprite::new(0.0, 0.0).with_depth(0.5));
+        batch.draw(&"a", 8, 8, &Sprite::new(1.0, 0.0).with_depth(0.2));
+        batch.draw(&"b", 4, 4, &Sprite::new(2.0, 0.0).with_depth(0.9));
+        batch.draw(&"a", 8, 8, &Sprite::new(3.0, 0.0).with_depth(0.2));
+        batch.end()
+    }
+
+    fn get_draws(data: &SpriteBatchData<&'static str>) -> Vec<(&'static str, usize, usize)> {
+        data.draws
+            .iter()
+            .map(|d| (d.texture, d.first_sprite, d.sprite_count))
+            .collect()
+    }
+
+    #[test]
+    fn deferred() {
+        let data = draw_sprites(SpriteSortMode::Deferred);
+        assert_eq!(data.vertices.len(), 16);
+        assert_eq!(get_depths(&data), vec![0.5, 0.2, 0.9, 0.2]);
+        // Only consecutive sprites with the same texture share a draw.
+        assert_eq!(
+            get_draws(&data),
+            vec![("a", 0, 2), ("b", 2, 1), ("a", 3, 1)]
+        );
+    }
+
+    #[test]
+    fn texture() {
+        let data = draw_sprites(SpriteSortMode::Texture);
+        assert_eq!(get_draws(&data), vec![("a", 0, 3), ("b", 3, 1)]);
+        // Stable, so the a sprites stay in the order they were drawn.
+        let x: Vec<f32> = data
+            .vertices
+            .iter()
+            .step_by(4)
+            .map(|v| v.position[0])
+            .collect();
+        assert_eq!(x, vec![0.0, 1.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn back_to_front() {
+        let data = draw_sprites(SpriteSortMode::BackToFront);
+        assert_eq!(get_depths(&data), vec![0.9, 0.5, 0.2, 0.2]);
+        assert_eq!(get_draws(&data), vec![("b", 0, 1), ("a", 1, 3)]);
+    }
+
+    #[test]
+    fn front_to_back() {
+        let data = draw_sprites(SpriteSortMode::FrontToBack);
+        assert_eq!(get_depths(&data), vec![0.2, 0.2, 0.5, 0.9]);
+        assert_eq!(get_draws(&data), vec![("a", 0, 3), ("b", 3, 1)]);
+        // Equal depths keep the order they were drawn in.
+        assert_eq!(data.vertices[0].position[0], 1.0);
+        assert_eq!(data.vertices[4].position[0], 3.0);
+    }
+
+    #[test]
+    fn texture_sizes() {
+        // Each texture keeps the size it was first drawn with, which the uvs are relative to.
+        let mut batch = SpriteBatch::new();
+        batch.begin(SpriteSortMode::Deferred);
+        let source = SpriteRect::new(0.0, 0.0, 2.0, 2.0);
+        batch.draw(&"a", 8, 8, &Sprite::new(0.0, 0.0).with_source(source));
+        batch.draw(&"b", 4, 4, &Sprite::new(0.0, 0.0).with_source(source));
+        batch.draw(&"a", 8, 8, &Sprite::new(0.0, 0.0).with_source(source));
+        let data = batch.end();
+
+        let right_u: Vec<f32> = data
+            .vertices
+            .iter()
+            .skip(3)
+            .step_by(4)
+            .map(|v| v.uv[0])
+            .collect();
+        assert_eq!(right_u, vec![0.25, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn batches_start_empty() {
+        let mut batch = SpriteBatch::new();
+        batch.begin(SpriteSortMode::Deferred);
+        batch.draw(&"a", 8, 8, &Sprite::new(0.0, 0.0));
+        batch.end();
+
+        batch.begin(SpriteSortMode::Deferred);
+        let data = batch.end();
+        assert!(data.vertices.is_empty());
+        assert!(data.draws.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn draw_outside_of_batch() {
+        let mut batch = SpriteBatch::new();
+        batch.draw(&"a", 8, 8, &Sprite::new(0.0, 0.0));
+    }
+}
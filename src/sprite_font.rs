@@ -0,0 +1,202 @@
+use font::{BitmapFont, FontPage, TextOptions};
+use image::ImageFormat;
+use sprite_batch::Sprite;
+use sprite_renderer::{SpriteRenderer, SpriteTexture};
+use std::path::Path;
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+use winapi::um::d3d11::{
+    ID3D11Device, ID3D11Resource, ID3D11ShaderResourceView, ID3D11Texture2D,
+    D3D11_BIND_SHADER_RESOURCE, D3D11_SUBRESOURCE_DATA, D3D11_TEXTURE2D_DESC,
+    D3D11_USAGE_IMMUTABLE,
+};
+use wio::com::ComPtr;
+
+// A BitmapFont with its pages as textures, drawn through a SpriteRenderer. Text goes wherever
+// sprites go, so positions are pixels with y going down.
+//
+// Holds device objects, so it has to be created again after the device is lost. Keep the
+// BitmapFont around to do that without reading the file again.
+pub struct SpriteFont {
+    font: BitmapFont,
+    pages: Vec<SpriteTexture>,
+}
+
+impl SpriteFont {
+    // Pages stored in the font are created here, load_page is called for those that are files.
+    // The sprite renderer blends premultiplied alpha, so that's what the pages should have.
+    pub unsafe fn new<F>(
+        device: &ID3D11Device,
+        font: BitmapFont,
+        mut load_page: F,
+    ) -> Result<SpriteFont, String>
+    where
+        F: FnMut(&Path) -> Result<SpriteTexture, String>,
+    {
+        let mut pages = Vec::with_capacity(font.get_pages().len());
+        for page in font.get_pages() {
+            let texture = match *page {
+                FontPage::File(ref path) => load_page(path)?,
+                FontPage::Image {
+                    width,
+                    height,
+                    format,
+                    pitch,
+                    ref data,
+                } => create_page_texture(device, width, height, format, pitch, data)?,
+            };
+            pages.push(texture);
+        }
+
+        Ok(SpriteFont { font, pages })
+    }
+
+    pub fn get_font(&self) -> &BitmapFont {
+        &self.font
+    }
+
+    pub fn measure(&self, text: &str, options: &TextOptions) -> [f32; 2] {
+        self.font.measure(text, options)
+    }
+
+    // Has to be called between begin and end of the sprite renderer. position is the top left of
+    // the text.
+    pub fn draw(
+        &self,
+        sprites: &mut SpriteRenderer,
+        text: &str,
+        position: [f32; 2],
+        color: [f32; 4],
+        options: &TextOptions,
+    ) {
+        for glyph in self.font.layout(text, options) {
+            if let Some(texture) = self.pages.get(glyph.page) {
+                let sprite = Sprite::new(
+                    position[0] + glyph.position[0],
+                    position[1] + glyph.position[1],
+                )
+                .with_source(glyph.source)
+                .with_color(color);
+                sprites.draw(texture, &sprite);
+            }
+        }
+    }
+}
+
+// The largest texture any feature level can have.
+const MAX_PAGE_SIZE: u32 = 16384;
+
+// CreateTexture2D reads pitch bytes for every row of pixels, or of blocks, without knowing how
+// much data there is, so the page has to add up before it gets there.
+fn check_page_data(
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    pitch: u32,
+    data: &[u8],
+) -> Result<(), String> {
+    let (image_format, _) = ImageFormat::from_dxgi_format(format)
+        .ok_or_else(|| format!("Font page has an unsupported format {}", format))?;
+    if width == 0 || height == 0 || width > MAX_PAGE_SIZE || height > MAX_PAGE_SIZE {
+        return Err(format!("Font page is {}x{}", width, height));
+    }
+
+    // Can't overflow with the size limited like above.
+    let (row_size, _) = image_format.get_surface_size(width, height).unwrap();
+    let rows = if image_format.is_compressed() {
+        height.div_ceil(4)
+    } else {
+        height
+    };
+    if pitch < row_size {
+        return Err(format!(
+            "Font page pitch {} is less than a row of {:?}, {}",
+            pitch, image_format, row_size
+        ));
+    }
+    if data.len() as u64 != pitch as u64 * rows as u64 {
+        return Err(format!(
+            "Font page has {} bytes rather than {} rows of {}",
+            data.len(),
+            rows,
+            pitch
+        ));
+    }
+    Ok(())
+}
+
+unsafe fn create_page_texture(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    pitch: u32,
+    data: &[u8],
+) -> Result<SpriteTexture, String> {
+    check_page_data(width, height, format, pitch, data)?;
+
+    let mut desc: D3D11_TEXTURE2D_DESC = std::mem::zeroed();
+    desc.Width = width;
+    desc.Height = height;
+    desc.MipLevels = 1;
+    desc.ArraySize = 1;
+    desc.Format = format;
+    desc.SampleDesc.Count = 1;
+    desc.Usage = D3D11_USAGE_IMMUTABLE;
+    desc.BindFlags = D3D11_BIND_SHADER_RESOURCE;
+
+    let initial_data = D3D11_SUBRESOURCE_DATA {
+        pSysMem: data.as_ptr() as *const _,
+        SysMemPitch: pitch,
+        SysMemSlicePitch: 0,
+    };
+
+    let mut texture_ptr: *mut ID3D11Texture2D = std::ptr::null_mut();
+    let hr = device.CreateTexture2D(&desc, &initial_data, &mut texture_ptr);
+    if ::failed(hr) {
+        return Err(format!("Failed to create font texture, HRESULT {:x}", hr));
+    }
+    let texture = ComPtr::from_raw(texture_ptr);
+
+    let mut view_ptr: *mut ID3D11ShaderResourceView = std::ptr::null_mut();
+    let hr = device.CreateShaderResourceView(
+        texture.as_raw() as *mut ID3D11Resource,
+        std::ptr::null(),
+        &mut view_ptr,
+    );
+    if ::failed(hr) {
+        return Err(format!(
+            "Failed to create font texture view, HRESULT {:x}",
+            hr
+        ));
+    }
+
+    Ok(SpriteTexture {
+        view: ComPtr::from_raw(view_ptr),
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winapi::shared::dxgiformat::{DXGI_FORMAT_BC3_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM};
+
+    #[test]
+    fn page_data_has_to_add_up() {
+        let rgba = DXGI_FORMAT_R8G8B8A8_UNORM;
+        assert!(check_page_data(4, 2, rgba, 16, &[0; 32]).is_ok());
+        // Padded rows are fine as long as every one of them is there.
+        assert!(check_page_data(4, 2, rgba, 20, &[0; 40]).is_ok());
+        assert!(check_page_data(4, 2, rgba, 12, &[0; 24]).is_err());
+        assert!(check_page_data(4, 2, rgba, 16, &[0; 16]).is_err());
+        assert!(check_page_data(0, 2, rgba, 16, &[]).is_err());
+        assert!(check_page_data(4, 2, 0, 16, &[0; 32]).is_err());
+
+        // Rows of 4x4 blocks, 16 bytes each.
+        let bc3 = DXGI_FORMAT_BC3_UNORM;
+        assert!(check_page_data(8, 6, bc3, 32, &[0; 64]).is_ok());
+        assert!(check_page_data(8, 6, bc3, 32, &[0; 192]).is_err());
+        assert!(check_page_data(8, 6, bc3, 16, &[0; 32]).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use constant_buffer::ConstantBuffer;
+use math::Mat4;
+use render_states::RenderStates;
+use shader::{ShaderDesc, ShaderHandle, ShaderLibrary, ShaderStage};
+use sprite_batch::{
+    get_sprite_indices, Sprite, SpriteBatch, SpriteSortMode, SpriteVertex, MAX_SPRITES_PER_DRAW,
+};
+use vertex_buffer::{create_input_layout, BufferUsage, IndexBuffer, VertexBuffer};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{
+    ID3D11Device, ID3D11DeviceContext, ID3D11InputLayout, ID3D11ShaderResourceView, D3D11_VIEWPORT,
+};
+use winapi::um::d3dcommon::D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST;
+use wio::com::ComPtr;
+
+impl_vertex! {
+    SpriteVertex {
+        position: [f32; 3] => "POSITION",
+        color: [f32; 4] => "COLOR",
+        uv: [f32; 2] => "TEXCOORD",
+    }
+}
+
+hlsl_struct! {
+    struct SpriteConstants {
+        // Row major, so positions are multiplied from the left.
+        transform: [[f32; 4]; 4],
+        distance_field_smoothing: f32,
+    }
+}
+
+#[derive(Clone)]
+pub struct SpriteTexture {
+    pub view: ComPtr<ID3D11ShaderResourceView>,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Sprites are batched by texture, and the same view is the same texture.
+impl PartialEq for SpriteTexture {
+    fn eq(&self, other: &SpriteTexture) -> bool {
+        self.view.as_raw() == other.view.as_raw()
+    }
+}
+
+// Draws SpriteBatch output with D3D11, in the spirit of the DirectXTK SpriteBatch. Sprite
+// coordinates are pixels in the current viewport, with y going down.
+//
+// Holds device objects, so it has to be created again after the device is lost.
+pub struct SpriteRenderer {
+    batch: SpriteBatch<SpriteTexture>,
+    transform: [[f32; 4]; 4],
+    distance_field_smoothing: Option<f32>,
+    vertex_shader: ShaderHandle,
+    pixel_shader: ShaderHandle,
+    distance_field_shader: ShaderHandle,
+    input_layout: ComPtr<ID3D11InputLayout>,
+    vertex_buffer: VertexBuffer<SpriteVertex>,
+    index_buffer: IndexBuffer,
+    constants: ConstantBuffer<SpriteConstants>,
+}
+
+impl SpriteRenderer {
+    pub unsafe fn new(
+        device: &ID3D11Device,
+        shaders: &mut ShaderLibrary,
+    ) -> Result<SpriteRenderer, String> {
+        let path = "shaders/sprite.hlsl";
+        let vertex_shader =
+            shaders.load(device, ShaderDesc::new(path, "VSMain", ShaderStage::Vertex));
+        let pixel_shader =
+            shaders.load(device, ShaderDesc::new(path, "PSMain", ShaderStage::Pixel));
+        let distance_field_shader = shaders.load(
+            device,
+            ShaderDesc::new(path, "PSDistanceField", ShaderStage::Pixel),
+        );
+
+        let bytecode = shaders
+            .get_bytecode(vertex_shader)
+            .ok_or_else(|| "The sprite vertex shader isn't available".to_string())?;
+        let input_layout = create_input_layout::<SpriteVertex>(device, bytecode)?;
+
+        let to_error = |hr: HRESULT| format!("Failed to create sprite buffers, HRESULT {:x}", hr);
+        let vertex_buffer =
+            VertexBuffer::new_dynamic(device, MAX_SPRITES_PER_DRAW * 4).map_err(to_error)?;
+        let index_buffer = IndexBuffer::new(
+            device,
+            &get_sprite_indices(MAX_SPRITES_PER_DRAW),
+            BufferUsage::Immutable,
+        )
+        .map_err(to_error)?;
+        let constants = ConstantBuffer::new(device).map_err(to_error)?;
+
+        Ok(SpriteRenderer {
+            batch: SpriteBatch::new(),
+            transform: Mat4::IDENTITY.rows,
+            distance_field_smoothing: None,
+            vertex_shader,
+            pixel_shader,
+            distance_field_shader,
+            input_layout,
+            vertex_buffer,
+            index_buffer,
+            constants,
+        })
+    }
+
+    pub fn begin(&mut self, sort_mode: SpriteSortMode) {
+        self.begin_with_transform(sort_mode, Mat4::IDENTITY.rows);
+    }
+
+    // The transform is applied to sprite positions before they're mapped to the viewport, e.g.
+    // for a 2D camera. Row major, like the rest of the renderer.
+    pub fn begin_with_transform(&mut self, sort_mode: SpriteSortMode, transform: [[f32; 4]; 4]) {
+        self.batch.begin(sort_mode);
+        self.transform = transform;
+    }
+
+    // Draws the textures as signed distance fields with the edge at alpha 0.5, like TrueTypeFont
+    // makes them, until this is called with None. smoothing is how far from the edge, in alpha,
+    // the sprites fade out; for text with a spread of s pixels drawn at a scale of k, about
+    // 0.5 / (s * k) looks sharp. Applies to the batches that end after the call.
+    pub fn set_distance_field(&mut self, smoothing: Option<f32>) {
+        self.distance_field_smoothing = smoothing;
+    }
+
+    pub fn draw(&mut self, texture: &SpriteTexture, sprite: &Sprite) {
+        self.batch
+            .draw(texture, texture.width, texture.height, sprite);
+    }
+
+    // Draws everything since begin. Leaves the pipeline state changed.
+    pub unsafe fn end(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        shaders: &ShaderLibrary,
+        states: &mut RenderStates,
+    ) -> Result<(), HRESULT> {
+        let data = self.batch.end();
+        if data.draws.is_empty() {
+            return Ok(());
+        }
+
+        // If a shader didn't compile the error has been printed already.
+        let pixel_shader = match self.distance_field_smoothing {
+            Some(_) => self.distance_field_shader,
+            None => self.pixel_shader,
+        };
+        let (vertex_shader, pixel_shader) = match (
+            shaders.get_vertex_shader(self.vertex_shader),
+            shaders.get_pixel_shader(pixel_shader),
+        ) {
+            (Some(vertex_shader), Some(pixel_shader)) => (vertex_shader, pixel_shader),
+            _ => return Ok(()),
+        };
+
+        let mut viewport_count = 1;
+        let mut viewport: D3D11_VIEWPORT = std::mem::zeroed();
+        context.RSGetViewports(&mut viewport_count, &mut viewport);
+        let constants = SpriteConstants {
+            transform: (Mat4::from(self.transform) * get_viewport_transform(&viewport).into())
+                .into(),
+            distance_field_smoothing: self.distance_field_smoothing.unwrap_or(0.0),
+        };
+        self.constants.set_data(context, &constants)?;
+
+        context.OMSetBlendState(states.premultiplied()?.as_raw(), &[1.0; 4], 0xffff_ffff);
+        context.OMSetDepthStencilState(states.depth_none()?.as_raw(), 0);
+        context.RSSetState(states.cull_none()?.as_raw());
+        context.PSSetSamplers(0, 1, &states.linear_clamp()?.as_raw());
+
+        context.IASetInputLayout(self.input_layout.as_raw());
+        context.IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        self.vertex_buffer.bind(context, 0);
+        self.index_buffer.bind(context);
+        context.VSSetShader(vertex_shader.as_raw(), std::ptr::null(), 0);
+        context.VSSetConstantBuffers(0, 1, &self.constants.get_buffer().as_raw());
+        context.PSSetConstantBuffers(0, 1, &self.constants.get_buffer().as_raw());
+        context.PSSetShader(pixel_shader.as_raw(), std::ptr::null(), 0);
+
+        // Upload as many sprites as fit in the vertex buffer, then draw each texture's share.
+        let sprite_count = data.vertices.len() / 4;
+        for start in (0..sprite_count).step_by(MAX_SPRITES_PER_DRAW) {
+            let end = std::cmp::min(start + MAX_SPRITES_PER_DRAW, sprite_count);
+            self.vertex_buffer
+                .set_data(context, &data.vertices[start * 4..end * 4])?;
+
+            for draw in data.draws.iter() {
+                let first = std::cmp::max(draw.first_sprite, start);
+                let last = std::cmp::min(draw.first_sprite + draw.sprite_count, end);
+                if first >= last {
+                    continue;
+                }
+
+                context.PSSetShaderResources(0, 1, &draw.texture.view.as_raw());
+                context.DrawIndexed(((last - first) * 6) as u32, 0, ((first - start) * 4) as i32);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Viewport pixels to clip space, with y going down.
+fn get_viewport_transform(viewport: &D3D11_VIEWPORT) -> [[f32; 4]; 4] {
+    let width = viewport.Width.max(1.0);
+    let height = viewport.Height.max(1.0);
+    [
+        [2.0 / width, 0.0, 0.0, 0.0],
+        [0.0, -2.0 / height, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0, 1.0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hlsl_layout::check_layout;
+
+    #[test]
+    fn constants_match_hlsl() {
+        assert_eq!(check_layout::<SpriteConstants>(), Ok(()));
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A game state blob that outlives the process. Written when the game is suspended and discarded
+// when it resumes, so a snapshot only survives if we never got to resume (e.g. the process was
+// killed while the machine was asleep). The next launch restores it. The contents are entirely
+// up to the game.
+pub struct StateSnapshot {
+    path: PathBuf,
+}
+
+impl StateSnapshot {
+    pub fn new<P: AsRef<Path>>(path: P) -> StateSnapshot {
+        StateSnapshot {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self, data: &[u8]) -> io::Result<()> {
+        // Write next to the real file first so a power loss halfway through doesn't leave us
+        // with a truncated snapshot.
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, data)?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    // Deletes the snapshot, if there is one.
+    pub fn discard(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    // Reads the snapshot and deletes it. Returns None if there is nothing to restore.
+    pub fn take(&self) -> io::Result<Option<Vec<u8>>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        fs::remove_file(&self.path)?;
+        Ok(Some(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_take_and_discard() {
+        let snapshot = StateSnapshot::new(std::env::temp_dir().join("state_snapshot_test.bin"));
+        snapshot.discard().unwrap();
+        assert_eq!(snapshot.take().unwrap(), None);
+
+        snapshot.save(&[1, 2, 3]).unwrap();
+        assert_eq!(snapshot.take().unwrap(), Some(vec![1, 2, 3]));
+        // Taking it deletes it, so it's only ever restored once.
+        assert_eq!(snapshot.take().unwrap(), None);
+
+        snapshot.save(&[4]).unwrap();
+        snapshot.discard().unwrap();
+        assert_eq!(snapshot.take().unwrap(), None);
+        // Nothing to discard is fine too.
+        snapshot.discard().unwrap();
+    }
+}
@@ -52,10 +52,17 @@ impl StepTimer {
         }
     }
 
-    pub fn tick<F>(&mut self, mut update_func: F)
+    pub fn tick<F>(&mut self, update_func: F)
     where
         F: FnMut(&mut StepTimer),
     {
+        let time_delta = self.read_time_delta();
+        self.tick_with_delta(time_delta, update_func);
+    }
+
+    // The time since the last call in canonical ticks, clamped the same way tick clamps it.
+    // Together with tick_with_delta this is tick split in two, so the delta can be recorded.
+    pub fn read_time_delta(&mut self) -> u64 {
         unsafe {
             let mut current_time: LARGE_INTEGER = std::mem::zeroed();
 
@@ -76,46 +83,58 @@ impl StepTimer {
             time_delta *= TICKS_PER_SECOND;
             time_delta /= *self.qpc_frequency.QuadPart() as u64;
 
-            let last_frame_count = self.frame_count;
-
-            if self.is_fixed_timestep {
-                // Fixed timestep update logic
-
-                // If the app is running very close to the target elapsed time (within 1/4 of a millisecond) just clamp
-                // the clock to exactly match the target value. This prevents tiny and irrelevant errors
-                // from accumulating over time. Without this clamping, a game that requested a 60 fps
-                // fixed update, running with vsync enabled on a 59.94 NTSC display, would eventually
-                // accumulate enough tiny errors that it would drop a frame. It is better to just round
-                // small deviations down to zero to leave things running smoothly.
-                if (time_delta - self.target_elapsed_ticks) < TICKS_PER_SECOND / 4000 {
-                    time_delta = self.target_elapsed_ticks;
-                }
-
-                self.leftover_ticks += time_delta;
-
-                while self.leftover_ticks >= self.target_elapsed_ticks {
-                    self.elapsed_ticks = self.target_elapsed_ticks;
-                    self.total_ticks += self.target_elapsed_ticks;
-                    self.leftover_ticks -= self.target_elapsed_ticks;
-                    self.frame_count += 1;
-
-                    update_func(self);
-                }
-            } else {
-                // Variable timestep update logic.
-                self.elapsed_ticks = time_delta;
-                self.total_ticks += time_delta;
-                self.leftover_ticks = 0;
+            time_delta
+        }
+    }
+
+    // Advances the clock by time_delta canonical ticks instead of asking QPC, e.g. when
+    // replaying a recording. The updates that run only depend on the delta and the timer settings.
+    pub fn tick_with_delta<F>(&mut self, time_delta: u64, mut update_func: F)
+    where
+        F: FnMut(&mut StepTimer),
+    {
+        let mut time_delta = time_delta;
+        let last_frame_count = self.frame_count;
+
+        if self.is_fixed_timestep {
+            // Fixed timestep update logic
+
+            // If the app is running very close to the target elapsed time (within 1/4 of a millisecond) just clamp
+            // the clock to exactly match the target value. This prevents tiny and irrelevant errors
+            // from accumulating over time. Without this clamping, a game that requested a 60 fps
+            // fixed update, running with vsync enabled on a 59.94 NTSC display, would eventually
+            // accumulate enough tiny errors that it would drop a frame. It is better to just round
+            // small deviations down to zero to leave things running smoothly.
+            if time_delta.abs_diff(self.target_elapsed_ticks) < TICKS_PER_SECOND / 4000 {
+                time_delta = self.target_elapsed_ticks;
+            }
+
+            self.leftover_ticks += time_delta;
+
+            while self.leftover_ticks >= self.target_elapsed_ticks {
+                self.elapsed_ticks = self.target_elapsed_ticks;
+                self.total_ticks += self.target_elapsed_ticks;
+                self.leftover_ticks -= self.target_elapsed_ticks;
                 self.frame_count += 1;
 
                 update_func(self);
             }
+        } else {
+            // Variable timestep update logic.
+            self.elapsed_ticks = time_delta;
+            self.total_ticks += time_delta;
+            self.leftover_ticks = 0;
+            self.frame_count += 1;
+
+            update_func(self);
+        }
 
-            // Track the current framerate
-            if self.frame_count != last_frame_count {
-                self.frames_this_second += 1;
-            }
+        // Track the current framerate
+        if self.frame_count != last_frame_count {
+            self.frames_this_second += 1;
+        }
 
+        unsafe {
             if self.qpc_second_counter >= *self.qpc_frequency.QuadPart() as u64 {
                 self.frames_per_second = self.frames_this_second;
                 self.frames_this_second = 0;
@@ -0,0 +1,315 @@
+use std::char::decode_utf16;
+use std::iter::once;
+use winapi::shared::minwindef::{BOOL, DWORD, LPARAM, LPVOID, LRESULT, UINT, WPARAM};
+use winapi::shared::ntdef::LONG;
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::um::imm::{
+    ImmGetContext, ImmReleaseContext, ImmSetCompositionWindow, CFS_EXCLUDE, CFS_POINT,
+    COMPOSITIONFORM, HIMC,
+};
+use winapi::um::winuser::{
+    DefWindowProcW, WM_CHAR, WM_IME_COMPOSITION, WM_IME_ENDCOMPOSITION, WM_IME_SETCONTEXT,
+    WM_IME_STARTCOMPOSITION,
+};
+
+// Not in the winapi version we're on.
+const GCS_COMPSTR: DWORD = 0x0008;
+const GCS_CURSORPOS: DWORD = 0x0080;
+const GCS_RESULTSTR: DWORD = 0x0800;
+const ISC_SHOWUICOMPOSITIONWINDOW: u32 = 0x8000_0000;
+const IACE_DEFAULT: DWORD = 0x0010;
+
+#[repr(C)]
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+struct CANDIDATEFORM {
+    dwIndex: DWORD,
+    dwStyle: DWORD,
+    ptCurrentPos: POINT,
+    rcArea: RECT,
+}
+
+#[link(name = "imm32")]
+extern "system" {
+    fn ImmGetCompositionStringW(himc: HIMC, index: DWORD, buf: LPVOID, buf_len: DWORD) -> LONG;
+    fn ImmSetCandidateWindow(himc: HIMC, candidate: *mut CANDIDATEFORM) -> BOOL;
+    fn ImmAssociateContextEx(hwnd: HWND, himc: HIMC, flags: DWORD) -> BOOL;
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TextInputEvent {
+    // A typed character, including committed IME text. Control characters like backspace
+    // (\u{8}) and enter (\r) come through as well.
+    Char(char),
+    CompositionStart,
+    // The IME composition string as it's being edited. The cursor is a byte offset into text.
+    Composition { text: String, cursor: usize },
+    CompositionEnd,
+}
+
+// Text entry for chat boxes and the like. Off by default so the IME doesn't eat keys meant for
+// the game; turn it on while a text field has focus. The IME draws its candidate list, the game
+// draws the composition string itself.
+pub struct TextInput {
+    window: HWND,
+    is_enabled: bool,
+    // The first half of a character outside the BMP, waiting for the second WM_CHAR.
+    high_surrogate: Option<u16>,
+    // Where the text field is in client coordinates, so the candidate list doesn't cover it.
+    input_rect: RECT,
+    events: Vec<TextInputEvent>,
+}
+
+impl TextInput {
+    pub fn new() -> TextInput {
+        TextInput {
+            window: std::ptr::null_mut(),
+            is_enabled: false,
+            high_surrogate: None,
+            input_rect: RECT {
+                left: 0,
+                top: 0,
+                right: 0,
+                bottom: 0,
+            },
+            events: Vec::new(),
+        }
+    }
+
+    pub unsafe fn set_window(&mut self, window: HWND) {
+        self.window = window;
+        self.apply_enabled();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.is_enabled {
+            return;
+        }
+
+        self.is_enabled = enabled;
+        self.high_surrogate = None;
+        unsafe {
+            self.apply_enabled();
+        }
+    }
+
+    unsafe fn apply_enabled(&mut self) {
+        if self.window.is_null() {
+            return;
+        }
+
+        // Detaching the input context is what actually turns the IME off for the window.
+        let flags = if self.is_enabled { IACE_DEFAULT } else { 0 };
+        ImmAssociateContextEx(self.window, std::ptr::null_mut(), flags);
+        if self.is_enabled {
+            self.update_ime_windows();
+        }
+    }
+
+    // The text field's rect in client coordinates. The candidate list opens below it.
+    pub fn set_input_rect(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.input_rect = RECT {
+            left: x,
+            top: y,
+            right: x + width,
+            bottom: y + height,
+        };
+        unsafe {
+            self.update_ime_windows();
+        }
+    }
+
+    unsafe fn update_ime_windows(&mut self) {
+        if self.window.is_null() || !self.is_enabled {
+            return;
+        }
+
+        let context = ImmGetContext(self.window);
+        if context.is_null() {
+            return;
+        }
+
+        let position = POINT {
+            x: self.input_rect.left,
+            y: self.input_rect.bottom,
+        };
+        let mut composition = COMPOSITIONFORM {
+            dwStyle: CFS_POINT,
+            ptCurrentPos: position,
+            rcArea: self.input_rect,
+        };
+        ImmSetCompositionWindow(context, &mut composition);
+
+        let mut candidate = CANDIDATEFORM {
+            dwIndex: 0,
+            dwStyle: CFS_EXCLUDE,
+            ptCurrentPos: position,
+            rcArea: self.input_rect,
+        };
+        ImmSetCandidateWindow(context, &mut candidate);
+
+        ImmReleaseContext(self.window, context);
+    }
+
+    // Returns the result for messages that must not reach DefWindowProc.
+    pub unsafe fn process_message(
+        &mut self,
+        message: UINT,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> Option<LRESULT> {
+        match message {
+            WM_CHAR if self.is_enabled => {
+                self.process_char(w_param as u16);
+                Some(0)
+            }
+            // We draw the composition string ourselves, so hide the IME's own.
+            WM_IME_SETCONTEXT if !self.window.is_null() => Some(DefWindowProcW(
+                self.window,
+                message,
+                w_param,
+                l_param & !(ISC_SHOWUICOMPOSITIONWINDOW as LPARAM),
+            )),
+            WM_IME_STARTCOMPOSITION if self.is_enabled => {
+                self.update_ime_windows();
+                self.events.push(TextInputEvent::CompositionStart);
+                Some(0)
+            }
+            WM_IME_COMPOSITION if self.is_enabled => {
+                self.process_composition(l_param as DWORD);
+                Some(0)
+            }
+            WM_IME_ENDCOMPOSITION if self.is_enabled => {
+                self.events.push(TextInputEvent::CompositionEnd);
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+
+    fn process_char(&mut self, unit: u16) {
+        let pending = self.high_surrogate.take();
+        if (0xd800..=0xdbff).contains(&unit) {
+            if pending.is_some() {
+                self.events
+                    .push(TextInputEvent::Char(std::char::REPLACEMENT_CHARACTER));
+            }
+            self.high_surrogate = Some(unit);
+            return;
+        }
+
+        // A surrogate without its other half decodes to U+FFFD.
+        let units = pending.into_iter().chain(once(unit));
+        for c in decode_utf16(units) {
+            let c = c.unwrap_or(std::char::REPLACEMENT_CHARACTER);
+            self.events.push(TextInputEvent::Char(c));
+        }
+    }
+
+    unsafe fn process_composition(&mut self, flags: DWORD) {
+        let context = ImmGetContext(self.window);
+        if context.is_null() {
+            return;
+        }
+
+        // Committed text is reported as characters here instead of letting DefWindowProc turn
+        // it into WM_IME_CHAR and then WM_CHAR messages.
+        if flags & GCS_RESULTSTR != 0 {
+            let result = get_composition_string(context, GCS_RESULTSTR);
+            self.events.extend(result.chars().map(TextInputEvent::Char));
+        }
+
+        if flags & GCS_COMPSTR != 0 {
+            let units = get_composition_units(context, GCS_COMPSTR);
+            let cursor_units =
+                ImmGetCompositionStringW(context, GCS_CURSORPOS, std::ptr::null_mut(), 0).max(0);
+
+            // The IME counts UTF-16 units, so convert the cursor along with the text.
+            let cursor_units = std::cmp::min(cursor_units as usize, units.len());
+            let text = String::from_utf16_lossy(&units);
+            let cursor = String::from_utf16_lossy(&units[..cursor_units]).len();
+            self.events
+                .push(TextInputEvent::Composition { text, cursor });
+        }
+
+        ImmReleaseContext(self.window, context);
+    }
+
+    // Events since the last call, in the order they happened.
+    pub fn take_events(&mut self) -> Vec<TextInputEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+unsafe fn get_composition_units(context: HIMC, index: DWORD) -> Vec<u16> {
+    // Sizes are in bytes, even for the W version.
+    let size = ImmGetCompositionStringW(context, index, std::ptr::null_mut(), 0);
+    if size <= 0 {
+        return Vec::new();
+    }
+
+    let mut units = vec![0u16; size as usize / 2];
+    ImmGetCompositionStringW(context, index, units.as_mut_ptr() as LPVOID, size as DWORD);
+    units
+}
+
+unsafe fn get_composition_string(context: HIMC, index: DWORD) -> String {
+    String::from_utf16_lossy(&get_composition_units(context, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_units(units: &[u16]) -> Vec<TextInputEvent> {
+        let mut input = TextInput::new();
+        input.set_enabled(true);
+        for &unit in units {
+            let result = unsafe { input.process_message(WM_CHAR, unit as WPARAM, 0) };
+            assert_eq!(result, Some(0));
+        }
+        input.take_events()
+    }
+
+    fn chars(s: &str) -> Vec<TextInputEvent> {
+        s.chars().map(TextInputEvent::Char).collect()
+    }
+
+    #[test]
+    fn surrogate_pair() {
+        assert_eq!(type_units(&[0xd83d, 0xde00]), chars("\u{1f600}"));
+        assert_eq!(
+            type_units(&[0x61, 0xd83d, 0xde00, 0x62]),
+            chars("a\u{1f600}b")
+        );
+    }
+
+    #[test]
+    fn lone_high_surrogate() {
+        assert_eq!(type_units(&[0xd83d, 0x61]), chars("\u{fffd}a"));
+        // A second high surrogate replaces the first, which still gets reported.
+        assert_eq!(
+            type_units(&[0xd83d, 0xd83d, 0xde00]),
+            chars("\u{fffd}\u{1f600}")
+        );
+        // One that's still waiting at the end reports nothing yet.
+        assert_eq!(type_units(&[0x61, 0xd83d]), chars("a"));
+    }
+
+    #[test]
+    fn lone_low_surrogate() {
+        assert_eq!(type_units(&[0xde00]), chars("\u{fffd}"));
+        assert_eq!(type_units(&[0x61, 0xde00, 0x62]), chars("a\u{fffd}b"));
+    }
+
+    #[test]
+    fn disabled_ignores_chars() {
+        let mut input = TextInput::new();
+        let result = unsafe { input.process_message(WM_CHAR, 0x61, 0) };
+        assert_eq!(result, None);
+        assert!(input.take_events().is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use image::{Image, ImageFormat, Subresource};
+use sprite_renderer::SpriteTexture;
+use std::path::Path;
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+use winapi::um::d3d11::{
+    D3D11CalcSubresource, ID3D11Device, ID3D11DeviceContext, ID3D11Resource,
+    ID3D11ShaderResourceView, ID3D11Texture2D, D3D11_BIND_RENDER_TARGET,
+    D3D11_BIND_SHADER_RESOURCE, D3D11_FORMAT_SUPPORT_MIP_AUTOGEN, D3D11_FORMAT_SUPPORT_TEXTURE2D,
+    D3D11_FORMAT_SUPPORT_TEXTURECUBE, D3D11_RESOURCE_MISC_GENERATE_MIPS,
+    D3D11_RESOURCE_MISC_TEXTURECUBE, D3D11_SHADER_RESOURCE_VIEW_DESC, D3D11_SUBRESOURCE_DATA,
+    D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, D3D11_USAGE_IMMUTABLE,
+};
+use winapi::um::d3dcommon::{
+    D3D11_SRV_DIMENSION_TEXTURE2D, D3D11_SRV_DIMENSION_TEXTURE2DARRAY,
+    D3D11_SRV_DIMENSION_TEXTURECUBE, D3D11_SRV_DIMENSION_TEXTURECUBEARRAY, D3D_FEATURE_LEVEL,
+    D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_10_1, D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_9_3,
+};
+use wio::com::ComPtr;
+
+// Whether the texture reads as sRGB, i.e. gets converted to linear when sampled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SrgbMode {
+    // As the file says.
+    #[default]
+    FromImage,
+    Force,
+    Ignore,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TextureOptions {
+    // Fills in the mip chain of images that only have the top level, where the device can.
+    pub generate_mips: bool,
+    pub srgb: SrgbMode,
+}
+
+impl TextureOptions {
+    pub fn new() -> TextureOptions {
+        TextureOptions::default()
+    }
+
+    pub fn with_generate_mips(mut self, generate_mips: bool) -> TextureOptions {
+        self.generate_mips = generate_mips;
+        self
+    }
+
+    pub fn with_srgb(mut self, srgb: SrgbMode) -> TextureOptions {
+        self.srgb = srgb;
+        self
+    }
+}
+
+pub struct Texture {
+    pub texture: ComPtr<ID3D11Texture2D>,
+    pub view: ComPtr<ID3D11ShaderResourceView>,
+    pub width: u32,
+    pub height: u32,
+    // Whether it reads as sRGB. Can be false when sRGB was asked for, where the device can't
+    // sample the sRGB version of the format and the plain one was used instead.
+    pub srgb: bool,
+}
+
+impl Texture {
+    pub fn get_sprite_texture(&self) -> SpriteTexture {
+        SpriteTexture {
+            view: self.view.clone(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+pub unsafe fn load_texture<P: AsRef<Path>>(
+    device: &ID3D11Device,
+    context: &ID3D11DeviceContext,
+    path: P,
+    options: &TextureOptions,
+) -> Result<Texture, String> {
+    let path = path.as_ref();
+    let image = Image::load(path).map_err(|e| format!("Failed to load {:?}: {}", path, e))?;
+    create_texture(device, context, &image, options)
+        .map_err(|e| format!("Failed to create a texture from {:?}: {}", path, e))
+}
+
+// Makes a texture with a view of the whole thing, checking the image against what the device's
+// feature level can do. The context is only used to generate mips.
+pub unsafe fn create_texture(
+    device: &ID3D11Device,
+    context: &ID3D11DeviceContext,
+    image: &Image,
+    options: &TextureOptions,
+) -> Result<Texture, String> {
+    let feature_level = device.GetFeatureLevel();
+    check_feature_level(image, feature_level)?;
+
+    let mut srgb = match options.srgb {
+        SrgbMode::FromImage => image.srgb,
+        SrgbMode::Force => true,
+        SrgbMode::Ignore => false,
+    };
+    let required_support = if image.is_cube_map {
+        D3D11_FORMAT_SUPPORT_TEXTURECUBE
+    } else {
+        D3D11_FORMAT_SUPPORT_TEXTURE2D
+    };
+
+    // Fall back to plain formats where the sRGB ones can't be sampled, see Texture::srgb.
+    let mut format = image.format.get_dxgi_format(srgb);
+    let mut support = get_format_support(device, format);
+    if support & required_support == 0 && srgb && image.format.has_srgb() {
+        srgb = false;
+        format = image.format.get_dxgi_format(false);
+        support = get_format_support(device, format);
+    }
+    if support & required_support == 0 {
+        return Err(format!("{:?} textures aren't supported", image.format));
+    }
+
+    // Feature level 9 can't do mips on textures that aren't a power of two.
+    let is_power_of_two = image.width.is_power_of_two() && image.height.is_power_of_two();
+    let can_generate_mips = support & D3D11_FORMAT_SUPPORT_MIP_AUTOGEN != 0
+        && (feature_level >= D3D_FEATURE_LEVEL_10_0 || is_power_of_two);
+    if options.generate_mips && image.mip_levels == 1 && can_generate_mips {
+        create_with_generated_mips(device, context, image, format, srgb)
+    } else {
+        create_immutable(device, image, format, srgb)
+    }
+}
+
+fn check_feature_level(image: &Image, feature_level: D3D_FEATURE_LEVEL) -> Result<(), String> {
+    let max_size = get_max_texture_size(feature_level, image.is_cube_map);
+    if image.width > max_size || image.height > max_size {
+        return Err(format!(
+            "{}x{} is larger than the {} the device allows",
+            image.width, image.height, max_size
+        ));
+    }
+
+    let cube_count = image.array_size / 6;
+    if image.is_cube_map && cube_count > 1 && feature_level < D3D_FEATURE_LEVEL_10_1 {
+        return Err("cube map arrays need feature level 10.1".to_string());
+    }
+    if !image.is_cube_map && image.array_size > 1 && feature_level < D3D_FEATURE_LEVEL_10_0 {
+        return Err("texture arrays need feature level 10.0".to_string());
+    }
+
+    let is_power_of_two = image.width.is_power_of_two() && image.height.is_power_of_two();
+    if image.mip_levels > 1 && !is_power_of_two && feature_level < D3D_FEATURE_LEVEL_10_0 {
+        return Err("mips of textures that aren't a power of two need feature level 10.0".into());
+    }
+
+    match image.format {
+        ImageFormat::Bc6hUf16 | ImageFormat::Bc6hSf16 | ImageFormat::Bc7
+            if feature_level < D3D_FEATURE_LEVEL_11_0 =>
+        {
+            Err(format!("{:?} needs feature level 11.0", image.format))
+        }
+        _ => Ok(()),
+    }
+}
+
+// From the feature level tables in the D3D11 documentation.
+fn get_max_texture_size(feature_level: D3D_FEATURE_LEVEL, is_cube_map: bool) -> u32 {
+    if feature_level >= D3D_FEATURE_LEVEL_11_0 {
+        16384
+    } else if feature_level >= D3D_FEATURE_LEVEL_10_0 {
+        8192
+    } else if feature_level >= D3D_FEATURE_LEVEL_9_3 {
+        4096
+    } else if is_cube_map {
+        512
+    } else {
+        2048
+    }
+}
+
+unsafe fn get_format_support(device: &ID3D11Device, format: DXGI_FORMAT) -> u32 {
+    let mut support = 0;
+    if ::failed(device.CheckFormatSupport(format, &mut support)) {
+        return 0;
+    }
+    support
+}
+
+// D3D reads each subresource through a pointer without knowing how much data there is, so all of
+// it has to be there.
+fn get_checked_subresources(image: &Image) -> Result<Vec<Subresource>, String> {
+    let size = image.get_data_size().map_err(|e| e.to_string())?;
+    if image.data.len() < size {
+        return Err(format!(
+            "the image has {} bytes of data rather than {}",
+            image.data.len(),
+            size
+        ));
+    }
+    image.get_subresources().map_err(|e| e.to_string())
+}
+
+unsafe fn create_immutable(
+    device: &ID3D11Device,
+    image: &Image,
+    format: DXGI_FORMAT,
+    srgb: bool,
+) -> Result<Texture, String> {
+    let subresources = get_checked_subresources(image)?;
+
+    let mut desc = get_texture_desc(image, format);
+    desc.MipLevels = image.mip_levels;
+    desc.Usage = D3D11_USAGE_IMMUTABLE;
+
+    let initial_data: Vec<D3D11_SUBRESOURCE_DATA> = subresources
+        .iter()
+        .map(|s| D3D11_SUBRESOURCE_DATA {
+            pSysMem: image.data[s.offset..].as_ptr() as *const _,
+            SysMemPitch: s.row_pitch,
+            SysMemSlicePitch: s.size as u32,
+        })
+        .collect();
+
+    let mut texture_ptr: *mut ID3D11Texture2D = std::ptr::null_mut();
+    let hr = device.CreateTexture2D(&desc, initial_data.as_ptr(), &mut texture_ptr);
+    if ::failed(hr) {
+        return Err(format!("Failed to create texture, HRESULT {:x}", hr));
+    }
+    let texture = ComPtr::from_raw(texture_ptr);
+
+    let view = create_view(device, &texture, image, format, image.mip_levels)?;
+    Ok(Texture {
+        texture,
+        view,
+        width: image.width,
+        height: image.height,
+        srgb,
+    })
+}
+
+// The top level of every slice gets uploaded and the GPU fills in the rest.
+unsafe fn create_with_generated_mips(
+    device: &ID3D11Device,
+    context: &ID3D11DeviceContext,
+    image: &Image,
+    format: DXGI_FORMAT,
+    srgb: bool,
+) -> Result<Texture, String> {
+    let subresources = get_checked_subresources(image)?;
+
+    let mut desc = get_texture_desc(image, format);
+    desc.MipLevels = 0;
+    desc.Usage = D3D11_USAGE_DEFAULT;
+    desc.BindFlags |= D3D11_BIND_RENDER_TARGET;
+    desc.MiscFlags |= D3D11_RESOURCE_MISC_GENERATE_MIPS;
+
+    let mut texture_ptr: *mut ID3D11Texture2D = std::ptr::null_mut();
+    let hr = device.CreateTexture2D(&desc, std::ptr::null(), &mut texture_ptr);
+    if ::failed(hr) {
+        return Err(format!("Failed to create texture, HRESULT {:x}", hr));
+    }
+    let texture = ComPtr::from_raw(texture_ptr);
+
+    // A mip count of 0 means the whole chain, which is how many there are now.
+    texture.GetDesc(&mut desc);
+    let mip_levels = desc.MipLevels;
+
+    // The image has a single mip, so its subresources are the slices.
+    for (slice, top) in subresources.iter().enumerate() {
+        context.UpdateSubresource(
+            texture.as_raw() as *mut ID3D11Resource,
+            D3D11CalcSubresource(0, slice as u32, mip_levels),
+            std::ptr::null(),
+            image.data[top.offset..].as_ptr() as *const _,
+            top.row_pitch,
+            top.size as u32,
+        );
+    }
+
+    let view = create_view(device, &texture, image, format, mip_levels)?;
+    context.GenerateMips(view.as_raw());
+    Ok(Texture {
+        texture,
+        view,
+        width: image.width,
+        height: image.height,
+        srgb,
+    })
+}
+
+fn get_texture_desc(image: &Image, format: DXGI_FORMAT) -> D3D11_TEXTURE2D_DESC {
+    let mut desc: D3D11_TEXTURE2D_DESC = unsafe { std::mem::zeroed() };
+    desc.Width = image.width;
+    desc.Height = image.height;
+    desc.ArraySize = image.array_size;
+    desc.Format = format;
+    desc.SampleDesc.Count = 1;
+    desc.BindFlags = D3D11_BIND_SHADER_RESOURCE;
+    if image.is_cube_map {
+        desc.MiscFlags = D3D11_RESOURCE_MISC_TEXTURECUBE;
+    }
+    desc
+}
+
+unsafe fn create_view(
+    device: &ID3D11Device,
+    texture: &ComPtr<ID3D11Texture2D>,
+    image: &Image,
+    format: DXGI_FORMAT,
+    mip_levels: u32,
+) -> Result<ComPtr<ID3D11ShaderResourceView>, String> {
+    let mut desc: D3D11_SHADER_RESOURCE_VIEW_DESC = std::mem::zeroed();
+    desc.Format = format;
+    if image.is_cube_map && image.array_size > 6 {
+        desc.ViewDimension = D3D11_SRV_DIMENSION_TEXTURECUBEARRAY;
+        let cube_array = desc.u.TextureCubeArray_mut();
+        cube_array.MipLevels = mip_levels;
+        cube_array.NumCubes = image.array_size / 6;
+    } else if image.is_cube_map {
+        desc.ViewDimension = D3D11_SRV_DIMENSION_TEXTURECUBE;
+        desc.u.TextureCube_mut().MipLevels = mip_levels;
+    } else if image.array_size > 1 {
+        desc.ViewDimension = D3D11_SRV_DIMENSION_TEXTURE2DARRAY;
+        let array = desc.u.Texture2DArray_mut();
+        array.MipLevels = mip_levels;
+        array.ArraySize = image.array_size;
+    } else {
+        desc.ViewDimension = D3D11_SRV_DIMENSION_TEXTURE2D;
+        desc.u.Texture2D_mut().MipLevels = mip_levels;
+    }
+
+    let mut view_ptr: *mut ID3D11ShaderResourceView = std::ptr::null_mut();
+    let hr = device.CreateShaderResourceView(
+        texture.as_raw() as *mut ID3D11Resource,
+        &desc,
+        &mut view_ptr,
+    );
+    if ::failed(hr) {
+        return Err(format!("Failed to create texture view, HRESULT {:x}", hr));
+    }
+    Ok(ComPtr::from_raw(view_ptr))
+}
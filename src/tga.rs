@@ -0,0 +1,224 @@
+// Truevision TGA files: true color, grayscale and color mapped, raw or run length encoded.
+// Always decodes to 8 bit RGBA.
+
+use image::{invalid_data, Image};
+use std::io;
+
+const HEADER_SIZE: usize = 18;
+
+// Image descriptor bits.
+const RIGHT_TO_LEFT: u8 = 0x10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+pub fn decode(data: &[u8]) -> io::Result<Image> {
+    if data.len() < HEADER_SIZE {
+        return Err(invalid_data("TGA file is truncated"));
+    }
+
+    let id_length = data[0] as usize;
+    let has_color_map = data[1] == 1;
+    let image_type = data[2];
+    let color_map_first = u16::from_le_bytes([data[3], data[4]]) as usize;
+    let color_map_length = u16::from_le_bytes([data[5], data[6]]) as usize;
+    let color_map_depth = data[7] as u32;
+    let width = u16::from_le_bytes([data[12], data[13]]) as u32;
+    let height = u16::from_le_bytes([data[14], data[15]]) as u32;
+    let pixel_depth = data[16] as u32;
+    let descriptor = data[17];
+
+    let is_rle = image_type & 8 != 0;
+    let kind = image_type & !8;
+    if ![1, 2, 3].contains(&kind) || data[1] > 1 {
+        return Err(invalid_data("not a supported TGA file"));
+    }
+    if width == 0 || height == 0 {
+        return Err(invalid_data("TGA image is empty"));
+    }
+    let valid_depth = match kind {
+        1 | 3 => [8, 16].contains(&pixel_depth),
+        _ => [15, 16, 24, 32].contains(&pixel_depth),
+    };
+    if !valid_depth || (has_color_map && ![15, 16, 24, 32].contains(&color_map_depth)) {
+        return Err(invalid_data("unsupported TGA pixel depth"));
+    }
+
+    let mut offset = HEADER_SIZE + id_length;
+    let mut color_map = Vec::new();
+    if has_color_map {
+        let entry_size = color_map_depth.div_ceil(8) as usize;
+        let entries = data
+            .get(offset..offset + entry_size * color_map_length)
+            .ok_or_else(|| invalid_data("TGA file is truncated"))?;
+        color_map = entries
+            .chunks(entry_size)
+            .map(|e| get_color(e, color_map_depth))
+            .collect::<io::Result<Vec<_>>>()?;
+        offset += entries.len();
+    }
+
+    let pixel_size = pixel_depth.div_ceil(8) as usize;
+    let pixel_count = (width * height) as usize;
+    let rest = data
+        .get(offset..)
+        .ok_or_else(|| invalid_data("TGA file is truncated"))?;
+    let raw = if is_rle {
+        // A packet holds at most 128 pixels, so don't allocate for more than the data can hold.
+        if pixel_count > rest.len().div_ceil(1 + pixel_size) * 128 {
+            return Err(invalid_data("TGA file is truncated"));
+        }
+        read_rle(rest, pixel_size, pixel_count)?
+    } else {
+        rest.get(..pixel_size * pixel_count)
+            .ok_or_else(|| invalid_data("TGA file is truncated"))?
+            .to_vec()
+    };
+
+    let mut pixels = vec![0u8; pixel_count * 4];
+    for (i, value) in raw.chunks(pixel_size).enumerate() {
+        let rgba = match kind {
+            1 => {
+                let index = match pixel_size {
+                    1 => value[0] as usize,
+                    _ => u16::from_le_bytes([value[0], value[1]]) as usize,
+                };
+                *index
+                    .checked_sub(color_map_first)
+                    .and_then(|i| color_map.get(i))
+                    .ok_or_else(|| invalid_data("TGA color index is out of range"))?
+            }
+            3 => match pixel_size {
+                1 => [value[0], value[0], value[0], 255],
+                _ => [value[0], value[0], value[0], value[1]],
+            },
+            _ => get_color(value, pixel_depth)?,
+        };
+
+        // Pixels go bottom to top and left to right unless the descriptor says otherwise.
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        let x = if descriptor & RIGHT_TO_LEFT != 0 {
+            width - 1 - x
+        } else {
+            x
+        };
+        let y = if descriptor & TOP_TO_BOTTOM != 0 {
+            y
+        } else {
+            height - 1 - y
+        };
+        let index = ((y * width + x) * 4) as usize;
+        pixels[index..index + 4].copy_from_slice(&rgba);
+    }
+
+    Ok(Image::new_rgba8(width, height, false, pixels))
+}
+
+// A BGR(A) color of the given bits per pixel as RGBA.
+fn get_color(value: &[u8], depth: u32) -> io::Result<[u8; 4]> {
+    match depth {
+        15 | 16 => {
+            let packed = u16::from_le_bytes([value[0], value[1]]);
+            let expand = |v: u16| ((v & 0x1f) as u32 * 255 / 31) as u8;
+            // The top bit is alpha in 16 bit files, but plenty of writers leave it zero, so it's
+            // ignored like most readers do.
+            Ok([
+                expand(packed >> 10),
+                expand(packed >> 5),
+                expand(packed),
+                255,
+            ])
+        }
+        24 => Ok([value[2], value[1], value[0], 255]),
+        32 => Ok([value[2], value[1], value[0], value[3]]),
+        _ => Err(invalid_data("unsupported TGA pixel depth")),
+    }
+}
+
+// Packets of a header byte followed by one pixel repeated or by several raw pixels.
+fn read_rle(data: &[u8], pixel_size: usize, pixel_count: usize) -> io::Result<Vec<u8>> {
+    let truncated = || invalid_data("TGA file is truncated");
+    let mut output = Vec::with_capacity(pixel_size * pixel_count);
+    let mut offset = 0;
+    while output.len() < pixel_size * pixel_count {
+        let header = *data.get(offset).ok_or_else(truncated)?;
+        offset += 1;
+        let count = (header & 0x7f) as usize + 1;
+
+        if header & 0x80 != 0 {
+            let pixel = data
+                .get(offset..offset + pixel_size)
+                .ok_or_else(truncated)?;
+            offset += pixel_size;
+            for _ in 0..count {
+                output.extend_from_slice(pixel);
+            }
+        } else {
+            let pixels = data
+                .get(offset..offset + pixel_size * count)
+                .ok_or_else(truncated)?;
+            offset += pixels.len();
+            output.extend_from_slice(pixels);
+        }
+    }
+
+    // A packet can run past the end of the image in broken files.
+    output.truncate(pixel_size * pixel_count);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_header(image_type: u8, width: u16, height: u16, pixel_depth: u8) -> Vec<u8> {
+        let mut header = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&[pixel_depth, 0]);
+        header
+    }
+
+    #[test]
+    fn decodes() {
+        // Bottom to top, so the first row in the file is the last one in the image.
+        let mut tga = make_header(2, 1, 2, 24);
+        tga.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        let image = decode(&tga).unwrap();
+        assert_eq!(image.data, vec![6, 5, 4, 255, 3, 2, 1, 255]);
+
+        // One run of two pixels.
+        let mut rle = make_header(10, 2, 1, 24);
+        rle.extend_from_slice(&[0x81, 1, 2, 3]);
+        let image = decode(&rle).unwrap();
+        assert_eq!(image.data, vec![3, 2, 1, 255, 3, 2, 1, 255]);
+    }
+
+    #[test]
+    fn id_past_the_end() {
+        let mut tga = make_header(10, 1, 1, 24);
+        tga[0] = 255;
+        assert!(decode(&tga).is_err());
+        tga[2] = 2;
+        assert!(decode(&tga).is_err());
+    }
+
+    #[test]
+    fn bad_depths() {
+        let mut tga = make_header(2, 1, 1, 0);
+        tga.extend_from_slice(&[0; 4]);
+        assert!(decode(&tga).is_err());
+
+        // Color mapped with an empty color map entry.
+        let mut tga = make_header(1, 1, 1, 8);
+        tga[1] = 1;
+        tga[5] = 1;
+        tga.extend_from_slice(&[0; 4]);
+        assert!(decode(&tga).is_err());
+    }
+
+    #[test]
+    fn more_pixels_than_data() {
+        let mut tga = make_header(10, 0xffff, 0xffff, 32);
+        tga.extend_from_slice(&[0xff, 1, 2, 3, 4]);
+        assert!(decode(&tga).is_err());
+    }
+}
@@ -0,0 +1,612 @@
+use font::{BitmapFont, Glyph};
+use glyph_atlas::{GlyphAtlas, GlyphBitmap};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::windef::{HDC, HFONT, HGDIOBJ};
+use winapi::um::wingdi::{
+    AddFontMemResourceEx, CreateCompatibleDC, CreateFontW, DeleteDC, DeleteObject,
+    GetGlyphIndicesW, GetGlyphOutlineW, GetKerningPairsW, GetTextFaceW, GetTextMetricsW,
+    RemoveFontMemResourceEx, SelectObject, ANTIALIASED_QUALITY, CLIP_DEFAULT_PRECIS,
+    DEFAULT_CHARSET, DEFAULT_PITCH, FIXED, FW_BOLD, FW_NORMAL, GDI_ERROR,
+    GGI_MARK_NONEXISTING_GLYPHS, GGO_GRAY8_BITMAP, GLYPHMETRICS, KERNINGPAIR, LF_FACESIZE, MAT2,
+    OUT_OUTLINE_PRECIS, TEXTMETRICW,
+};
+use winapi::um::winnt::HANDLE;
+
+const DEFAULT_PAGE_SIZE: u32 = 512;
+
+// GetGlyphIndicesW marks characters the font doesn't have with this.
+const MISSING_GLYPH: u16 = 0xffff;
+
+// GGO_GRAY8_BITMAP coverage goes from 0 to this.
+const GRAY8_LEVELS: u32 = 64;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrueTypeOptions {
+    // The size of the em square at a DPI scale of 1.
+    pub pixel_size: f32,
+    // Glyphs are rasterized at pixel_size times this, and all metrics are in those pixels.
+    pub dpi_scale: f32,
+    // Makes the pages distance fields reaching this many pixels out from the glyphs, for
+    // SpriteRenderer::set_distance_field. Plain coverage if None.
+    pub distance_field_spread: Option<u32>,
+    pub page_size: u32,
+}
+
+impl TrueTypeOptions {
+    pub fn new(pixel_size: f32) -> TrueTypeOptions {
+        TrueTypeOptions {
+            pixel_size,
+            dpi_scale: 1.0,
+            distance_field_spread: None,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    pub fn with_dpi_scale(mut self, dpi_scale: f32) -> TrueTypeOptions {
+        self.dpi_scale = dpi_scale;
+        self
+    }
+
+    pub fn with_distance_field(mut self, spread: u32) -> TrueTypeOptions {
+        self.distance_field_spread = Some(spread);
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: u32) -> TrueTypeOptions {
+        self.page_size = page_size;
+        self
+    }
+}
+
+// A TTF or OTF font rasterized with GDI into a glyph atlas, one character at a time as text
+// needs it. The result is a BitmapFont, so it's laid out and drawn like any other; to cook it,
+// prepare every character it'll need and write it with BitmapFont::to_spritefont_bytes.
+//
+// Only has GDI objects, so it survives the device being lost, unlike SpriteFonts made from it.
+pub struct TrueTypeFont {
+    dc: HDC,
+    gdi_font: HFONT,
+    previous_font: HGDIOBJ,
+    resource: HANDLE,
+    ascent: f32,
+    font: BitmapFont,
+    atlas: GlyphAtlas,
+    // Characters that have been looked at already, whether or not the font has them.
+    known: HashSet<char>,
+    // Characters the font has but whose glyphs don't fit on a page, in the order they came up.
+    too_large: Vec<char>,
+}
+
+impl TrueTypeFont {
+    pub fn load<P: AsRef<Path>>(path: P, options: &TrueTypeOptions) -> io::Result<TrueTypeFont> {
+        TrueTypeFont::from_bytes(&fs::read(path)?, options)
+    }
+
+    pub fn from_bytes(data: &[u8], options: &TrueTypeOptions) -> io::Result<TrueTypeFont> {
+        // GDI only knows fonts by name, even the ones it got from memory.
+        let family = get_family_name(data)
+            .ok_or_else(|| invalid_data("font has no family name GDI could find it by"))?;
+        let mut face_name: Vec<u16> = family.encode_utf16().take(LF_FACESIZE - 1).collect();
+        face_name.push(0);
+        // And then picks the face of the family by these, so they have to be this font's.
+        let style = get_font_style(data);
+
+        unsafe {
+            let mut font_count: DWORD = 0;
+            let resource = AddFontMemResourceEx(
+                data.as_ptr() as *mut _,
+                data.len() as DWORD,
+                std::ptr::null_mut(),
+                &mut font_count,
+            );
+            if resource.is_null() {
+                return Err(invalid_data("GDI couldn't load the font"));
+            }
+
+            // From here on drop cleans up whatever was created.
+            let mut font = TrueTypeFont {
+                dc: CreateCompatibleDC(std::ptr::null_mut()),
+                gdi_font: std::ptr::null_mut(),
+                previous_font: std::ptr::null_mut(),
+                resource,
+                ascent: 0.0,
+                font: BitmapFont::new(0.0),
+                atlas: GlyphAtlas::new(options.page_size, options.distance_field_spread),
+                known: HashSet::new(),
+                too_large: Vec::new(),
+            };
+            if font.dc.is_null() {
+                return Err(io::Error::other("failed to create a GDI device context"));
+            }
+
+            let height = (options.pixel_size * options.dpi_scale).round() as i32;
+            font.gdi_font = CreateFontW(
+                -height,
+                0,
+                0,
+                0,
+                style.weight as i32,
+                style.italic as DWORD,
+                0,
+                0,
+                DEFAULT_CHARSET,
+                OUT_OUTLINE_PRECIS,
+                CLIP_DEFAULT_PRECIS,
+                ANTIALIASED_QUALITY,
+                DEFAULT_PITCH,
+                face_name.as_ptr(),
+            );
+            if font.gdi_font.is_null() {
+                return Err(io::Error::other("failed to create the GDI font"));
+            }
+            font.previous_font = SelectObject(font.dc, font.gdi_font as HGDIOBJ);
+
+            // GDI quietly substitutes another font for a name it doesn't know.
+            let mut selected_name = [0u16; LF_FACESIZE];
+            if GetTextFaceW(font.dc, LF_FACESIZE as i32, selected_name.as_mut_ptr()) == 0 {
+                return Err(io::Error::other("failed to get the selected font's name"));
+            }
+            let selected_name = ::from_wide(&selected_name);
+            let requested_name = ::from_wide(&face_name);
+            if !selected_name.eq_ignore_ascii_case(&requested_name) {
+                return Err(io::Error::other(format!(
+                    "GDI picked {} instead of {}",
+                    selected_name, requested_name
+                )));
+            }
+
+            let mut metrics: TEXTMETRICW = std::mem::zeroed();
+            if GetTextMetricsW(font.dc, &mut metrics) == 0 {
+                return Err(io::Error::other("failed to get the font metrics"));
+            }
+            font.ascent = metrics.tmAscent as f32;
+            font.font = BitmapFont::new((metrics.tmHeight + metrics.tmExternalLeading) as f32);
+            font.add_kerning();
+
+            if font.prepare("?") && font.font.get_glyph('?').is_some() {
+                font.font.set_default_char(Some('?'));
+            }
+            Ok(font)
+        }
+    }
+
+    pub fn get_font(&self) -> &BitmapFont {
+        &self.font
+    }
+
+    // Characters prepare had to leave out because their glyphs are larger than a page. They
+    // draw as the default character, a larger page size fixes them.
+    pub fn get_too_large_chars(&self) -> &[char] {
+        &self.too_large
+    }
+
+    // Adds every character of the text that isn't in the atlas yet. Returns true if anything was
+    // added, in which case SpriteFonts made from get_font have to be made again to show it.
+    pub fn prepare(&mut self, text: &str) -> bool {
+        let mut changed_pages: Vec<usize> = Vec::new();
+        let mut changed = false;
+        for c in text.chars() {
+            let glyph = match self.add_char(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+
+            changed = true;
+            if glyph.source.width > 0.0 && !changed_pages.contains(&glyph.page) {
+                changed_pages.push(glyph.page);
+            }
+        }
+
+        // New pages have to be added in order.
+        changed_pages.sort();
+        for page in changed_pages {
+            self.font.set_page(page, self.atlas.get_page(page));
+        }
+        changed
+    }
+
+    fn add_char(&mut self, c: char) -> Option<Glyph> {
+        if c == '\n' || c == '\r' || !self.known.insert(c) {
+            return None;
+        }
+
+        let bitmap = unsafe { self.rasterize(c)? };
+        let glyph = self.atlas.insert(&bitmap);
+        match glyph {
+            Some(glyph) => self.font.add_glyph(c, glyph),
+            None => self.too_large.push(c),
+        }
+        glyph
+    }
+
+    // None if the font doesn't have the character. GDI takes UTF-16 code units, so that includes
+    // everything outside the basic multilingual plane.
+    unsafe fn rasterize(&self, c: char) -> Option<GlyphBitmap> {
+        let code = c as u32;
+        if code > 0xffff {
+            return None;
+        }
+
+        // GDI would draw a box for characters the font doesn't have, leave them to the default
+        // character instead.
+        let mut glyph_index = 0;
+        GetGlyphIndicesW(
+            self.dc,
+            &(code as u16),
+            1,
+            &mut glyph_index,
+            GGI_MARK_NONEXISTING_GLYPHS,
+        );
+        if glyph_index == MISSING_GLYPH {
+            return None;
+        }
+
+        let one = FIXED { fract: 0, value: 1 };
+        let zero = FIXED { fract: 0, value: 0 };
+        let identity = MAT2 {
+            eM11: one,
+            eM12: zero,
+            eM21: zero,
+            eM22: one,
+        };
+
+        let mut metrics: GLYPHMETRICS = std::mem::zeroed();
+        let size = GetGlyphOutlineW(
+            self.dc,
+            code,
+            GGO_GRAY8_BITMAP,
+            &mut metrics,
+            0,
+            std::ptr::null_mut(),
+            &identity,
+        );
+        if size == GDI_ERROR {
+            return None;
+        }
+
+        let offset = [
+            metrics.gmptGlyphOrigin.x as f32,
+            self.ascent - metrics.gmptGlyphOrigin.y as f32,
+        ];
+        let advance = metrics.gmCellIncX as f32;
+
+        // Nothing to draw, e.g. a space.
+        if size == 0 {
+            return Some(GlyphBitmap {
+                width: 0,
+                height: 0,
+                coverage: Vec::new(),
+                offset,
+                advance,
+            });
+        }
+
+        let mut buffer = vec![0u8; size as usize];
+        let result = GetGlyphOutlineW(
+            self.dc,
+            code,
+            GGO_GRAY8_BITMAP,
+            &mut metrics,
+            size,
+            buffer.as_mut_ptr() as *mut _,
+            &identity,
+        );
+        if result == GDI_ERROR {
+            return None;
+        }
+
+        // Rows are DWORD aligned.
+        let width = metrics.gmBlackBoxX;
+        let height = metrics.gmBlackBoxY;
+        let pitch = (width + 3) & !3;
+        let mut coverage = Vec::with_capacity((width * height) as usize);
+        for row in buffer.chunks(pitch as usize).take(height as usize) {
+            for &value in row.iter().take(width as usize) {
+                coverage.push((value as u32 * 255 / GRAY8_LEVELS) as u8);
+            }
+        }
+
+        Some(GlyphBitmap {
+            width,
+            height,
+            coverage,
+            offset,
+            advance,
+        })
+    }
+
+    unsafe fn add_kerning(&mut self) {
+        let count = GetKerningPairsW(self.dc, 0, std::ptr::null_mut());
+        if count == 0 {
+            return;
+        }
+
+        let mut pairs: Vec<KERNINGPAIR> = vec![std::mem::zeroed(); count as usize];
+        let count = GetKerningPairsW(self.dc, count, pairs.as_mut_ptr());
+        for pair in pairs.iter().take(count as usize) {
+            let first = std::char::from_u32(pair.wFirst as u32);
+            let second = std::char::from_u32(pair.wSecond as u32);
+            if let (Some(first), Some(second)) = (first, second) {
+                self.font
+                    .add_kerning(first, second, pair.iKernAmount as f32);
+            }
+        }
+    }
+}
+
+impl Drop for TrueTypeFont {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.previous_font.is_null() {
+                SelectObject(self.dc, self.previous_font);
+            }
+            if !self.gdi_font.is_null() {
+                DeleteObject(self.gdi_font as HGDIOBJ);
+            }
+            if !self.dc.is_null() {
+                DeleteDC(self.dc);
+            }
+            RemoveFontMemResourceEx(self.resource);
+        }
+    }
+}
+
+// Where the table with the given tag starts. Takes the first font of a collection.
+fn find_table(data: &[u8], tag: &[u8; 4]) -> Option<usize> {
+    let font_offset = if data.starts_with(b"ttcf") {
+        read_u32(data, 12)? as usize
+    } else {
+        0
+    };
+
+    let table_count = read_u16(data, font_offset + 4)? as usize;
+    let record = (0..table_count)
+        .map(|i| font_offset + 12 + i * 16)
+        .find(|&record| data.get(record..record + 4) == Some(&tag[..]))?;
+    Some(read_u32(data, record + 8)? as usize)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// The family name from the font's name table, preferring the English one Windows uses.
+pub fn get_family_name(data: &[u8]) -> Option<String> {
+    let table = find_table(data, b"name")?;
+    let name_count = read_u16(data, table + 2)? as usize;
+    let strings = table + read_u16(data, table + 4)? as usize;
+
+    // Lower is better.
+    let mut best: Option<(u32, String)> = None;
+    for i in 0..name_count {
+        let record = table + 6 + i * 12;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        let language = read_u16(data, record + 4)?;
+        let name_id = read_u16(data, record + 6)?;
+        let length = read_u16(data, record + 8)? as usize;
+        let offset = strings + read_u16(data, record + 10)? as usize;
+
+        // 1 is the family name.
+        if name_id != 1 {
+            continue;
+        }
+        let bytes = data.get(offset..offset + length)?;
+        let utf16 = || {
+            let units: Vec<u16> = bytes
+                .chunks(2)
+                .filter(|c| c.len() == 2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        };
+
+        let (rank, name) = match (platform, encoding) {
+            // Windows, 0x409 being US English.
+            (3, _) if language == 0x409 => (0, utf16()),
+            (3, _) => (1, utf16()),
+            // Unicode
+            (0, _) => (2, utf16()),
+            // Macintosh Roman, close enough to Latin-1 for names.
+            (1, 0) => (3, bytes.iter().map(|&b| b as char).collect()),
+            _ => continue,
+        };
+        if best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank) {
+            best = Some((rank, name));
+        }
+    }
+
+    best.map(|(_, name)| name)
+}
+
+// The weight and slant CreateFontW has to ask for to get this face of its family.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FontStyle {
+    pub weight: u16,
+    pub italic: bool,
+}
+
+// From the OS/2 table, or the head table's style bits for old fonts without one. Regular if
+// there's neither.
+pub fn get_font_style(data: &[u8]) -> FontStyle {
+    let os2 = find_table(data, b"OS/2").and_then(|table| {
+        let weight = read_u16(data, table + 4)?;
+        let selection = read_u16(data, table + 62)?;
+        Some(FontStyle {
+            weight,
+            italic: selection & 1 != 0,
+        })
+    });
+    let head = || {
+        let table = find_table(data, b"head")?;
+        let mac_style = read_u16(data, table + 44)?;
+        let weight = if mac_style & 1 != 0 {
+            FW_BOLD
+        } else {
+            FW_NORMAL
+        };
+        Some(FontStyle {
+            weight: weight as u16,
+            italic: mac_style & 2 != 0,
+        })
+    };
+
+    os2.or_else(head).unwrap_or(FontStyle {
+        weight: FW_NORMAL as u16,
+        italic: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just the table directory and the given tables, which is all the parsing looks at.
+    fn make_font(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut font = vec![0, 1, 0, 0];
+        font.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        font.extend_from_slice(&[0; 6]);
+
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, table) in tables {
+            font.extend_from_slice(&tag[..]);
+            font.extend_from_slice(&[0; 4]);
+            font.extend_from_slice(&(offset as u32).to_be_bytes());
+            font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in tables {
+            font.extend_from_slice(table);
+        }
+        font
+    }
+
+    // Records as (platform, encoding, language, name id, bytes).
+    fn make_name_table(records: &[(u16, u16, u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut table = Vec::new();
+        table.extend_from_slice(&0u16.to_be_bytes());
+        table.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        table.extend_from_slice(&(6 + records.len() as u16 * 12).to_be_bytes());
+
+        let mut strings = Vec::new();
+        for &(platform, encoding, language, name_id, ref bytes) in records {
+            for value in [
+                platform,
+                encoding,
+                language,
+                name_id,
+                bytes.len() as u16,
+                strings.len() as u16,
+            ]
+            .iter()
+            {
+                table.extend_from_slice(&value.to_be_bytes());
+            }
+            strings.extend_from_slice(bytes);
+        }
+        table.extend(strings);
+        table
+    }
+
+    fn utf16_be(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn family_name() {
+        let name = make_name_table(&[
+            (1, 0, 0, 1, b"Mac Name".to_vec()),
+            (3, 1, 0x407, 1, utf16_be("Deutscher Name")),
+            (3, 1, 0x409, 2, utf16_be("Bold")),
+            (3, 1, 0x409, 1, utf16_be("English Name")),
+        ]);
+        let font = make_font(&[(b"head", vec![0; 54]), (b"name", name)]);
+        assert_eq!(get_family_name(&font), Some("English Name".to_string()));
+    }
+
+    #[test]
+    fn family_name_fallbacks() {
+        let mac_only = make_font(&[(b"name", make_name_table(&[(1, 0, 0, 1, b"Mac".to_vec())]))]);
+        assert_eq!(get_family_name(&mac_only), Some("Mac".to_string()));
+
+        let subfamily_only = make_font(&[(
+            b"name",
+            make_name_table(&[(3, 1, 0x409, 2, utf16_be("Bold"))]),
+        )]);
+        assert_eq!(get_family_name(&subfamily_only), None);
+
+        assert_eq!(get_family_name(&make_font(&[(b"head", vec![0; 54])])), None);
+        assert_eq!(get_family_name(&[0, 1, 0, 0]), None);
+    }
+
+    #[test]
+    fn family_name_in_collection() {
+        let name = make_name_table(&[(3, 1, 0x409, 1, utf16_be("First"))]);
+        let mut font = make_font(&[(b"name", name)]);
+
+        // The header, then a single offset to the font, whose table offsets are from the start
+        // of the whole file.
+        let mut collection = b"ttcf".to_vec();
+        collection.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1]);
+        collection.extend_from_slice(&16u32.to_be_bytes());
+        let table_offset = u32::from_be_bytes([font[20], font[21], font[22], font[23]]);
+        font[20..24].copy_from_slice(&(table_offset + 16).to_be_bytes());
+        collection.extend(font);
+
+        assert_eq!(get_family_name(&collection), Some("First".to_string()));
+    }
+
+    #[test]
+    fn style_from_os2() {
+        let mut os2 = vec![0; 78];
+        os2[4..6].copy_from_slice(&300u16.to_be_bytes());
+        os2[62..64].copy_from_slice(&1u16.to_be_bytes());
+        // The head table says bold, but OS/2 wins.
+        let mut head = vec![0; 54];
+        head[44..46].copy_from_slice(&1u16.to_be_bytes());
+
+        let font = make_font(&[(b"OS/2", os2), (b"head", head)]);
+        assert_eq!(
+            get_font_style(&font),
+            FontStyle {
+                weight: 300,
+                italic: true
+            }
+        );
+    }
+
+    #[test]
+    fn style_from_head() {
+        let mut head = vec![0; 54];
+        head[44..46].copy_from_slice(&3u16.to_be_bytes());
+        assert_eq!(
+            get_font_style(&make_font(&[(b"head", head)])),
+            FontStyle {
+                weight: FW_BOLD as u16,
+                italic: true
+            }
+        );
+
+        assert_eq!(
+            get_font_style(&make_font(&[])),
+            FontStyle {
+                weight: FW_NORMAL as u16,
+                italic: false
+            }
+        );
+    }
+}
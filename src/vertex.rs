@@ -0,0 +1,266 @@
+use winapi::shared::dxgiformat::{
+    DXGI_FORMAT, DXGI_FORMAT_R32G32B32A32_FLOAT, DXGI_FORMAT_R32G32B32A32_SINT,
+    DXGI_FORMAT_R32G32B32A32_UINT, DXGI_FORMAT_R32G32B32_FLOAT, DXGI_FORMAT_R32G32B32_SINT,
+    DXGI_FORMAT_R32G32B32_UINT, DXGI_FORMAT_R32G32_FLOAT, DXGI_FORMAT_R32G32_SINT,
+    DXGI_FORMAT_R32G32_UINT, DXGI_FORMAT_R32_FLOAT, DXGI_FORMAT_R32_SINT, DXGI_FORMAT_R32_UINT,
+    DXGI_FORMAT_R8G8B8A8_UINT, DXGI_FORMAT_R8G8B8A8_UNORM,
+};
+
+// What the shader sees when it reads an attribute. Normalized formats read as floats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ComponentType {
+    Float,
+    Uint,
+    Sint,
+}
+
+// Rust types that can be vertex attributes, with the format the input assembler reads them as.
+pub trait VertexFormat {
+    fn get_format() -> DXGI_FORMAT;
+    fn get_component_type() -> ComponentType;
+}
+
+macro_rules! vertex_formats {
+    ($($type:ty => $format:ident, $component_type:ident;)*) => {
+        $(
+            impl VertexFormat for $type {
+                fn get_format() -> DXGI_FORMAT {
+                    $format
+                }
+
+                fn get_component_type() -> ComponentType {
+                    ComponentType::$component_type
+                }
+            }
+        )*
+    };
+}
+
+vertex_formats! {
+    f32 => DXGI_FORMAT_R32_FLOAT, Float;
+    [f32; 2] => DXGI_FORMAT_R32G32_FLOAT, Float;
+    [f32; 3] => DXGI_FORMAT_R32G32B32_FLOAT, Float;
+    [f32; 4] => DXGI_FORMAT_R32G32B32A32_FLOAT, Float;
+    u32 => DXGI_FORMAT_R32_UINT, Uint;
+    [u32; 2] => DXGI_FORMAT_R32G32_UINT, Uint;
+    [u32; 3] => DXGI_FORMAT_R32G32B32_UINT, Uint;
+    [u32; 4] => DXGI_FORMAT_R32G32B32A32_UINT, Uint;
+    i32 => DXGI_FORMAT_R32_SINT, Sint;
+    [i32; 2] => DXGI_FORMAT_R32G32_SINT, Sint;
+    [i32; 3] => DXGI_FORMAT_R32G32B32_SINT, Sint;
+    [i32; 4] => DXGI_FORMAT_R32G32B32A32_SINT, Sint;
+    // Colors, 0 to 255 in the vertex and 0 to 1 in the shader.
+    [u8; 4] => DXGI_FORMAT_R8G8B8A8_UNORM, Float;
+}
+
+// Bone indices and the like, which the shader should see as integers.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct U8x4(pub [u8; 4]);
+
+vertex_formats! {
+    U8x4 => DXGI_FORMAT_R8G8B8A8_UINT, Uint;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VertexAttribute {
+    pub semantic: &'static str,
+    // TEXCOORD0, TEXCOORD1 and so on. Counts up for each attribute with the same semantic.
+    pub semantic_index: u32,
+    pub format: DXGI_FORMAT,
+    pub component_type: ComponentType,
+    pub offset: usize,
+}
+
+// Implemented by impl_vertex!. The attributes end up in the input layout in this order.
+pub trait Vertex: Copy {
+    fn get_attributes() -> Vec<VertexAttribute>;
+}
+
+// Numbers attributes that share a semantic, in order.
+pub fn get_semantic_index(previous: &[VertexAttribute], semantic: &str) -> u32 {
+    previous
+        .iter()
+        .filter(|a| a.semantic.eq_ignore_ascii_case(semantic))
+        .count() as u32
+}
+
+// One input of a vertex shader, as reported by reflection.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ShaderInput {
+    pub semantic: String,
+    pub semantic_index: u32,
+    pub component_type: ComponentType,
+}
+
+// Every input the shader reads has to come from an attribute with the same semantic and the same
+// kind of components. Attributes the shader doesn't read are fine.
+pub fn check_shader_inputs(
+    attributes: &[VertexAttribute],
+    inputs: &[ShaderInput],
+) -> Result<(), String> {
+    for input in inputs {
+        let attribute = attributes.iter().find(|a| {
+            a.semantic.eq_ignore_ascii_case(&input.semantic)
+                && a.semantic_index == input.semantic_index
+        });
+
+        match attribute {
+            None => {
+                return Err(format!(
+                    "the shader reads {}{} but the vertex doesn't have it",
+                    input.semantic, input.semantic_index
+                ))
+            }
+            Some(a) if a.component_type != input.component_type => {
+                return Err(format!(
+                    "the shader reads {}{} as {:?} but the vertex has {:?}",
+                    input.semantic, input.semantic_index, input.component_type, a.component_type
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+// The input layout of a #[repr(C)] vertex struct, e.g.
+//
+//     impl_vertex! {
+//         VertexPositionColor {
+//             position: [f32; 3] => "POSITION",
+//             color: [f32; 4] => "COLOR",
+//         }
+//     }
+//
+// The struct itself is declared wherever it belongs, which needn't know about D3D.
+macro_rules! impl_vertex {
+    (
+        $name:ident {
+            $($field:ident : $field_type:ty => $semantic:literal,)*
+        }
+    ) => {
+        impl $crate::vertex::Vertex for $name {
+            fn get_attributes() -> Vec<$crate::vertex::VertexAttribute> {
+                use $crate::vertex::VertexFormat;
+
+                let mut attributes = Vec::new();
+                $(
+                    let semantic_index = $crate::vertex::get_semantic_index(&attributes, $semantic);
+                    attributes.push($crate::vertex::VertexAttribute {
+                        semantic: $semantic,
+                        semantic_index,
+                        format: <$field_type>::get_format(),
+                        component_type: <$field_type>::get_component_type(),
+                        offset: std::mem::offset_of!($name, $field),
+                    });
+                )*
+                attributes
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct SkinnedVertex {
+        position: [f32; 3],
+        texcoord0: [f32; 2],
+        texcoord1: [f32; 2],
+        bone_indices: U8x4,
+        bone_weights: [u8; 4],
+    }
+
+    impl_vertex! {
+        SkinnedVertex {
+            position: [f32; 3] => "POSITION",
+            texcoord0: [f32; 2] => "TEXCOORD",
+            texcoord1: [f32; 2] => "texcoord",
+            bone_indices: U8x4 => "BLENDINDICES",
+            bone_weights: [u8; 4] => "BLENDWEIGHT",
+        }
+    }
+
+    fn input(semantic: &str, semantic_index: u32, component_type: ComponentType) -> ShaderInput {
+        ShaderInput {
+            semantic: semantic.to_string(),
+            semantic_index,
+            component_type,
+        }
+    }
+
+    #[test]
+    fn attributes() {
+        let attributes = SkinnedVertex::get_attributes();
+        let summary: Vec<_> = attributes
+            .iter()
+            .map(|a| (a.semantic, a.semantic_index, a.offset))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("POSITION", 0, 0),
+                ("TEXCOORD", 0, 12),
+                ("texcoord", 1, 20),
+                ("BLENDINDICES", 0, 28),
+                ("BLENDWEIGHT", 0, 32),
+            ]
+        );
+
+        assert_eq!(attributes[3].format, DXGI_FORMAT_R8G8B8A8_UINT);
+        assert_eq!(attributes[3].component_type, ComponentType::Uint);
+        assert_eq!(attributes[4].format, DXGI_FORMAT_R8G8B8A8_UNORM);
+        assert_eq!(attributes[4].component_type, ComponentType::Float);
+    }
+
+    #[test]
+    fn semantic_index() {
+        let attributes = SkinnedVertex::get_attributes();
+        assert_eq!(get_semantic_index(&attributes, "TexCoord"), 2);
+        assert_eq!(get_semantic_index(&attributes, "COLOR"), 0);
+        assert_eq!(get_semantic_index(&[], "TEXCOORD"), 0);
+    }
+
+    #[test]
+    fn matching_inputs() {
+        let attributes = SkinnedVertex::get_attributes();
+        let inputs = [
+            input("POSITION", 0, ComponentType::Float),
+            input("TEXCOORD", 1, ComponentType::Float),
+            input("BlendIndices", 0, ComponentType::Uint),
+        ];
+        assert_eq!(check_shader_inputs(&attributes, &inputs), Ok(()));
+        assert_eq!(check_shader_inputs(&attributes, &[]), Ok(()));
+    }
+
+    #[test]
+    fn missing_semantic() {
+        let attributes = SkinnedVertex::get_attributes();
+        for missing in [
+            input("NORMAL", 0, ComponentType::Float),
+            input("TEXCOORD", 2, ComponentType::Float),
+        ] {
+            let error = check_shader_inputs(&attributes, &[missing]).unwrap_err();
+            assert!(error.contains("doesn't have it"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn component_type_mismatch() {
+        let attributes = SkinnedVertex::get_attributes();
+        let inputs = [input("BLENDINDICES", 0, ComponentType::Float)];
+        assert_eq!(
+            check_shader_inputs(&attributes, &inputs),
+            Err("the shader reads BLENDINDICES0 as Float but the vertex has Uint".to_string())
+        );
+
+        // A normalized color reads as a float, not as the bytes it's stored as.
+        let inputs = [input("BLENDWEIGHT", 0, ComponentType::Uint)];
+        assert!(check_shader_inputs(&attributes, &inputs).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use vertex::{check_shader_inputs, ComponentType, ShaderInput, Vertex};
+use winapi::shared::dxgiformat::{DXGI_FORMAT, DXGI_FORMAT_R16_UINT, DXGI_FORMAT_R32_UINT};
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d11::{
+    ID3D11Buffer, ID3D11Device, ID3D11DeviceContext, ID3D11InputLayout, ID3D11Resource,
+    D3D11_BIND_INDEX_BUFFER, D3D11_BIND_VERTEX_BUFFER, D3D11_BUFFER_DESC, D3D11_CPU_ACCESS_WRITE,
+    D3D11_INPUT_ELEMENT_DESC, D3D11_INPUT_PER_VERTEX_DATA, D3D11_MAPPED_SUBRESOURCE,
+    D3D11_MAP_WRITE_DISCARD, D3D11_SUBRESOURCE_DATA, D3D11_USAGE, D3D11_USAGE_DYNAMIC,
+    D3D11_USAGE_IMMUTABLE,
+};
+use winapi::um::d3d11shader::{
+    ID3D11ShaderReflection, D3D11_SHADER_DESC, D3D11_SIGNATURE_PARAMETER_DESC,
+};
+use winapi::um::d3dcommon::{
+    D3D_NAME_UNDEFINED, D3D_REGISTER_COMPONENT_SINT32, D3D_REGISTER_COMPONENT_UINT32,
+};
+use winapi::um::d3dcompiler::D3DReflect;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferUsage {
+    // Written once when it's created, fastest for the GPU.
+    Immutable,
+    // Rewritten from the CPU with set_data, e.g. every frame.
+    Dynamic,
+}
+
+impl BufferUsage {
+    fn get_d3d_usage(&self) -> (D3D11_USAGE, u32) {
+        match *self {
+            BufferUsage::Immutable => (D3D11_USAGE_IMMUTABLE, 0),
+            BufferUsage::Dynamic => (D3D11_USAGE_DYNAMIC, D3D11_CPU_ACCESS_WRITE),
+        }
+    }
+}
+
+// Creates a buffer big enough for capacity items of T, filled with data if there is any.
+// Immutable buffers can't be created empty.
+unsafe fn create_buffer<T: Copy>(
+    device: &ID3D11Device,
+    data: &[T],
+    capacity: usize,
+    usage: BufferUsage,
+    bind_flags: u32,
+) -> Result<ComPtr<ID3D11Buffer>, HRESULT> {
+    assert!(data.len() <= capacity);
+    assert!(usage == BufferUsage::Dynamic || !data.is_empty());
+
+    let (d3d_usage, cpu_access_flags) = usage.get_d3d_usage();
+    let desc = D3D11_BUFFER_DESC {
+        ByteWidth: (capacity * std::mem::size_of::<T>()) as u32,
+        Usage: d3d_usage,
+        BindFlags: bind_flags,
+        CPUAccessFlags: cpu_access_flags,
+        MiscFlags: 0,
+        StructureByteStride: 0,
+    };
+    let initial_data = D3D11_SUBRESOURCE_DATA {
+        pSysMem: data.as_ptr() as *const _,
+        SysMemPitch: 0,
+        SysMemSlicePitch: 0,
+    };
+    let initial_data_ptr = if data.is_empty() {
+        std::ptr::null()
+    } else {
+        &initial_data as *const _
+    };
+
+    let mut buffer_ptr: *mut ID3D11Buffer = std::ptr::null_mut();
+    let hr = device.CreateBuffer(&desc, initial_data_ptr, &mut buffer_ptr);
+    if ::failed(hr) {
+        return Err(hr);
+    }
+
+    Ok(ComPtr::from_raw(buffer_ptr))
+}
+
+// Replaces the start of a dynamic buffer with data; the rest is undefined afterwards.
+unsafe fn write_buffer<T: Copy>(
+    context: &ID3D11DeviceContext,
+    buffer: &ComPtr<ID3D11Buffer>,
+    data: &[T],
+) -> Result<(), HRESULT> {
+    let resource = buffer.as_raw() as *mut ID3D11Resource;
+    let mut mapped: D3D11_MAPPED_SUBRESOURCE = std::mem::zeroed();
+    let hr = context.Map(resource, 0, D3D11_MAP_WRITE_DISCARD, 0, &mut mapped);
+    if ::failed(hr) {
+        return Err(hr);
+    }
+
+    std::ptr::copy_nonoverlapping(data.as_ptr(), mapped.pData as *mut T, data.len());
+    context.Unmap(resource, 0);
+    Ok(())
+}
+
+pub struct VertexBuffer<V: Vertex> {
+    buffer: ComPtr<ID3D11Buffer>,
+    usage: BufferUsage,
+    capacity: usize,
+    count: usize,
+    phantom: PhantomData<V>,
+}
+
+impl<V: Vertex> VertexBuffer<V> {
+    pub unsafe fn new(
+        device: &ID3D11Device,
+        vertices: &[V],
+        usage: BufferUsage,
+    ) -> Result<VertexBuffer<V>, HRESULT> {
+        VertexBuffer::with_capacity(device, vertices, vertices.len(), usage)
+    }
+
+    // A dynamic buffer that starts out empty and can take up to capacity vertices.
+    pub unsafe fn new_dynamic(
+        device: &ID3D11Device,
+        capacity: usize,
+    ) -> Result<VertexBuffer<V>, HRESULT> {
+        VertexBuffer::with_capacity(device, &[], capacity, BufferUsage::Dynamic)
+    }
+
+    unsafe fn with_capacity(
+        device: &ID3D11Device,
+        vertices: &[V],
+        capacity: usize,
+        usage: BufferUsage,
+    ) -> Result<VertexBuffer<V>, HRESULT> {
+        let buffer = create_buffer(device, vertices, capacity, usage, D3D11_BIND_VERTEX_BUFFER)?;
+        Ok(VertexBuffer {
+            buffer,
+            usage,
+            capacity,
+            count: vertices.len(),
+            phantom: PhantomData,
+        })
+    }
+
+    // Only for dynamic buffers, and no more vertices than the buffer was created with.
+    pub unsafe fn set_data(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        vertices: &[V],
+    ) -> Result<(), HRESULT> {
+        assert!(self.usage == BufferUsage::Dynamic);
+        assert!(vertices.len() <= self.capacity);
+
+        write_buffer(context, &self.buffer, vertices)?;
+        self.count = vertices.len();
+        Ok(())
+    }
+
+    pub unsafe fn bind(&self, context: &ID3D11DeviceContext, slot: u32) {
+        let stride = std::mem::size_of::<V>() as u32;
+        let offset = 0;
+        context.IASetVertexBuffers(slot, 1, &self.buffer.as_raw(), &stride, &offset);
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_buffer(&self) -> &ComPtr<ID3D11Buffer> {
+        &self.buffer
+    }
+}
+
+// 16-bit indices save memory and bandwidth, 32-bit ones are needed past 65536 vertices.
+pub trait Index: Copy {
+    fn get_format() -> DXGI_FORMAT;
+}
+
+impl Index for u16 {
+    fn get_format() -> DXGI_FORMAT {
+        DXGI_FORMAT_R16_UINT
+    }
+}
+
+impl Index for u32 {
+    fn get_format() -> DXGI_FORMAT {
+        DXGI_FORMAT_R32_UINT
+    }
+}
+
+pub struct IndexBuffer {
+    buffer: ComPtr<ID3D11Buffer>,
+    format: DXGI_FORMAT,
+    usage: BufferUsage,
+    capacity: usize,
+    count: usize,
+}
+
+impl IndexBuffer {
+    pub unsafe fn new<I: Index>(
+        device: &ID3D11Device,
+        indices: &[I],
+        usage: BufferUsage,
+    ) -> Result<IndexBuffer, HRESULT> {
+        IndexBuffer::with_capacity(device, indices, indices.len(), usage)
+    }
+
+    pub unsafe fn new_dynamic<I: Index>(
+        device: &ID3D11Device,
+        capacity: usize,
+    ) -> Result<IndexBuffer, HRESULT> {
+        IndexBuffer::with_capacity::<I>(device, &[], capacity, BufferUsage::Dynamic)
+    }
+
+    unsafe fn with_capacity<I: Index>(
+        device: &ID3D11Device,
+        indices: &[I],
+        capacity: usize,
+        usage: BufferUsage,
+    ) -> Result<IndexBuffer, HRESULT> {
+        let buffer = create_buffer(device, indices, capacity, usage, D3D11_BIND_INDEX_BUFFER)?;
+        Ok(IndexBuffer {
+            buffer,
+            format: I::get_format(),
+            usage,
+            capacity,
+            count: indices.len(),
+        })
+    }
+
+    // Only for dynamic buffers, with the same index type the buffer was created with.
+    pub unsafe fn set_data<I: Index>(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        indices: &[I],
+    ) -> Result<(), HRESULT> {
+        assert!(self.usage == BufferUsage::Dynamic);
+        assert!(I::get_format() == self.format);
+        assert!(indices.len() <= self.capacity);
+
+        write_buffer(context, &self.buffer, indices)?;
+        self.count = indices.len();
+        Ok(())
+    }
+
+    pub unsafe fn bind(&self, context: &ID3D11DeviceContext) {
+        context.IASetIndexBuffer(self.buffer.as_raw(), self.format, 0);
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    pub fn get_format(&self) -> DXGI_FORMAT {
+        self.format
+    }
+
+    pub fn get_buffer(&self) -> &ComPtr<ID3D11Buffer> {
+        &self.buffer
+    }
+}
+
+// The inputs a compiled vertex shader reads, leaving out system values like SV_VertexID that
+// don't come from the vertex buffer.
+pub unsafe fn get_shader_inputs(shader_bytecode: &[u8]) -> Result<Vec<ShaderInput>, HRESULT> {
+    let mut reflection_ptr: *mut ID3D11ShaderReflection = std::ptr::null_mut();
+    let hr = D3DReflect(
+        shader_bytecode.as_ptr() as *const _,
+        shader_bytecode.len(),
+        &ID3D11ShaderReflection::uuidof(),
+        &mut reflection_ptr as *mut *mut _ as *mut *mut _,
+    );
+    if ::failed(hr) {
+        return Err(hr);
+    }
+    let reflection = ComPtr::from_raw(reflection_ptr);
+
+    let mut shader_desc: D3D11_SHADER_DESC = std::mem::zeroed();
+    let hr = reflection.GetDesc(&mut shader_desc);
+    if ::failed(hr) {
+        return Err(hr);
+    }
+
+    let mut inputs = Vec::new();
+    for i in 0..shader_desc.InputParameters {
+        let mut desc: D3D11_SIGNATURE_PARAMETER_DESC = std::mem::zeroed();
+        let hr = reflection.GetInputParameterDesc(i, &mut desc);
+        if ::failed(hr) {
+            return Err(hr);
+        }
+        if desc.SystemValueType != D3D_NAME_UNDEFINED {
+            continue;
+        }
+
+        let component_type = match desc.ComponentType {
+            D3D_REGISTER_COMPONENT_UINT32 => ComponentType::Uint,
+            D3D_REGISTER_COMPONENT_SINT32 => ComponentType::Sint,
+            _ => ComponentType::Float,
+        };
+        inputs.push(ShaderInput {
+            semantic: CStr::from_ptr(desc.SemanticName)
+                .to_string_lossy()
+                .into_owned(),
+            semantic_index: desc.SemanticIndex,
+            component_type,
+        });
+    }
+
+    Ok(inputs)
+}
+
+// Builds the input layout for V, after making sure V has everything the shader reads. The
+// bytecode is the vertex shader's, e.g. from ShaderLibrary::get_bytecode.
+pub unsafe fn create_input_layout<V: Vertex>(
+    device: &ID3D11Device,
+    shader_bytecode: &[u8],
+) -> Result<ComPtr<ID3D11InputLayout>, String> {
+    let attributes = V::get_attributes();
+    let inputs = get_shader_inputs(shader_bytecode)
+        .map_err(|hr| format!("Shader reflection failed with HRESULT {:x}", hr))?;
+    check_shader_inputs(&attributes, &inputs)
+        .map_err(|e| format!("{}: {}", std::any::type_name::<V>(), e))?;
+
+    let semantics: Vec<CString> = attributes
+        .iter()
+        .map(|a| CString::new(a.semantic).unwrap())
+        .collect();
+    let elements: Vec<D3D11_INPUT_ELEMENT_DESC> = attributes
+        .iter()
+        .zip(semantics.iter())
+        .map(|(attribute, semantic)| D3D11_INPUT_ELEMENT_DESC {
+            SemanticName: semantic.as_ptr(),
+            SemanticIndex: attribute.semantic_index,
+            Format: attribute.format,
+            InputSlot: 0,
+            AlignedByteOffset: attribute.offset as u32,
+            InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+            InstanceDataStepRate: 0,
+        })
+        .collect();
+
+    let mut layout_ptr: *mut ID3D11InputLayout = std::ptr::null_mut();
+    let hr = device.CreateInputLayout(
+        elements.as_ptr(),
+        elements.len() as u32,
+        shader_bytecode.as_ptr() as *const _,
+        shader_bytecode.len(),
+        &mut layout_ptr,
+    );
+    if ::failed(hr) {
+        return Err(format!("CreateInputLayout failed with HRESULT {:x}", hr));
+    }
+
+    Ok(ComPtr::from_raw(layout_ptr))
+}